@@ -1,117 +1,83 @@
+use std::cell::RefCell;
 use std::fmt;
+use std::rc::Rc;
 use ya6502::memory::dump_zero_page;
 use ya6502::memory::Inspect;
+use ya6502::memory::Poke;
 use ya6502::memory::Read;
 use ya6502::memory::Write;
-use ya6502::memory::{Memory, ReadError, ReadResult, WriteError, WriteResult};
+use ya6502::memory::{Mapping, MemoryMap, ReadDevice, ReadWriteDevice};
+use ya6502::memory::{Memory, ReadResult, WriteResult};
 
 /// Dispatches read/write calls to various devices with memory-mapped interfaces:
-/// TIA, RAM, RIOT (not yet implemented), and ROM.
+/// TIA, RAM, RIOT, and ROM.
 #[derive(Debug)]
-pub struct AddressSpace<T, Ram, Riot, Rom>
-where
-    T: Memory,
-    Ram: Memory,
-    Riot: Memory,
-    Rom: Read,
-{
-    pub tia: T,
-    pub ram: Ram,
-    pub riot: Riot,
-    pub rom: Rom,
-}
-
-enum MemoryArea {
-    Tia,
-    Ram,
-    Riot,
-    Rom,
+pub struct AddressSpace<T, Ram, Riot, Rom> {
+    pub tia: Rc<RefCell<T>>,
+    pub ram: Rc<RefCell<Ram>>,
+    pub riot: Rc<RefCell<Riot>>,
+    pub rom: Rc<RefCell<Rom>>,
+    memory_map: MemoryMap,
 }
 
-impl<T, Ram, Riot, Rom> Inspect for AddressSpace<T, Ram, Riot, Rom>
+impl<T, Ram, Riot, Rom> AddressSpace<T, Ram, Riot, Rom>
 where
-    T: Memory + Inspect,
-    Ram: Memory + Inspect,
-    Riot: Memory + Inspect,
-    Rom: Read + Inspect,
+    T: ReadWriteDevice + Poke + 'static,
+    Ram: ReadWriteDevice + Poke + 'static,
+    Riot: ReadWriteDevice + Poke + 'static,
+    Rom: ReadDevice + Poke + 'static,
 {
-    fn inspect(&self, address: u16) -> ReadResult {
-        match map_address(address) {
-            Some(MemoryArea::Tia) => self.tia.inspect(address),
-            Some(MemoryArea::Ram) => self.ram.inspect(address),
-            Some(MemoryArea::Rom) => self.rom.inspect(address),
-            Some(MemoryArea::Riot) => self.riot.inspect(address),
-            None => Err(ReadError { address }),
+    pub fn with_devices(tia: T, ram: Ram, riot: Riot, rom: Rom) -> Self {
+        let tia = Rc::new(RefCell::new(tia));
+        let ram = Rc::new(RefCell::new(ram));
+        let riot = Rc::new(RefCell::new(riot));
+        let rom = Rc::new(RefCell::new(rom));
+
+        // The chips are only selected using address lines A12, A9, and A7, so
+        // each of them is mirrored all over the address space. Since the
+        // decoding is complete, there's no open bus.
+        let mut memory_map = MemoryMap::new(0);
+        memory_map.mount(Mapping::read_write(0x0000..=0x007F, tia.clone()).with_mirror(0xEF00));
+        memory_map.mount(Mapping::read_write(0x0080..=0x00FF, ram.clone()).with_mirror(0xED00));
+        memory_map.mount(Mapping::read_write(0x0280..=0x02FF, riot.clone()).with_mirror(0xED00));
+        memory_map.mount(Mapping::read_only(0x1000..=0x1FFF, rom.clone()).with_mirror(0xE000));
+        Self {
+            tia,
+            ram,
+            riot,
+            rom,
+            memory_map,
         }
     }
 }
 
-impl<T, Ram, Riot, Rom> Read for AddressSpace<T, Ram, Riot, Rom>
-where
-    T: Memory,
-    Ram: Memory,
-    Riot: Memory,
-    Rom: Read,
-{
-    fn read(&mut self, address: u16) -> ReadResult {
-        match map_address(address) {
-            Some(MemoryArea::Tia) => self.tia.read(address),
-            Some(MemoryArea::Ram) => self.ram.read(address),
-            Some(MemoryArea::Rom) => self.rom.read(address),
-            Some(MemoryArea::Riot) => self.riot.read(address),
-            None => Err(ReadError { address }),
-        }
+impl<T, Ram, Riot, Rom> Inspect for AddressSpace<T, Ram, Riot, Rom> {
+    fn inspect(&self, address: u16) -> ReadResult {
+        self.memory_map.inspect(address)
     }
 }
 
-impl<T, Ram, Riot, Rom> Write for AddressSpace<T, Ram, Riot, Rom>
-where
-    T: Memory,
-    Ram: Memory,
-    Riot: Memory,
-    Rom: Read,
-{
-    fn write(&mut self, address: u16, value: u8) -> WriteResult {
-        match map_address(address) {
-            Some(MemoryArea::Tia) => self.tia.write(address, value),
-            Some(MemoryArea::Ram) => self.ram.write(address, value),
-            Some(MemoryArea::Rom) => Ok(()),
-            Some(MemoryArea::Riot) => self.riot.write(address, value),
-            None => Err(WriteError { address, value }),
-        }
+impl<T, Ram, Riot, Rom> Read for AddressSpace<T, Ram, Riot, Rom> {
+    fn read(&mut self, address: u16) -> ReadResult {
+        self.memory_map.read(address)
     }
 }
 
-impl<T, Ram, Riot, Rom> Memory for AddressSpace<T, Ram, Riot, Rom>
-where
-    T: Memory,
-    Ram: Memory,
-    Riot: Memory,
-    Rom: Read,
-{
+impl<T, Ram, Riot, Rom> Write for AddressSpace<T, Ram, Riot, Rom> {
+    fn write(&mut self, address: u16, value: u8) -> WriteResult {
+        self.memory_map.write(address, value)
+    }
 }
 
-fn map_address(address: u16) -> Option<MemoryArea> {
-    if address & 0b0001_0000_0000_0000 != 0 {
-        Some(MemoryArea::Rom)
-    } else if address & 0b0000_0000_1000_0000 == 0 {
-        Some(MemoryArea::Tia)
-    } else if address & 0b0000_0010_1000_0000 == 0b0000_0000_1000_0000 {
-        Some(MemoryArea::Ram)
-    } else if address & 0b0000_0010_1000_0000 == 0b0000_0010_1000_0000 {
-        Some(MemoryArea::Riot)
-    } else {
-        None
+impl<T, Ram, Riot, Rom> Poke for AddressSpace<T, Ram, Riot, Rom> {
+    fn poke(&mut self, address: u16, value: u8) -> WriteResult {
+        self.memory_map.poke(address, value)
     }
 }
 
-impl<T, Ram, Riot, Rom> fmt::Display for AddressSpace<T, Ram, Riot, Rom>
-where
-    T: Memory + Inspect,
-    Ram: Memory + Inspect,
-    Riot: Memory + Inspect,
-    Rom: Read + Inspect,
-{
+impl<T, Ram, Riot, Rom> Memory for AddressSpace<T, Ram, Riot, Rom> {}
+
+impl<T, Ram, Riot, Rom> fmt::Display for AddressSpace<T, Ram, Riot, Rom> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         dump_zero_page(self, f)
     }
@@ -123,14 +89,18 @@ mod tests {
     use std::error;
     use ya6502::memory::Ram;
 
+    fn address_space_with(value: u8) -> AddressSpace<Ram, Ram, Ram, Ram> {
+        AddressSpace::with_devices(
+            Ram::initialized_with(value, 16),
+            Ram::initialized_with(value, 16),
+            Ram::initialized_with(value, 16),
+            Ram::initialized_with(value, 16),
+        )
+    }
+
     #[test]
     fn reads_and_writes() -> Result<(), Box<dyn error::Error>> {
-        let mut address_space = AddressSpace {
-            tia: Ram::new(16),
-            ram: Ram::new(16),
-            riot: Ram::new(16),
-            rom: Ram::new(16),
-        };
+        let mut address_space = address_space_with(0);
         address_space.write(0, 8)?; // Start of TIA
         address_space.write(0x7F, 5)?; // End of TIA
         address_space.write(0x80, 81)?; // Start of RAM
@@ -139,26 +109,24 @@ mod tests {
         address_space.write(0x29F, 68)?; // End of RIOT
 
         // Note: we can't "officially" write to ROM using an AddressSpace.
-        address_space.rom.bytes[0xF000] = 15; // Start of ROM
-        address_space.rom.bytes[0xFFFF] = 25; // End of ROM
+        address_space.rom.borrow_mut().bytes[0x1000] = 15; // Start of ROM
+        address_space.rom.borrow_mut().bytes[0x1FFF] = 25; // End of ROM
 
-        assert_eq!(address_space.tia.read(0)?, 8);
-        assert_eq!(address_space.tia.read(0x7F)?, 5);
+        assert_eq!(address_space.tia.borrow().bytes[0], 8);
+        assert_eq!(address_space.tia.borrow().bytes[0x7F], 5);
         assert_eq!(address_space.read(0)?, 8);
         assert_eq!(address_space.read(0x7F)?, 5);
 
-        assert_eq!(address_space.ram.read(0x80)?, 81);
-        assert_eq!(address_space.ram.read(0xFF)?, 45);
+        assert_eq!(address_space.ram.borrow().bytes[0x80], 81);
+        assert_eq!(address_space.ram.borrow().bytes[0xFF], 45);
         assert_eq!(address_space.read(0x80)?, 81);
         assert_eq!(address_space.read(0xFF)?, 45);
 
-        assert_eq!(address_space.riot.read(0x280)?, 67);
-        assert_eq!(address_space.riot.read(0x29F)?, 68);
+        assert_eq!(address_space.riot.borrow().bytes[0x280], 67);
+        assert_eq!(address_space.riot.borrow().bytes[0x29F], 68);
         assert_eq!(address_space.read(0x280)?, 67);
         assert_eq!(address_space.read(0x29F)?, 68);
 
-        assert_eq!(address_space.rom.read(0xF000)?, 15);
-        assert_eq!(address_space.rom.read(0xFFFF)?, 25);
         assert_eq!(address_space.read(0xF000)?, 15);
         assert_eq!(address_space.read(0xFFFF)?, 25);
 
@@ -167,12 +135,12 @@ mod tests {
 
     #[test]
     fn address_mapping() {
-        let mut address_space = AddressSpace {
-            tia: Ram::initialized_with(1, 16),
-            ram: Ram::initialized_with(2, 16),
-            riot: Ram::initialized_with(3, 16),
-            rom: Ram::initialized_with(4, 16),
-        };
+        let mut address_space = AddressSpace::with_devices(
+            Ram::initialized_with(1, 16),
+            Ram::initialized_with(2, 16),
+            Ram::initialized_with(3, 16),
+            Ram::initialized_with(4, 16),
+        );
 
         assert_eq!(address_space.read(0x8F45).unwrap(), 1);
         assert_eq!(address_space.read(0x6CD3).unwrap(), 2);
@@ -182,9 +150,27 @@ mod tests {
         address_space.write(0xA33F, 11).unwrap();
         address_space.write(0xC59A, 12).unwrap();
         address_space.write(0x86AB, 13).unwrap();
+        address_space.write(0x7123, 14).unwrap();
 
-        assert_eq!(address_space.tia.bytes[0xA33F], 11);
-        assert_eq!(address_space.ram.bytes[0xC59A], 12);
-        assert_eq!(address_space.riot.bytes[0x86AB], 13);
+        // The devices receive addresses with the mirror bits cleared.
+        assert_eq!(address_space.tia.borrow().bytes[0x003F], 11);
+        assert_eq!(address_space.ram.borrow().bytes[0x009A], 12);
+        assert_eq!(address_space.riot.borrow().bytes[0x02AB], 13);
+        assert_eq!(address_space.rom.borrow().bytes[0x1123], 4);
+    }
+
+    #[test]
+    fn poking() {
+        let mut address_space = address_space_with(0);
+
+        address_space.poke(0x0002, 1).unwrap();
+        address_space.poke(0x0081, 2).unwrap();
+        address_space.poke(0x0282, 3).unwrap();
+        address_space.poke(0xF003, 4).unwrap();
+
+        assert_eq!(address_space.tia.borrow().bytes[0x0002], 1);
+        assert_eq!(address_space.ram.borrow().bytes[0x0081], 2);
+        assert_eq!(address_space.riot.borrow().bytes[0x0282], 3);
+        assert_eq!(address_space.rom.borrow().bytes[0x1003], 4);
     }
 }
@@ -1,7 +1,10 @@
 use common::app::AppController;
 use common::app::MachineController;
 use common::debugger::adapter::DebugAdapter;
+use common::debugger::symbols::Symbols;
 use common::debugger::Debugger;
+use common::trace::TraceWriter;
+use common::vcd::BusRecorder;
 use image::RgbaImage;
 use piston_window::{Button, ButtonState, Event, Input, Key, Loop};
 use std::sync::atomic::AtomicBool;
@@ -21,6 +24,18 @@ impl<'a, A: DebugAdapter> AtariController<'a, A> {
         };
     }
 
+    pub fn set_bus_recorder(&mut self, bus_recorder: BusRecorder) {
+        self.machine_controller.set_bus_recorder(bus_recorder);
+    }
+
+    pub fn set_trace_writer(&mut self, trace_writer: TraceWriter) {
+        self.machine_controller.set_trace_writer(trace_writer);
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.machine_controller.set_symbols(symbols);
+    }
+
     fn mut_atari(&mut self) -> &mut Atari {
         self.machine_controller.mut_machine()
     }
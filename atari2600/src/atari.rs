@@ -7,50 +7,66 @@ use crate::tia;
 use crate::tia::Tia;
 use common::app::FrameStatus;
 use common::app::Machine;
+use common::app::MachineTickResult;
+use common::trace::BeamPosition;
+use common::vcd::cpu_pin_signals;
+use common::vcd::Signal;
 use delegate::delegate;
 use enum_map::{enum_map, Enum, EnumMap};
 use image;
 use image::RgbaImage;
-use std::error;
+use std::cell::Ref;
+use std::cell::RefMut;
+use ya6502::cpu::bus::BusObserver;
+use ya6502::cpu::instructions::InstructionSet;
+use ya6502::cpu::variant::Mos6507;
 use ya6502::cpu::Cpu;
+use ya6502::cpu::MachineEditor;
 use ya6502::cpu::MachineInspector;
 use ya6502::memory::Ram;
 use ya6502::memory::Rom;
+use ya6502::memory::WriteResult;
+use ya6502::power_on::PowerOnState;
 
 pub type AtariAddressSpace = AddressSpace<Tia, Ram, Riot, Rom>;
 
 impl AtariAddressSpace {
-    pub fn new(rom: Rom) -> Self {
-        Self {
-            tia: Tia::new(),
-            ram: Ram::new(7),
-            riot: Riot::new(),
+    pub fn new(rom: Rom, power_on_state: &PowerOnState) -> Self {
+        Self::with_devices(
+            Tia::new(),
+            Ram::with_power_on_state(power_on_state, "RAM", 7),
+            Riot::with_power_on_state(power_on_state),
             rom,
-        }
+        )
     }
 }
 
 pub struct Atari {
-    cpu: Cpu<AtariAddressSpace>,
+    cpu: Cpu<AtariAddressSpace, Mos6507>,
     frame_renderer: FrameRenderer,
     audio_consumer: AudioConsumer,
     switch_positions: EnumMap<Switch, SwitchPosition>,
     joysticks: EnumMap<JoystickPort, Joystick>,
 
     at_cpu_cycle: bool,
+    /// Number of scanlines since the last VSYNC signal. TIA itself doesn't
+    /// count them; it's only used for debugging purposes.
+    scanline: u32,
 }
 
 impl Machine for Atari {
-    /// Performs a single clock tick. If it resulted in an error reported by the
-    /// CPU, dump debug information on standard error stream and return
-    /// `TickResult::Error`.
-    fn tick(&mut self) -> Result<FrameStatus, Box<dyn error::Error>> {
+    /// Performs a single clock tick. Returns an error if the CPU reported one.
+    fn tick(&mut self) -> MachineTickResult {
         let tia_result = self.mut_tia().tick();
+        if tia_result.video.vsync {
+            self.scanline = 0;
+        } else if self.tia().column() == 0 {
+            self.scanline += 1;
+        }
         self.at_cpu_cycle = tia_result.cpu_tick;
         if self.at_cpu_cycle {
-            if let Err(e) = self.cpu.tick() {
-                return Err(e);
-            }
+            self.cpu.set_rdy_pin(tia_result.cpu_rdy);
+            self.cpu.tick()?;
         }
         if tia_result.riot_tick {
             self.mut_riot().tick();
@@ -77,6 +93,27 @@ impl Machine for Atari {
     fn display_state(&self) -> String {
         format!("{}\n{}", self.cpu(), self.cpu().memory())
     }
+
+    fn set_bus_observer(
+        &mut self,
+        observer: Option<Box<dyn BusObserver>>,
+    ) -> Option<Box<dyn BusObserver>> {
+        self.cpu.set_bus_observer(observer)
+    }
+
+    fn signals(&self) -> Vec<Signal> {
+        let mut signals = cpu_pin_signals(&self.cpu);
+        signals.push(Signal::new("tia", "column", 8, self.tia().column() as u64));
+        signals.push(Signal::new("tia", "scanline", 16, self.scanline as u64));
+        signals
+    }
+
+    fn beam_position(&self) -> BeamPosition {
+        BeamPosition {
+            line: self.scanline as usize,
+            column: self.tia().column() as usize,
+        }
+    }
 }
 
 impl MachineInspector for Atari {
@@ -89,6 +126,8 @@ impl MachineInspector for Atari {
             fn reg_sp(&self) -> u8;
             fn flags(&self) -> u8;
             fn inspect_memory(&self, address: u16) -> u8;
+            fn cycles(&self) -> u64;
+            fn instruction_set(&self) -> &'static InstructionSet;
         }
     }
 
@@ -97,20 +136,36 @@ impl MachineInspector for Atari {
     }
 }
 
+impl MachineEditor for Atari {
+    delegate! {
+        to self.cpu {
+            fn poke_memory(&mut self, address: u16, value: u8) -> WriteResult;
+            fn set_reg_pc(&mut self, value: u16);
+            fn set_reg_a(&mut self, value: u8);
+            fn set_reg_x(&mut self, value: u8);
+            fn set_reg_y(&mut self, value: u8);
+            fn set_reg_sp(&mut self, value: u8);
+            fn set_flags(&mut self, value: u8);
+        }
+    }
+}
+
 impl Atari {
     pub fn new(
         address_space: Box<AtariAddressSpace>,
+        power_on_state: &PowerOnState,
         frame_renderer: FrameRenderer,
         audio_consumer: AudioConsumer,
     ) -> Self {
         let mut atari = Atari {
-            cpu: Cpu::new(address_space),
+            cpu: Cpu::with_power_on_state(address_space, power_on_state),
             frame_renderer,
             audio_consumer,
             switch_positions: enum_map! { _ => SwitchPosition::Up },
             joysticks: enum_map! { _ => Joystick::new() },
 
             at_cpu_cycle: false,
+            scanline: 0,
         };
 
         atari.update_switches_riot_port();
@@ -118,16 +173,20 @@ impl Atari {
         return atari;
     }
 
-    pub fn cpu(&self) -> &Cpu<AtariAddressSpace> {
+    pub fn cpu(&self) -> &Cpu<AtariAddressSpace, Mos6507> {
         &self.cpu
     }
 
-    fn mut_tia(&mut self) -> &mut Tia {
-        return &mut self.cpu.mut_memory().tia;
+    fn tia(&self) -> Ref<'_, Tia> {
+        return self.cpu.memory().tia.borrow();
     }
 
-    fn mut_riot(&mut self) -> &mut Riot {
-        return &mut self.cpu.mut_memory().riot;
+    fn mut_tia(&mut self) -> RefMut<'_, Tia> {
+        return self.cpu.mut_memory().tia.borrow_mut();
+    }
+
+    fn mut_riot(&mut self) -> RefMut<'_, Riot> {
+        return self.cpu.mut_memory().riot.borrow_mut();
     }
 
     pub fn switch_position(&self, switch: Switch) -> SwitchPosition {
@@ -287,12 +346,13 @@ mod tests {
     use crate::test_utils::assert_images_equal;
     use crate::test_utils::atari_with_rom;
     use crate::test_utils::read_test_rom;
+    use common::app::MachineError;
     use common::test_utils::read_test_image;
     use image::DynamicImage;
     use test::Bencher;
-    use ya6502::cpu::{opcodes, CpuHaltedError};
+    use ya6502::cpu::{opcodes, CpuError};
 
-    fn next_frame(atari: &mut Atari) -> Result<RgbaImage, Box<dyn error::Error>> {
+    fn next_frame(atari: &mut Atari) -> Result<RgbaImage, MachineError> {
         loop {
             match atari.tick() {
                 Ok(FrameStatus::Pending) => {}
@@ -354,15 +414,13 @@ mod tests {
         let mut atari = atari_with_rom("halt.bin");
 
         let expected_image = read_test_image("reports_halt.png");
-        assert_eq!(
-            *(*next_frame(&mut atari).unwrap_err())
-                .downcast_ref::<CpuHaltedError>()
-                .unwrap(),
-            CpuHaltedError {
+        assert!(matches!(
+            next_frame(&mut atari),
+            Err(MachineError::Cpu(CpuError::Halted {
                 opcode: opcodes::HLT1,
-                address: 0xF2BA
-            }
-        );
+                pc: 0xF2BA
+            }))
+        ));
         let actual_image = DynamicImage::ImageRgba8(atari.frame_image().clone());
         assert_images_equal(actual_image, expected_image, "reports_halt");
     }
@@ -512,10 +570,14 @@ mod tests {
     fn benchmark(b: &mut Bencher) {
         let rom = read_test_rom("horizontal_stripes.bin");
         b.iter(|| {
-            let address_space = Box::new(AtariAddressSpace::new(Rom::new(&rom).unwrap()));
+            let address_space = Box::new(AtariAddressSpace::new(
+                Rom::new(&rom).unwrap(),
+                &PowerOnState::Zero,
+            ));
             let (consumer, _) = create_consumer_and_source();
             let mut atari = Atari::new(
                 address_space,
+                &PowerOnState::Zero,
                 FrameRendererBuilder::new()
                     .with_palette(colors::ntsc_palette())
                     .build(),
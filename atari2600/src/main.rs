@@ -16,6 +16,7 @@ use atari::{Atari, AtariAddressSpace};
 use clap::Parser;
 use common::app::Application;
 use common::app::CommonCliArguments;
+use common::app::Machine;
 use common::debugger::adapter::TcpDebugAdapter;
 use frame_renderer::FrameRendererBuilder;
 use std::sync::atomic::Ordering;
@@ -37,10 +38,12 @@ fn main() {
     // Create and initialize components of the emulated system.
     let address_space = Box::new(AtariAddressSpace::new(
         Rom::new(&rom_bytes[..]).expect("Unable to load the ROM into Atari"),
+        &args.common.power_on,
     ));
     let (audio_consumer, stream, _sink) = audio::initialize();
     let mut atari = Atari::new(
         address_space,
+        &args.common.power_on,
         FrameRendererBuilder::new()
             .with_palette(colors::ntsc_palette())
             .with_height(210)
@@ -54,12 +57,28 @@ fn main() {
         None
     };
 
-    let mut app = Application::new(
-        AtariController::new(&mut atari, debugger_adapter),
-        "Atari 2600",
-        5,
-        3,
-    );
+    let bus_recorder = args
+        .common
+        .bus_recorder(&atari.signals())
+        .expect("Unable to create the bus trace file");
+    let trace_writer = args
+        .common
+        .trace_writer()
+        .expect("Unable to create the trace log file");
+    let symbols = args
+        .common
+        .symbols()
+        .expect("Unable to load the symbol files");
+    let mut controller = AtariController::new(&mut atari, debugger_adapter);
+    if let Some(bus_recorder) = bus_recorder {
+        controller.set_bus_recorder(bus_recorder);
+    }
+    if let Some(trace_writer) = trace_writer {
+        controller.set_trace_writer(trace_writer);
+    }
+    controller.set_symbols(symbols);
+
+    let mut app = Application::new(controller, "Atari 2600", 5, 3);
     let interrupted = app.interrupted();
 
     signal_hook::flag::register(signal_hook::consts::SIGINT, interrupted)
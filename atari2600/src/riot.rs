@@ -1,8 +1,10 @@
 use rand::Rng;
 use ya6502::memory::Inspect;
+use ya6502::memory::Poke;
 use ya6502::memory::Read;
 use ya6502::memory::Write;
 use ya6502::memory::{Memory, ReadError, ReadResult, WriteError, WriteResult};
+use ya6502::power_on::PowerOnState;
 
 /// A MOS Technology 6532 RIOT chip. Note that originally, this chip also
 /// included 128 bytes of RAM, but for the sake of single-responsibility
@@ -64,7 +66,13 @@ enum EdgeDetectionMode {
 
 impl Riot {
     pub fn new() -> Riot {
-        let mut rng = rand::thread_rng();
+        Self::with_power_on_state(&PowerOnState::Random)
+    }
+
+    /// Creates a new RIOT with its timer state determined by a given power-on
+    /// policy.
+    pub fn with_power_on_state(power_on_state: &PowerOnState) -> Riot {
+        let mut rng = power_on_state.rng("RIOT");
         Riot {
             timer_divider: rng.gen(),
             interval_length: [1, 8, 64, 1024][rng.gen_range(0..4)],
@@ -180,6 +188,24 @@ impl Write for Riot {
     }
 }
 
+impl Poke for Riot {
+    /// Changes the register values without restarting the timer or clearing
+    /// interrupt flags. Poking port data registers changes the output
+    /// registers; input pins are still driven from outside.
+    fn poke(&mut self, address: u16, value: u8) -> WriteResult {
+        match canonical_read_address(address) {
+            registers::SWCHA => self.reg_swcha = value,
+            registers::SWACNT => self.reg_swacnt = value,
+            registers::SWCHB => self.reg_swchb = value,
+            registers::SWBCNT => self.reg_swbcnt = value,
+            registers::INTIM => self.reg_intim = value,
+            registers::TIMINT => self.reg_timint = value,
+            _ => return Err(WriteError { address, value }),
+        };
+        Ok(())
+    }
+}
+
 impl Memory for Riot {}
 
 fn canonical_read_address(address: u16) -> u16 {
@@ -330,6 +356,33 @@ mod tests {
         assert_eq!(riot.read(registers::INTIM).unwrap(), 0x03);
     }
 
+    #[test]
+    fn poking_timer() {
+        let mut riot = Riot::new();
+        riot.write(registers::TIM64T, 0x01).unwrap();
+        for _ in 0..(64 + 1) {
+            riot.tick();
+        }
+        assert_eq!(riot.inspect(registers::INTIM).unwrap(), 0xFF);
+        assert_ne!(
+            riot.inspect(registers::TIMINT).unwrap() & flags::TIMINT_TIMER,
+            0
+        );
+
+        // Poking neither changes the interval nor clears the interrupt flag.
+        riot.poke(registers::INTIM, 0x10).unwrap();
+        assert_eq!(riot.inspect(registers::INTIM).unwrap(), 0x10);
+        assert_ne!(
+            riot.inspect(registers::TIMINT).unwrap() & flags::TIMINT_TIMER,
+            0
+        );
+        riot.tick();
+        assert_eq!(riot.inspect(registers::INTIM).unwrap(), 0x0F);
+
+        riot.poke(registers::TIMINT, 0).unwrap();
+        assert_eq!(riot.inspect(registers::TIMINT).unwrap(), 0);
+    }
+
     #[test]
     fn input_ports() {
         let mut riot = Riot::new();
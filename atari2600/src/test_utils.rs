@@ -12,6 +12,7 @@ use image::DynamicImage;
 use std::iter;
 use std::path::Path;
 use ya6502::memory::Rom;
+use ya6502::power_on::PowerOnState;
 
 /// Decodes a convenient, character-based representation of a TIA video output to
 /// an iterator over a `VideoOutput` structure. Useful for representing test
@@ -102,10 +103,14 @@ pub fn encode_audio<I: Iterator<Item = u8>>(outputs: I) -> String {
 
 pub fn atari_with_rom(file_name: &str) -> Atari {
     let rom = read_test_rom(file_name);
-    let address_space = Box::new(AtariAddressSpace::new(Rom::new(&rom).unwrap()));
+    let address_space = Box::new(AtariAddressSpace::new(
+        Rom::new(&rom).unwrap(),
+        &PowerOnState::Zero,
+    ));
     let (consumer, _) = create_consumer_and_source();
     let mut atari = Atari::new(
         address_space,
+        &PowerOnState::Zero,
         FrameRendererBuilder::new()
             .with_palette(colors::ntsc_palette())
             .build(),
@@ -10,9 +10,10 @@ use delay_buffer::DelayBuffer;
 use enum_map::{enum_map, Enum, EnumMap};
 use sprite::{missile_reset_delay_for_player, set_reg_nusiz, Sprite};
 use ya6502::memory::Inspect;
+use ya6502::memory::Poke;
 use ya6502::memory::Read;
 use ya6502::memory::Write;
-use ya6502::memory::{Memory, ReadError, ReadResult, WriteResult};
+use ya6502::memory::{Memory, ReadError, ReadResult, WriteError, WriteResult};
 
 #[derive(Debug, Enum, Copy, Clone)]
 pub enum Port {
@@ -155,6 +156,12 @@ impl Tia {
         }
     }
 
+    /// Returns the column counter, which determines the horizontal position of
+    /// the pixel that will be emitted in the next cycle.
+    pub fn column(&self) -> u32 {
+        self.column_counter
+    }
+
     /// Processes a single TIA clock cycle. Returns a TIA output structure. A
     /// single cycle is the time needed to render a single pixel.
     pub fn tick(&mut self) -> TiaOutput {
@@ -297,7 +304,8 @@ impl Tia {
             },
             audio: self.audio_tick(),
             riot_tick: self.column_counter % 3 == 0,
-            cpu_tick: !self.wait_for_sync && self.column_counter % 3 == 0,
+            cpu_tick: self.column_counter % 3 == 0,
+            cpu_rdy: !self.wait_for_sync,
         };
 
         self.column_counter = (self.column_counter + 1) % TOTAL_WIDTH;
@@ -402,6 +410,29 @@ impl Read for Tia {
     }
 }
 
+impl Poke for Tia {
+    /// Changes the values of read registers (collision latches and input
+    /// ports), i.e. the ones that are visible through [`Inspect`]. Note that
+    /// input port registers will be overwritten as soon as the port state
+    /// changes.
+    fn poke(&mut self, address: u16, value: u8) -> WriteResult {
+        match address & 0b0000_1111 {
+            registers::CXM0P => self.reg_cxm0p = value,
+            registers::CXM1P => self.reg_cxm1p = value,
+            registers::CXP0FB => self.reg_cxp0fb = value,
+            registers::CXP1FB => self.reg_cxp1fb = value,
+            registers::CXM0FB => self.reg_cxm0fb = value,
+            registers::CXM1FB => self.reg_cxm1fb = value,
+            registers::CXBLPF => self.reg_cxblpf = value,
+            registers::CXPPMM => self.reg_cxppmm = value,
+            registers::INPT4 => self.reg_inpt[Port::Input4] = value,
+            registers::INPT5 => self.reg_inpt[Port::Input5] = value,
+            _ => return Err(WriteError { address, value }),
+        };
+        Ok(())
+    }
+}
+
 impl Write for Tia {
     fn write(&mut self, address: u16, value: u8) -> WriteResult {
         match address & 0b0011_1111 {
@@ -503,9 +534,11 @@ impl Memory for Tia {}
 pub struct TiaOutput {
     pub video: VideoOutput,
     pub audio: Option<AudioOutput>,
-    /// If `true`, TIA allows CPU to perform a tick. Otherwise, the CPU is put on
-    /// hold.
+    /// If `true`, TIA tells CPU to perform a tick.
     pub cpu_tick: bool,
+    /// State of the CPU RDY line. It's pulled low by writing to WSYNC until
+    /// the beginning of the next scanline, which puts the CPU on hold.
+    pub cpu_rdy: bool,
     /// If `true`, TIA tells RIOT to perform a tick.
     pub riot_tick: bool,
 }
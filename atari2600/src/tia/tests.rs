@@ -146,12 +146,12 @@ fn freezes_cpu_until_wsync() {
     tia.tick();
     tia.write(registers::WSYNC, 0x00).unwrap();
     for i in 1..TOTAL_WIDTH {
-        assert_eq!(tia.tick().cpu_tick, false, "for index {}", i);
+        let output = tia.tick();
+        assert_eq!(output.cpu_rdy, false, "for index {}", i);
+        assert_eq!(output.cpu_tick, i % 3 == 0, "for index {}", i);
     }
-    assert_eq!(tia.tick().cpu_tick, true);
-    assert_eq!(tia.tick().cpu_tick, false);
-    assert_eq!(tia.tick().cpu_tick, false);
-    assert_eq!(tia.tick().cpu_tick, true);
+    assert_eq!(tia.tick().cpu_rdy, true);
+    assert_eq!(tia.tick().cpu_rdy, true);
 }
 
 #[test]
@@ -1024,6 +1024,23 @@ fn latched_input_ports() {
     assert_eq!(tia.read(registers::INPT4).unwrap(), 0);
 }
 
+#[test]
+fn poking_read_registers() {
+    let mut tia = Tia::new();
+    tia.poke(registers::CXM0P, 0xC0).unwrap();
+    tia.poke(0x1240 + registers::CXPPMM, 0x40).unwrap();
+    tia.poke(registers::INPT5, 0).unwrap();
+    assert_eq!(tia.inspect(registers::CXM0P).unwrap(), 0xC0);
+    assert_eq!(tia.inspect(registers::CXPPMM).unwrap(), 0x40);
+    assert_eq!(tia.inspect(registers::INPT5).unwrap(), 0);
+    assert!(tia.poke(registers::INPT5 + 1, 0).is_err());
+
+    // Poking doesn't strobe the write registers that share the addresses.
+    tia.poke(registers::CXCLR, flags::INPUT_HIGH).unwrap();
+    assert_eq!(tia.inspect(registers::CXM0P).unwrap(), 0xC0);
+    assert_eq!(tia.inspect(registers::INPT4).unwrap(), flags::INPUT_HIGH);
+}
+
 #[test]
 fn generates_audio() {
     let mut tia = Tia::new();
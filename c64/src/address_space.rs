@@ -1,70 +1,102 @@
 use crate::port::Port;
+use std::cell::Ref;
 use std::cell::RefCell;
+use std::cell::RefMut;
 use std::fmt;
 use std::rc::Rc;
 use ya6502::memory::dump_zero_page;
 use ya6502::memory::Inspect;
+use ya6502::memory::Mapping;
+use ya6502::memory::MappingId;
 use ya6502::memory::Memory;
+use ya6502::memory::MemoryMap;
+use ya6502::memory::Poke;
 use ya6502::memory::Ram;
 use ya6502::memory::Read;
+use ya6502::memory::ReadDevice;
 use ya6502::memory::ReadError;
 use ya6502::memory::ReadResult;
+use ya6502::memory::ReadWriteDevice;
 use ya6502::memory::Rom;
 use ya6502::memory::Write;
 use ya6502::memory::WriteError;
 use ya6502::memory::WriteResult;
 
+/// Priority of the ROM, I/O, and CPU port mappings that overlay the RAM.
+const CHIP_PRIORITY: i32 = 1;
+/// Priority of the cartridge ROM mappings, which overlay the built-in ROMs.
+const CARTRIDGE_PRIORITY: i32 = 2;
+
 /// A C64 address space, as visible from the 6510 CPU perspective, through the
 /// C64 PLA chip. Note that technically, it also will handle the CPU port
 /// (addresses 0x0000 and 0x0001), although it should technically be handled by
 /// the CPU itself. This is because the CPU port controls the address space
 /// layout.
 #[derive(Debug)]
-pub struct AddressSpace<Vic, Sid, Cia>
+pub struct AddressSpace<Vic, Cia>
 where
     Vic: Memory,
-    Sid: Memory,
     Cia: Memory,
 {
-    cpu_port: Port,
-    ram: Rc<RefCell<Ram>>,
-    basic_rom: Rom,
-    vic: Vic,
-    sid: Sid,
-    color_ram: Rc<RefCell<Ram>>, // TODO: replace with an actual single-nibble RAM
-    cia1: Cia,
-    cia2: Cia,
-    kernal_rom: Rom,
-    pub cartridge: Option<Cartridge>,
+    cpu_port: Rc<RefCell<CpuPort>>,
+    vic: Rc<RefCell<Vic>>,
+    cia1: Rc<RefCell<Cia>>,
+    cia2: Rc<RefCell<Cia>>,
+    /// Mappings of the currently inserted cartridge.
+    cartridge_mappings: Vec<MappingId>,
+    memory_map: MemoryMap,
 }
 
-impl<Vic, Sid, Cia> AddressSpace<Vic, Sid, Cia>
+impl<Vic, Cia> AddressSpace<Vic, Cia>
 where
     Vic: Memory,
-    Sid: Memory,
     Cia: Memory,
 {
-    pub fn mut_vic(&mut self) -> &mut Vic {
-        &mut self.vic
+    pub fn vic(&self) -> Ref<'_, Vic> {
+        self.vic.borrow()
+    }
+    pub fn mut_vic(&mut self) -> RefMut<'_, Vic> {
+        self.vic.borrow_mut()
     }
-    pub fn mut_cia1(&mut self) -> &mut Cia {
-        &mut self.cia1
+    pub fn mut_cia1(&mut self) -> RefMut<'_, Cia> {
+        self.cia1.borrow_mut()
     }
-    pub fn mut_cia2(&mut self) -> &mut Cia {
-        &mut self.cia2
+    pub fn mut_cia2(&mut self) -> RefMut<'_, Cia> {
+        self.cia2.borrow_mut()
     }
-    pub fn mut_cpu_port(&mut self) -> &mut Port {
-        &mut self.cpu_port
+    pub fn mut_cpu_port(&mut self) -> RefMut<'_, Port> {
+        RefMut::map(self.cpu_port.borrow_mut(), |cpu_port| &mut cpu_port.0)
+    }
+
+    /// Inserts a cartridge, or removes the current one if `None` is given.
+    pub fn set_cartridge(&mut self, cartridge: Option<Cartridge>) {
+        // The memory map doesn't support unmounting, so the mappings of a
+        // removed cartridge just stay disabled.
+        for id in self.cartridge_mappings.drain(..) {
+            self.memory_map.set_enabled(id, false);
+        }
+        if let Some(Cartridge { mode, rom }) = cartridge {
+            let rom = Rc::new(RefCell::new(rom));
+            let ranges = match mode {
+                CartridgeMode::Standard8k => vec![0x8000..=0x9FFF],
+                CartridgeMode::Standard16k => vec![0x8000..=0xBFFF],
+                CartridgeMode::Ultimax => vec![0x8000..=0x9FFF, 0xE000..=0xFFFF],
+            };
+            for range in ranges {
+                let mapping =
+                    Mapping::read_only(range, rom.clone()).with_priority(CARTRIDGE_PRIORITY);
+                self.cartridge_mappings.push(self.memory_map.mount(mapping));
+            }
+        }
     }
 }
 
-impl<Vic, Sid, Cia> AddressSpace<Vic, Sid, Cia>
+impl<Vic, Cia> AddressSpace<Vic, Cia>
 where
-    Vic: Memory,
-    Sid: Memory,
-    Cia: Memory,
+    Vic: Memory + ReadWriteDevice + Poke + 'static,
+    Cia: Memory + ReadWriteDevice + Poke + 'static,
 {
-    pub fn new(
+    pub fn new<Sid: ReadWriteDevice + 'static>(
         ram: Rc<RefCell<Ram>>,
         basic_rom: Rom,
         vic: Vic,
@@ -80,148 +112,171 @@ where
         // if no Datasette) and 5 (attempting to read from the motor output
         // driver) are just wild guess, but mostly irrelevant.
         cpu_port.pins = 0b0011_0111;
+        let cpu_port = Rc::new(RefCell::new(CpuPort(cpu_port)));
+        let vic = Rc::new(RefCell::new(vic));
+        let cia1 = Rc::new(RefCell::new(cia1));
+        let cia2 = Rc::new(RefCell::new(cia2));
+
+        // For now, we only support the default memory layout, so the mappings
+        // are fixed, except for cartridges. Note that the ROMs are mounted for
+        // reading only, so writes go to the RAM underneath.
+        let mut memory_map = MemoryMap::new(0);
+        let chip_mappings = [
+            Mapping::read_write(0x0000..=0x0001, cpu_port.clone()),
+            Mapping::read_only(0xA000..=0xBFFF, Rc::new(RefCell::new(basic_rom))),
+            Mapping::read_write(0xD000..=0xD3FF, vic.clone()),
+            // The SID doesn't hold any state yet, so there's nothing to poke.
+            Mapping::read_write_without_poke(0xD400..=0xD7FF, Rc::new(RefCell::new(sid))),
+            Mapping::read_write(0xD800..=0xDBFF, color_ram),
+            Mapping::read_write(0xDC00..=0xDCFF, cia1.clone()),
+            Mapping::read_write(0xDD00..=0xDDFF, cia2.clone()),
+            Mapping::read_write(0xDE00..=0xDFFF, Rc::new(RefCell::new(ExpansionIo))),
+            Mapping::read_only(0xE000..=0xFFFF, Rc::new(RefCell::new(kernal_rom))),
+        ];
+        memory_map.mount(Mapping::read_write(0x0000..=0xFFFF, ram));
+        for mapping in chip_mappings {
+            memory_map.mount(mapping.with_priority(CHIP_PRIORITY));
+        }
+
         return Self {
             cpu_port,
-            ram,
-            basic_rom,
             vic,
-            sid,
-            color_ram,
             cia1,
             cia2,
-            kernal_rom,
-            cartridge: None,
+            cartridge_mappings: vec![],
+            memory_map,
         };
     }
 }
 
-impl<Vic, Sid, Cia> Inspect for AddressSpace<Vic, Sid, Cia>
+impl<Vic, Cia> Inspect for AddressSpace<Vic, Cia>
 where
-    Vic: Memory + Inspect,
-    Sid: Memory + Inspect,
-    Cia: Memory + Inspect,
+    Vic: Memory,
+    Cia: Memory,
 {
-    // TODO: Reuse the address matching code between inspect() and read()!
     fn inspect(&self, address: u16) -> ReadResult {
-        match address {
-            0x0000 => Ok(self.cpu_port.direction),
-            0x0001 => Ok(self.cpu_port.read()),
-            0x8000..=0x9FFF => match &self.cartridge {
-                Some(Cartridge { mode: _, rom }) => rom.inspect(address),
-                _ => self.ram.borrow().inspect(address),
-            },
-            0xA000..=0xBFFF => match &self.cartridge {
-                Some(Cartridge {
-                    mode: CartridgeMode::Standard16k,
-                    rom,
-                }) => rom.inspect(address),
-                _ => self.basic_rom.inspect(address),
-            },
-            0xD000..=0xD3FF => self.vic.inspect(address),
-            0xD400..=0xD7FF => self.sid.inspect(address),
-            0xD800..=0xDBFF => self.color_ram.borrow().inspect(address),
-            0xDC00..=0xDCFF => self.cia1.inspect(address),
-            0xDD00..=0xDDFF => self.cia2.inspect(address),
-            0xDE00..=0xDFFF => Err(ReadError { address }),
-            0xE000..=0xFFFF => match &self.cartridge {
-                Some(Cartridge {
-                    mode: CartridgeMode::Ultimax,
-                    rom,
-                }) => rom.inspect(address),
-                _ => self.kernal_rom.inspect(address),
-            },
-            _ => self.ram.borrow().inspect(address),
-        }
+        self.memory_map.inspect(address)
     }
 }
 
-impl<Vic, Sid, Cia> Read for AddressSpace<Vic, Sid, Cia>
+impl<Vic, Cia> Read for AddressSpace<Vic, Cia>
 where
     Vic: Memory,
-    Sid: Memory,
     Cia: Memory,
 {
-    // TODO: Reuse the address matching code between inspect() and read()!
     fn read(&mut self, address: u16) -> ReadResult {
-        match address {
-            0x0000 => Ok(self.cpu_port.direction),
-            0x0001 => Ok(self.cpu_port.read()),
-            0x8000..=0x9FFF => match &mut self.cartridge {
-                Some(Cartridge { mode: _, rom }) => rom.read(address),
-                _ => self.ram.borrow_mut().read(address),
-            },
-            0xA000..=0xBFFF => match &mut self.cartridge {
-                Some(Cartridge {
-                    mode: CartridgeMode::Standard16k,
-                    rom,
-                }) => rom.read(address),
-                _ => self.basic_rom.read(address),
-            },
-            0xD000..=0xD3FF => self.vic.read(address),
-            0xD400..=0xD7FF => self.sid.read(address),
-            0xD800..=0xDBFF => self.color_ram.borrow_mut().read(address),
-            0xDC00..=0xDCFF => self.cia1.read(address),
-            0xDD00..=0xDDFF => self.cia2.read(address),
-            0xDE00..=0xDFFF => Err(ReadError { address }),
-            0xE000..=0xFFFF => match &mut self.cartridge {
-                Some(Cartridge {
-                    mode: CartridgeMode::Ultimax,
-                    rom,
-                }) => rom.read(address),
-                _ => self.kernal_rom.read(address),
-            },
-            _ => self.ram.borrow_mut().read(address),
-        }
+        self.memory_map.read(address)
     }
 }
 
-impl<Vic, Sid, Cia> Write for AddressSpace<Vic, Sid, Cia>
+impl<Vic, Cia> Write for AddressSpace<Vic, Cia>
 where
     Vic: Memory,
-    Sid: Memory,
     Cia: Memory,
 {
     fn write(&mut self, address: u16, value: u8) -> WriteResult {
-        match address {
-            0x0000 => Ok(self.cpu_port.direction = value),
-            0x0001 => {
-                // For now, only allow one memory layout.
-                if value & 0b0000_0111 == 0b0000_0111 {
-                    Ok(self.cpu_port.register = value)
-                } else {
-                    Err(WriteError { address, value })
-                }
-            }
-            0xD000..=0xD3FF => self.vic.write(address, value),
-            0xD400..=0xD7FF => self.sid.write(address, value),
-            0xD800..=0xDBFF => self.color_ram.borrow_mut().write(address, value),
-            0xDC00..=0xDCFF => self.cia1.write(address, value),
-            0xDD00..=0xDDFF => self.cia2.write(address, value),
-            0xDE00..=0xDFFF => Err(WriteError { address, value }),
-            _ => self.ram.borrow_mut().write(address, value),
-        }
+        self.memory_map.write(address, value)
     }
 }
 
-impl<Vic, Sid, Cia> Memory for AddressSpace<Vic, Sid, Cia>
+impl<Vic, Cia> Poke for AddressSpace<Vic, Cia>
 where
     Vic: Memory,
-    Sid: Memory,
     Cia: Memory,
 {
+    /// Pokes whatever is visible for reading at a given address, so that ROM
+    /// areas get patched instead of the RAM underneath.
+    fn poke(&mut self, address: u16, value: u8) -> WriteResult {
+        self.memory_map.poke(address, value)
+    }
 }
 
-impl<Vic, Sid, Cia> fmt::Display for AddressSpace<Vic, Sid, Cia>
+impl<Vic, Cia> Memory for AddressSpace<Vic, Cia>
 where
-    Vic: Memory + Inspect,
-    Sid: Memory + Inspect,
-    Cia: Memory + Inspect,
+    Vic: Memory,
+    Cia: Memory,
+{
+}
+
+impl<Vic, Cia> fmt::Display for AddressSpace<Vic, Cia>
+where
+    Vic: Memory,
+    Cia: Memory,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         dump_zero_page(self, f)
     }
 }
 
+/// The 6510 CPU port: a data direction register at 0x0000 and a data register
+/// at 0x0001.
+#[derive(Debug)]
+struct CpuPort(Port);
+
+impl Inspect for CpuPort {
+    fn inspect(&self, address: u16) -> ReadResult {
+        match address {
+            0x0000 => Ok(self.0.direction),
+            _ => Ok(self.0.read()),
+        }
+    }
+}
+
+impl Read for CpuPort {
+    fn read(&mut self, address: u16) -> ReadResult {
+        self.inspect(address)
+    }
+}
+
+impl Write for CpuPort {
+    fn write(&mut self, address: u16, value: u8) -> WriteResult {
+        match address {
+            0x0000 => self.0.direction = value,
+            // For now, only allow one memory layout.
+            _ if value & 0b0000_0111 == 0b0000_0111 => self.0.register = value,
+            _ => return Err(WriteError { address, value }),
+        }
+        Ok(())
+    }
+}
+
+impl Poke for CpuPort {
+    /// Writing to the CPU port has no side effects, so poking is the same as
+    /// writing, including the memory layout validation.
+    fn poke(&mut self, address: u16, value: u8) -> WriteResult {
+        self.write(address, value)
+    }
+}
+
+/// The I/O areas of the expansion port. None of the supported cartridges uses
+/// them, so any access is reported as an error.
+#[derive(Debug)]
+struct ExpansionIo;
+
+impl Inspect for ExpansionIo {
+    fn inspect(&self, address: u16) -> ReadResult {
+        Err(ReadError { address })
+    }
+}
+
+impl Read for ExpansionIo {
+    fn read(&mut self, address: u16) -> ReadResult {
+        self.inspect(address)
+    }
+}
+
+impl Write for ExpansionIo {
+    fn write(&mut self, address: u16, value: u8) -> WriteResult {
+        Err(WriteError { address, value })
+    }
+}
+
+impl Poke for ExpansionIo {
+    fn poke(&mut self, address: u16, value: u8) -> WriteResult {
+        Err(WriteError { address, value })
+    }
+}
+
 #[derive(Debug)]
 pub struct Cartridge {
     pub mode: CartridgeMode,
@@ -243,49 +298,39 @@ pub enum CartridgeMode {
 
 /// An address space, as visible by the VIC-II chip. Note that it doesn't
 /// include the Color RAM, since it's addressed using a separate address line.
+/// The VIC-II only has 14 address lines, so the whole thing is mirrored every
+/// 16KiB.
 #[derive(Debug)]
-pub struct VicAddressSpace<Ram, ChrRam>
-where
-    Ram: Read,
-    ChrRam: Read,
-{
-    ram: Rc<RefCell<Ram>>,
-    char_rom: Rc<RefCell<ChrRam>>,
+pub struct VicAddressSpace {
+    memory_map: MemoryMap,
 }
 
-impl<Ram, ChrRam> VicAddressSpace<Ram, ChrRam>
-where
-    Ram: Read,
-    ChrRam: Read,
-{
-    pub fn new(ram: Rc<RefCell<Ram>>, char_rom: Rc<RefCell<ChrRam>>) -> Self {
-        Self { ram, char_rom }
+impl VicAddressSpace {
+    pub fn new<Ram, ChrRom>(ram: Rc<RefCell<Ram>>, char_rom: Rc<RefCell<ChrRom>>) -> Self
+    where
+        Ram: ReadDevice + 'static,
+        ChrRom: ReadDevice + 'static,
+    {
+        let mut memory_map = MemoryMap::new(0);
+        memory_map.mount(Mapping::read_only_without_poke(0x0000..=0x3FFF, ram).with_mirror(0xC000));
+        memory_map.mount(
+            Mapping::read_only_without_poke(0x1000..=0x1FFF, char_rom)
+                .with_mirror(0xC000)
+                .with_priority(CHIP_PRIORITY),
+        );
+        Self { memory_map }
     }
 }
 
-impl<Ram, ChrRam> Inspect for VicAddressSpace<Ram, ChrRam>
-where
-    Ram: Read + Inspect,
-    ChrRam: Read + Inspect,
-{
-    // TODO: Reuse the address matching code between inspect() and read()!
+impl Inspect for VicAddressSpace {
     fn inspect(&self, address: u16) -> ReadResult {
-        let address = address & 0x3FFF;
-        match address {
-            0x1000..=0x1FFF => self.char_rom.borrow().inspect(address),
-            _ => self.ram.borrow().inspect(address),
-        }
+        self.memory_map.inspect(address)
     }
 }
 
-impl<Ram: Read, ChrRam: Read> Read for VicAddressSpace<Ram, ChrRam> {
-    // TODO: Reuse the address matching code between inspect() and read()!
+impl Read for VicAddressSpace {
     fn read(&mut self, address: u16) -> ReadResult {
-        let address = address & 0x3FFF;
-        match address {
-            0x1000..=0x1FFF => self.char_rom.borrow_mut().read(address),
-            _ => self.ram.borrow_mut().read(address),
-        }
+        self.memory_map.read(address)
     }
 }
 
@@ -293,9 +338,13 @@ impl<Ram: Read, ChrRam: Read> Read for VicAddressSpace<Ram, ChrRam> {
 mod tests {
     use super::*;
 
-    fn new_address_space() -> AddressSpace<Ram, Ram, Ram> {
+    fn new_address_space() -> AddressSpace<Ram, Ram> {
+        new_address_space_with_ram(Rc::new(RefCell::new(Ram::new(16))))
+    }
+
+    fn new_address_space_with_ram(ram: Rc<RefCell<Ram>>) -> AddressSpace<Ram, Ram> {
         AddressSpace::new(
-            Rc::new(RefCell::new(Ram::new(16))),
+            ram,
             Rom::new(&[0xBA; 0x2000]).unwrap(),
             Ram::new(10),
             Ram::new(10),
@@ -306,16 +355,17 @@ mod tests {
         )
     }
 
-    fn new_vic_address_space() -> VicAddressSpace<Ram, Rom> {
+    fn new_vic_address_space(ram: Rc<RefCell<Ram>>) -> VicAddressSpace {
         VicAddressSpace::new(
-            Rc::new(RefCell::new(Ram::new(16))),
+            ram,
             Rc::new(RefCell::new(Rom::new(&[0xCC; 0x1000]).unwrap())),
         )
     }
 
     #[test]
     fn reads_and_writes() {
-        let mut address_space = new_address_space();
+        let ram = Rc::new(RefCell::new(Ram::new(16)));
+        let mut address_space = new_address_space_with_ram(ram.clone());
         address_space.write(0x0002, 33).unwrap(); // RAM
         address_space.write(0x9FFF, 65).unwrap(); // RAM
         address_space.write(0xA000, 82).unwrap(); // RAM under BASIC ROM
@@ -336,9 +386,9 @@ mod tests {
         address_space.write(0xFFFF, 45).unwrap(); // RAM under KERNEL ROM
 
         // RAM
-        assert_eq!(address_space.ram.borrow_mut().read(0x0002).unwrap(), 33);
+        assert_eq!(ram.borrow_mut().read(0x0002).unwrap(), 33);
         assert_eq!(address_space.read(0x0002).unwrap(), 33);
-        assert_eq!(address_space.ram.borrow_mut().read(0x9FFF).unwrap(), 65);
+        assert_eq!(ram.borrow_mut().read(0x9FFF).unwrap(), 65);
         assert_eq!(address_space.read(0x9FFF).unwrap(), 65);
 
         // BASIC ROM
@@ -346,67 +396,107 @@ mod tests {
         assert_eq!(address_space.read(0xBFFF).unwrap(), 0xBA);
 
         // RAM under BASIC ROM
-        assert_eq!(address_space.ram.borrow_mut().read(0xA000).unwrap(), 82);
-        assert_eq!(address_space.ram.borrow_mut().read(0xBFFF).unwrap(), 67);
+        assert_eq!(ram.borrow_mut().read(0xA000).unwrap(), 82);
+        assert_eq!(ram.borrow_mut().read(0xBFFF).unwrap(), 67);
 
         // RAM
-        assert_eq!(address_space.ram.borrow_mut().read(0xC000).unwrap(), 143);
+        assert_eq!(ram.borrow_mut().read(0xC000).unwrap(), 143);
         assert_eq!(address_space.read(0xC000).unwrap(), 143);
-        assert_eq!(address_space.ram.borrow_mut().read(0xCFFF).unwrap(), 213);
+        assert_eq!(ram.borrow_mut().read(0xCFFF).unwrap(), 213);
         assert_eq!(address_space.read(0xCFFF).unwrap(), 213);
 
         // VIC
-        assert_eq!(address_space.vic.read(0x0).unwrap(), 73);
+        assert_eq!(address_space.mut_vic().read(0x0).unwrap(), 73);
         assert_eq!(address_space.read(0xD000).unwrap(), 73);
-        assert_eq!(address_space.vic.read(0x3FF).unwrap(), 11);
+        assert_eq!(address_space.mut_vic().read(0x3FF).unwrap(), 11);
         assert_eq!(address_space.read(0xD3FF).unwrap(), 11);
 
         // SID
-        assert_eq!(address_space.sid.read(0x0).unwrap(), 178);
         assert_eq!(address_space.read(0xD400).unwrap(), 178);
-        assert_eq!(address_space.sid.read(0x3FF).unwrap(), 132);
         assert_eq!(address_space.read(0xD7FF).unwrap(), 132);
 
         // Color RAM
-        assert_eq!(
-            address_space.color_ram.borrow_mut().read(0xD800).unwrap(),
-            5
-        );
         assert_eq!(address_space.read(0xD800).unwrap(), 5);
-        assert_eq!(
-            address_space.color_ram.borrow_mut().read(0xDBFF).unwrap(),
-            15
-        );
         assert_eq!(address_space.read(0xDBFF).unwrap(), 15);
 
         // CIA1
-        assert_eq!(address_space.cia1.read(0x0).unwrap(), 78);
+        assert_eq!(address_space.mut_cia1().read(0x0).unwrap(), 78);
         assert_eq!(address_space.read(0xDC00).unwrap(), 78);
-        assert_eq!(address_space.cia1.read(0xFF).unwrap(), 79);
+        assert_eq!(address_space.mut_cia1().read(0xFF).unwrap(), 79);
         assert_eq!(address_space.read(0xDCFF).unwrap(), 79);
 
         // CIA2
-        assert_eq!(address_space.cia2.read(0x0).unwrap(), 88);
+        assert_eq!(address_space.mut_cia2().read(0x0).unwrap(), 88);
         assert_eq!(address_space.read(0xDD00).unwrap(), 88);
-        assert_eq!(address_space.cia2.read(0xFF).unwrap(), 89);
+        assert_eq!(address_space.mut_cia2().read(0xFF).unwrap(), 89);
         assert_eq!(address_space.read(0xDDFF).unwrap(), 89);
 
+        // None of the I/O chips is backed by RAM.
+        for address in [0xD000, 0xD400, 0xD800, 0xDC00, 0xDD00] {
+            assert_eq!(ram.borrow().bytes[address], 0);
+        }
+
         // KERNEL ROM
         assert_eq!(address_space.read(0xE000).unwrap(), 0xA1);
         assert_eq!(address_space.read(0xFFFF).unwrap(), 0xA1);
 
         // RAM under KERNEL ROM
-        assert_eq!(address_space.ram.borrow_mut().read(0xE000).unwrap(), 87);
-        assert_eq!(address_space.ram.borrow_mut().read(0xFFFF).unwrap(), 45);
+        assert_eq!(ram.borrow_mut().read(0xE000).unwrap(), 87);
+        assert_eq!(ram.borrow_mut().read(0xFFFF).unwrap(), 45);
+    }
+
+    #[test]
+    fn poking() {
+        let ram = Rc::new(RefCell::new(Ram::new(16)));
+        let mut address_space = new_address_space_with_ram(ram.clone());
+        address_space.poke(0x0002, 1).unwrap(); // RAM
+        address_space.poke(0xA000, 2).unwrap(); // BASIC ROM
+        address_space.poke(0xD020, 3).unwrap(); // VIC
+        address_space.poke(0xD800, 4).unwrap(); // Color RAM
+        address_space.poke(0xDC01, 5).unwrap(); // CIA1
+        address_space.poke(0xFFFF, 6).unwrap(); // KERNAL ROM
+        assert!(address_space.poke(0xD400, 7).is_err()); // SID
+        assert!(address_space.poke(0xDE00, 7).is_err()); // Expansion I/O
+
+        assert_eq!(address_space.read(0x0002).unwrap(), 1);
+        assert_eq!(address_space.read(0xA000).unwrap(), 2);
+        assert_eq!(address_space.read(0xD020).unwrap(), 3);
+        assert_eq!(address_space.read(0xD800).unwrap(), 4);
+        assert_eq!(address_space.read(0xDC01).unwrap(), 5);
+        assert_eq!(address_space.read(0xFFFF).unwrap(), 6);
+
+        // ROMs get patched, but the RAM underneath stays intact.
+        assert_eq!(ram.borrow().inspect(0xA000).unwrap(), 0);
+        assert_eq!(ram.borrow().inspect(0xFFFF).unwrap(), 0);
+        assert_eq!(address_space.read(0xA001).unwrap(), 0xBA);
+    }
+
+    #[test]
+    fn cpu_port_poking() {
+        let mut address_space = new_address_space();
+        address_space.poke(0x0001, 0b0010_0111).unwrap();
+        address_space.poke(0x0000, 0b0010_0111).unwrap();
+        assert_eq!(address_space.read(0x0001).unwrap(), 0b0011_0111);
+        assert_eq!(address_space.inspect(0x0000).unwrap(), 0b0010_0111);
+
+        // Just like regular writes, poking rejects unsupported memory layouts.
+        assert_eq!(
+            address_space.poke(0x0001, 0b0010_0000),
+            Err(WriteError {
+                address: 0x0001,
+                value: 0b0010_0000
+            })
+        );
+        assert_eq!(address_space.read(0x0001).unwrap(), 0b0011_0111);
     }
 
     #[test]
     fn cartridge_8k() {
         let mut address_space = new_address_space();
-        address_space.cartridge = Some(Cartridge {
+        address_space.set_cartridge(Some(Cartridge {
             mode: CartridgeMode::Standard8k,
             rom: Rom::new(&[1; 0x10000]).unwrap(),
-        });
+        }));
 
         assert_eq!(address_space.read(0x7FFF).unwrap(), 0);
         assert_eq!(address_space.read(0x8000).unwrap(), 1);
@@ -417,10 +507,10 @@ mod tests {
     #[test]
     fn cartridge_16k() {
         let mut address_space = new_address_space();
-        address_space.cartridge = Some(Cartridge {
+        address_space.set_cartridge(Some(Cartridge {
             mode: CartridgeMode::Standard16k,
             rom: Rom::new(&[2; 0x10000]).unwrap(),
-        });
+        }));
 
         assert_eq!(address_space.read(0x7FFF).unwrap(), 0);
         assert_eq!(address_space.read(0x8000).unwrap(), 2);
@@ -432,10 +522,10 @@ mod tests {
     #[test]
     fn cartridge_ultimax() {
         let mut address_space = new_address_space();
-        address_space.cartridge = Some(Cartridge {
+        address_space.set_cartridge(Some(Cartridge {
             mode: CartridgeMode::Ultimax,
             rom: Rom::new(&[3; 0x10000]).unwrap(),
-        });
+        }));
 
         assert_eq!(address_space.read(0x7FFF).unwrap(), 0);
         assert_eq!(address_space.read(0x8000).unwrap(), 3);
@@ -447,6 +537,24 @@ mod tests {
         assert_eq!(address_space.read(0x0000).unwrap(), 0);
     }
 
+    #[test]
+    fn cartridge_removal() {
+        let mut address_space = new_address_space();
+        address_space.set_cartridge(Some(Cartridge {
+            mode: CartridgeMode::Ultimax,
+            rom: Rom::new(&[3; 0x10000]).unwrap(),
+        }));
+        address_space.set_cartridge(Some(Cartridge {
+            mode: CartridgeMode::Standard8k,
+            rom: Rom::new(&[1; 0x10000]).unwrap(),
+        }));
+        assert_eq!(address_space.read(0x8000).unwrap(), 1);
+        assert_eq!(address_space.read(0xE000).unwrap(), 0xA1);
+
+        address_space.set_cartridge(None);
+        assert_eq!(address_space.read(0x8000).unwrap(), 0);
+    }
+
     #[test]
     fn cpu_port_direction() {
         let mut address_space = new_address_space();
@@ -463,11 +571,12 @@ mod tests {
 
     #[test]
     fn vic_reads() {
-        let mut address_space = new_vic_address_space();
-        address_space.ram.borrow_mut().write(0x0000, 165).unwrap(); // RAM
-        address_space.ram.borrow_mut().write(0x0FFF, 212).unwrap(); // RAM
-        address_space.ram.borrow_mut().write(0x2000, 96).unwrap(); // RAM
-        address_space.ram.borrow_mut().write(0x3FFF, 68).unwrap(); // RAM
+        let ram = Rc::new(RefCell::new(Ram::new(16)));
+        let mut address_space = new_vic_address_space(ram.clone());
+        ram.borrow_mut().write(0x0000, 165).unwrap(); // RAM
+        ram.borrow_mut().write(0x0FFF, 212).unwrap(); // RAM
+        ram.borrow_mut().write(0x2000, 96).unwrap(); // RAM
+        ram.borrow_mut().write(0x3FFF, 68).unwrap(); // RAM
 
         // RAM
         assert_eq!(address_space.read(0x0000).unwrap(), 165);
@@ -484,8 +593,9 @@ mod tests {
 
     #[test]
     fn vic_mirroring() {
-        let mut address_space = new_vic_address_space();
-        address_space.ram.borrow_mut().write(0x2345, 12).unwrap();
+        let ram = Rc::new(RefCell::new(Ram::new(16)));
+        let mut address_space = new_vic_address_space(ram.clone());
+        ram.borrow_mut().write(0x2345, 12).unwrap();
         assert_eq!(address_space.read(0x6345).unwrap(), 12);
         assert_eq!(address_space.read(0xA345).unwrap(), 12);
         assert_eq!(address_space.read(0xE345).unwrap(), 12);
@@ -4,7 +4,10 @@ use crate::keyboard::KeyState;
 use common::app::AppController;
 use common::app::MachineController;
 use common::debugger::adapter::DebugAdapter;
+use common::debugger::symbols::Symbols;
 use common::debugger::Debugger;
+use common::trace::TraceWriter;
+use common::vcd::BusRecorder;
 use image::RgbaImage;
 use piston::Button;
 use piston::ButtonArgs;
@@ -31,6 +34,18 @@ impl<'a, A: DebugAdapter> C64Controller<'a, A> {
             r_gui_key_pressed: false,
         }
     }
+
+    pub fn set_bus_recorder(&mut self, bus_recorder: BusRecorder) {
+        self.machine_controller.set_bus_recorder(bus_recorder);
+    }
+
+    pub fn set_trace_writer(&mut self, trace_writer: TraceWriter) {
+        self.machine_controller.set_trace_writer(trace_writer);
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.machine_controller.set_symbols(symbols);
+    }
 }
 
 impl<'a, A: DebugAdapter> AppController for C64Controller<'a, A> {
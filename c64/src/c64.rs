@@ -12,6 +12,11 @@ use crate::tape::Datasette;
 use crate::Vic;
 use common::app::FrameStatus;
 use common::app::Machine;
+use common::app::MachineError;
+use common::app::MachineTickResult;
+use common::trace::BeamPosition;
+use common::vcd::cpu_pin_signals;
+use common::vcd::Signal;
 use delegate::delegate;
 use image::RgbaImage;
 use std::cell::RefCell;
@@ -19,15 +24,21 @@ use std::error::Error;
 use std::fs;
 use std::path::Path;
 use std::rc::Rc;
+use ya6502::cpu::bus::BusObserver;
+use ya6502::cpu::instructions::InstructionSet;
+use ya6502::cpu::variant::Mos6510;
 use ya6502::cpu::Cpu;
+use ya6502::cpu::MachineEditor;
 use ya6502::cpu::MachineInspector;
 use ya6502::memory::Ram;
 use ya6502::memory::Rom;
+use ya6502::memory::WriteResult;
+use ya6502::power_on::PowerOnState;
 
-pub type C64AddressSpace = AddressSpace<Vic<VicAddressSpace<Ram, Rom>, Ram>, Sid, Cia>;
+pub type C64AddressSpace = AddressSpace<Vic<VicAddressSpace, Ram>, Cia>;
 
 pub struct C64 {
-    cpu: Cpu<C64AddressSpace>,
+    cpu: Cpu<C64AddressSpace, Mos6510>,
     frame_renderer: FrameRenderer,
 
     cpu_clock_divider: u32,
@@ -48,11 +59,19 @@ impl Machine for C64 {
         self.cpu.reset();
     }
 
-    fn tick(&mut self) -> Result<FrameStatus, Box<dyn Error>> {
-        let vic_result = self.cpu.mut_memory().mut_vic().tick()?;
-        let cia1 = self.cpu.mut_memory().mut_cia1();
-        let keyboard_scan_result = self.keyboard.scan(cia1.read_port(PortName::A));
-        cia1.write_port(PortName::B, keyboard_scan_result);
+    fn tick(&mut self) -> MachineTickResult {
+        let vic_result = self
+            .cpu
+            .mut_memory()
+            .mut_vic()
+            .tick()
+            .map_err(|e| MachineError::Other(Box::new(e)))?;
+        let cia1_port_a = self.cpu.mut_memory().mut_cia1().read_port(PortName::A);
+        let keyboard_scan_result = self.keyboard.scan(cia1_port_a);
+        self.cpu
+            .mut_memory()
+            .mut_cia1()
+            .write_port(PortName::B, keyboard_scan_result);
         if self.at_cpu_cycle() {
             self.cpu.tick()?;
             self.cia1_irq = self.cpu.mut_memory().mut_cia1().tick();
@@ -91,6 +110,34 @@ impl Machine for C64 {
     fn display_state(&self) -> String {
         format!("{}\n{}", self.cpu(), self.cpu().memory())
     }
+
+    fn set_bus_observer(
+        &mut self,
+        observer: Option<Box<dyn BusObserver>>,
+    ) -> Option<Box<dyn BusObserver>> {
+        self.cpu.set_bus_observer(observer)
+    }
+
+    fn signals(&self) -> Vec<Signal> {
+        let vic = self.cpu.memory().vic();
+        let mut signals = cpu_pin_signals(&self.cpu);
+        signals.push(Signal::new(
+            "vic",
+            "raster_line",
+            9,
+            vic.raster_line() as u64,
+        ));
+        signals.push(Signal::new("vic", "x", 10, vic.x() as u64));
+        signals
+    }
+
+    fn beam_position(&self) -> BeamPosition {
+        let vic = self.cpu.memory().vic();
+        BeamPosition {
+            line: vic.raster_line(),
+            column: vic.x(),
+        }
+    }
 }
 
 impl MachineInspector for C64 {
@@ -103,6 +150,8 @@ impl MachineInspector for C64 {
             fn reg_sp(&self) -> u8;
             fn flags(&self) -> u8;
             fn inspect_memory(&self, address: u16) -> u8;
+            fn cycles(&self) -> u64;
+            fn instruction_set(&self) -> &'static InstructionSet;
         }
     }
 
@@ -111,30 +160,53 @@ impl MachineInspector for C64 {
     }
 }
 
+impl MachineEditor for C64 {
+    delegate! {
+        to self.cpu {
+            fn poke_memory(&mut self, address: u16, value: u8) -> WriteResult;
+            fn set_reg_pc(&mut self, value: u16);
+            fn set_reg_a(&mut self, value: u8);
+            fn set_reg_x(&mut self, value: u8);
+            fn set_reg_y(&mut self, value: u8);
+            fn set_reg_sp(&mut self, value: u8);
+            fn set_flags(&mut self, value: u8);
+        }
+    }
+}
+
 impl C64 {
-    pub fn new() -> Result<Self, Box<dyn Error>> {
+    pub fn new(power_on_state: &PowerOnState) -> Result<Self, Box<dyn Error>> {
         let basic_rom = fs::read(Path::new(env!("OUT_DIR")).join("roms").join("basic.bin"))?;
         let char_rom = fs::read(Path::new(env!("OUT_DIR")).join("roms").join("char.bin"))?;
         let kernal_rom = fs::read(Path::new(env!("OUT_DIR")).join("roms").join("kernal.bin"))?;
-        let ram = Rc::new(RefCell::new(Ram::new(16)));
-        let color_ram = Rc::new(RefCell::new(Ram::new(10)));
+        let ram = Rc::new(RefCell::new(Ram::with_power_on_state(
+            power_on_state,
+            "RAM",
+            16,
+        )));
+        let color_ram = Rc::new(RefCell::new(Ram::with_power_on_state(
+            power_on_state,
+            "color RAM",
+            10,
+        )));
+        let address_space = Box::new(C64AddressSpace::new(
+            ram.clone(),
+            Rom::new(&basic_rom)?,
+            Vic::new(
+                Box::new(VicAddressSpace::new(
+                    ram,
+                    Rc::new(RefCell::new(Rom::new(&char_rom)?)),
+                )),
+                color_ram.clone(),
+            ),
+            Sid::new(),
+            color_ram,
+            Cia::new(),
+            Cia::new(),
+            Rom::new(&kernal_rom)?,
+        ));
         Ok(C64 {
-            cpu: Cpu::new(Box::new(C64AddressSpace::new(
-                ram.clone(),
-                Rom::new(&basic_rom)?,
-                Vic::new(
-                    Box::new(VicAddressSpace::new(
-                        ram,
-                        Rc::new(RefCell::new(Rom::new(&char_rom)?)),
-                    )),
-                    color_ram.clone(),
-                ),
-                Sid::new(),
-                color_ram,
-                Cia::new(),
-                Cia::new(),
-                Rom::new(&kernal_rom)?,
-            ))),
+            cpu: Cpu::with_power_on_state(address_space, power_on_state),
             frame_renderer: FrameRenderer::default(),
 
             cpu_clock_divider: 0,
@@ -151,14 +223,14 @@ impl C64 {
     }
 
     pub fn set_cartridge(&mut self, cartridge: Option<Cartridge>) {
-        self.cpu.mut_memory().cartridge = cartridge;
+        self.cpu.mut_memory().set_cartridge(cartridge);
     }
 
     pub fn set_key_state(&mut self, key: Key, state: KeyState) {
         self.keyboard.set_key_state(key, state);
     }
 
-    pub fn cpu(&self) -> &Cpu<C64AddressSpace> {
+    pub fn cpu(&self) -> &Cpu<C64AddressSpace, Mos6510> {
         &self.cpu
     }
 
@@ -3,6 +3,7 @@ use crate::timer::Timer;
 use enum_map::{Enum, EnumMap};
 use ya6502::memory::Inspect;
 use ya6502::memory::Memory;
+use ya6502::memory::Poke;
 use ya6502::memory::Read;
 use ya6502::memory::ReadError;
 use ya6502::memory::Write;
@@ -156,6 +157,38 @@ impl Write for Cia {
     }
 }
 
+impl Poke for Cia {
+    /// Changes the register values directly: timer addresses change the
+    /// counters (not latches), the interrupt control register changes the
+    /// interrupt status, and writing control registers doesn't load the
+    /// timers.
+    fn poke(&mut self, address: u16, value: u8) -> Result<(), WriteError> {
+        match address & 0b1111 {
+            registers::PRA => self.ports[PortName::A].register = value,
+            registers::PRB => self.ports[PortName::B].register = value,
+            registers::DDRA => self.ports[PortName::A].direction = value,
+            registers::DDRB => self.ports[PortName::B].direction = value,
+            registers::TA_LO => poke_counter_lo(&mut self.timer_a, value),
+            registers::TA_HI => poke_counter_hi(&mut self.timer_a, value),
+            registers::TB_LO => poke_counter_lo(&mut self.timer_b, value),
+            registers::TB_HI => poke_counter_hi(&mut self.timer_b, value),
+            registers::ICR => self.reg_interrupt_status = value,
+            registers::CRA => self.timer_a.poke_control(value),
+            registers::CRB => self.timer_b.poke_control(value),
+            _ => return Err(WriteError { address, value }),
+        };
+        Ok(())
+    }
+}
+
+fn poke_counter_lo(timer: &mut Timer, value: u8) {
+    timer.set_counter(timer.counter() & 0xFF00 | value as u16);
+}
+
+fn poke_counter_hi(timer: &mut Timer, value: u8) {
+    timer.set_counter(timer.counter() & 0xFF | (value as u16) << 8);
+}
+
 impl Memory for Cia {}
 
 #[allow(dead_code)]
@@ -251,6 +284,34 @@ mod tests {
         assert_eq!(cia.read(registers::DDRA).unwrap(), 0x14);
     }
 
+    #[test]
+    fn poking() {
+        use crate::timer::flags::*;
+
+        let mut cia = Cia::new();
+        cia.write(registers::TA_HI, 0x23).unwrap();
+        cia.write(registers::TA_LO, 0x01).unwrap(); // Latch 0x2301
+
+        // Poking changes the counter, but not the latch.
+        cia.poke(registers::TA_HI, 0x01).unwrap();
+        cia.poke(registers::TA_LO, 0x02).unwrap();
+        cia.poke(registers::CRA, LOAD | START).unwrap();
+        assert_eq!(cia.inspect(registers::TA_HI).unwrap(), 0x01);
+        assert_eq!(cia.inspect(registers::TA_LO).unwrap(), 0x02);
+        assert_eq!(cia.inspect(registers::CRA).unwrap(), START);
+        cia.tick();
+        assert_eq!(cia.inspect(registers::TA_LO).unwrap(), 0x01);
+
+        cia.write(registers::CRA, LOAD | START).unwrap();
+        assert_eq!(cia.inspect(registers::TA_HI).unwrap(), 0x23);
+        assert_eq!(cia.inspect(registers::TA_LO).unwrap(), 0x01);
+
+        // Poking the interrupt control register changes the status.
+        cia.poke(registers::ICR, 0x81).unwrap();
+        assert_eq!(cia.read(registers::ICR).unwrap(), 0x81);
+        assert!(cia.poke(0x08, 0).is_err());
+    }
+
     macro_rules! test_timer {
         (
             $fn_name_basics:ident,
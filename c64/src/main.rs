@@ -22,6 +22,7 @@ use crate::c64::C64;
 use clap::Parser;
 use common::app::Application;
 use common::app::CommonCliArguments;
+use common::app::Machine;
 use common::debugger::adapter::TcpDebugAdapter;
 use std::fs::File;
 use std::io;
@@ -45,7 +46,7 @@ struct Args {
 fn main() {
     let args = Args::parse();
 
-    let mut c64 = C64::new().expect("Unable to initialize C64");
+    let mut c64 = C64::new(&args.common.power_on).expect("Unable to initialize C64");
 
     // Load the cartridge ROM image, if specified. So far, only Ultimax mode is
     // supported.
@@ -71,12 +72,28 @@ fn main() {
         None
     };
 
-    let mut app = Application::new(
-        C64Controller::new(&mut c64, debugger_adapter),
-        "Commodore 64",
-        2,
-        2,
-    );
+    let bus_recorder = args
+        .common
+        .bus_recorder(&c64.signals())
+        .expect("Unable to create the bus trace file");
+    let trace_writer = args
+        .common
+        .trace_writer()
+        .expect("Unable to create the trace log file");
+    let symbols = args
+        .common
+        .symbols()
+        .expect("Unable to load the symbol files");
+    let mut controller = C64Controller::new(&mut c64, debugger_adapter);
+    if let Some(bus_recorder) = bus_recorder {
+        controller.set_bus_recorder(bus_recorder);
+    }
+    if let Some(trace_writer) = trace_writer {
+        controller.set_trace_writer(trace_writer);
+    }
+    controller.set_symbols(symbols);
+
+    let mut app = Application::new(controller, "Commodore 64", 2, 2);
 
     let interrupted = app.interrupted();
     signal_hook::flag::register(signal_hook::consts::SIGINT, interrupted)
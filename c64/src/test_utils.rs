@@ -6,12 +6,13 @@ use crate::C64;
 use common::app::AppController;
 use common::app::FrameStatus;
 use common::app::Machine;
+use common::app::MachineError;
 use image::RgbaImage;
-use std::error::Error;
 use std::path::Path;
 use ya6502::memory::Rom;
+use ya6502::power_on::PowerOnState;
 
-pub fn next_frame(c64: &mut C64) -> Result<RgbaImage, Box<dyn Error>> {
+pub fn next_frame(c64: &mut C64) -> Result<RgbaImage, MachineError> {
     loop {
         match c64.tick() {
             Ok(FrameStatus::Pending) => {}
@@ -45,7 +46,7 @@ pub fn read_test_rom(name: &str) -> Vec<u8> {
 }
 
 pub fn c64_with_cartridge_uninitialized(file_name: &str) -> C64 {
-    let mut c64 = C64::new().unwrap();
+    let mut c64 = C64::new(&PowerOnState::Zero).unwrap();
     c64.set_cartridge(Some(Cartridge {
         mode: CartridgeMode::Ultimax,
         rom: Rom::new(&read_test_rom(file_name)).unwrap(),
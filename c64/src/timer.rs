@@ -26,6 +26,12 @@ impl Timer {
         Ok(())
     }
 
+    /// Changes the control register directly, without validating the mode or
+    /// loading the counter. Useful for debugging.
+    pub fn poke_control(&mut self, value: u8) {
+        self.control = value & !flags::LOAD;
+    }
+
     pub fn set_latch(&mut self, value: u16) {
         self.latch = value;
     }
@@ -38,6 +44,11 @@ impl Timer {
         self.counter
     }
 
+    /// Changes the counter value directly. Useful for debugging.
+    pub fn set_counter(&mut self, value: u16) {
+        self.counter = value;
+    }
+
     /// Performs a tick, returns `true` on underflow
     pub fn tick(&mut self) -> bool {
         if self.control & flags::START != 0 {
@@ -4,6 +4,7 @@ use std::cell::RefCell;
 use std::rc::Rc;
 use ya6502::memory::Inspect;
 use ya6502::memory::Memory;
+use ya6502::memory::Poke;
 use ya6502::memory::Read;
 use ya6502::memory::ReadError;
 use ya6502::memory::ReadResult;
@@ -85,6 +86,17 @@ where
         }
     }
 
+    /// Returns the raster line that is currently being drawn.
+    pub fn raster_line(&self) -> usize {
+        self.raster_counter
+    }
+
+    /// Returns the raw X coordinate of the pixel that will be drawn in the next
+    /// tick.
+    pub fn x(&self) -> usize {
+        self.x_counter
+    }
+
     /// Emulates a single tick of the pixel clock and returns a pixel color. For
     /// simplicity, we don't distinguish between blanking and visible pixels.
     /// This is different from TIA, since TIA is controlled to much higher
@@ -327,6 +339,35 @@ impl<GrMem: Read, ChrMem: Read> Write for Vic<GrMem, ChrMem> {
     }
 }
 
+impl<GrMem: Read, ChrMem: Read> Poke for Vic<GrMem, ChrMem> {
+    /// Changes the register values without validation or acknowledging
+    /// interrupts. Note that poking the raster register changes the raster
+    /// line that triggers an interrupt, not the current raster line.
+    fn poke(&mut self, address: u16, value: u8) -> WriteResult {
+        match address {
+            registers::CONTROL_1 => {
+                self.reg_control_1 = value & !flags::CONTROL_1_RASTER_8;
+                self.irq_raster_line = self.irq_raster_line & 0b1111_1111
+                    | ((value & flags::CONTROL_1_RASTER_8) as usize) << 1;
+            }
+            registers::RASTER => {
+                self.irq_raster_line = self.irq_raster_line & 0b1_0000_0000 | value as usize;
+            }
+            registers::CONTROL_2 => self.reg_control_2 = value | flags::CONTROL_2_UNUSED,
+            registers::INTERRUPT => self.reg_interrupt = value | flags::INTERRUPT_UNUSED,
+            registers::INTERRUPT_MASK => {
+                self.reg_interrupt_mask = value | flags::INTERRUPT_MASK_UNUSED
+            }
+            registers::BORDER_COLOR => self.reg_border_color = value | flags::COLOR_UNUSED,
+            registers::BACKGROUND_COLOR_0 => {
+                self.reg_background_color = value | flags::COLOR_UNUSED
+            }
+            _ => return Err(WriteError { address, value }),
+        }
+        Ok(())
+    }
+}
+
 impl<GrMem: Read, ChrMem: Read> Memory for Vic<GrMem, ChrMem> {}
 
 /// Converts raster line number to Y position on the rendered screen.
@@ -503,6 +503,41 @@ fn raster_irq_bit_8() {
     assert_eq!(vic_output.video_output.raster_line, 1);
 }
 
+#[test]
+fn poking_registers() {
+    let mut vic = initialized_vic_for_testing();
+    vic.poke(registers::INTERRUPT, flags::INTERRUPT_RASTER)
+        .unwrap();
+    vic.poke(registers::INTERRUPT_MASK, flags::INTERRUPT_RASTER)
+        .unwrap();
+    vic.poke(registers::BORDER_COLOR, 0x05).unwrap();
+    assert_eq!(
+        vic.inspect(registers::INTERRUPT).unwrap(),
+        flags::INTERRUPT_UNUSED | flags::INTERRUPT_RASTER,
+    );
+    assert_eq!(
+        vic.inspect(registers::BORDER_COLOR).unwrap(),
+        flags::COLOR_UNUSED | 0x05,
+    );
+
+    // Poking the raster register sets up the raster IRQ.
+    vic.poke(registers::INTERRUPT, 0).unwrap();
+    vic.poke(registers::RASTER, 60).unwrap();
+    let vic_output = tick_until_irq(&mut vic);
+    assert_eq!(vic_output.video_output.raster_line, 60);
+
+    // Unlike writing, poking doesn't validate the value.
+    assert!(vic
+        .write(registers::CONTROL_2, flags::CONTROL_2_MCM)
+        .is_err());
+    vic.poke(registers::CONTROL_2, flags::CONTROL_2_MCM)
+        .unwrap();
+    assert_eq!(
+        vic.inspect(registers::CONTROL_2).unwrap(),
+        flags::CONTROL_2_UNUSED | flags::CONTROL_2_MCM,
+    );
+}
+
 #[test]
 fn screen_on_off() {
     let mut vic = initialized_vic_for_testing();
@@ -1,16 +1,33 @@
 use crate::debugger::adapter::DebugAdapter;
+use crate::debugger::symbols::Symbols;
 use crate::debugger::Debugger;
+use crate::trace::{AddressRange, BeamPosition, CycleWindow, TraceWriter};
+use crate::vcd;
+use crate::vcd::{BusRecorder, Signal, Trigger};
 use clap::Parser;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use image::RgbaImage;
 use piston::{Event, EventLoop, WindowSettings};
 use piston_window::{
     Filter, G2d, G2dTexture, G2dTextureContext, GfxDevice, PistonWindow, Texture, TextureSettings,
 };
 use sdl2_window::Sdl2Window;
+use std::cell::RefCell;
 use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use ya6502::cpu::bus::BusAccess;
+use ya6502::cpu::bus::BusObserver;
+use ya6502::cpu::CpuError;
+use ya6502::cpu::MachineEditor;
 use ya6502::cpu::MachineInspector;
+use ya6502::power_on::PowerOnState;
 
 #[derive(Parser)]
 pub struct CommonCliArguments {
@@ -18,18 +35,143 @@ pub struct CommonCliArguments {
     pub debugger: bool,
     #[clap(long, default_value = "1234")]
     pub debugger_port: u16,
+    /// Initial state of registers and memory: "random", "zero", "pattern", or
+    /// a numeric random seed.
+    #[clap(long, default_value = "zero")]
+    pub power_on: PowerOnState,
+    /// Records a cycle-exact bus trace to a given Value Change Dump file.
+    #[clap(long)]
+    pub vcd: Option<String>,
+    /// Starts recording the bus trace at a given CPU cycle, or once the CPU
+    /// executes an instruction at a given address ("pc:ADDRESS").
+    #[clap(long)]
+    pub vcd_start: Option<Trigger>,
+    /// Stops recording the bus trace at a given CPU cycle, or once the CPU
+    /// executes an instruction at a given address ("pc:ADDRESS").
+    #[clap(long)]
+    pub vcd_stop: Option<Trigger>,
+    /// Writes an instruction trace log to a given file, or to the standard
+    /// output if "-" is given.
+    #[clap(long)]
+    pub trace: Option<String>,
+    /// Compresses the instruction trace log with gzip.
+    #[clap(long)]
+    pub trace_gzip: bool,
+    /// Only logs instructions located in a given address range
+    /// ("START-END", inclusive). Can be used multiple times.
+    #[clap(long)]
+    pub trace_pc: Vec<AddressRange>,
+    /// Only logs instructions executed in a given CPU cycle window
+    /// ("START-END", exclusive; either end can be omitted).
+    #[clap(long)]
+    pub trace_cycles: Option<CycleWindow>,
+    /// Loads debugger symbols from an ld65 debug info file (--dbgfile) or a
+    /// VICE label file. Can be used multiple times.
+    #[clap(long)]
+    pub symbols: Vec<String>,
+}
+
+impl CommonCliArguments {
+    /// Creates a bus recorder, if requested by the `--vcd` argument. The
+    /// `signals` declare the machine-specific signals.
+    pub fn bus_recorder(&self, signals: &[Signal]) -> io::Result<Option<BusRecorder>> {
+        self.vcd
+            .as_ref()
+            .map(|path| {
+                BusRecorder::new(
+                    Box::new(BufWriter::new(File::create(path)?)),
+                    self.vcd_start,
+                    self.vcd_stop,
+                    signals,
+                )
+            })
+            .transpose()
+    }
+
+    /// Creates an instruction trace writer, if requested by the `--trace`
+    /// argument.
+    pub fn trace_writer(&self) -> io::Result<Option<TraceWriter>> {
+        let path = match &self.trace {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let out: Box<dyn Write> = if path == "-" {
+            Box::new(BufWriter::new(io::stdout()))
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+        let out: Box<dyn Write> = if self.trace_gzip {
+            Box::new(GzEncoder::new(out, Compression::default()))
+        } else {
+            out
+        };
+        Ok(Some(TraceWriter::new(
+            out,
+            self.trace_pc.clone(),
+            self.trace_cycles.unwrap_or_default(),
+        )))
+    }
+
+    /// Loads the symbol files given by the `--symbols` arguments.
+    pub fn symbols(&self) -> Result<Symbols, String> {
+        let mut symbols = Symbols::new();
+        for path in &self.symbols {
+            symbols.load(path)?;
+        }
+        Ok(symbols)
+    }
+}
+
+/// Parses a number, either decimal or hexadecimal, prefixed with `$` or `0x`.
+pub fn parse_number(s: &str) -> Result<u64, String> {
+    let result = if let Some(hex) = s.strip_prefix('$').or_else(|| s.strip_prefix("0x")) {
+        u64::from_str_radix(hex, 16)
+    } else {
+        s.parse()
+    };
+    result.map_err(|_| format!("Invalid number: {:?}", s))
+}
+
+/// Parses an address using the same syntax as [`parse_number`].
+pub fn parse_address(s: &str) -> Result<u16, String> {
+    let address = parse_number(s)?;
+    u16::try_from(address).map_err(|_| format!("Address out of range: {:?}", s))
 }
 
 /// A generic interface that provides basic operations common to all emulated
 /// machines.
-pub trait Machine: MachineInspector {
+pub trait Machine: MachineInspector + MachineEditor {
     fn reset(&mut self);
     fn tick(&mut self) -> MachineTickResult;
     fn frame_image(&self) -> &RgbaImage;
     fn display_state(&self) -> String;
+    /// Installs an observer of the CPU bus, or removes it if `None` is given.
+    /// Returns the previously installed observer.
+    fn set_bus_observer(
+        &mut self,
+        observer: Option<Box<dyn BusObserver>>,
+    ) -> Option<Box<dyn BusObserver>>;
+    /// Returns the current state of the CPU pins and chip-specific signals,
+    /// such as the beam position, for the bus recorder. The list of signals
+    /// needs to be the same every time.
+    fn signals(&self) -> Vec<Signal>;
+    /// Returns the current position of the video beam for the instruction
+    /// trace log.
+    fn beam_position(&self) -> BeamPosition;
 }
 
-pub type MachineTickResult = Result<FrameStatus, Box<dyn Error>>;
+pub type MachineTickResult = Result<FrameStatus, MachineError>;
+
+/// An error that stops the machine.
+#[derive(thiserror::Error, Debug)]
+pub enum MachineError {
+    #[error(transparent)]
+    Cpu(#[from] CpuError),
+
+    /// An error reported by any other chip.
+    #[error("{0}")]
+    Other(Box<dyn Error>),
+}
 
 pub enum FrameStatus {
     Pending,
@@ -42,16 +184,62 @@ pub struct MachineController<'a, M: Machine, A: DebugAdapter> {
     running: bool,
     interrupted: Arc<AtomicBool>,
     debugger: Option<Debugger<A>>,
+    bus_recorder: Option<Rc<RefCell<BusRecorder>>>,
+    trace_writer: Option<TraceWriter>,
 }
 
 impl<'a, M: Machine, A: DebugAdapter> MachineController<'a, M, A> {
     pub fn new(machine: &'a mut M, debugger: Option<Debugger<A>>) -> Self {
-        return Self {
+        let mut controller = Self {
             machine,
             running: false,
             interrupted: Arc::new(AtomicBool::new(false)),
             debugger,
+            bus_recorder: None,
+            trace_writer: None,
+        };
+        controller.install_bus_observer();
+        return controller;
+    }
+
+    /// Starts recording the machine's bus activity.
+    pub fn set_bus_recorder(&mut self, bus_recorder: BusRecorder) {
+        self.bus_recorder = Some(Rc::new(RefCell::new(bus_recorder)));
+        self.install_bus_observer();
+    }
+
+    /// Installs a bus observer that passes the bus accesses to the debugger
+    /// (for data breakpoints) and to the bus recorder, if they are present.
+    fn install_bus_observer(&mut self) {
+        let mut observers: Vec<Box<dyn BusObserver>> = vec![];
+        if let Some(debugger) = &self.debugger {
+            observers.push(debugger.bus_observer());
+        }
+        if let Some(bus_recorder) = &self.bus_recorder {
+            observers.push(vcd::bus_observer(bus_recorder));
+        }
+        let observer: Box<dyn BusObserver> = match observers.len() {
+            0 => return,
+            1 => observers.pop().unwrap(),
+            _ => Box::new(move |access: &BusAccess| {
+                for observer in &mut observers {
+                    observer.observe(access);
+                }
+            }),
         };
+        self.machine.set_bus_observer(Some(observer));
+    }
+
+    /// Starts writing the instruction trace log.
+    pub fn set_trace_writer(&mut self, trace_writer: TraceWriter) {
+        self.trace_writer = Some(trace_writer);
+    }
+
+    /// Passes the symbols to the debugger, if there is one.
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        if let Some(debugger) = &mut self.debugger {
+            debugger.set_symbols(symbols);
+        }
     }
 
     pub fn machine(&self) -> &M {
@@ -80,12 +268,26 @@ impl<'a, M: Machine, A: DebugAdapter> MachineController<'a, M, A> {
             match self.tick() {
                 Ok(FrameStatus::Pending) => {}
                 Ok(FrameStatus::Complete) => return,
-                Err(e) => {
-                    self.running = false;
-                    eprintln!("ERROR: {}. Machine halted.", e);
-                    eprintln!("{}", self.display_state());
+                Err(e) => self.handle_error(e),
+            }
+        }
+    }
+
+    /// Stops the machine after an error. If there's a debugger attached, it
+    /// gets notified, and the machine can be resumed from there; otherwise,
+    /// the machine stays halted until reset.
+    fn handle_error(&mut self, error: MachineError) {
+        match &mut self.debugger {
+            Some(debugger) => {
+                if let Err(e) = debugger.stop_on_exception(error.to_string()) {
+                    eprintln!("Debugger error: {}", e);
                 }
             }
+            None => {
+                self.running = false;
+                eprintln!("ERROR: {}. Machine halted.", error);
+                eprintln!("{}", self.display_state());
+            }
         }
     }
 
@@ -105,6 +307,25 @@ impl<'a, M: Machine, A: DebugAdapter> MachineController<'a, M, A> {
                 eprintln!("Debugger error: {}", e);
             }
         }
+        if let Some(bus_recorder) = &self.bus_recorder {
+            // Signals are recorded once per CPU cycle, after the cycle is
+            // complete.
+            let mut bus_recorder = bus_recorder.borrow_mut();
+            let cycles = self.machine.cycles();
+            if cycles > 0 && bus_recorder.needs_signals(cycles - 1) {
+                if let Err(e) = bus_recorder.record_signals(cycles - 1, &self.machine.signals()) {
+                    eprintln!("Bus recorder error: {}", e);
+                }
+            }
+        }
+        if let Some(trace_writer) = &mut self.trace_writer {
+            if self.machine.at_instruction_start() {
+                let beam_position = self.machine.beam_position();
+                if let Err(e) = trace_writer.trace(self.machine, Some(beam_position)) {
+                    eprintln!("Trace writer error: {}", e);
+                }
+            }
+        }
         tick_result
     }
 
@@ -229,10 +450,14 @@ impl View {
 mod tests {
     use super::*;
     use crate::debugger::adapter::FakeDebugAdapter;
-    use crate::debugger::dap_types::Request;
+    use crate::debugger::core::StopReason;
+    use crate::debugger::dap_types::{Event, Message, MessageEnvelope, Request, StoppedEvent};
     use image::Pixel;
     use image::Rgba;
+    use std::assert_matches::assert_matches;
     use std::fmt;
+    use ya6502::cpu::instructions::{InstructionSet, NMOS_INSTRUCTIONS};
+    use ya6502::memory::WriteResult;
 
     /// A very simple machine. All it does is producing three gray pixels with
     /// increasing luminosity.
@@ -271,7 +496,7 @@ mod tests {
         }
         fn tick(&mut self) -> MachineTickResult {
             if self.broken {
-                return Err(Box::new(SomeError {}));
+                return Err(MachineError::Other(Box::new(SomeError {})));
             }
             self.image.put_pixel(self.x, 0, self.color);
             if self.x >= 2 {
@@ -289,6 +514,18 @@ mod tests {
         fn display_state(&self) -> String {
             format!("x={}", self.x)
         }
+        fn set_bus_observer(
+            &mut self,
+            _: Option<Box<dyn BusObserver>>,
+        ) -> Option<Box<dyn BusObserver>> {
+            None
+        }
+        fn signals(&self) -> Vec<Signal> {
+            Vec::new()
+        }
+        fn beam_position(&self) -> BeamPosition {
+            BeamPosition::default()
+        }
     }
 
     impl MachineInspector for TestMachine {
@@ -316,6 +553,33 @@ mod tests {
         fn inspect_memory(&self, _: u16) -> u8 {
             0
         }
+        fn cycles(&self) -> u64 {
+            0
+        }
+        fn instruction_set(&self) -> &'static InstructionSet {
+            &NMOS_INSTRUCTIONS
+        }
+    }
+
+    impl MachineEditor for TestMachine {
+        fn poke_memory(&mut self, _: u16, _: u8) -> WriteResult {
+            Ok(())
+        }
+        fn set_reg_pc(&mut self, _: u16) {}
+        fn set_reg_a(&mut self, _: u8) {}
+        fn set_reg_x(&mut self, _: u8) {}
+        fn set_reg_y(&mut self, _: u8) {}
+        fn set_reg_sp(&mut self, _: u8) {}
+        fn set_flags(&mut self, _: u8) {}
+    }
+
+    #[test]
+    fn parses_addresses() {
+        assert_eq!(parse_address("1024"), Ok(0x0400));
+        assert_eq!(parse_address("$F000"), Ok(0xF000));
+        assert_eq!(parse_address("0xbffc"), Ok(0xBFFC));
+        assert!(parse_address("$10000").is_err());
+        assert!(parse_address("F000").is_err());
     }
 
     #[test]
@@ -423,6 +687,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn machine_controller_reports_errors_to_debugger() {
+        let debug_adapter = FakeDebugAdapter::default();
+        let mut machine = TestMachine::new();
+        let mut controller =
+            MachineController::new(&mut machine, Some(Debugger::new(debug_adapter.clone())));
+        controller.reset();
+
+        debug_adapter.push_request(Request::Continue {});
+        controller.run_until_end_of_frame();
+        while debug_adapter.pop_outgoing().is_some() {}
+
+        controller.machine.broken = true;
+        controller.run_until_end_of_frame();
+        assert_matches!(
+            debug_adapter.pop_outgoing(),
+            Some(MessageEnvelope {
+                message: Message::Event(event),
+                ..
+            }) if event == Event::Stopped(StoppedEvent {
+                thread_id: 1,
+                reason: StopReason::Exception,
+                description: Some("SomeError".to_string()),
+                all_threads_stopped: true,
+            })
+        );
+        assert_matches!(debug_adapter.pop_outgoing(), None);
+
+        // The machine can be resumed from the debugger without a reset.
+        controller.machine.broken = false;
+        debug_adapter.push_request(Request::Continue {});
+        controller.run_until_end_of_frame();
+        assert_eq!(
+            controller.frame_image().clone().into_raw(),
+            RgbaImage::from_pixel(3, 1, Rgba::from_channels(2, 2, 2, 255)).into_raw(),
+        );
+    }
+
     #[test]
     fn debugger_stepping() {
         let debug_adapter = FakeDebugAdapter::default();
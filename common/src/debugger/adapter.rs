@@ -289,6 +289,7 @@ mod tests {
             message: Message::Response(ResponseEnvelope {
                 request_seq: 1,
                 success: true,
+                message: None,
                 response: Response::Attach,
             }),
         }
@@ -1,7 +1,13 @@
+use crate::debugger::expression::Expression;
+use crate::debugger::expression::HitCondition;
+use crate::trace::AddressRange;
 use bounded_vec_deque::BoundedVecDeque;
 use serde::Deserialize;
 use serde::Serialize;
 use std::mem::replace;
+use ya6502::cpu::bus::BusAccess;
+use ya6502::cpu::bus::BusAccessKind;
+use ya6502::cpu::bus::BusOperation;
 use ya6502::cpu::opcodes;
 use ya6502::cpu::MachineInspector;
 
@@ -17,7 +23,17 @@ enum RunMode {
 pub struct DebuggerCore {
     run_mode: RunMode,
     last_stop_reason: Option<StopReason>,
-    instruction_breakpoints: Vec<u16>,
+    instruction_breakpoints: Vec<InstructionBreakpoint>,
+    /// Breakpoints set by function names. They work just like the instruction
+    /// breakpoints, but they are managed separately by the client.
+    function_breakpoints: Vec<InstructionBreakpoint>,
+    data_breakpoints: Vec<DataBreakpoint>,
+    /// The first access that triggered a data breakpoint since the execution
+    /// has been resumed. We don't stop immediately, but at the beginning of the
+    /// next instruction, so that the CPU state is consistent.
+    data_breakpoint_hit: Option<DataBreakpointHit>,
+    /// Address of the instruction that is currently being executed.
+    instruction_pc: u16,
     /// Stack frames, captured by recognizing JSR/RTS instructions. Note that
     /// this is not a simple vector, but a bounded deque, since we can't
     /// guarantee that the underlying program is sane and won't overflow the
@@ -37,16 +53,54 @@ impl DebuggerCore {
             run_mode: RunMode::Stopped,
             last_stop_reason: None,
             instruction_breakpoints: vec![],
+            function_breakpoints: vec![],
+            data_breakpoints: vec![],
+            data_breakpoint_hit: None,
+            instruction_pc: 0,
             stack_frames: BoundedVecDeque::new(256),
             will_enter_subroutine: true,
             will_return_from_subroutine: false,
         }
     }
 
-    pub fn set_instruction_breakpoints(&mut self, breakpoints: Vec<u16>) {
+    /// Replaces all instruction breakpoints. Note that this also resets the
+    /// hit counts.
+    pub fn set_instruction_breakpoints(&mut self, breakpoints: Vec<InstructionBreakpoint>) {
         self.instruction_breakpoints = breakpoints;
     }
 
+    /// Replaces all function breakpoints. Note that this also resets the hit
+    /// counts.
+    pub fn set_function_breakpoints(&mut self, breakpoints: Vec<InstructionBreakpoint>) {
+        self.function_breakpoints = breakpoints;
+    }
+
+    pub fn set_data_breakpoints(&mut self, breakpoints: Vec<DataBreakpoint>) {
+        self.data_breakpoints = breakpoints;
+    }
+
+    /// Checks a memory access performed by the CPU against the data
+    /// breakpoints. Expected to be called for every bus access, before calling
+    /// [`update`](Self::update) for the cycle in which it happened. Instruction
+    /// fetches are ignored, since that's what instruction breakpoints are for.
+    pub fn observe_bus_access(&mut self, access: &BusAccess) {
+        if self.run_mode != RunMode::Running || self.data_breakpoint_hit.is_some() {
+            return;
+        }
+        if matches!(
+            access.kind,
+            BusAccessKind::OpcodeFetch | BusAccessKind::OperandFetch
+        ) {
+            return;
+        }
+        if self.data_breakpoints.iter().any(|b| b.matches(access)) {
+            self.data_breakpoint_hit = Some(DataBreakpointHit {
+                access: *access,
+                pc: self.instruction_pc,
+            });
+        }
+    }
+
     /// Reads the machine state. Expected to be called after the CPU is
     /// initialized, and then after every single cycle.
     pub fn update(&mut self, inspector: &impl MachineInspector) {
@@ -62,6 +116,7 @@ impl DebuggerCore {
                 self.stack_frames.pop_back();
                 self.will_return_from_subroutine = false;
             }
+            self.instruction_pc = inspector.reg_pc();
             let opcode = inspector.inspect_memory(inspector.reg_pc());
             match opcode {
                 opcodes::JSR => {
@@ -77,8 +132,19 @@ impl DebuggerCore {
             }
             match self.run_mode {
                 RunMode::Running => {
-                    if self.instruction_breakpoints.contains(&inspector.reg_pc()) {
+                    let pc = inspector.reg_pc();
+                    // Note that we need to check all breakpoints, even if we
+                    // already know that we're going to stop, to count their
+                    // hits correctly.
+                    let hit = check_breakpoints(&mut self.instruction_breakpoints, pc, inspector);
+                    let function_hit =
+                        check_breakpoints(&mut self.function_breakpoints, pc, inspector);
+                    if self.data_breakpoint_hit.is_some() {
+                        self.stop(StopReason::DataBreakpoint);
+                    } else if hit {
                         self.stop(StopReason::Breakpoint);
+                    } else if function_hit {
+                        self.stop(StopReason::FunctionBreakpoint);
                     }
                 }
                 RunMode::SteppingIn => self.stop(StopReason::Step),
@@ -105,6 +171,12 @@ impl DebuggerCore {
         replace(&mut self.last_stop_reason, None)
     }
 
+    /// Returns the access that caused the last stop, if it was caused by a
+    /// data breakpoint.
+    pub fn data_breakpoint_hit(&self) -> Option<DataBreakpointHit> {
+        self.data_breakpoint_hit
+    }
+
     pub fn stack_trace(&self, inspector: &impl MachineInspector) -> Vec<StackFrame> {
         let mut frames: Vec<StackFrame> = self.stack_frames.clone().into_unbounded().into();
         frames.reverse();
@@ -125,12 +197,20 @@ impl DebuggerCore {
     fn run(&mut self, mode: RunMode) {
         self.run_mode = mode;
         self.last_stop_reason = None;
+        self.data_breakpoint_hit = None;
     }
 
     pub fn pause(&mut self) {
         self.stop(StopReason::Pause);
     }
 
+    /// Stops the execution after the machine reported an error. Unlike other
+    /// stop reasons, this one is not reported by `last_stop_reason`, since the
+    /// caller is responsible for describing the error.
+    pub fn stop_on_exception(&mut self) {
+        self.run_mode = RunMode::Stopped;
+    }
+
     fn stop(&mut self, reason: StopReason) {
         self.run_mode = RunMode::Stopped;
         self.last_stop_reason = Some(reason);
@@ -161,6 +241,114 @@ impl DebuggerCore {
     }
 }
 
+/// Registers reaching a given address by all of the breakpoints located there.
+/// Returns `true` if any of them should stop the execution.
+fn check_breakpoints(
+    breakpoints: &mut [InstructionBreakpoint],
+    pc: u16,
+    inspector: &impl MachineInspector,
+) -> bool {
+    let mut hit = false;
+    for breakpoint in breakpoints {
+        if breakpoint.address == pc {
+            hit |= breakpoint.hit(inspector);
+        }
+    }
+    hit
+}
+
+/// An instruction breakpoint, optionally with a condition that needs to be met
+/// and a hit condition that decides which of the hits actually stop the
+/// execution.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstructionBreakpoint {
+    address: u16,
+    condition: Option<Expression>,
+    hit_condition: Option<HitCondition>,
+    /// Number of times the breakpoint has been reached with its condition
+    /// met.
+    hit_count: u64,
+}
+
+impl InstructionBreakpoint {
+    pub fn new(address: u16) -> Self {
+        Self {
+            address,
+            condition: None,
+            hit_condition: None,
+            hit_count: 0,
+        }
+    }
+
+    pub fn with_condition(mut self, condition: Expression) -> Self {
+        self.condition = Some(condition);
+        self
+    }
+
+    pub fn with_hit_condition(mut self, hit_condition: HitCondition) -> Self {
+        self.hit_condition = Some(hit_condition);
+        self
+    }
+
+    /// Registers reaching the breakpoint's address and returns `true` if it
+    /// should stop the execution. If the condition can't be evaluated (for
+    /// example, because of division by zero), we stop anyway, so that the
+    /// user can see what's going on.
+    fn hit(&mut self, inspector: &impl MachineInspector) -> bool {
+        let condition_met = match &self.condition {
+            Some(condition) => !matches!(condition.evaluate(inspector), Ok(0)),
+            None => true,
+        };
+        if !condition_met {
+            return false;
+        }
+        self.hit_count += 1;
+        match self.hit_condition {
+            Some(hit_condition) => hit_condition.matches(self.hit_count),
+            None => true,
+        }
+    }
+}
+
+/// A breakpoint that stops the execution when the CPU accesses any address in
+/// a given range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DataBreakpoint {
+    range: AddressRange,
+    access_type: DataBreakpointAccessType,
+}
+
+impl DataBreakpoint {
+    pub fn new(range: AddressRange, access_type: DataBreakpointAccessType) -> Self {
+        Self { range, access_type }
+    }
+
+    fn matches(&self, access: &BusAccess) -> bool {
+        let operation_matches = match self.access_type {
+            DataBreakpointAccessType::Read => access.operation == BusOperation::Read,
+            DataBreakpointAccessType::Write => access.operation == BusOperation::Write,
+            DataBreakpointAccessType::ReadWrite => true,
+        };
+        operation_matches && self.range.contains(access.address)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum DataBreakpointAccessType {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// A memory access that triggered a data breakpoint, along with the address of
+/// the instruction that performed it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DataBreakpointHit {
+    pub access: BusAccess,
+    pub pc: u16,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct StackFrame {
     pub entry: u16,
@@ -174,11 +362,18 @@ pub enum StopReason {
     Pause,
     Step,
     Breakpoint,
+    #[serde(rename = "function breakpoint")]
+    FunctionBreakpoint,
+    #[serde(rename = "data breakpoint")]
+    DataBreakpoint,
+    Exception,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
     use ya6502::cpu::Cpu;
     use ya6502::cpu_with_code;
     use ya6502::memory::Ram;
@@ -196,6 +391,27 @@ mod tests {
         panic!("CPU still running at PC={:04X}", cpu.reg_pc());
     }
 
+    /// Works like `tick_while_running`, but also reports the bus accesses to
+    /// the debugger core.
+    fn tick_while_running_with_bus(dc: &mut DebuggerCore, cpu: &mut Cpu<Ram>) {
+        let accesses = Rc::new(RefCell::new(vec![]));
+        let observed_accesses = accesses.clone();
+        cpu.set_bus_observer(Some(Box::new(move |access: &BusAccess| {
+            observed_accesses.borrow_mut().push(*access)
+        })));
+        for _ in 0..1000 {
+            if dc.stopped() {
+                return;
+            }
+            cpu.tick().unwrap();
+            for access in accesses.borrow_mut().drain(..) {
+                dc.observe_bus_access(&access);
+            }
+            dc.update(cpu);
+        }
+        panic!("CPU still running at PC={:04X}", cpu.reg_pc());
+    }
+
     #[test]
     fn runs_and_pauses() {
         let mut cpu = cpu_with_code! {
@@ -250,6 +466,10 @@ mod tests {
         dc.pause();
         dc.step_into();
         assert_eq!(dc.last_stop_reason(), None);
+
+        dc.stop_on_exception();
+        assert!(dc.stopped());
+        assert_eq!(dc.last_stop_reason(), None);
     }
 
     #[test]
@@ -482,7 +702,7 @@ mod tests {
         };
         let mut dc = DebuggerCore::new();
         dc.update(&cpu);
-        dc.set_instruction_breakpoints(vec![0xF002]);
+        dc.set_instruction_breakpoints(vec![InstructionBreakpoint::new(0xF002)]);
         dc.resume();
 
         tick_while_running(&mut dc, &mut cpu);
@@ -490,7 +710,10 @@ mod tests {
         assert_eq!(dc.last_stop_reason(), Some(StopReason::Breakpoint));
 
         cpu.reset();
-        dc.set_instruction_breakpoints(vec![0xF001, 0xF003]);
+        dc.set_instruction_breakpoints(vec![
+            InstructionBreakpoint::new(0xF001),
+            InstructionBreakpoint::new(0xF003),
+        ]);
 
         dc.resume();
         tick_while_running(&mut dc, &mut cpu);
@@ -503,6 +726,201 @@ mod tests {
         assert_eq!(dc.last_stop_reason(), Some(StopReason::Breakpoint));
     }
 
+    #[test]
+    fn function_breakpoints() {
+        let mut cpu = cpu_with_code! {
+                nop
+                nop
+                nop
+                nop
+            loop:
+                jmp loop
+        };
+        let mut dc = DebuggerCore::new();
+        dc.update(&cpu);
+        dc.set_function_breakpoints(vec![
+            InstructionBreakpoint::new(0xF001),
+            InstructionBreakpoint::new(0xF002),
+        ]);
+        dc.set_instruction_breakpoints(vec![InstructionBreakpoint::new(0xF002)]);
+
+        dc.resume();
+        tick_while_running(&mut dc, &mut cpu);
+        assert_eq!(cpu.reg_pc(), 0xF001);
+        assert_eq!(dc.last_stop_reason(), Some(StopReason::FunctionBreakpoint));
+
+        // Instruction breakpoints take precedence.
+        dc.resume();
+        tick_while_running(&mut dc, &mut cpu);
+        assert_eq!(cpu.reg_pc(), 0xF002);
+        assert_eq!(dc.last_stop_reason(), Some(StopReason::Breakpoint));
+
+        cpu.reset();
+        dc.set_function_breakpoints(vec![]);
+        dc.resume();
+        tick_while_running(&mut dc, &mut cpu);
+        assert_eq!(cpu.reg_pc(), 0xF002);
+        assert_eq!(dc.last_stop_reason(), Some(StopReason::Breakpoint));
+    }
+
+    #[test]
+    fn conditional_breakpoints() {
+        let mut cpu = cpu_with_code! {
+                ldx #0     // 0xF000
+            loop:
+                inx        // 0xF002
+                stx 0x80   // 0xF003
+                jmp loop   // 0xF005
+        };
+        let mut dc = DebuggerCore::new();
+        dc.update(&cpu);
+        dc.set_instruction_breakpoints(vec![InstructionBreakpoint::new(0xF005)
+            .with_condition("X == 3 && [$80] == 3".parse().unwrap())]);
+
+        dc.resume();
+        tick_while_running(&mut dc, &mut cpu);
+        assert_eq!(cpu.reg_pc(), 0xF005);
+        assert_eq!(cpu.reg_x(), 3);
+        assert_eq!(dc.last_stop_reason(), Some(StopReason::Breakpoint));
+
+        dc.set_instruction_breakpoints(vec![
+            InstructionBreakpoint::new(0xF005).with_condition("X / (X - 5)".parse().unwrap())
+        ]);
+        dc.resume();
+        tick_while_running(&mut dc, &mut cpu);
+        assert_eq!(cpu.reg_x(), 4);
+        dc.resume();
+        // A condition that fails to evaluate stops the execution.
+        tick_while_running(&mut dc, &mut cpu);
+        assert_eq!(cpu.reg_x(), 5);
+    }
+
+    #[test]
+    fn hit_count_breakpoints() {
+        let mut cpu = cpu_with_code! {
+                ldx #0     // 0xF000
+            loop:
+                inx        // 0xF002
+                jmp loop   // 0xF003
+        };
+        let mut dc = DebuggerCore::new();
+        dc.update(&cpu);
+        dc.set_instruction_breakpoints(vec![
+            InstructionBreakpoint::new(0xF002).with_hit_condition(HitCondition::Multiple(3)),
+            InstructionBreakpoint::new(0xF002)
+                .with_condition("X >= 4".parse().unwrap())
+                .with_hit_condition(HitCondition::Equal(2)),
+        ]);
+
+        dc.resume();
+        tick_while_running(&mut dc, &mut cpu);
+        assert_eq!(cpu.reg_x(), 2);
+        dc.resume();
+        tick_while_running(&mut dc, &mut cpu);
+        assert_eq!(cpu.reg_x(), 5);
+        dc.resume();
+        tick_while_running(&mut dc, &mut cpu);
+        assert_eq!(cpu.reg_x(), 8);
+    }
+
+    #[test]
+    fn data_breakpoints() {
+        let mut cpu = cpu_with_code! {
+                lda #1     // 0xF000
+                sta 0x80   // 0xF002
+                lda 0x81   // 0xF004
+                inc 0x82   // 0xF006
+                pha        // 0xF008
+            loop:
+                jmp loop   // 0xF009
+        };
+        let mut dc = DebuggerCore::new();
+        dc.update(&cpu);
+        dc.set_data_breakpoints(vec![
+            DataBreakpoint::new(
+                AddressRange {
+                    start: 0x80,
+                    end: 0x82,
+                },
+                DataBreakpointAccessType::Write,
+            ),
+            DataBreakpoint::new(
+                AddressRange {
+                    start: 0x81,
+                    end: 0x81,
+                },
+                DataBreakpointAccessType::Read,
+            ),
+            DataBreakpoint::new(
+                AddressRange {
+                    start: 0x0100,
+                    end: 0x01FF,
+                },
+                DataBreakpointAccessType::ReadWrite,
+            ),
+        ]);
+
+        dc.resume();
+        tick_while_running_with_bus(&mut dc, &mut cpu);
+        assert_eq!(cpu.reg_pc(), 0xF004);
+        assert_eq!(dc.last_stop_reason(), Some(StopReason::DataBreakpoint));
+        let hit = dc.data_breakpoint_hit().unwrap();
+        assert_eq!(hit.pc, 0xF002);
+        assert_eq!(hit.access.operation, BusOperation::Write);
+        assert_eq!(hit.access.address, 0x80);
+        assert_eq!(hit.access.value, 1);
+
+        dc.resume();
+        assert_eq!(dc.data_breakpoint_hit(), None);
+        tick_while_running_with_bus(&mut dc, &mut cpu);
+        assert_eq!(cpu.reg_pc(), 0xF006);
+        let hit = dc.data_breakpoint_hit().unwrap();
+        assert_eq!(hit.pc, 0xF004);
+        assert_eq!(hit.access.operation, BusOperation::Read);
+        assert_eq!(hit.access.address, 0x81);
+
+        // A read-modify-write instruction is caught by its first (dummy) write.
+        dc.resume();
+        tick_while_running_with_bus(&mut dc, &mut cpu);
+        assert_eq!(cpu.reg_pc(), 0xF008);
+        let hit = dc.data_breakpoint_hit().unwrap();
+        assert_eq!(hit.pc, 0xF006);
+        assert_eq!(hit.access.kind, BusAccessKind::Dummy);
+        assert_eq!(hit.access.address, 0x82);
+
+        dc.resume();
+        tick_while_running_with_bus(&mut dc, &mut cpu);
+        assert_eq!(cpu.reg_pc(), 0xF009);
+        let hit = dc.data_breakpoint_hit().unwrap();
+        assert_eq!(hit.pc, 0xF008);
+        assert_eq!(hit.access.kind, BusAccessKind::Stack);
+    }
+
+    #[test]
+    fn data_breakpoints_ignore_instruction_fetches() {
+        let mut cpu = cpu_with_code! {
+                lda 0x80   // 0xF000
+                nop        // 0xF002
+                lda abs 0xF000 // 0xF003
+            loop:
+                jmp loop   // 0xF006
+        };
+        let mut dc = DebuggerCore::new();
+        dc.update(&cpu);
+        dc.set_data_breakpoints(vec![DataBreakpoint::new(
+            AddressRange {
+                start: 0xF000,
+                end: 0xF002,
+            },
+            DataBreakpointAccessType::ReadWrite,
+        )]);
+
+        dc.resume();
+        tick_while_running_with_bus(&mut dc, &mut cpu);
+        assert_eq!(cpu.reg_pc(), 0xF006);
+        assert_eq!(dc.data_breakpoint_hit().unwrap().pc, 0xF003);
+    }
+
     #[test]
     fn stack_frames_only_top() {
         let mut cpu = cpu_with_code! {
@@ -9,6 +9,7 @@
 //! Note that this crate deliberately doesn't contain all of the types, and the
 //! types only have the fields that we really use.
 
+use crate::debugger::core::DataBreakpointAccessType;
 use crate::debugger::core::StopReason;
 use serde::Deserialize;
 use serde::Serialize;
@@ -36,6 +37,9 @@ pub enum Request {
     Initialize(InitializeArguments),
     SetExceptionBreakpoints {},
     SetInstructionBreakpoints(SetInstructionBreakpointsArguments),
+    SetFunctionBreakpoints(SetFunctionBreakpointsArguments),
+    DataBreakpointInfo(DataBreakpointInfoArguments),
+    SetDataBreakpoints(SetDataBreakpointsArguments),
     Attach {},
     Threads,
     StackTrace {},
@@ -43,6 +47,10 @@ pub enum Request {
     Variables(VariablesArguments),
     Disassemble(DisassembleArguments),
     ReadMemory(ReadMemoryArguments),
+    WriteMemory(WriteMemoryArguments),
+    SetVariable(SetVariableArguments),
+    SetExpression(SetExpressionArguments),
+    Evaluate(EvaluateArguments),
 
     Continue {},
     Pause {},
@@ -65,6 +73,32 @@ pub struct SetInstructionBreakpointsArguments {
     pub breakpoints: Vec<InstructionBreakpoint>,
 }
 
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SetFunctionBreakpointsArguments {
+    pub breakpoints: Vec<FunctionBreakpoint>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DataBreakpointInfoArguments {
+    /// Reference to the container of the variable given by [`name`]. If it's
+    /// not present, the name is an address expression.
+    pub variables_reference: Option<i64>,
+    pub name: String,
+    /// Number of bytes to watch, starting at the address given by [`name`].
+    pub bytes: Option<i64>,
+    /// If `true`, the name is a memory address, even if it looks like an
+    /// address range.
+    pub as_address: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SetDataBreakpointsArguments {
+    pub breakpoints: Vec<DataBreakpoint>,
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ScopesArguments {
@@ -102,10 +136,47 @@ pub struct ReadMemoryArguments {
     pub count: i64,
 }
 
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WriteMemoryArguments {
+    pub memory_reference: String,
+    pub offset: Option<i64>,
+    /// If true, we write as much as we can, instead of failing if the memory
+    /// region is not entirely writable.
+    pub allow_partial: Option<bool>,
+    /// Base64-encoded bytes to write.
+    pub data: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SetVariableArguments {
+    pub variables_reference: i64,
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SetExpressionArguments {
+    pub expression: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EvaluateArguments {
+    pub expression: String,
+    /// Where the expression comes from: `watch`, `hover`, `repl`, etc.
+    pub context: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct ResponseEnvelope {
     pub request_seq: i64,
     pub success: bool,
+    /// Explains why the request failed.
+    pub message: Option<String>,
 
     #[serde(flatten)]
     pub response: Response,
@@ -117,13 +188,21 @@ pub enum Response {
     Initialize(Capabilities),
     SetExceptionBreakpoints,
     SetInstructionBreakpoints(SetInstructionBreakpointsResponse),
+    SetFunctionBreakpoints(SetFunctionBreakpointsResponse),
+    DataBreakpointInfo(DataBreakpointInfoResponse),
+    SetDataBreakpoints(SetDataBreakpointsResponse),
     Attach,
     Threads(ThreadsResponse),
     StackTrace(StackTraceResponse),
     Scopes(ScopesResponse),
     Variables(VariablesResponse),
-    Disassemble(DisassembleResponse),
-    ReadMemory(ReadMemoryResponse),
+    /// Failed requests don't have a body, hence the `Option`s.
+    Disassemble(Option<DisassembleResponse>),
+    ReadMemory(Option<ReadMemoryResponse>),
+    WriteMemory(Option<WriteMemoryResponse>),
+    SetVariable(Option<SetVariableResponse>),
+    SetExpression(Option<SetExpressionResponse>),
+    Evaluate(Option<EvaluateResponse>),
 
     Continue {},
     Pause,
@@ -137,9 +216,18 @@ pub enum Response {
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct Capabilities {
+    pub supports_conditional_breakpoints: bool,
+    pub supports_hit_conditional_breakpoints: bool,
+    pub supports_data_breakpoints: bool,
+    pub supports_data_breakpoint_bytes: bool,
+    pub supports_function_breakpoints: bool,
     pub supports_disassemble_request: bool,
     pub supports_instruction_breakpoints: bool,
     pub supports_read_memory_request: bool,
+    pub supports_write_memory_request: bool,
+    pub supports_set_variable: bool,
+    pub supports_set_expression: bool,
+    pub supports_evaluate_for_hovers: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -148,6 +236,29 @@ pub struct SetInstructionBreakpointsResponse {
     pub breakpoints: Vec<Breakpoint>,
 }
 
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SetFunctionBreakpointsResponse {
+    pub breakpoints: Vec<Breakpoint>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DataBreakpointInfoResponse {
+    /// An identifier to be used in [`DataBreakpoint::data_id`], or `None` if
+    /// no data breakpoint can be set.
+    pub data_id: Option<String>,
+    /// Describes what is going to be watched, or why it can't be watched.
+    pub description: String,
+    pub access_types: Option<Vec<DataBreakpointAccessType>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SetDataBreakpointsResponse {
+    pub breakpoints: Vec<Breakpoint>,
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ThreadsResponse {
@@ -202,6 +313,31 @@ pub struct ReadMemoryResponse {
     pub unreadable_bytes: i64,
 }
 
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WriteMemoryResponse {
+    pub bytes_written: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SetVariableResponse {
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SetExpressionResponse {
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EvaluateResponse {
+    pub result: String,
+    pub variables_reference: i64,
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct DisassembledInstruction {
@@ -210,6 +346,8 @@ pub struct DisassembledInstruction {
     pub address: String,
     pub instruction_bytes: String,
     pub instruction: String,
+    /// Name of the label at the instruction address, if any.
+    pub symbol: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -232,6 +370,8 @@ pub enum Event {
 #[serde(rename_all = "camelCase")]
 pub struct StoppedEvent {
     pub reason: StopReason,
+    /// Full reason of the stop, displayed in the UI as is.
+    pub description: Option<String>,
     pub thread_id: i64,
     pub all_threads_stopped: bool,
 }
@@ -258,13 +398,35 @@ pub struct Thread {
 pub struct InstructionBreakpoint {
     pub instruction_reference: String,
     pub offset: Option<i64>,
+    pub condition: Option<String>,
+    pub hit_condition: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionBreakpoint {
+    /// Name of the symbol at the beginning of the function.
+    pub name: String,
+    pub condition: Option<String>,
+    pub hit_condition: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct Breakpoint {
     pub verified: bool,
-    pub instruction_reference: String,
+    /// Explains why the breakpoint couldn't be verified.
+    pub message: Option<String>,
+    /// Only present for instruction breakpoints.
+    pub instruction_reference: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DataBreakpoint {
+    /// An identifier obtained from [`DataBreakpointInfoResponse::data_id`].
+    pub data_id: String,
+    pub access_type: Option<DataBreakpointAccessType>,
 }
 
 /// This empty struct is here only because `Serde` doesn't allow us to use an
@@ -327,15 +489,62 @@ mod tests {
                         InstructionBreakpoint {
                             instruction_reference: "0xAB12".to_string(),
                             offset: None,
+                            condition: None,
+                            hit_condition: None,
                         },
                         InstructionBreakpoint {
                             instruction_reference: "0x12AB".to_string(),
                             offset: Some(-12),
+                            condition: Some("X == $10".to_string()),
+                            hit_condition: Some("%3".to_string()),
+                        }
+                    ]
+                }
+            )),
+        },
+        set_function_breakpoints_request: MessageEnvelope {
+            seq: 4,
+            message: Message::Request(Request::SetFunctionBreakpoints(
+                SetFunctionBreakpointsArguments {
+                    breakpoints: vec![
+                        FunctionBreakpoint {
+                            name: "Kernel".to_string(),
+                            condition: None,
+                            hit_condition: None,
+                        },
+                        FunctionBreakpoint {
+                            name: "DrawScore".to_string(),
+                            condition: Some("A == 0".to_string()),
+                            hit_condition: Some(">= 2".to_string()),
                         }
                     ]
                 }
             )),
         },
+        data_breakpoint_info_request: MessageEnvelope {
+            seq: 4,
+            message: Message::Request(Request::DataBreakpointInfo(DataBreakpointInfoArguments {
+                variables_reference: None,
+                name: "$0080".to_string(),
+                bytes: Some(2),
+                as_address: Some(true),
+            })),
+        },
+        set_data_breakpoints_request: MessageEnvelope {
+            seq: 5,
+            message: Message::Request(Request::SetDataBreakpoints(SetDataBreakpointsArguments {
+                breakpoints: vec![
+                    DataBreakpoint {
+                        data_id: "$0080-$0081".to_string(),
+                        access_type: Some(DataBreakpointAccessType::Write),
+                    },
+                    DataBreakpoint {
+                        data_id: "$D012-$D012".to_string(),
+                        access_type: Some(DataBreakpointAccessType::ReadWrite),
+                    },
+                ],
+            })),
+        },
         attach_request: MessageEnvelope {
             seq: 2,
             message: Message::Request(Request::Attach {}),
@@ -377,6 +586,37 @@ mod tests {
                 count: 131072,
             })),
         },
+        write_memory_request: MessageEnvelope {
+            seq: 16,
+            message: Message::Request(Request::WriteMemory(WriteMemoryArguments {
+                memory_reference: "0x0080".to_string(),
+                offset: Some(2),
+                allow_partial: None,
+                data: "3q0=".to_string(),
+            })),
+        },
+        set_variable_request: MessageEnvelope {
+            seq: 17,
+            message: Message::Request(Request::SetVariable(SetVariableArguments {
+                variables_reference: 1,
+                name: "X".to_string(),
+                value: "$10".to_string(),
+            })),
+        },
+        set_expression_request: MessageEnvelope {
+            seq: 18,
+            message: Message::Request(Request::SetExpression(SetExpressionArguments {
+                expression: "[$80]".to_string(),
+                value: "A + 1".to_string(),
+            })),
+        },
+        evaluate_request: MessageEnvelope {
+            seq: 19,
+            message: Message::Request(Request::Evaluate(EvaluateArguments {
+                expression: "{$FFFC}".to_string(),
+                context: Some("watch".to_string()),
+            })),
+        },
         continue_request: MessageEnvelope {
             seq: 10,
             message: Message::Request(Request::Continue {}),
@@ -411,10 +651,20 @@ mod tests {
             message: Message::Response(ResponseEnvelope {
                 request_seq: 11,
                 success: true,
+                message: None,
                 response: Response::Initialize(Capabilities {
+                    supports_conditional_breakpoints: true,
+                    supports_hit_conditional_breakpoints: true,
+                    supports_data_breakpoints: true,
+                    supports_data_breakpoint_bytes: true,
+                    supports_function_breakpoints: true,
                     supports_disassemble_request: true,
                     supports_instruction_breakpoints: true,
                     supports_read_memory_request: true,
+                    supports_write_memory_request: true,
+                    supports_set_variable: true,
+                    supports_set_expression: true,
+                    supports_evaluate_for_hovers: true,
                 }),
             }),
         },
@@ -423,6 +673,7 @@ mod tests {
             message: Message::Response(ResponseEnvelope {
                 request_seq: 12,
                 success: true,
+                message: None,
                 response: Response::SetExceptionBreakpoints,
             }),
         },
@@ -431,21 +682,94 @@ mod tests {
             message: Message::Response(ResponseEnvelope {
                 request_seq: 76,
                 success: true,
+                message: None,
                 response: Response::SetInstructionBreakpoints(
                     SetInstructionBreakpointsResponse {
-                        breakpoints: vec![Breakpoint {
-                            verified: true,
-                            instruction_reference: "0x9876".to_string(),
-                        }]
+                        breakpoints: vec![
+                            Breakpoint {
+                                verified: true,
+                                message: None,
+                                instruction_reference: Some("0x9876".to_string()),
+                            },
+                            Breakpoint {
+                                verified: false,
+                                message: Some("Unknown name: Q".to_string()),
+                                instruction_reference: Some("0x9877".to_string()),
+                            },
+                        ]
                     }
                 ),
             }),
         },
+        set_function_breakpoints_response: MessageEnvelope {
+            seq: 3,
+            message: Message::Response(ResponseEnvelope {
+                request_seq: 4,
+                success: true,
+                message: None,
+                response: Response::SetFunctionBreakpoints(
+                    SetFunctionBreakpointsResponse {
+                        breakpoints: vec![
+                            Breakpoint {
+                                verified: true,
+                                message: None,
+                                instruction_reference: Some("0xF012".to_string()),
+                            },
+                            Breakpoint {
+                                verified: false,
+                                message: Some("Unknown symbol: DrawScore".to_string()),
+                                instruction_reference: None,
+                            },
+                        ]
+                    }
+                ),
+            }),
+        },
+        data_breakpoint_info_response: MessageEnvelope {
+            seq: 3,
+            message: Message::Response(ResponseEnvelope {
+                request_seq: 4,
+                success: true,
+                message: None,
+                response: Response::DataBreakpointInfo(DataBreakpointInfoResponse {
+                    data_id: Some("$0080-$0081".to_string()),
+                    description: "$0080-$0081".to_string(),
+                    access_types: Some(vec![
+                        DataBreakpointAccessType::Read,
+                        DataBreakpointAccessType::Write,
+                        DataBreakpointAccessType::ReadWrite,
+                    ]),
+                }),
+            }),
+        },
+        set_data_breakpoints_response: MessageEnvelope {
+            seq: 4,
+            message: Message::Response(ResponseEnvelope {
+                request_seq: 5,
+                success: true,
+                message: None,
+                response: Response::SetDataBreakpoints(SetDataBreakpointsResponse {
+                    breakpoints: vec![
+                        Breakpoint {
+                            verified: true,
+                            message: None,
+                            instruction_reference: None,
+                        },
+                        Breakpoint {
+                            verified: true,
+                            message: None,
+                            instruction_reference: None,
+                        },
+                    ],
+                }),
+            }),
+        },
         attach_response: MessageEnvelope {
             seq: 3,
             message: Message::Response(ResponseEnvelope {
                 request_seq: 13,
                 success: true,
+                message: None,
                 response: Response::Attach,
             }),
         },
@@ -454,6 +778,7 @@ mod tests {
             message: Message::Response(ResponseEnvelope {
                 request_seq: 14,
                 success: true,
+                message: None,
                 response: Response::Threads(ThreadsResponse {
                     threads: vec![Thread {
                         id: 1,
@@ -467,6 +792,7 @@ mod tests {
             message: Message::Response(ResponseEnvelope {
                 request_seq: 19,
                 success: true,
+                message: None,
                 response: Response::StackTrace(StackTraceResponse {
                     stack_frames: vec![StackFrame {
                         id: 1,
@@ -484,6 +810,7 @@ mod tests {
             message: Message::Response(ResponseEnvelope {
                 request_seq: 82,
                 success: true,
+                message: None,
                 response: Response::Scopes(ScopesResponse {
                     scopes: vec![Scope {
                         name: "Registers".to_string(),
@@ -499,6 +826,7 @@ mod tests {
             message: Message::Response(ResponseEnvelope {
                 request_seq: 74,
                 success: true,
+                message: None,
                 response: Response::Variables(VariablesResponse {
                     variables: vec![Variable {
                         name: "A".to_string(),
@@ -514,20 +842,23 @@ mod tests {
             message: Message::Response(ResponseEnvelope {
                 request_seq: 63,
                 success: true,
-                response: Response::Disassemble(DisassembleResponse {
+                message: None,
+                response: Response::Disassemble(Some(DisassembleResponse {
                     instructions: vec![
                         DisassembledInstruction {
                             address: "0xBEEF".to_string(),
                             instruction_bytes: "A9 76".to_string(),
                             instruction: "LDA #$76".to_string(),
+                            symbol: Some("Start".to_string()),
                         },
                         DisassembledInstruction {
                             address: "0xBEF1".to_string(),
                             instruction_bytes: "8D 4F C9".to_string(),
                             instruction: "STA $C94F".to_string(),
+                            symbol: None,
                         },
                     ],
-                }),
+                })),
             }),
         },
         read_memory_response: MessageEnvelope {
@@ -535,11 +866,66 @@ mod tests {
             message: Message::Response(ResponseEnvelope {
                 request_seq: 83,
                 success: true,
-                response: Response::ReadMemory(ReadMemoryResponse {
+                message: None,
+                response: Response::ReadMemory(Some(ReadMemoryResponse {
                     address: "0xDEAD".to_string(),
                     data: "vu8=".to_string(),
                     unreadable_bytes: 0,
-                }),
+                })),
+            }),
+        },
+        write_memory_response: MessageEnvelope {
+            seq: 77,
+            message: Message::Response(ResponseEnvelope {
+                request_seq: 16,
+                success: true,
+                message: None,
+                response: Response::WriteMemory(Some(WriteMemoryResponse {
+                    bytes_written: 2,
+                })),
+            }),
+        },
+        set_variable_response: MessageEnvelope {
+            seq: 78,
+            message: Message::Response(ResponseEnvelope {
+                request_seq: 17,
+                success: true,
+                message: None,
+                response: Response::SetVariable(Some(SetVariableResponse {
+                    value: "$10".to_string(),
+                })),
+            }),
+        },
+        set_variable_error_response: MessageEnvelope {
+            seq: 79,
+            message: Message::Response(ResponseEnvelope {
+                request_seq: 18,
+                success: false,
+                message: Some("Value out of range: 256".to_string()),
+                response: Response::SetVariable(None),
+            }),
+        },
+        set_expression_response: MessageEnvelope {
+            seq: 80,
+            message: Message::Response(ResponseEnvelope {
+                request_seq: 18,
+                success: true,
+                message: None,
+                response: Response::SetExpression(Some(SetExpressionResponse {
+                    value: "$13".to_string(),
+                })),
+            }),
+        },
+        evaluate_response: MessageEnvelope {
+            seq: 81,
+            message: Message::Response(ResponseEnvelope {
+                request_seq: 19,
+                success: true,
+                message: None,
+                response: Response::Evaluate(Some(EvaluateResponse {
+                    result: "$F000 (61440, %1111000000000000)".to_string(),
+                    variables_reference: 0,
+                })),
             }),
         },
         continue_response: MessageEnvelope {
@@ -547,6 +933,7 @@ mod tests {
             message: Message::Response(ResponseEnvelope {
                 request_seq: 9,
                 success: true,
+                message: None,
                 response: Response::Continue{},
             }),
         },
@@ -555,6 +942,7 @@ mod tests {
             message: Message::Response(ResponseEnvelope {
                 request_seq: 10,
                 success: true,
+                message: None,
                 response: Response::Pause,
             }),
         },
@@ -563,6 +951,7 @@ mod tests {
             message: Message::Response(ResponseEnvelope {
                 request_seq: 87,
                 success: true,
+                message: None,
                 response: Response::Next,
             }),
         },
@@ -571,6 +960,7 @@ mod tests {
             message: Message::Response(ResponseEnvelope {
                 request_seq: 13,
                 success: true,
+                message: None,
                 response: Response::StepIn,
             }),
         },
@@ -579,6 +969,7 @@ mod tests {
             message: Message::Response(ResponseEnvelope {
                 request_seq: 72,
                 success: true,
+                message: None,
                 response: Response::StepOut,
             }),
         },
@@ -587,6 +978,7 @@ mod tests {
             message: Message::Response(ResponseEnvelope {
                 request_seq: 89,
                 success: true,
+                message: None,
                 response: Response::Disconnect,
             }),
         },
@@ -595,10 +987,20 @@ mod tests {
             seq: 74,
             message: Message::Event(Event::Initialized),
         },
+        stopped_event_data_breakpoint: MessageEnvelope {
+            seq: 11,
+            message: Message::Event(Event::Stopped(StoppedEvent {
+                reason: StopReason::DataBreakpoint,
+                description: Some("Write of $01 to $0080 at $F002".to_string()),
+                thread_id: 1,
+                all_threads_stopped: true,
+            })),
+        },
         stopped_event: MessageEnvelope {
             seq: 10,
             message: Message::Event(Event::Stopped(StoppedEvent {
                 reason: StopReason::Entry,
+                description: None,
                 thread_id: 1,
                 all_threads_stopped: true,
             })),
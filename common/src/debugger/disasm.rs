@@ -1,10 +1,21 @@
 use crate::debugger::dap_types::DisassembledInstruction;
-use std::fmt;
-use std::fmt::Display;
-use std::fmt::Formatter;
+use crate::debugger::symbols::Symbols;
 use std::iter;
+use ya6502::cpu::instructions::{AddressingMode, InstructionInfo};
 use ya6502::cpu::MachineInspector;
 
+/// Selects which opcodes are recognized by the disassembler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpcodeSet {
+    /// Only the documented instructions. Since it's way more likely to
+    /// stumble upon data than an undocumented opcode, this is the right choice
+    /// for disassembling arbitrary memory regions.
+    Documented,
+    /// All opcodes with known semantics. Undocumented instructions are marked
+    /// with an asterisk, like in the reference logs of other emulators.
+    All,
+}
+
 /// Disassembles a memory region. The region starts at `start_address`. First
 /// `margin` instructions are ignored to allow for a "runway" in disassembling
 /// the initial, potentially ambiguous, chain of instructions. The function
@@ -13,9 +24,33 @@ use ya6502::cpu::MachineInspector;
 /// space that is known to be a valid start of an instruction (e.g. it's
 /// currently a PC or belongs to a chain of already disassembled instructions).
 /// This way, multiple disassembly requests for adjacent or overlapping memory
-/// regions are guaranteed to produce a coherent output.
+/// regions are guaranteed to produce a coherent output. Addresses that have
+/// labels in the `symbols` table are replaced with the label names. Only the
+/// documented opcodes are recognized.
 pub fn disassemble<I: MachineInspector>(
     inspector: &I,
+    symbols: &Symbols,
+    origin: u16,
+    start_address: u16,
+    margin: usize,
+    length: usize,
+) -> Vec<DisassembledInstruction> {
+    disassemble_opcodes(
+        inspector,
+        symbols,
+        OpcodeSet::Documented,
+        origin,
+        start_address,
+        margin,
+        length,
+    )
+}
+
+/// Same as [`disassemble`], but recognizes a given set of opcodes.
+pub fn disassemble_opcodes<I: MachineInspector>(
+    inspector: &I,
+    symbols: &Symbols,
+    opcode_set: OpcodeSet,
     origin: u16,
     start_address: u16,
     margin: usize,
@@ -24,17 +59,18 @@ pub fn disassemble<I: MachineInspector>(
     let mut memory_stream = MemoryStream::new(inspector, start_address);
     return iter::from_fn(|| {
         let instruction_start = memory_stream.ptr;
-        let instruction = read_instruction_unless_crosses_origin(&mut memory_stream, origin);
+        let instruction =
+            read_instruction_unless_crosses_origin(&mut memory_stream, opcode_set, origin);
 
         use itertools::Itertools;
         let all_bytes = instruction.to_raw_bytes();
         let mnemonic = match instruction.descriptor {
-            Some(descriptor) => descriptor.mnemonic,
-            None => "",
-        }
-        .to_string();
+            Some(descriptor) if descriptor.documented => descriptor.mnemonic.to_string(),
+            Some(descriptor) => format!("*{}", descriptor.mnemonic),
+            None => "".to_string(),
+        };
         let argument = match instruction.argument {
-            Some(argument) => format!("{}", argument),
+            Some(argument) => argument.format(symbols),
             None => "".to_string(),
         };
         let instruction_parts = [mnemonic, argument];
@@ -43,6 +79,7 @@ pub fn disassemble<I: MachineInspector>(
             address: format!("0x{:04X}", instruction_start),
             instruction_bytes: format!("{:02X}", all_bytes.iter().format(" ")),
             instruction: format!("{}", non_empty_instruction_parts.format(" ")),
+            symbol: symbols.label(instruction_start).map(str::to_string),
         });
     })
     .skip(margin)
@@ -50,15 +87,16 @@ pub fn disassemble<I: MachineInspector>(
     .collect();
 }
 
-fn read_instruction_unless_crosses_origin<'a, I>(
+fn read_instruction_unless_crosses_origin<I>(
     stream: &mut MemoryStream<I>,
+    opcode_set: OpcodeSet,
     origin: u16,
-) -> Instruction<'a>
+) -> Instruction
 where
     I: MachineInspector,
 {
     let instruction_start = stream.ptr;
-    let instruction = stream.read_instruction();
+    let instruction = stream.read_instruction(opcode_set);
     let crossed_origin = (instruction_start < origin && origin < stream.ptr)
         || (stream.ptr < instruction_start && instruction_start < origin)
         || (origin < stream.ptr && stream.ptr < instruction_start);
@@ -84,7 +122,7 @@ pub fn seek_instruction<I: MachineInspector>(inspector: &I, origin: u16, offset:
 
     if offset >= 0 {
         for _ in 0..offset {
-            stream.read_instruction();
+            stream.read_instruction(OpcodeSet::Documented);
         }
         return stream.ptr;
     } else {
@@ -113,7 +151,7 @@ pub fn seek_instruction<I: MachineInspector>(inspector: &I, origin: u16, offset:
             let ptr = stream.ptr.wrapping_sub(1);
             stream.ptr = ptr;
 
-            let instruction = stream.read_instruction();
+            let instruction = stream.read_instruction(OpcodeSet::Documented);
             let is_unknown = instruction.descriptor.is_none();
             let instruction_length: usize = stream.ptr.wrapping_sub(ptr).into();
 
@@ -191,23 +229,6 @@ fn seeking_backward_finished(chain_links: &[ChainLink], offset: i64) -> bool {
             .all(|link| link.num_instructions >= -offset)
 }
 
-#[derive(Clone, Copy, Debug)]
-enum AddressingMode {
-    Accumulator,
-    Immediate,
-    Implied,
-    Relative,
-    Absolute,
-    ZeroPage,
-    Indirect,
-    AbsoluteIndexedX,
-    AbsoluteIndexedY,
-    ZeroPageIndexedX,
-    ZeroPageIndexedY,
-    ZeroPageXIndirect,
-    ZeroPageIndirectY,
-}
-
 /// Encapsulates an instruction argument for a given addressing mode.
 #[derive(Clone, Copy, Debug)]
 enum Argument {
@@ -224,30 +245,43 @@ enum Argument {
     ZeroPageIndexedY(u8),
     ZeroPageXIndirect(u8),
     ZeroPageIndirectY(u8),
+    ZeroPageIndirect(u8),
+    AbsoluteIndexedIndirect(u16),
 }
 
-impl Display for Argument {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+impl Argument {
+    /// Formats the argument, using symbol names instead of addresses whenever
+    /// possible. Immediate values are never replaced, since they are rarely
+    /// addresses.
+    fn format(self, symbols: &Symbols) -> String {
         use Argument::*;
+        let word = |address: u16| match symbols.label(address) {
+            Some(label) => label.to_string(),
+            None => format!("${:04X}", address),
+        };
+        let byte = |address: u8| match symbols.label(address.into()) {
+            Some(label) => label.to_string(),
+            None => format!("${:02X}", address),
+        };
         match self {
-            Accumulator => write!(f, "A"),
-            Immediate(arg) => write!(f, "#${:02X}", arg),
-            Implied => Ok(()),
-            Relative { resolved, .. } => write!(f, "${:04X}", resolved),
-            Absolute(arg) => write!(f, "${:04X}", arg),
-            ZeroPage(arg) => write!(f, "${:02X}", arg),
-            Indirect(arg) => write!(f, "(${:04X})", arg),
-            AbsoluteIndexedX(arg) => write!(f, "${:04X},X", arg),
-            AbsoluteIndexedY(arg) => write!(f, "${:04X},Y", arg),
-            ZeroPageIndexedX(arg) => write!(f, "${:02X},X", arg),
-            ZeroPageIndexedY(arg) => write!(f, "${:02X},Y", arg),
-            ZeroPageXIndirect(arg) => write!(f, "(${:02X},X)", arg),
-            ZeroPageIndirectY(arg) => write!(f, "(${:02X}),Y", arg),
+            Accumulator => "A".to_string(),
+            Immediate(arg) => format!("#${:02X}", arg),
+            Implied => "".to_string(),
+            Relative { resolved, .. } => word(resolved),
+            Absolute(arg) => word(arg),
+            ZeroPage(arg) => byte(arg),
+            Indirect(arg) => format!("({})", word(arg)),
+            AbsoluteIndexedX(arg) => format!("{},X", word(arg)),
+            AbsoluteIndexedY(arg) => format!("{},Y", word(arg)),
+            ZeroPageIndexedX(arg) => format!("{},X", byte(arg)),
+            ZeroPageIndexedY(arg) => format!("{},Y", byte(arg)),
+            ZeroPageXIndirect(arg) => format!("({},X)", byte(arg)),
+            ZeroPageIndirectY(arg) => format!("({}),Y", byte(arg)),
+            ZeroPageIndirect(arg) => format!("({})", byte(arg)),
+            AbsoluteIndexedIndirect(arg) => format!("({},X)", word(arg)),
         }
     }
-}
 
-impl Argument {
     /// Returns instruction argument as a byte vector.
     fn to_raw_bytes(self) -> Vec<u8> {
         use Argument::*;
@@ -259,38 +293,47 @@ impl Argument {
             | ZeroPageIndexedX(arg)
             | ZeroPageIndexedY(arg)
             | ZeroPageXIndirect(arg)
-            | ZeroPageIndirectY(arg) => vec![arg],
-            Absolute(arg) | Indirect(arg) | AbsoluteIndexedX(arg) | AbsoluteIndexedY(arg) => {
-                arg.to_le_bytes().to_vec()
-            }
+            | ZeroPageIndirectY(arg)
+            | ZeroPageIndirect(arg) => vec![arg],
+            Absolute(arg)
+            | Indirect(arg)
+            | AbsoluteIndexedX(arg)
+            | AbsoluteIndexedY(arg)
+            | AbsoluteIndexedIndirect(arg) => arg.to_le_bytes().to_vec(),
         }
     }
 }
 
-impl AddressingMode {
-    /// Reads an instruction argument from a memory stream.
-    fn read_argument<'a, I>(self, stream: &mut MemoryStream<'a, I>) -> Argument
-    where
-        I: MachineInspector,
-    {
-        match self {
-            AddressingMode::Accumulator => Argument::Accumulator,
-            AddressingMode::Immediate => Argument::Immediate(stream.read_byte()),
-            AddressingMode::Implied => Argument::Implied,
-            AddressingMode::Relative => {
-                let arg = stream.read_byte();
-                let resolved = stream.ptr.wrapping_add(arg as i8 as u16);
-                Argument::Relative { arg, resolved }
-            }
-            AddressingMode::Absolute => Argument::Absolute(stream.read_word()),
-            AddressingMode::ZeroPage => Argument::ZeroPage(stream.read_byte()),
-            AddressingMode::Indirect => Argument::Indirect(stream.read_word()),
-            AddressingMode::AbsoluteIndexedX => Argument::AbsoluteIndexedX(stream.read_word()),
-            AddressingMode::AbsoluteIndexedY => Argument::AbsoluteIndexedY(stream.read_word()),
-            AddressingMode::ZeroPageIndexedX => Argument::ZeroPageIndexedX(stream.read_byte()),
-            AddressingMode::ZeroPageIndexedY => Argument::ZeroPageIndexedY(stream.read_byte()),
-            AddressingMode::ZeroPageXIndirect => Argument::ZeroPageXIndirect(stream.read_byte()),
-            AddressingMode::ZeroPageIndirectY => Argument::ZeroPageIndirectY(stream.read_byte()),
+/// Reads an instruction argument for a given addressing mode from a memory
+/// stream.
+fn read_argument<'a, I>(
+    addressing_mode: AddressingMode,
+    stream: &mut MemoryStream<'a, I>,
+) -> Argument
+where
+    I: MachineInspector,
+{
+    match addressing_mode {
+        AddressingMode::Accumulator => Argument::Accumulator,
+        AddressingMode::Immediate => Argument::Immediate(stream.read_byte()),
+        AddressingMode::Implied => Argument::Implied,
+        AddressingMode::Relative => {
+            let arg = stream.read_byte();
+            let resolved = stream.ptr.wrapping_add(arg as i8 as u16);
+            Argument::Relative { arg, resolved }
+        }
+        AddressingMode::Absolute => Argument::Absolute(stream.read_word()),
+        AddressingMode::ZeroPage => Argument::ZeroPage(stream.read_byte()),
+        AddressingMode::Indirect => Argument::Indirect(stream.read_word()),
+        AddressingMode::AbsoluteIndexedX => Argument::AbsoluteIndexedX(stream.read_word()),
+        AddressingMode::AbsoluteIndexedY => Argument::AbsoluteIndexedY(stream.read_word()),
+        AddressingMode::ZeroPageIndexedX => Argument::ZeroPageIndexedX(stream.read_byte()),
+        AddressingMode::ZeroPageIndexedY => Argument::ZeroPageIndexedY(stream.read_byte()),
+        AddressingMode::ZeroPageXIndirect => Argument::ZeroPageXIndirect(stream.read_byte()),
+        AddressingMode::ZeroPageIndirectY => Argument::ZeroPageIndirectY(stream.read_byte()),
+        AddressingMode::ZeroPageIndirect => Argument::ZeroPageIndirect(stream.read_byte()),
+        AddressingMode::AbsoluteIndexedIndirect => {
+            Argument::AbsoluteIndexedIndirect(stream.read_word())
         }
     }
 }
@@ -315,13 +358,14 @@ impl<'a, I: MachineInspector> MemoryStream<'a, I> {
         let msb = self.read_byte();
         return u16::from_le_bytes([lsb, msb]);
     }
-    // Note: it's neceessary to explicitly declare <'b> here, since otherwise,
-    // the returned instruction implicitly borrows `self` mutably. Don't even
-    // ask me how.
-    fn read_instruction<'b>(&mut self) -> Instruction<'b> {
+    fn read_instruction(&mut self, opcode_set: OpcodeSet) -> Instruction {
         let opcode = self.read_byte();
-        let descriptor = INSTRUCTION_DESCRIPTORS.with(|descriptors| descriptors[opcode as usize]);
-        let argument = descriptor.map(|d| d.addressing_mode.read_argument(self));
+        let info = &self.inspector.instruction_set()[opcode as usize];
+        let descriptor = match opcode_set {
+            OpcodeSet::Documented if !info.documented => None,
+            _ => Some(info),
+        };
+        let argument = descriptor.map(|d| read_argument(d.addressing_mode, self));
         return Instruction {
             opcode,
             argument,
@@ -330,13 +374,13 @@ impl<'a, I: MachineInspector> MemoryStream<'a, I> {
     }
 }
 
-struct Instruction<'a> {
+struct Instruction {
     opcode: u8,
     argument: Option<Argument>,
-    descriptor: Option<InstructionDescriptor<'a>>,
+    descriptor: Option<&'static InstructionInfo>,
 }
 
-impl<'a> Instruction<'a> {
+impl Instruction {
     fn to_raw_bytes(&self) -> Vec<u8> {
         let arg_bytes = match self.argument {
             Some(arg) => arg.to_raw_bytes(),
@@ -346,223 +390,12 @@ impl<'a> Instruction<'a> {
     }
 }
 
-#[derive(Clone, Copy)]
-struct InstructionDescriptor<'a> {
-    mnemonic: &'a str,
-    addressing_mode: AddressingMode,
-}
-
-type InstructionDescriptorMap<'a> = [Option<InstructionDescriptor<'a>>; 256];
-
-thread_local! {
-    /// A map that describes addressing modes of all possible opcodes.
-    static INSTRUCTION_DESCRIPTORS: InstructionDescriptorMap<'static> = all_instruction_descriptors();
-}
-
-fn all_instruction_descriptors<'a>() -> InstructionDescriptorMap<'a> {
-    use ya6502::cpu::opcodes::*;
-    use AddressingMode::*;
-    let mut descriptors = [None; 256];
-
-    define_instruction(&mut descriptors, NOP, "NOP", Implied);
-
-    define_instruction(&mut descriptors, LDA_IMM, "LDA", Immediate);
-    define_instruction(&mut descriptors, LDA_ZP, "LDA", ZeroPage);
-    define_instruction(&mut descriptors, LDA_ZP_X, "LDA", ZeroPageIndexedX);
-    define_instruction(&mut descriptors, LDA_ABS, "LDA", Absolute);
-    define_instruction(&mut descriptors, LDA_ABS_X, "LDA", AbsoluteIndexedX);
-    define_instruction(&mut descriptors, LDA_ABS_Y, "LDA", AbsoluteIndexedY);
-    define_instruction(&mut descriptors, LDA_X_INDIR, "LDA", ZeroPageXIndirect);
-    define_instruction(&mut descriptors, LDA_INDIR_Y, "LDA", ZeroPageIndirectY);
-
-    define_instruction(&mut descriptors, LDX_IMM, "LDX", Immediate);
-    define_instruction(&mut descriptors, LDX_ZP, "LDX", ZeroPage);
-    define_instruction(&mut descriptors, LDX_ZP_Y, "LDX", ZeroPageIndexedY);
-    define_instruction(&mut descriptors, LDX_ABS, "LDX", Absolute);
-    define_instruction(&mut descriptors, LDX_ABS_Y, "LDX", AbsoluteIndexedY);
-
-    define_instruction(&mut descriptors, LDY_IMM, "LDY", Immediate);
-    define_instruction(&mut descriptors, LDY_ZP, "LDY", ZeroPage);
-    define_instruction(&mut descriptors, LDY_ZP_X, "LDY", ZeroPageIndexedX);
-    define_instruction(&mut descriptors, LDY_ABS, "LDY", Absolute);
-    define_instruction(&mut descriptors, LDY_ABS_X, "LDY", AbsoluteIndexedX);
-
-    define_instruction(&mut descriptors, STA_ZP, "STA", ZeroPage);
-    define_instruction(&mut descriptors, STA_ZP_X, "STA", ZeroPageIndexedX);
-    define_instruction(&mut descriptors, STA_ABS, "STA", Absolute);
-    define_instruction(&mut descriptors, STA_ABS_X, "STA", AbsoluteIndexedX);
-    define_instruction(&mut descriptors, STA_ABS_Y, "STA", AbsoluteIndexedY);
-    define_instruction(&mut descriptors, STA_X_INDIR, "STA", ZeroPageXIndirect);
-    define_instruction(&mut descriptors, STA_INDIR_Y, "STA", ZeroPageIndirectY);
-
-    define_instruction(&mut descriptors, STX_ZP, "STX", ZeroPage);
-    define_instruction(&mut descriptors, STX_ZP_Y, "STX", ZeroPageIndexedY);
-    define_instruction(&mut descriptors, STX_ABS, "STX", Absolute);
-
-    define_instruction(&mut descriptors, STY_ZP, "STY", ZeroPage);
-    define_instruction(&mut descriptors, STY_ZP_X, "STY", ZeroPageIndexedX);
-    define_instruction(&mut descriptors, STY_ABS, "STY", Absolute);
-
-    define_instruction(&mut descriptors, AND_IMM, "AND", Immediate);
-    define_instruction(&mut descriptors, AND_ZP, "AND", ZeroPage);
-    define_instruction(&mut descriptors, AND_ZP_X, "AND", ZeroPageIndexedX);
-    define_instruction(&mut descriptors, AND_ABS, "AND", Absolute);
-    define_instruction(&mut descriptors, AND_ABS_X, "AND", AbsoluteIndexedX);
-    define_instruction(&mut descriptors, AND_ABS_Y, "AND", AbsoluteIndexedY);
-    define_instruction(&mut descriptors, AND_X_INDIR, "AND", ZeroPageXIndirect);
-    define_instruction(&mut descriptors, AND_INDIR_Y, "AND", ZeroPageIndirectY);
-
-    define_instruction(&mut descriptors, ORA_IMM, "ORA", Immediate);
-    define_instruction(&mut descriptors, ORA_ZP, "ORA", ZeroPage);
-    define_instruction(&mut descriptors, ORA_ZP_X, "ORA", ZeroPageIndexedX);
-    define_instruction(&mut descriptors, ORA_ABS, "ORA", Absolute);
-    define_instruction(&mut descriptors, ORA_ABS_X, "ORA", AbsoluteIndexedX);
-    define_instruction(&mut descriptors, ORA_ABS_Y, "ORA", AbsoluteIndexedY);
-    define_instruction(&mut descriptors, ORA_X_INDIR, "ORA", ZeroPageXIndirect);
-    define_instruction(&mut descriptors, ORA_INDIR_Y, "ORA", ZeroPageIndirectY);
-
-    define_instruction(&mut descriptors, EOR_IMM, "EOR", Immediate);
-    define_instruction(&mut descriptors, EOR_ZP, "EOR", ZeroPage);
-    define_instruction(&mut descriptors, EOR_ZP_X, "EOR", ZeroPageIndexedX);
-    define_instruction(&mut descriptors, EOR_ABS, "EOR", Absolute);
-    define_instruction(&mut descriptors, EOR_ABS_X, "EOR", AbsoluteIndexedX);
-    define_instruction(&mut descriptors, EOR_ABS_Y, "EOR", AbsoluteIndexedY);
-    define_instruction(&mut descriptors, EOR_X_INDIR, "EOR", ZeroPageXIndirect);
-    define_instruction(&mut descriptors, EOR_INDIR_Y, "EOR", ZeroPageIndirectY);
-
-    define_instruction(&mut descriptors, ASL_A, "ASL", Accumulator);
-    define_instruction(&mut descriptors, ASL_ZP, "ASL", ZeroPage);
-    define_instruction(&mut descriptors, ASL_ZP_X, "ASL", ZeroPageIndexedX);
-    define_instruction(&mut descriptors, ASL_ABS, "ASL", Absolute);
-    define_instruction(&mut descriptors, ASL_ABS_X, "ASL", AbsoluteIndexedX);
-
-    define_instruction(&mut descriptors, LSR_A, "LSR", Accumulator);
-    define_instruction(&mut descriptors, LSR_ZP, "LSR", ZeroPage);
-    define_instruction(&mut descriptors, LSR_ZP_X, "LSR", ZeroPageIndexedX);
-    define_instruction(&mut descriptors, LSR_ABS, "LSR", Absolute);
-    define_instruction(&mut descriptors, LSR_ABS_X, "LSR", AbsoluteIndexedX);
-
-    define_instruction(&mut descriptors, ROL_A, "ROL", Accumulator);
-    define_instruction(&mut descriptors, ROL_ZP, "ROL", ZeroPage);
-    define_instruction(&mut descriptors, ROL_ZP_X, "ROL", ZeroPageIndexedX);
-    define_instruction(&mut descriptors, ROL_ABS, "ROL", Absolute);
-    define_instruction(&mut descriptors, ROL_ABS_X, "ROL", AbsoluteIndexedX);
-
-    define_instruction(&mut descriptors, ROR_A, "ROR", Accumulator);
-    define_instruction(&mut descriptors, ROR_ZP, "ROR", ZeroPage);
-    define_instruction(&mut descriptors, ROR_ZP_X, "ROR", ZeroPageIndexedX);
-    define_instruction(&mut descriptors, ROR_ABS, "ROR", Absolute);
-    define_instruction(&mut descriptors, ROR_ABS_X, "ROR", AbsoluteIndexedX);
-
-    define_instruction(&mut descriptors, CMP_IMM, "CMP", Immediate);
-    define_instruction(&mut descriptors, CMP_ZP, "CMP", ZeroPage);
-    define_instruction(&mut descriptors, CMP_ZP_X, "CMP", ZeroPageIndexedX);
-    define_instruction(&mut descriptors, CMP_ABS, "CMP", Absolute);
-    define_instruction(&mut descriptors, CMP_ABS_X, "CMP", AbsoluteIndexedX);
-    define_instruction(&mut descriptors, CMP_ABS_Y, "CMP", AbsoluteIndexedY);
-    define_instruction(&mut descriptors, CMP_X_INDIR, "CMP", ZeroPageXIndirect);
-    define_instruction(&mut descriptors, CMP_INDIR_Y, "CMP", ZeroPageIndirectY);
-
-    define_instruction(&mut descriptors, CPX_IMM, "CPX", Immediate);
-    define_instruction(&mut descriptors, CPX_ZP, "CPX", ZeroPage);
-    define_instruction(&mut descriptors, CPX_ABS, "CPX", Absolute);
-
-    define_instruction(&mut descriptors, CPY_IMM, "CPY", Immediate);
-    define_instruction(&mut descriptors, CPY_ZP, "CPY", ZeroPage);
-    define_instruction(&mut descriptors, CPY_ABS, "CPY", Absolute);
-
-    define_instruction(&mut descriptors, BIT_ZP, "BIT", ZeroPage);
-    define_instruction(&mut descriptors, BIT_ABS, "BIT", Absolute);
-
-    define_instruction(&mut descriptors, ADC_IMM, "ADC", Immediate);
-    define_instruction(&mut descriptors, ADC_ZP, "ADC", ZeroPage);
-    define_instruction(&mut descriptors, ADC_ZP_X, "ADC", ZeroPageIndexedX);
-    define_instruction(&mut descriptors, ADC_ABS, "ADC", Absolute);
-    define_instruction(&mut descriptors, ADC_ABS_X, "ADC", AbsoluteIndexedX);
-    define_instruction(&mut descriptors, ADC_ABS_Y, "ADC", AbsoluteIndexedY);
-    define_instruction(&mut descriptors, ADC_X_INDIR, "ADC", ZeroPageXIndirect);
-    define_instruction(&mut descriptors, ADC_INDIR_Y, "ADC", ZeroPageIndirectY);
-
-    define_instruction(&mut descriptors, SBC_IMM, "SBC", Immediate);
-    define_instruction(&mut descriptors, SBC_ZP, "SBC", ZeroPage);
-    define_instruction(&mut descriptors, SBC_ZP_X, "SBC", ZeroPageIndexedX);
-    define_instruction(&mut descriptors, SBC_ABS, "SBC", Absolute);
-    define_instruction(&mut descriptors, SBC_ABS_X, "SBC", AbsoluteIndexedX);
-    define_instruction(&mut descriptors, SBC_ABS_Y, "SBC", AbsoluteIndexedY);
-    define_instruction(&mut descriptors, SBC_X_INDIR, "SBC", ZeroPageXIndirect);
-    define_instruction(&mut descriptors, SBC_INDIR_Y, "SBC", ZeroPageIndirectY);
-
-    define_instruction(&mut descriptors, INC_ZP, "INC", ZeroPage);
-    define_instruction(&mut descriptors, INC_ZP_X, "INC", ZeroPageIndexedX);
-    define_instruction(&mut descriptors, INC_ABS, "INC", Absolute);
-    define_instruction(&mut descriptors, INC_ABS_X, "INC", AbsoluteIndexedX);
-
-    define_instruction(&mut descriptors, DEC_ZP, "DEC", ZeroPage);
-    define_instruction(&mut descriptors, DEC_ZP_X, "DEC", ZeroPageIndexedX);
-    define_instruction(&mut descriptors, DEC_ABS, "DEC", Absolute);
-    define_instruction(&mut descriptors, DEC_ABS_X, "DEC", AbsoluteIndexedX);
-
-    define_instruction(&mut descriptors, INX, "INX", Implied);
-    define_instruction(&mut descriptors, INY, "INY", Implied);
-    define_instruction(&mut descriptors, DEX, "DEX", Implied);
-    define_instruction(&mut descriptors, DEY, "DEY", Implied);
-
-    define_instruction(&mut descriptors, TAX, "TAX", Implied);
-    define_instruction(&mut descriptors, TAY, "TAY", Implied);
-    define_instruction(&mut descriptors, TXA, "TXA", Implied);
-    define_instruction(&mut descriptors, TYA, "TYA", Implied);
-    define_instruction(&mut descriptors, TXS, "TXS", Implied);
-    define_instruction(&mut descriptors, TSX, "TSX", Implied);
-
-    define_instruction(&mut descriptors, PHP, "PHP", Implied);
-    define_instruction(&mut descriptors, PHA, "PHA", Implied);
-    define_instruction(&mut descriptors, PLP, "PLP", Implied);
-    define_instruction(&mut descriptors, PLA, "PLA", Implied);
-
-    define_instruction(&mut descriptors, SEI, "SEI", Implied);
-    define_instruction(&mut descriptors, CLI, "CLI", Implied);
-    define_instruction(&mut descriptors, SED, "SED", Implied);
-    define_instruction(&mut descriptors, CLD, "CLD", Implied);
-    define_instruction(&mut descriptors, SEC, "SEC", Implied);
-    define_instruction(&mut descriptors, CLC, "CLC", Implied);
-    define_instruction(&mut descriptors, CLV, "CLV", Implied);
-
-    define_instruction(&mut descriptors, BEQ, "BEQ", Relative);
-    define_instruction(&mut descriptors, BNE, "BNE", Relative);
-    define_instruction(&mut descriptors, BCC, "BCC", Relative);
-    define_instruction(&mut descriptors, BCS, "BCS", Relative);
-    define_instruction(&mut descriptors, BPL, "BPL", Relative);
-    define_instruction(&mut descriptors, BMI, "BMI", Relative);
-    define_instruction(&mut descriptors, BVS, "BVS", Relative);
-    define_instruction(&mut descriptors, BVC, "BVC", Relative);
-
-    define_instruction(&mut descriptors, JMP_ABS, "JMP", Absolute);
-    define_instruction(&mut descriptors, JMP_INDIR, "JMP", Indirect);
-    define_instruction(&mut descriptors, JSR, "JSR", Absolute);
-    define_instruction(&mut descriptors, RTS, "RTS", Implied);
-    define_instruction(&mut descriptors, BRK, "BRK", Implied);
-    define_instruction(&mut descriptors, RTI, "RTI", Implied);
-
-    return descriptors;
-}
-
-fn define_instruction<'a>(
-    descriptors: &mut InstructionDescriptorMap<'a>,
-    opcode: u8,
-    mnemonic: &'a str,
-    addressing_mode: AddressingMode,
-) {
-    descriptors[opcode as usize] = Some(InstructionDescriptor {
-        mnemonic,
-        addressing_mode,
-    });
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ya6502::cpu::variant::Cmos65C02;
     use ya6502::cpu_with_code;
-    use ya6502::test_utils::cpu_with_program;
+    use ya6502::test_utils::{cpu_variant_with_program, cpu_with_program};
 
     fn disassembled(
         address: &str,
@@ -573,6 +406,7 @@ mod tests {
             address: address.to_string(),
             instruction_bytes: instruction_bytes.to_string(),
             instruction: instruction.to_string(),
+            symbol: None,
         }
     }
 
@@ -738,9 +572,12 @@ mod tests {
                 bne loop
         };
 
-        assert_eq!(disassemble(&cpu, 0xF000, 0xF000, 0, 0), vec![]);
         assert_eq!(
-            disassemble(&cpu, 0xF000, 0xF000, 0, 5),
+            disassemble(&cpu, &Symbols::new(), 0xF000, 0xF000, 0, 0),
+            vec![]
+        );
+        assert_eq!(
+            disassemble(&cpu, &Symbols::new(), 0xF000, 0xF000, 0, 5),
             vec![
                 disassembled("0xF000", "A5 45", "LDA $45"),
                 disassembled("0xF002", "A2 04", "LDX #$04"),
@@ -750,7 +587,7 @@ mod tests {
             ]
         );
         assert_eq!(
-            disassemble(&cpu, 0xF002, 0xF002, 0, 2),
+            disassemble(&cpu, &Symbols::new(), 0xF002, 0xF002, 0, 2),
             vec![
                 disassembled("0xF002", "A2 04", "LDX #$04"),
                 disassembled("0xF004", "9D EF BE", "STA $BEEF,X"),
@@ -758,11 +595,71 @@ mod tests {
         );
     }
 
+    #[test]
+    fn disassemble_all_opcodes() {
+        use ya6502::cpu::opcodes::*;
+        let cpu = cpu_with_program(&[
+            LAX_ZP,
+            0x45,
+            NOP_ABS_X1,
+            0x34,
+            0x12,
+            DCP_INDIR_Y,
+            0x10,
+            LDA_IMM,
+            0x01,
+        ]);
+
+        assert_eq!(
+            disassemble_opcodes(&cpu, &Symbols::new(), OpcodeSet::All, 0xF000, 0xF000, 0, 4),
+            vec![
+                disassembled("0xF000", "A7 45", "*LAX $45"),
+                disassembled("0xF002", "1C 34 12", "*NOP $1234,X"),
+                disassembled("0xF005", "D3 10", "*DCP ($10),Y"),
+                disassembled("0xF007", "A9 01", "LDA #$01"),
+            ]
+        );
+        assert_eq!(
+            disassemble(&cpu, &Symbols::new(), 0xF000, 0xF000, 0, 1),
+            vec![disassembled("0xF000", "A7", "")]
+        );
+    }
+
+    #[test]
+    fn disassemble_with_symbols() {
+        let cpu = cpu_with_code! {
+            start:
+                lda 0x80
+                sta abs 0x0080,x
+                jmp (0xFFFC)
+                ldx #0x80
+                bne start
+        };
+        let mut symbols = Symbols::new();
+        symbols.insert("start", 0xF000);
+        symbols.insert("counter", 0x0080);
+        symbols.insert("reset_vector", 0xFFFC);
+
+        assert_eq!(
+            disassemble(&cpu, &symbols, 0xF000, 0xF000, 0, 5),
+            vec![
+                DisassembledInstruction {
+                    symbol: Some("start".to_string()),
+                    ..disassembled("0xF000", "A5 80", "LDA counter")
+                },
+                disassembled("0xF002", "9D 80 00", "STA counter,X"),
+                disassembled("0xF005", "6C FC FF", "JMP (reset_vector)"),
+                disassembled("0xF008", "A2 80", "LDX #$80"),
+                disassembled("0xF00A", "D0 F4", "BNE start"),
+            ]
+        );
+    }
+
     #[test]
     fn disassemble_unknown_instruction() {
         let cpu = cpu_with_program(&[0xEA, 0x67, 0xEA]);
         assert_eq!(
-            disassemble(&cpu, 0xF000, 0xF000, 0, 3),
+            disassemble(&cpu, &Symbols::new(), 0xF000, 0xF000, 0, 3),
             vec![
                 disassembled("0xF000", "EA", "NOP"),
                 disassembled("0xF001", "67", ""),
@@ -780,7 +677,7 @@ mod tests {
         };
 
         assert_eq!(
-            disassemble(&cpu, 0xF002, 0xF000, 0, 3),
+            disassemble(&cpu, &Symbols::new(), 0xF002, 0xF000, 0, 3),
             vec![
                 disassembled("0xF000", "A5 45", "LDA $45"),
                 disassembled("0xF002", "85 EA", "STA $EA"),
@@ -788,7 +685,7 @@ mod tests {
             ]
         );
         assert_eq!(
-            disassemble(&cpu, 0xF003, 0xF000, 0, 4),
+            disassemble(&cpu, &Symbols::new(), 0xF003, 0xF000, 0, 4),
             vec![
                 disassembled("0xF000", "A5 45", "LDA $45"),
                 disassembled("0xF002", "85", ""),
@@ -806,7 +703,7 @@ mod tests {
                 stx 0x46
         };
         assert_eq!(
-            disassemble(&cpu, 0xF003, 0xF000, 1, 2),
+            disassemble(&cpu, &Symbols::new(), 0xF003, 0xF000, 1, 2),
             vec![
                 disassembled("0xF002", "E8", "INX"),
                 disassembled("0xF003", "86 46", "STX $46"),
@@ -814,6 +711,29 @@ mod tests {
         )
     }
 
+    #[test]
+    fn disassemble_cmos_opcodes() {
+        let cpu = cpu_variant_with_program::<Cmos65C02>(&[
+            0x80, 0x02, // BRA $F004
+            0x64, 0x12, // STZ $12
+            0xB2, 0x34, // LDA ($34)
+            0x7C, 0x00, 0xF1, // JMP ($F100,X)
+            0xDA, // PHX
+            0x03, // NOP
+        ]);
+        assert_eq!(
+            disassemble_opcodes(&cpu, &Symbols::new(), OpcodeSet::All, 0xF000, 0xF000, 0, 6),
+            vec![
+                disassembled("0xF000", "80 02", "BRA $F004"),
+                disassembled("0xF002", "64 12", "STZ $12"),
+                disassembled("0xF004", "B2 34", "LDA ($34)"),
+                disassembled("0xF006", "7C 00 F1", "JMP ($F100,X)"),
+                disassembled("0xF009", "DA", "PHX"),
+                disassembled("0xF00A", "03", "*NOP"),
+            ]
+        )
+    }
+
     /// Tests some incredibly rare edge cases that occur when we perform
     /// wrapping arithmetic operations close to the wrapping point.
     #[test]
@@ -823,7 +743,7 @@ mod tests {
         cpu.mut_memory().bytes[0xFFFE] = 0x85;
         cpu.mut_memory().bytes[0xFFFF] = 0xEA;
         assert_eq!(
-            disassemble(&cpu, 0xFFFF, 0xFFFE, 0, 1),
+            disassemble(&cpu, &Symbols::new(), 0xFFFF, 0xFFFE, 0, 1),
             vec![disassembled("0xFFFE", "85", "")]
         );
 
@@ -831,7 +751,7 @@ mod tests {
         cpu.mut_memory().bytes[0xFFFF] = 0x85;
         cpu.mut_memory().bytes[0x0000] = 0xEA;
         assert_eq!(
-            disassemble(&cpu, 0x0000, 0xFFFF, 0, 1),
+            disassemble(&cpu, &Symbols::new(), 0x0000, 0xFFFF, 0, 1),
             vec![disassembled("0xFFFF", "85", "")]
         );
     }
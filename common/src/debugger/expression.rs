@@ -0,0 +1,613 @@
+//! Expressions that are evaluated against the machine state, used as
+//! breakpoint conditions and watch expressions. Registers, flags, and memory
+//! locations can also be assigned to. Expressions use C-like operators and
+//! precedence, and can refer to registers (`A`, `X`, `Y`, `SP`, `PC`, and `P`
+//! for the whole flag register), individual flags (`N`, `V`, `B`, `D`, `I`,
+//! `Z`, `C`), memory (`[$80]` is the byte at address `$80`, and `{$FFFC}` is
+//! the little-endian word at `$FFFC`), and symbols. Numbers are decimal,
+//! hexadecimal (prefixed with `$` or `0x`), or binary (prefixed with `%`).
+//! Register and flag names are case-insensitive, so `x == $10 && [$80] > 3` is
+//! a valid expression; symbol names are not. Symbol names may be qualified with
+//! scopes (`foo::bar`) and refer to cheap local symbols (`Start@loop`).
+
+use crate::app::parse_number;
+use crate::debugger::symbols::Symbols;
+use std::str::FromStr;
+use ya6502::cpu::flags;
+use ya6502::cpu::MachineEditor;
+use ya6502::cpu::MachineInspector;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    A,
+    X,
+    Y,
+    SP,
+    PC,
+    P,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expression {
+    Number(i64),
+    Register(Register),
+    /// A single flag, given as a mask of the flag register.
+    Flag(u8),
+    /// A byte read from a given address.
+    Memory(Box<Expression>),
+    /// A little-endian word read from a given address.
+    Word(Box<Expression>),
+    Unary(&'static str, Box<Expression>),
+    Binary(&'static str, Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    pub fn evaluate(&self, inspector: &impl MachineInspector) -> Result<i64, String> {
+        Ok(match self {
+            Expression::Number(value) => *value,
+            Expression::Register(register) => match register {
+                Register::A => inspector.reg_a().into(),
+                Register::X => inspector.reg_x().into(),
+                Register::Y => inspector.reg_y().into(),
+                Register::SP => inspector.reg_sp().into(),
+                Register::PC => inspector.reg_pc().into(),
+                Register::P => inspector.flags().into(),
+            },
+            Expression::Flag(mask) => (inspector.flags() & mask != 0).into(),
+            Expression::Memory(address) => inspector
+                .inspect_memory(evaluate_address(address, inspector)?)
+                .into(),
+            Expression::Word(address) => {
+                let address = evaluate_address(address, inspector)?;
+                u16::from_le_bytes([
+                    inspector.inspect_memory(address),
+                    inspector.inspect_memory(address.wrapping_add(1)),
+                ])
+                .into()
+            }
+            Expression::Unary(op, operand) => {
+                let value = operand.evaluate(inspector)?;
+                match *op {
+                    "-" => value.wrapping_neg(),
+                    "!" => (value == 0).into(),
+                    "~" => !value,
+                    _ => unreachable!("Unknown operator {}", op),
+                }
+            }
+            Expression::Binary(op, left, right) => {
+                let left = left.evaluate(inspector)?;
+                // Short-circuit the logical operators, so that the conditions
+                // can be guarded.
+                match (*op, left != 0) {
+                    ("&&", false) => return Ok(0),
+                    ("||", true) => return Ok(1),
+                    _ => {}
+                }
+                let right = right.evaluate(inspector)?;
+                match *op {
+                    "&&" | "||" => (right != 0).into(),
+                    "|" => left | right,
+                    "^" => left ^ right,
+                    "&" => left & right,
+                    "==" => (left == right).into(),
+                    "!=" => (left != right).into(),
+                    "<" => (left < right).into(),
+                    "<=" => (left <= right).into(),
+                    ">" => (left > right).into(),
+                    ">=" => (left >= right).into(),
+                    "<<" => left.wrapping_shl(right as u32),
+                    ">>" => left.wrapping_shr(right as u32),
+                    "+" => left.wrapping_add(right),
+                    "-" => left.wrapping_sub(right),
+                    "*" => left.wrapping_mul(right),
+                    "/" | "%" if right == 0 => return Err("Division by zero".to_string()),
+                    "/" => left.wrapping_div(right),
+                    "%" => left.wrapping_rem(right),
+                    _ => unreachable!("Unknown operator {}", op),
+                }
+            }
+        })
+    }
+
+    /// Stores a value in the register, flag, or memory location that this
+    /// expression refers to. Flags only accept 0 and 1.
+    pub fn assign(
+        &self,
+        machine: &mut (impl MachineInspector + MachineEditor),
+        value: i64,
+    ) -> Result<(), String> {
+        let out_of_range = || format!("Value out of range: {}", value);
+        match self {
+            Expression::Register(Register::PC) => {
+                machine.set_reg_pc(u16::try_from(value).map_err(|_| out_of_range())?)
+            }
+            Expression::Register(register) => {
+                let value = u8::try_from(value).map_err(|_| out_of_range())?;
+                match register {
+                    Register::A => machine.set_reg_a(value),
+                    Register::X => machine.set_reg_x(value),
+                    Register::Y => machine.set_reg_y(value),
+                    Register::SP => machine.set_reg_sp(value),
+                    Register::P => machine.set_flags(value),
+                    Register::PC => unreachable!(),
+                }
+            }
+            Expression::Flag(mask) => match value {
+                0 => machine.set_flags(machine.flags() & !mask),
+                1 => machine.set_flags(machine.flags() | mask),
+                _ => return Err(out_of_range()),
+            },
+            Expression::Memory(address) => {
+                let address = evaluate_address(address, machine)?;
+                let value = u8::try_from(value).map_err(|_| out_of_range())?;
+                machine
+                    .poke_memory(address, value)
+                    .map_err(|e| e.to_string())?;
+            }
+            Expression::Word(address) => {
+                let address = evaluate_address(address, machine)?;
+                let [low, high] = u16::try_from(value)
+                    .map_err(|_| out_of_range())?
+                    .to_le_bytes();
+                machine
+                    .poke_memory(address, low)
+                    .and_then(|_| machine.poke_memory(address.wrapping_add(1), high))
+                    .map_err(|e| e.to_string())?;
+            }
+            _ => return Err("Only registers, flags, and memory can be assigned to".to_string()),
+        }
+        Ok(())
+    }
+}
+
+fn evaluate_address(
+    address: &Expression,
+    inspector: &impl MachineInspector,
+) -> Result<u16, String> {
+    let address = address.evaluate(inspector)?;
+    u16::try_from(address).map_err(|_| format!("Address out of range: {}", address))
+}
+
+impl Expression {
+    /// Parses an expression, replacing symbol names with their values. Register
+    /// and flag names take precedence over symbols.
+    pub fn parse(s: &str, symbols: &Symbols) -> Result<Self, String> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+            symbols,
+        };
+        let expression = parser.binary(0)?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(expression),
+            Some(token) => Err(format!("Unexpected {}", token)),
+        }
+    }
+}
+
+impl FromStr for Expression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s, &Symbols::new())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "number {}", value),
+            Token::Name(name) => write!(f, "'{}'", name),
+            Token::Operator(op) => write!(f, "'{}'", op),
+        }
+    }
+}
+
+/// Operators, sorted so that longer ones are matched first.
+const OPERATORS: [&str; 26] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "<", ">", "+", "-", "*", "/", "%", "&", "|",
+    "^", "!", "~", "(", ")", "[", "]", "{", "}",
+];
+
+/// Binary operators, from the lowest to the highest precedence.
+const BINARY_OPERATORS: [&[&str]; 10] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        // A `%` is a binary number prefix wherever an operand is expected, and
+        // a modulo operator otherwise.
+        let expects_operand = !matches!(
+            tokens.last(),
+            Some(Token::Number(_) | Token::Name(_) | Token::Operator(")" | "]" | "}"))
+        );
+        let word_length = |start: usize| {
+            rest[start..]
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .map_or(rest.len(), |length| start + length)
+        };
+        let length = if c == '$' || c.is_ascii_digit() {
+            let length = word_length(1);
+            tokens.push(Token::Number(parse_number(&rest[..length])? as i64));
+            length
+        } else if c == '%' && expects_operand {
+            let length = word_length(1);
+            let value = i64::from_str_radix(&rest[1..length], 2)
+                .map_err(|_| format!("Invalid number: {:?}", &rest[..length]))?;
+            tokens.push(Token::Number(value));
+            length
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '@' {
+            let length = name_length(rest);
+            tokens.push(Token::Name(rest[..length].to_string()));
+            length
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Operator(op));
+            op.len()
+        } else {
+            return Err(format!("Unexpected character: {:?}", c));
+        };
+        rest = rest[length..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Returns the length of a name at the beginning of a given string. Apart from
+/// the usual identifier characters, names of symbols may contain `::` scope
+/// separators and `@` that starts the name of a cheap local symbol.
+fn name_length(s: &str) -> usize {
+    let mut length = 0;
+    while let Some(c) = s[length..].chars().next() {
+        if c.is_ascii_alphanumeric() || c == '_' || c == '@' {
+            length += 1;
+        } else if s[length..].starts_with("::") {
+            length += 2;
+        } else {
+            break;
+        }
+    }
+    length
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    symbols: &'a Symbols,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Operator(actual)) if *actual == op => Ok(()),
+            Some(token) => Err(format!("Expected '{}', got {}", op, token)),
+            None => Err(format!("Expected '{}'", op)),
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expression, String> {
+        if level == BINARY_OPERATORS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(Token::Operator(op)) = self.tokens.get(self.position) {
+            if !BINARY_OPERATORS[level].contains(op) {
+                break;
+            }
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            left = Expression::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(Token::Operator(op @ ("-" | "!" | "~"))) => {
+                Ok(Expression::Unary(op, Box::new(self.unary()?)))
+            }
+            Some(Token::Operator("(")) => {
+                let expression = self.binary(0)?;
+                self.expect(")")?;
+                Ok(expression)
+            }
+            Some(Token::Operator("[")) => {
+                let address = self.binary(0)?;
+                self.expect("]")?;
+                Ok(Expression::Memory(Box::new(address)))
+            }
+            Some(Token::Operator("{")) => {
+                let address = self.binary(0)?;
+                self.expect("}")?;
+                Ok(Expression::Word(Box::new(address)))
+            }
+            Some(Token::Number(value)) => Ok(Expression::Number(*value)),
+            Some(Token::Name(name)) => name_expression(name, self.symbols),
+            Some(token) => Err(format!("Unexpected {}", token)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn name_expression(name: &str, symbols: &Symbols) -> Result<Expression, String> {
+    Ok(match name.to_uppercase().as_str() {
+        "A" => Expression::Register(Register::A),
+        "X" => Expression::Register(Register::X),
+        "Y" => Expression::Register(Register::Y),
+        "SP" => Expression::Register(Register::SP),
+        "PC" => Expression::Register(Register::PC),
+        "P" => Expression::Register(Register::P),
+        "N" => Expression::Flag(flags::N),
+        "V" => Expression::Flag(flags::V),
+        "B" => Expression::Flag(flags::B),
+        "D" => Expression::Flag(flags::D),
+        "I" => Expression::Flag(flags::I),
+        "Z" => Expression::Flag(flags::Z),
+        "C" => Expression::Flag(flags::C),
+        _ => match symbols.address(name) {
+            Some(address) => Expression::Number(address.into()),
+            None => return Err(format!("Unknown name: {}", name)),
+        },
+    })
+}
+
+/// Decides which hits of a breakpoint actually stop the execution. Uses the
+/// syntax of VS Code hit conditions: `5` or `==5` stops only at the 5th hit,
+/// `>=5` and `>5` at every hit starting from the 5th or 6th one, respectively,
+/// and `%5` at every 5th hit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HitCondition {
+    Equal(u64),
+    AtLeast(u64),
+    Greater(u64),
+    Multiple(u64),
+}
+
+impl HitCondition {
+    /// Checks the condition, given the number of hits so far (including the
+    /// current one).
+    pub fn matches(&self, hit_count: u64) -> bool {
+        match *self {
+            HitCondition::Equal(n) => hit_count == n,
+            HitCondition::AtLeast(n) => hit_count >= n,
+            HitCondition::Greater(n) => hit_count > n,
+            HitCondition::Multiple(n) => hit_count.checked_rem(n) == Some(0),
+        }
+    }
+}
+
+impl FromStr for HitCondition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (operator, number) = s.split_at(
+            s.find(|c: char| c.is_ascii_alphanumeric() || c == '$')
+                .unwrap_or(s.len()),
+        );
+        let number = parse_number(number.trim_end())?;
+        Ok(match operator.trim() {
+            "" | "==" => HitCondition::Equal(number),
+            ">=" => HitCondition::AtLeast(number),
+            ">" => HitCondition::Greater(number),
+            "%" if number == 0 => return Err("Hit count multiple can't be zero".to_string()),
+            "%" => HitCondition::Multiple(number),
+            _ => return Err(format!("Invalid hit condition: {:?}", s)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ya6502::cpu::MockMachine;
+    use ya6502::cpu::MockMachineInspector;
+    use ya6502::memory::WriteError;
+
+    fn evaluate(source: &str) -> Result<i64, String> {
+        let mut inspector = MockMachineInspector::new();
+        inspector.expect_reg_a().return_const(0x12);
+        inspector.expect_reg_x().return_const(0x10);
+        inspector.expect_reg_y().return_const(0xFF);
+        inspector.expect_reg_sp().return_const(0xFD);
+        inspector.expect_reg_pc().return_const(0xF123u16);
+        inspector.expect_flags().return_const(flags::N | flags::C);
+        inspector
+            .expect_inspect_memory()
+            .returning(|address| (address & 0xFF) as u8 ^ 0x01);
+        source.parse::<Expression>()?.evaluate(&inspector)
+    }
+
+    #[test]
+    fn registers_flags_and_memory() {
+        assert_eq!(evaluate("A"), Ok(0x12));
+        assert_eq!(evaluate("x"), Ok(0x10));
+        assert_eq!(evaluate("Y + 1"), Ok(0x100));
+        assert_eq!(evaluate("SP"), Ok(0xFD));
+        assert_eq!(evaluate("pc"), Ok(0xF123));
+        assert_eq!(evaluate("P"), Ok(0x81));
+        assert_eq!(evaluate("N + C * 2 + Z * 4"), Ok(3));
+        assert_eq!(evaluate("[$80]"), Ok(0x81));
+        assert_eq!(evaluate("[X + 0x1000]"), Ok(0x11));
+        assert_eq!(evaluate("{$80}"), Ok(0x8081));
+        assert_eq!(evaluate("{$FFFF}"), Ok(0x01FE));
+        assert_eq!(evaluate("[{$80} - $8000] + 1"), Ok(0x81));
+        assert!(evaluate("[-1]").is_err());
+        assert!(evaluate("{$10000}").is_err());
+    }
+
+    #[test]
+    fn symbols() {
+        let mut symbols = Symbols::new();
+        symbols.insert("start", 0xF000);
+        symbols.insert("Counter", 0x0080);
+        symbols.insert("x", 0x1234);
+        let parse = |source| Expression::parse(source, &symbols);
+        assert_eq!(parse("start"), Ok(Expression::Number(0xF000)));
+        assert_eq!(
+            parse("[Counter]"),
+            Ok(Expression::Memory(Box::new(Expression::Number(0x80))))
+        );
+        assert_eq!(parse("x"), Ok(Expression::Register(Register::X)));
+        assert!(parse("counter").is_err());
+        assert!(parse("end").is_err());
+
+        symbols.insert("Kernel::Loop", 0xF010);
+        symbols.insert("Kernel::Loop@wait", 0xF012);
+        let parse = |source| Expression::parse(source, &symbols);
+        assert_eq!(
+            parse("Kernel::Loop@wait - Kernel::Loop"),
+            Ok(Expression::Binary(
+                "-",
+                Box::new(Expression::Number(0xF012)),
+                Box::new(Expression::Number(0xF010))
+            ))
+        );
+        assert!(parse("Kernel:Loop").is_err());
+        assert!(parse("Loop").is_err());
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(evaluate("1 + 2 * 3"), Ok(7));
+        assert_eq!(evaluate("(1 + 2) * 3"), Ok(9));
+        assert_eq!(evaluate("10 - 2 - 3"), Ok(5));
+        assert_eq!(evaluate("7 % 4 + %101"), Ok(8));
+        assert_eq!(evaluate("1 << 4 | 1"), Ok(0x11));
+        assert_eq!(evaluate("$F0 & ~$30 ^ 1"), Ok(0xC1));
+        assert_eq!(evaluate("-X"), Ok(-0x10));
+        assert_eq!(evaluate("!0 + !5"), Ok(1));
+        assert_eq!(evaluate("X == $10 && [$80] > 3"), Ok(1));
+        assert_eq!(evaluate("X != $10 || A <= 1"), Ok(0));
+        assert_eq!(evaluate("1 < 2 == 1"), Ok(1));
+        assert_eq!(evaluate("A >= $12 && A < $13"), Ok(1));
+    }
+
+    #[test]
+    fn short_circuits() {
+        assert_eq!(evaluate("0 && 1 / 0"), Ok(0));
+        assert_eq!(evaluate("1 || 1 / 0"), Ok(1));
+        assert!(evaluate("1 && 1 / 0").is_err());
+        assert!(evaluate("A % 0").is_err());
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert!("".parse::<Expression>().is_err());
+        assert!("1 +".parse::<Expression>().is_err());
+        assert!("(1".parse::<Expression>().is_err());
+        assert!("[1)".parse::<Expression>().is_err());
+        assert!("{1]".parse::<Expression>().is_err());
+        assert!("1 2".parse::<Expression>().is_err());
+        assert!("Q".parse::<Expression>().is_err());
+        assert!("%102".parse::<Expression>().is_err());
+        assert!("X = 1".parse::<Expression>().is_err());
+        assert!("$XY".parse::<Expression>().is_err());
+    }
+
+    #[test]
+    fn assignments() {
+        let assign = |target: &str, value: i64, machine: &mut MockMachine| {
+            target.parse::<Expression>()?.assign(machine, value)
+        };
+        let mut machine = MockMachine::new();
+        machine
+            .expect_set_reg_a()
+            .withf(|value| *value == 0x12)
+            .return_const(());
+        machine
+            .expect_set_reg_pc()
+            .withf(|value| *value == 0xF123)
+            .return_const(());
+        machine.expect_reg_x().return_const(0x10);
+        machine.expect_flags().return_const(flags::N | flags::C);
+        machine
+            .expect_set_flags()
+            .withf(|value| *value == flags::N | flags::C | flags::Z)
+            .return_const(());
+        machine
+            .expect_set_flags()
+            .withf(|value| *value == flags::N)
+            .return_const(());
+        machine
+            .expect_poke_memory()
+            .withf(|address, value| (*address, *value) == (0x0090, 0xAB))
+            .returning(|_, _| Ok(()));
+        machine
+            .expect_poke_memory()
+            .withf(|address, value| (*address, *value) == (0xFFFC, 0x34))
+            .returning(|_, _| Ok(()));
+        machine
+            .expect_poke_memory()
+            .withf(|address, value| (*address, *value) == (0xFFFD, 0x12))
+            .returning(|_, _| Ok(()));
+        machine
+            .expect_poke_memory()
+            .withf(|address, _| *address == 0xD000)
+            .returning(|address, value| Err(WriteError { address, value }));
+
+        assert_eq!(assign("A", 0x12, &mut machine), Ok(()));
+        assert_eq!(assign("pc", 0xF123, &mut machine), Ok(()));
+        assert_eq!(assign("Z", 1, &mut machine), Ok(()));
+        assert_eq!(assign("C", 0, &mut machine), Ok(()));
+        assert_eq!(assign("[$80 + X]", 0xAB, &mut machine), Ok(()));
+        assert_eq!(assign("{$FFFC}", 0x1234, &mut machine), Ok(()));
+        assert_eq!(
+            assign("[$D000]", 1, &mut machine),
+            Err("Unable to write $01 to address $D000".to_string())
+        );
+        assert_eq!(
+            assign("X", 0x100, &mut machine),
+            Err("Value out of range: 256".to_string())
+        );
+        assert!(assign("PC", -1, &mut machine).is_err());
+        assert!(assign("{$FFFC}", 0x10000, &mut machine).is_err());
+        assert!(assign("C", 2, &mut machine).is_err());
+        assert!(assign("X + 1", 1, &mut machine).is_err());
+    }
+
+    #[test]
+    fn hit_conditions() {
+        assert_eq!("5".parse(), Ok(HitCondition::Equal(5)));
+        assert_eq!("== 5".parse(), Ok(HitCondition::Equal(5)));
+        assert_eq!(">=$10".parse(), Ok(HitCondition::AtLeast(16)));
+        assert_eq!("> 5".parse(), Ok(HitCondition::Greater(5)));
+        assert_eq!("%3".parse(), Ok(HitCondition::Multiple(3)));
+        assert!("%0".parse::<HitCondition>().is_err());
+        assert!("<5".parse::<HitCondition>().is_err());
+        assert!("".parse::<HitCondition>().is_err());
+
+        let hits = |condition: HitCondition| {
+            (1..=10)
+                .filter(|count| condition.matches(*count))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(hits(HitCondition::Equal(5)), [5]);
+        assert_eq!(hits(HitCondition::AtLeast(8)), [8, 9, 10]);
+        assert_eq!(hits(HitCondition::Greater(8)), [9, 10]);
+        assert_eq!(hits(HitCondition::Multiple(3)), [3, 6, 9]);
+    }
+}
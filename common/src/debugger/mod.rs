@@ -1,20 +1,31 @@
 pub mod adapter;
 pub mod dap_types;
 
-mod core;
-mod disasm;
+pub(crate) mod core;
+pub(crate) mod disasm;
+mod expression;
 mod protocol;
+pub mod symbols;
 mod tests;
 
+use crate::app::parse_address;
 use crate::debugger::adapter::DebugAdapter;
 use crate::debugger::adapter::DebugAdapterError;
 use crate::debugger::adapter::DebugAdapterResult;
+use crate::debugger::core::DataBreakpoint;
+use crate::debugger::core::DataBreakpointAccessType;
+use crate::debugger::core::DataBreakpointHit;
 use crate::debugger::core::DebuggerCore;
+use crate::debugger::core::InstructionBreakpoint;
 use crate::debugger::core::StopReason;
 use crate::debugger::dap_types::Breakpoint;
 use crate::debugger::dap_types::Capabilities;
+use crate::debugger::dap_types::DataBreakpointInfoArguments;
+use crate::debugger::dap_types::DataBreakpointInfoResponse;
 use crate::debugger::dap_types::DisassembleArguments;
 use crate::debugger::dap_types::DisassembleResponse;
+use crate::debugger::dap_types::EvaluateArguments;
+use crate::debugger::dap_types::EvaluateResponse;
 use crate::debugger::dap_types::Event;
 use crate::debugger::dap_types::InitializeArguments;
 use crate::debugger::dap_types::Message;
@@ -28,8 +39,16 @@ use crate::debugger::dap_types::Scope;
 use crate::debugger::dap_types::ScopePresentationHint;
 use crate::debugger::dap_types::ScopesArguments;
 use crate::debugger::dap_types::ScopesResponse;
+use crate::debugger::dap_types::SetDataBreakpointsArguments;
+use crate::debugger::dap_types::SetDataBreakpointsResponse;
+use crate::debugger::dap_types::SetExpressionArguments;
+use crate::debugger::dap_types::SetExpressionResponse;
+use crate::debugger::dap_types::SetFunctionBreakpointsArguments;
+use crate::debugger::dap_types::SetFunctionBreakpointsResponse;
 use crate::debugger::dap_types::SetInstructionBreakpointsArguments;
 use crate::debugger::dap_types::SetInstructionBreakpointsResponse;
+use crate::debugger::dap_types::SetVariableArguments;
+use crate::debugger::dap_types::SetVariableResponse;
 use crate::debugger::dap_types::StackFrame;
 use crate::debugger::dap_types::StackTraceResponse;
 use crate::debugger::dap_types::StoppedEvent;
@@ -38,13 +57,27 @@ use crate::debugger::dap_types::ThreadsResponse;
 use crate::debugger::dap_types::Variable;
 use crate::debugger::dap_types::VariablesArguments;
 use crate::debugger::dap_types::VariablesResponse;
+use crate::debugger::dap_types::WriteMemoryArguments;
+use crate::debugger::dap_types::WriteMemoryResponse;
 use crate::debugger::disasm::disassemble;
 use crate::debugger::disasm::seek_instruction;
+use crate::debugger::expression::Expression;
+use crate::debugger::expression::HitCondition;
+use crate::debugger::expression::Register;
+use crate::debugger::symbols::Symbols;
+use crate::trace::AddressRange;
+use std::cell::RefCell;
 use std::cmp::max;
 use std::cmp::min;
+use std::rc::Rc;
 use std::sync::mpsc::TryRecvError;
+use ya6502::cpu::bus::BusAccess;
+use ya6502::cpu::bus::BusObserver;
+use ya6502::cpu::bus::BusOperation;
+use ya6502::cpu::flags;
 use ya6502::cpu::flags::flags_to_string;
 use ya6502::cpu::flags::FlagRepresentation;
+use ya6502::cpu::MachineEditor;
 use ya6502::cpu::MachineInspector;
 
 /// Default margin for disassembling code. Whenever a disassembly request comes
@@ -56,6 +89,18 @@ const DISASSEMBLY_MARGIN: usize = 20;
 
 const REGISTERS_VARIABLES_REFERENCE: i64 = 1;
 const MEMORY_VARIABLES_REFERENCE: i64 = 2;
+const FLAGS_VARIABLES_REFERENCE: i64 = 3;
+
+/// Flags that are displayed as separate variables. The B flag is not here,
+/// since it doesn't really exist in the flag register.
+const FLAG_VARIABLES: [(&str, u8); 6] = [
+    ("N", flags::N),
+    ("V", flags::V),
+    ("D", flags::D),
+    ("I", flags::I),
+    ("Z", flags::Z),
+    ("C", flags::C),
+];
 
 /// A debugger for 6502-based machines. Uses Debug Adapter Protocol internally
 /// to communicate with a debugger UI.
@@ -63,6 +108,11 @@ pub struct Debugger<A: DebugAdapter> {
     adapter: A,
     sequence_number: i64,
     core: DebuggerCore,
+    /// Bus accesses collected by the observer returned by
+    /// [`Debugger::bus_observer`] since the last update.
+    bus_accesses: Rc<RefCell<Vec<BusAccess>>>,
+    /// Symbols that can be used in expressions.
+    symbols: Symbols,
 }
 
 type RequestOutcome<A> = (
@@ -70,44 +120,90 @@ type RequestOutcome<A> = (
     Option<Box<dyn FnOnce(&mut Debugger<A>) -> DebugAdapterResult<()>>>,
 );
 
+/// Describes a request that failed: a response without a body and an error
+/// message.
+type RequestError = (Response, String);
+
 impl<A: DebugAdapter> Debugger<A> {
     pub fn new(adapter: A) -> Self {
         Self {
             adapter,
             sequence_number: 0,
             core: DebuggerCore::new(),
+            bus_accesses: Rc::new(RefCell::new(vec![])),
+            symbols: Symbols::new(),
         }
     }
 
+    /// Creates an observer that needs to be installed on the CPU bus in order
+    /// for the data breakpoints to work.
+    pub fn bus_observer(&self) -> Box<dyn BusObserver> {
+        let bus_accesses = self.bus_accesses.clone();
+        Box::new(move |access: &BusAccess| bus_accesses.borrow_mut().push(*access))
+    }
+
+    /// Replaces the symbols used for naming stack frames, disassembling, and
+    /// evaluating expressions.
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
     pub fn stopped(&self) -> bool {
         self.core.stopped()
     }
 
     pub fn update(&mut self, inspector: &impl MachineInspector) -> DebugAdapterResult<()> {
+        for access in self.bus_accesses.borrow_mut().drain(..) {
+            self.core.observe_bus_access(&access);
+        }
         self.core.update(inspector);
         if let Some(reason) = self.core.last_stop_reason() {
+            let description = match reason {
+                StopReason::DataBreakpoint => self
+                    .core
+                    .data_breakpoint_hit()
+                    .map(data_breakpoint_description),
+                _ => None,
+            };
             self.send_event(Event::Stopped(StoppedEvent {
                 thread_id: 1,
                 reason,
+                description,
                 all_threads_stopped: true,
             }))?;
         }
         Ok(())
     }
 
-    pub fn process_messages(&mut self, inspector: &impl MachineInspector) {
+    /// Stops the execution and notifies the client about an error reported by
+    /// the machine.
+    pub fn stop_on_exception(&mut self, description: String) -> DebugAdapterResult<()> {
+        self.core.stop_on_exception();
+        self.send_event(Event::Stopped(StoppedEvent {
+            thread_id: 1,
+            reason: StopReason::Exception,
+            description: Some(description),
+            all_threads_stopped: true,
+        }))
+    }
+
+    pub fn process_messages(&mut self, machine: &mut (impl MachineInspector + MachineEditor)) {
         loop {
             match self.adapter.try_receive_message() {
-                Ok(envelope) => self.process_message(envelope, inspector),
+                Ok(envelope) => self.process_message(envelope, machine),
                 Err(DebugAdapterError::TryRecvError(TryRecvError::Empty)) => return,
                 Err(e) => panic!("{}", e),
             }
         }
     }
 
-    fn process_message(&mut self, envelope: MessageEnvelope, inspector: &impl MachineInspector) {
+    fn process_message(
+        &mut self,
+        envelope: MessageEnvelope,
+        machine: &mut (impl MachineInspector + MachineEditor),
+    ) {
         match envelope.message {
-            Message::Request(request) => self.process_request(envelope.seq, request, inspector),
+            Message::Request(request) => self.process_request(envelope.seq, request, machine),
             other => eprintln!("Unsupported message: {:?}", other),
         };
     }
@@ -116,31 +212,43 @@ impl<A: DebugAdapter> Debugger<A> {
         &mut self,
         request_seq: i64,
         request: Request,
-        inspector: &impl MachineInspector,
+        machine: &mut (impl MachineInspector + MachineEditor),
     ) {
-        let (response, continuation) = match request {
-            Request::Initialize(args) => self.initialize(args),
-            Request::SetExceptionBreakpoints {} => self.set_exception_breakpoints(),
-            Request::SetInstructionBreakpoints(args) => self.set_instruction_breakpoints(args),
-            Request::Attach {} => self.attach(),
-            Request::Threads => self.threads(),
-            Request::StackTrace {} => self.stack_trace(inspector),
-            Request::Scopes(args) => self.scopes(args),
-            Request::Variables(args) => self.variables(inspector, args),
-            Request::Disassemble(args) => self.disassemble(inspector, args),
-            Request::ReadMemory(args) => self.read_memory(inspector, args),
-
-            Request::Continue {} => self.resume(),
-            Request::Pause {} => self.pause(),
-            Request::Next {} => self.next(inspector),
-            Request::StepIn {} => self.step_in(),
-            Request::StepOut {} => self.step_out(),
-
-            Request::Disconnect(_) => self.disconnect(),
+        let outcome = match request {
+            Request::Initialize(args) => Ok(self.initialize(args)),
+            Request::SetExceptionBreakpoints {} => Ok(self.set_exception_breakpoints()),
+            Request::SetInstructionBreakpoints(args) => Ok(self.set_instruction_breakpoints(args)),
+            Request::SetFunctionBreakpoints(args) => Ok(self.set_function_breakpoints(args)),
+            Request::DataBreakpointInfo(args) => Ok(self.data_breakpoint_info(args)),
+            Request::SetDataBreakpoints(args) => Ok(self.set_data_breakpoints(args)),
+            Request::Attach {} => Ok(self.attach()),
+            Request::Threads => Ok(self.threads()),
+            Request::StackTrace {} => Ok(self.stack_trace(machine)),
+            Request::Scopes(args) => Ok(self.scopes(args)),
+            Request::Variables(args) => Ok(self.variables(machine, args)),
+            Request::Disassemble(args) => self.disassemble(machine, args),
+            Request::ReadMemory(args) => self.read_memory(machine, args),
+            Request::WriteMemory(args) => self.write_memory(machine, args),
+            Request::SetVariable(args) => self.set_variable(machine, args),
+            Request::SetExpression(args) => self.set_expression(machine, args),
+            Request::Evaluate(args) => self.evaluate(machine, args),
+
+            Request::Continue {} => Ok(self.resume()),
+            Request::Pause {} => Ok(self.pause()),
+            Request::Next {} => Ok(self.next(machine)),
+            Request::StepIn {} => Ok(self.step_in()),
+            Request::StepOut {} => Ok(self.step_out()),
+
+            Request::Disconnect(_) => Ok(self.disconnect()),
+        };
+        let (response, message, continuation) = match outcome {
+            Ok((response, continuation)) => (response, None, continuation),
+            Err((response, message)) => (response, Some(message), None),
         };
         self.send_message(Message::Response(ResponseEnvelope {
             request_seq,
-            success: true,
+            success: message.is_none(),
+            message,
             response,
         }))
         .unwrap();
@@ -160,9 +268,18 @@ impl<A: DebugAdapter> Debugger<A> {
         );
         (
             Response::Initialize(Capabilities {
+                supports_conditional_breakpoints: true,
+                supports_hit_conditional_breakpoints: true,
+                supports_data_breakpoints: true,
+                supports_data_breakpoint_bytes: true,
+                supports_function_breakpoints: true,
                 supports_disassemble_request: true,
                 supports_instruction_breakpoints: true,
                 supports_read_memory_request: true,
+                supports_write_memory_request: true,
+                supports_set_variable: true,
+                supports_set_expression: true,
+                supports_evaluate_for_hovers: true,
             }),
             Some(Box::new(|me| me.send_event(Event::Initialized))),
         )
@@ -176,25 +293,134 @@ impl<A: DebugAdapter> Debugger<A> {
         &mut self,
         args: SetInstructionBreakpointsArguments,
     ) -> RequestOutcome<A> {
-        let addresses_iter = args.breakpoints.iter().map(|breakpoint| {
-            (i64::from_str_radix(
-                breakpoint.instruction_reference.strip_prefix("0x").unwrap(),
-                16,
-            )
-            .unwrap()
-                + breakpoint.offset.unwrap_or(0)) as u16
-        });
-        self.core
-            .set_instruction_breakpoints(addresses_iter.clone().collect());
+        let mut core_breakpoints = vec![];
+        let breakpoints = args
+            .breakpoints
+            .iter()
+            .map(|breakpoint| {
+                let address = (i64::from_str_radix(
+                    breakpoint.instruction_reference.strip_prefix("0x").unwrap(),
+                    16,
+                )
+                .unwrap()
+                    + breakpoint.offset.unwrap_or(0)) as u16;
+                let message = match instruction_breakpoint(
+                    address,
+                    &self.symbols,
+                    breakpoint.condition.as_deref(),
+                    breakpoint.hit_condition.as_deref(),
+                ) {
+                    Ok(core_breakpoint) => {
+                        core_breakpoints.push(core_breakpoint);
+                        None
+                    }
+                    Err(message) => Some(message),
+                };
+                Breakpoint {
+                    verified: message.is_none(),
+                    message,
+                    instruction_reference: Some(format!("0x{:04X}", address)),
+                }
+            })
+            .collect();
+        self.core.set_instruction_breakpoints(core_breakpoints);
         (
-            Response::SetInstructionBreakpoints(SetInstructionBreakpointsResponse {
-                breakpoints: addresses_iter
-                    .map(|address| Breakpoint {
-                        verified: true,
-                        instruction_reference: format!("0x{:04X}", address),
-                    })
-                    .collect(),
-            }),
+            Response::SetInstructionBreakpoints(SetInstructionBreakpointsResponse { breakpoints }),
+            None,
+        )
+    }
+
+    fn set_function_breakpoints(
+        &mut self,
+        args: SetFunctionBreakpointsArguments,
+    ) -> RequestOutcome<A> {
+        let mut core_breakpoints = vec![];
+        let breakpoints = args
+            .breakpoints
+            .iter()
+            .map(|breakpoint| {
+                let address = self.symbols.address(&breakpoint.name);
+                let result = match address {
+                    Some(address) => instruction_breakpoint(
+                        address,
+                        &self.symbols,
+                        breakpoint.condition.as_deref(),
+                        breakpoint.hit_condition.as_deref(),
+                    ),
+                    None => Err(format!("Unknown symbol: {}", breakpoint.name)),
+                };
+                let message = match result {
+                    Ok(core_breakpoint) => {
+                        core_breakpoints.push(core_breakpoint);
+                        None
+                    }
+                    Err(message) => Some(message),
+                };
+                Breakpoint {
+                    verified: message.is_none(),
+                    message,
+                    instruction_reference: address.map(|address| format!("0x{:04X}", address)),
+                }
+            })
+            .collect();
+        self.core.set_function_breakpoints(core_breakpoints);
+        (
+            Response::SetFunctionBreakpoints(SetFunctionBreakpointsResponse { breakpoints }),
+            None,
+        )
+    }
+
+    fn data_breakpoint_info(&self, args: DataBreakpointInfoArguments) -> RequestOutcome<A> {
+        let response = match data_address_range(&args) {
+            Ok(range) => DataBreakpointInfoResponse {
+                data_id: Some(format_address_range(range)),
+                description: format_address_range(range),
+                access_types: Some(vec![
+                    DataBreakpointAccessType::Read,
+                    DataBreakpointAccessType::Write,
+                    DataBreakpointAccessType::ReadWrite,
+                ]),
+            },
+            Err(message) => DataBreakpointInfoResponse {
+                data_id: None,
+                description: message,
+                access_types: None,
+            },
+        };
+        (Response::DataBreakpointInfo(response), None)
+    }
+
+    fn set_data_breakpoints(&mut self, args: SetDataBreakpointsArguments) -> RequestOutcome<A> {
+        let mut core_breakpoints = vec![];
+        let breakpoints = args
+            .breakpoints
+            .iter()
+            .map(|breakpoint| {
+                let message = match breakpoint.data_id.parse::<AddressRange>() {
+                    Ok(range) => {
+                        // The access type is optional in the protocol; if it's
+                        // missing, we assume that the client is interested in
+                        // changing the value.
+                        core_breakpoints.push(DataBreakpoint::new(
+                            range,
+                            breakpoint
+                                .access_type
+                                .unwrap_or(DataBreakpointAccessType::Write),
+                        ));
+                        None
+                    }
+                    Err(message) => Some(message),
+                };
+                Breakpoint {
+                    verified: message.is_none(),
+                    message,
+                    instruction_reference: None,
+                }
+            })
+            .collect();
+        self.core.set_data_breakpoints(core_breakpoints);
+        (
+            Response::SetDataBreakpoints(SetDataBreakpointsResponse { breakpoints }),
             None,
         )
     }
@@ -205,6 +431,7 @@ impl<A: DebugAdapter> Debugger<A> {
             Some(Box::new(|me| {
                 me.send_event(Event::Stopped(StoppedEvent {
                     reason: StopReason::Entry,
+                    description: None,
                     thread_id: 1,
                     all_threads_stopped: true,
                 }))
@@ -232,7 +459,10 @@ impl<A: DebugAdapter> Debugger<A> {
             .enumerate()
             .map(|(i, frame)| StackFrame {
                 id: (num_frames - i) as i64,
-                name: format_word(frame.entry),
+                name: match self.symbols.label(frame.entry) {
+                    Some(label) => label.to_string(),
+                    None => format_word(frame.entry),
+                },
                 instruction_pointer_reference: format!("0x{:04X}", frame.pc),
                 line: 0,
                 column: 0,
@@ -287,10 +517,19 @@ impl<A: DebugAdapter> Debugger<A> {
                 Variable {
                     name: "FLAGS".to_string(),
                     value: flags_to_string(inspector.flags(), FlagRepresentation::Letters),
-                    variables_reference: 0,
+                    variables_reference: FLAGS_VARIABLES_REFERENCE,
                     memory_reference: None,
                 },
             ],
+            FLAGS_VARIABLES_REFERENCE => FLAG_VARIABLES
+                .iter()
+                .map(|(name, mask)| Variable {
+                    name: name.to_string(),
+                    value: format_flag(inspector.flags() & mask),
+                    variables_reference: 0,
+                    memory_reference: None,
+                })
+                .collect(),
             MEMORY_VARIABLES_REFERENCE => vec![Variable {
                 name: "Memory".to_string(),
                 value: "$0000".to_string(),
@@ -309,10 +548,9 @@ impl<A: DebugAdapter> Debugger<A> {
         &self,
         inspector: &impl MachineInspector,
         args: DisassembleArguments,
-    ) -> RequestOutcome<A> {
-        let mem_reference =
-            i64::from_str_radix(&args.memory_reference.strip_prefix("0x").unwrap(), 16).unwrap();
-        let origin = (mem_reference + args.offset.unwrap_or(0)) as u16;
+    ) -> Result<RequestOutcome<A>, RequestError> {
+        let origin = memory_address(&args.memory_reference, args.offset)
+            .map_err(|message| (Response::Disassemble(None), message))? as u16;
         let disassembly_start = seek_instruction(
             inspector,
             origin,
@@ -320,39 +558,135 @@ impl<A: DebugAdapter> Debugger<A> {
         );
         let instructions = disassemble(
             inspector,
+            &self.symbols,
             origin,
             disassembly_start,
             DISASSEMBLY_MARGIN,
             usize::try_from(args.instruction_count).unwrap(),
         );
-        (
-            Response::Disassemble(DisassembleResponse { instructions }),
+        Ok((
+            Response::Disassemble(Some(DisassembleResponse { instructions })),
             None,
-        )
+        ))
     }
 
     fn read_memory(
         &self,
         inspector: &impl MachineInspector,
         args: ReadMemoryArguments,
-    ) -> RequestOutcome<A> {
-        let start_address =
-            i64::from_str_radix(&args.memory_reference.strip_prefix("0x").unwrap(), 16).unwrap()
-                + args.offset.unwrap_or(0);
+    ) -> Result<RequestOutcome<A>, RequestError> {
+        let start_address = memory_address(&args.memory_reference, args.offset)
+            .map_err(|message| (Response::ReadMemory(None), message))?;
         let requested_end_address = start_address + args.count;
         let end_address = min(requested_end_address, 0x10000);
         let mem_dump: Vec<u8> = (start_address..end_address)
             .map(|a| inspector.inspect_memory(a as u16))
             .collect();
         let data = base64::encode(mem_dump);
-        (
-            Response::ReadMemory(ReadMemoryResponse {
+        Ok((
+            Response::ReadMemory(Some(ReadMemoryResponse {
                 address: format!("0x{:04X}", start_address),
                 data,
                 unreadable_bytes: max(requested_end_address - 0x10000, 0),
-            }),
+            })),
             None,
-        )
+        ))
+    }
+
+    fn write_memory(
+        &self,
+        machine: &mut (impl MachineInspector + MachineEditor),
+        args: WriteMemoryArguments,
+    ) -> Result<RequestOutcome<A>, RequestError> {
+        let fail = |message| (Response::WriteMemory(None), message);
+        let start_address = memory_address(&args.memory_reference, args.offset).map_err(fail)?;
+        let data = base64::decode(&args.data).map_err(|e| fail(format!("Invalid data: {}", e)))?;
+        // Remember the original values, so that a failed request can leave
+        // the memory intact.
+        let mut original_values = vec![];
+        let mut error = None;
+        for (address, value) in (start_address..).zip(data) {
+            let address = match u16::try_from(address) {
+                Ok(address) => address,
+                Err(_) => {
+                    error = Some(format!("Address out of range: {}", address));
+                    break;
+                }
+            };
+            let original_value = machine.inspect_memory(address);
+            if let Err(e) = machine.poke_memory(address, value) {
+                error = Some(e.to_string());
+                break;
+            }
+            original_values.push((address, original_value));
+        }
+        if let (Some(message), false) = (error, args.allow_partial == Some(true)) {
+            for (address, value) in original_values.into_iter().rev() {
+                // These addresses have just been poked successfully, so
+                // restoring them is not going to fail.
+                let _ = machine.poke_memory(address, value);
+            }
+            return Err(fail(message));
+        }
+        Ok((
+            Response::WriteMemory(Some(WriteMemoryResponse {
+                bytes_written: original_values.len() as i64,
+            })),
+            None,
+        ))
+    }
+
+    fn set_variable(
+        &self,
+        machine: &mut (impl MachineInspector + MachineEditor),
+        args: SetVariableArguments,
+    ) -> Result<RequestOutcome<A>, RequestError> {
+        let location = match (args.variables_reference, args.name.as_str()) {
+            (REGISTERS_VARIABLES_REFERENCE, "FLAGS") => Ok(Expression::Register(Register::P)),
+            (REGISTERS_VARIABLES_REFERENCE | FLAGS_VARIABLES_REFERENCE, name) => name.parse(),
+            (_, name) => Err(format!("{} can't be changed", name)),
+        };
+        let value = location
+            .and_then(|location| assign(machine, &location, &args.value, &self.symbols))
+            .map_err(|message| (Response::SetVariable(None), message))?;
+        Ok((
+            Response::SetVariable(Some(SetVariableResponse { value })),
+            None,
+        ))
+    }
+
+    fn set_expression(
+        &self,
+        machine: &mut (impl MachineInspector + MachineEditor),
+        args: SetExpressionArguments,
+    ) -> Result<RequestOutcome<A>, RequestError> {
+        let value = Expression::parse(&args.expression, &self.symbols)
+            .and_then(|location| assign(machine, &location, &args.value, &self.symbols))
+            .map_err(|message| (Response::SetExpression(None), message))?;
+        Ok((
+            Response::SetExpression(Some(SetExpressionResponse { value })),
+            None,
+        ))
+    }
+
+    /// Evaluates an expression from the Watch panel, the debug console, or a
+    /// hover. Since we don't have any structured values, all contexts are
+    /// treated the same.
+    fn evaluate(
+        &self,
+        inspector: &impl MachineInspector,
+        args: EvaluateArguments,
+    ) -> Result<RequestOutcome<A>, RequestError> {
+        let value = Expression::parse(&args.expression, &self.symbols)
+            .and_then(|expression| expression.evaluate(inspector))
+            .map_err(|message| (Response::Evaluate(None), message))?;
+        Ok((
+            Response::Evaluate(Some(EvaluateResponse {
+                result: format_value(value),
+                variables_reference: 0,
+            })),
+            None,
+        ))
     }
 
     fn resume(&mut self) -> RequestOutcome<A> {
@@ -367,6 +701,7 @@ impl<A: DebugAdapter> Debugger<A> {
             Some(Box::new(|me| {
                 me.send_event(Event::Stopped(StoppedEvent {
                     reason: StopReason::Pause,
+                    description: None,
                     thread_id: 1,
                     all_threads_stopped: true,
                 }))
@@ -408,6 +743,106 @@ impl<A: DebugAdapter> Debugger<A> {
     }
 }
 
+/// Creates a core breakpoint out of the conditions received from the client.
+/// Empty conditions are ignored, since that's what VS Code sends after the user
+/// removes a condition.
+fn instruction_breakpoint(
+    address: u16,
+    symbols: &Symbols,
+    condition: Option<&str>,
+    hit_condition: Option<&str>,
+) -> Result<InstructionBreakpoint, String> {
+    let mut breakpoint = InstructionBreakpoint::new(address);
+    if let Some(condition) = condition.filter(|c| !c.trim().is_empty()) {
+        breakpoint = breakpoint.with_condition(Expression::parse(condition, symbols)?);
+    }
+    if let Some(hit_condition) = hit_condition.filter(|c| !c.trim().is_empty()) {
+        breakpoint = breakpoint.with_hit_condition(hit_condition.parse::<HitCondition>()?);
+    }
+    Ok(breakpoint)
+}
+
+/// Figures out which addresses should be watched by a data breakpoint. Since we
+/// only support watching the memory, the name needs to be either an address, an
+/// address range (`START-END`), or the memory variable, which points to the
+/// beginning of the address space. Unless a range is given, the number of
+/// watched bytes comes from [`DataBreakpointInfoArguments::bytes`].
+fn data_address_range(args: &DataBreakpointInfoArguments) -> Result<AddressRange, String> {
+    let name = args.name.trim();
+    let start = match args.variables_reference {
+        Some(REGISTERS_VARIABLES_REFERENCE | FLAGS_VARIABLES_REFERENCE) => {
+            return Err("Registers can't be watched".to_string());
+        }
+        Some(MEMORY_VARIABLES_REFERENCE) => 0x0000,
+        _ if args.as_address != Some(true) && name.contains('-') => {
+            let range = name.parse::<AddressRange>()?;
+            if range.start > range.end {
+                return Err(format!("Invalid address range: {}", name));
+            }
+            return Ok(range);
+        }
+        _ => parse_address(name)?,
+    };
+    let bytes = args.bytes.unwrap_or(1);
+    let end = bytes
+        .checked_add(i64::from(start) - 1)
+        .and_then(|end| u16::try_from(end).ok());
+    match end {
+        Some(end) if bytes > 0 => Ok(AddressRange { start, end }),
+        _ => Err(format!("Invalid number of bytes: {}", bytes)),
+    }
+}
+
+fn data_breakpoint_description(hit: DataBreakpointHit) -> String {
+    let (operation, preposition) = match hit.access.operation {
+        BusOperation::Read => ("Read", "from"),
+        BusOperation::Write => ("Write", "to"),
+    };
+    format!(
+        "{} of {} {} {} at {}",
+        operation,
+        format_byte(hit.access.value),
+        preposition,
+        format_word(hit.access.address),
+        format_word(hit.pc)
+    )
+}
+
+/// Evaluates a value and stores it in a given location. Returns the new value
+/// of the location, formatted the same way as in the variables view.
+fn assign(
+    machine: &mut (impl MachineInspector + MachineEditor),
+    location: &Expression,
+    value: &str,
+    symbols: &Symbols,
+) -> Result<String, String> {
+    let value = Expression::parse(value, symbols)?.evaluate(machine)?;
+    location.assign(machine, value)?;
+    Ok(match location {
+        Expression::Register(Register::PC) => format_word(machine.reg_pc()),
+        Expression::Register(Register::P) => {
+            flags_to_string(machine.flags(), FlagRepresentation::Letters)
+        }
+        Expression::Flag(mask) => format_flag(machine.flags() & mask),
+        Expression::Word(_) => format_word(location.evaluate(machine)? as u16),
+        _ => format_byte(location.evaluate(machine)? as u8),
+    })
+}
+
+/// Parses a memory reference sent by the client, which is always one of the
+/// hexadecimal addresses that we sent before, and applies an offset to it.
+fn memory_address(memory_reference: &str, offset: Option<i64>) -> Result<i64, String> {
+    memory_reference
+        .strip_prefix("0x")
+        .and_then(|hex| i64::from_str_radix(hex, 16).ok())
+        .and_then(|address| address.checked_add(offset.unwrap_or(0)))
+        .ok_or_else(|| format!("Invalid memory reference: {}", memory_reference))
+}
+
+fn format_address_range(range: AddressRange) -> String {
+    format!("{}-{}", format_word(range.start), format_word(range.end))
+}
+
 fn format_byte(val: u8) -> String {
     format!("${:02X}", val)
 }
@@ -416,6 +851,20 @@ fn format_word(val: u16) -> String {
     format!("${:04X}", val)
 }
 
+/// Formats a result of an expression. Bytes and words are shown in
+/// hexadecimal, decimal, and binary; anything else only in decimal.
+fn format_value(val: i64) -> String {
+    match val {
+        0..=0xFF => format!("${:02X} ({}, %{:08b})", val, val, val),
+        0x100..=0xFFFF => format!("${:04X} ({}, %{:016b})", val, val, val),
+        _ => val.to_string(),
+    }
+}
+
+fn format_flag(val: u8) -> String {
+    if val != 0 { "1" } else { "0" }.to_string()
+}
+
 fn byte_variable(name: &str, value: u8) -> Variable {
     Variable {
         name: name.to_string(),
@@ -0,0 +1,320 @@
+//! Symbol tables that map names to addresses and back. Symbols are loaded from
+//! the debug information produced by the cc65 toolchain: ld65 debug info files
+//! (`--dbgfile`) and VICE label files (`-Ln`, also produced by
+//! `ya6502::asm`).
+
+use crate::app::parse_number;
+use std::collections::HashMap;
+use std::fs;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols {
+    addresses: HashMap<String, u16>,
+    /// Names used for given addresses in stack frames and disassembly. If
+    /// there's more than one symbol at a given address, the first one wins.
+    labels: HashMap<u16, String>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a symbol that names an address in stack frames and disassembly.
+    /// Existing definitions take precedence over the new one: if a name is
+    /// already defined, the new symbol is ignored.
+    pub fn insert(&mut self, name: &str, address: u16) {
+        if self.addresses.contains_key(name) {
+            return;
+        }
+        self.addresses.insert(name.to_string(), address);
+        self.labels
+            .entry(address)
+            .or_insert_with(|| name.to_string());
+    }
+
+    /// Adds a symbol that can only be used in expressions, like a constant
+    /// that isn't an address of anything. Just like with [`Symbols::insert`],
+    /// existing definitions take precedence.
+    pub fn insert_equate(&mut self, name: &str, value: u16) {
+        self.addresses.entry(name.to_string()).or_insert(value);
+    }
+
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    /// Loads symbols from a given file. ld65 debug info files are recognized
+    /// by their `version` header; everything else is treated as a VICE label
+    /// file.
+    pub fn load(&mut self, path: &str) -> Result<(), String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", path, e))?;
+        if contents.trim_start().starts_with("version") {
+            self.parse_debug_info(&contents)
+        } else {
+            self.parse_vice_labels(&contents)
+        }
+        .map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Parses an ld65 debug info file. Only the `scope` and `sym` lines are
+    /// taken into account. Symbols defined in named scopes are qualified with
+    /// the scope names (`foo::bar`), and cheap local symbols with the name of
+    /// the symbol that they belong to (`Start@loop`). Only labels are used to
+    /// name addresses; equates can only be used in expressions. Labels are
+    /// added before equates, so that they take precedence.
+    pub fn parse_debug_info(&mut self, contents: &str) -> Result<(), String> {
+        let mut scopes = HashMap::new();
+        let mut entries = HashMap::new();
+        let mut labels = vec![];
+        let mut equates = vec![];
+        for (line_number, line) in contents.lines().enumerate() {
+            let (kind, attributes) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            if kind != "scope" && kind != "sym" {
+                continue;
+            }
+            let attributes = parse_attributes(attributes.trim())
+                .map_err(|e| format!("line {}: {}", line_number + 1, e))?;
+            let (id, entry) = match (attributes.get("id"), attributes.get("name")) {
+                (Some(id), Some(name)) => (
+                    *id,
+                    DebugInfoEntry {
+                        name,
+                        scope: attributes.get("scope").copied(),
+                        parent: attributes.get("parent").copied(),
+                    },
+                ),
+                _ => return Err(format!("line {}: Missing id or name", line_number + 1)),
+            };
+            if kind == "scope" {
+                scopes.insert(id, entry);
+                continue;
+            }
+            entries.insert(id, entry);
+            let value = match attributes.get("val") {
+                Some(value) => value,
+                // Imports don't have values; they are defined in other modules.
+                None => continue,
+            };
+            let value =
+                parse_number(value).map_err(|e| format!("line {}: {}", line_number + 1, e))?;
+            let address = match u16::try_from(value) {
+                Ok(address) => address,
+                // Skip constants that can't be addresses.
+                Err(_) => continue,
+            };
+            if attributes.get("type") == Some(&"lab") || attributes.contains_key("seg") {
+                labels.push((id, address));
+            } else {
+                equates.push((id, address));
+            }
+        }
+
+        let qualified_name = |id| {
+            let entry: &DebugInfoEntry = &entries[id];
+            match entry.parent.and_then(|parent| entries.get(parent)) {
+                Some(parent) => format!(
+                    "{}{}{}",
+                    scope_prefix(&scopes, parent.scope),
+                    parent.name,
+                    entry.name
+                ),
+                None => format!("{}{}", scope_prefix(&scopes, entry.scope), entry.name),
+            }
+        };
+        for (id, address) in labels {
+            self.insert(&qualified_name(id), address);
+        }
+        for (id, value) in equates {
+            self.insert_equate(&qualified_name(id), value);
+        }
+        Ok(())
+    }
+
+    /// Parses a VICE label file, consisting of lines like `al C:F000 .start`.
+    /// Other VICE monitor commands are ignored.
+    pub fn parse_vice_labels(&mut self, contents: &str) -> Result<(), String> {
+        for (line_number, line) in contents.lines().enumerate() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.first() != Some(&"al") {
+                continue;
+            }
+            let error = || format!("line {}: Invalid label: {:?}", line_number + 1, line);
+            let (address, name) = match words[..] {
+                [_, address, name] => (address, name),
+                _ => return Err(error()),
+            };
+            let address = address.strip_prefix("C:").unwrap_or(address);
+            let address = u32::from_str_radix(address, 16)
+                .ok()
+                .and_then(|address| u16::try_from(address).ok())
+                .ok_or_else(error)?;
+            self.insert(name.strip_prefix('.').unwrap_or(name), address);
+        }
+        Ok(())
+    }
+}
+
+/// A scope or a symbol of an ld65 debug info file. The parent of a scope is
+/// its enclosing scope, and the parent of a cheap local symbol is the symbol
+/// that it belongs to.
+struct DebugInfoEntry<'a> {
+    name: &'a str,
+    scope: Option<&'a str>,
+    parent: Option<&'a str>,
+}
+
+/// Returns names of a given scope and its enclosing scopes, each of them
+/// followed by `::`. Unnamed scopes, like the top-level ones, are skipped.
+fn scope_prefix(scopes: &HashMap<&str, DebugInfoEntry>, id: Option<&str>) -> String {
+    let mut names = vec![];
+    let mut scope = id.and_then(|id| scopes.get(id));
+    // Limit the depth, in case the scopes form a cycle.
+    while let Some(entry) = scope.filter(|_| names.len() <= scopes.len()) {
+        names.push(entry.name);
+        scope = entry.parent.and_then(|id| scopes.get(id));
+    }
+    names
+        .iter()
+        .rev()
+        .filter(|name| !name.is_empty())
+        .map(|name| format!("{}::", name))
+        .collect()
+}
+
+/// Parses a comma-separated list of `key=value` attributes of an ld65 debug
+/// info line. Values may be quoted.
+fn parse_attributes(s: &str) -> Result<HashMap<&str, &str>, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = s;
+    while !rest.is_empty() {
+        let (key, value) = rest
+            .split_once('=')
+            .ok_or_else(|| format!("Expected KEY=VALUE, got {:?}", rest))?;
+        let (value, tail) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted
+                    .find('"')
+                    .ok_or_else(|| format!("Unterminated string: {:?}", value))?;
+                (&quoted[..end], &quoted[end + 1..])
+            }
+            None => value.split_at(value.find(',').unwrap_or(value.len())),
+        };
+        attributes.insert(key, value);
+        rest = tail.strip_prefix(',').unwrap_or(tail);
+    }
+    Ok(attributes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups() {
+        let mut symbols = Symbols::new();
+        symbols.insert("start", 0xF000);
+        symbols.insert("reset", 0xF000);
+        symbols.insert("counter", 0x0080);
+        symbols.insert("counter", 0x0081);
+
+        assert_eq!(symbols.address("start"), Some(0xF000));
+        assert_eq!(symbols.address("reset"), Some(0xF000));
+        assert_eq!(symbols.address("counter"), Some(0x0080));
+        assert_eq!(symbols.address("Start"), None);
+        assert_eq!(symbols.label(0xF000), Some("start"));
+        assert_eq!(symbols.label(0x0080), Some("counter"));
+        assert_eq!(symbols.label(0x0081), None);
+    }
+
+    #[test]
+    fn debug_info() {
+        let mut symbols = Symbols::new();
+        symbols
+            .parse_debug_info(
+                "version\tmajor=2,minor=0\n\
+                 info\tcsym=0,file=1,lib=0,line=4,mod=1,scope=1,seg=2,span=3,sym=5,type=2\n\
+                 file\tid=0,name=\"kernel, v2.s\",size=512,mtime=0x6000CAFE,mod=0\n\
+                 seg\tid=0,name=\"CODE\",start=0x00F000,size=0x0010,addrsize=absolute,type=ro\n\
+                 sym\tid=0,name=\"ZERO\",addrsize=zeropage,scope=0,def=1,val=0x0,type=equ\n\
+                 sym\tid=1,name=\"VSYNC\",addrsize=zeropage,scope=0,def=2,val=0x0,type=lab\n\
+                 sym\tid=2,name=\"Start\",addrsize=absolute,size=2,scope=0,def=3,val=0xF000,seg=0,type=lab\n\
+                 sym\tid=3,name=\"BigConstant\",addrsize=far,scope=0,def=4,val=0x123456,type=equ\n\
+                 sym\tid=4,name=\"Imported\",addrsize=absolute,scope=0,ref=5,type=imp\n\
+                 sym\tid=5,name=\"LINES\",addrsize=absolute,scope=0,def=5,val=0xF004,type=equ\n",
+            )
+            .unwrap();
+
+        assert_eq!(symbols.address("Start"), Some(0xF000));
+        assert_eq!(symbols.address("ZERO"), Some(0x0000));
+        assert_eq!(symbols.address("LINES"), Some(0xF004));
+        assert_eq!(symbols.address("BigConstant"), None);
+        assert_eq!(symbols.address("Imported"), None);
+        assert_eq!(symbols.label(0xF000), Some("Start"));
+        assert_eq!(symbols.label(0x0000), Some("VSYNC"));
+        // Equates are not labels.
+        assert_eq!(symbols.label(0xF004), None);
+
+        assert!(Symbols::new()
+            .parse_debug_info("sym\tid=0,name=\"Start")
+            .is_err());
+        assert!(Symbols::new().parse_debug_info("sym\tid=0,val").is_err());
+        assert!(Symbols::new()
+            .parse_debug_info("sym\tname=\"Start\",val=0xXYZ")
+            .is_err());
+    }
+
+    #[test]
+    fn debug_info_scopes() {
+        let mut symbols = Symbols::new();
+        symbols
+            .parse_debug_info(
+                "scope\tid=0,name=\"\",mod=0,size=32\n\
+                 scope\tid=1,name=\"Kernel\",mod=0,type=scope,size=16,parent=0\n\
+                 scope\tid=2,name=\"Loop\",mod=0,type=scope,size=8,parent=1,sym=2\n\
+                 sym\tid=0,name=\"Start\",addrsize=absolute,scope=0,def=1,val=0xF000,seg=0,type=lab\n\
+                 sym\tid=1,name=\"@loop\",addrsize=absolute,parent=0,def=2,val=0xF002,seg=0,type=lab\n\
+                 sym\tid=2,name=\"Loop\",addrsize=absolute,scope=1,def=3,val=0xF010,seg=0,type=lab\n\
+                 sym\tid=3,name=\"Lines\",addrsize=zeropage,scope=2,def=4,val=0xC0,type=equ\n\
+                 sym\tid=4,name=\"@loop\",addrsize=absolute,parent=2,def=5,val=0xF012,seg=0,type=lab\n",
+            )
+            .unwrap();
+
+        assert_eq!(symbols.address("Start"), Some(0xF000));
+        assert_eq!(symbols.address("Start@loop"), Some(0xF002));
+        assert_eq!(symbols.address("Kernel::Loop"), Some(0xF010));
+        assert_eq!(symbols.address("Kernel::Loop::Lines"), Some(0xC0));
+        assert_eq!(symbols.address("Kernel::Loop@loop"), Some(0xF012));
+        assert_eq!(symbols.address("@loop"), None);
+        assert_eq!(symbols.address("Loop"), None);
+        assert_eq!(symbols.label(0xF002), Some("Start@loop"));
+        assert_eq!(symbols.label(0xF012), Some("Kernel::Loop@loop"));
+        assert_eq!(symbols.label(0x00C0), None);
+    }
+
+    #[test]
+    fn vice_labels() {
+        let mut symbols = Symbols::new();
+        symbols
+            .parse_vice_labels(
+                "al C:F000 .Start\n\
+                 al 000080 .counter\n\
+                 \n\
+                 break F000\n",
+            )
+            .unwrap();
+
+        assert_eq!(symbols.address("Start"), Some(0xF000));
+        assert_eq!(symbols.address("counter"), Some(0x0080));
+        assert_eq!(symbols.label(0x0080), Some("counter"));
+
+        assert!(Symbols::new().parse_vice_labels("al C:F000").is_err());
+        assert!(Symbols::new().parse_vice_labels("al C:XYZ .Start").is_err());
+        assert!(Symbols::new().parse_vice_labels("al 10000 .Start").is_err());
+    }
+}
@@ -3,7 +3,9 @@
 use super::*;
 use crate::debugger::adapter::FakeDebugAdapter;
 use crate::debugger::dap_types::Breakpoint;
+use crate::debugger::dap_types::DataBreakpoint;
 use crate::debugger::dap_types::DisassembledInstruction;
+use crate::debugger::dap_types::FunctionBreakpoint;
 use crate::debugger::dap_types::InitializeArguments;
 use crate::debugger::dap_types::InstructionBreakpoint;
 use crate::debugger::dap_types::MessageEnvelope;
@@ -11,9 +13,13 @@ use crate::debugger::dap_types::ScopesArguments;
 use crate::debugger::dap_types::SetInstructionBreakpointsArguments;
 use crate::debugger::dap_types::VariablesArguments;
 use std::assert_matches::assert_matches;
+use std::cell::RefCell;
+use std::rc::Rc;
 use ya6502::cpu::Cpu;
-use ya6502::cpu::MockMachineInspector;
+use ya6502::cpu::MockMachine;
 use ya6502::cpu_with_code;
+use ya6502::memory::Mapping;
+use ya6502::memory::MemoryMap;
 use ya6502::memory::Ram;
 use ya6502::test_utils::cpu_with_program;
 
@@ -32,6 +38,28 @@ fn assert_responded_with(adapter: &FakeDebugAdapter, expected_response: Response
     assert_eq!(response, expected_response);
 }
 
+fn assert_failed_with(
+    adapter: &FakeDebugAdapter,
+    expected_response: Response,
+    expected_message: &str,
+) {
+    assert_matches!(
+        adapter.pop_outgoing(),
+        Some(MessageEnvelope {
+            message: Message::Response(ResponseEnvelope {
+                success: false,
+                message: Some(message),
+                response,
+                ..
+            }),
+            ..
+        }) if response == expected_response && message == expected_message,
+        "Expected failure: {:?}, {:?}",
+        expected_response,
+        expected_message,
+    );
+}
+
 fn assert_emitted(adapter: &FakeDebugAdapter, expected_event: Event) {
     assert_matches!(
         adapter.pop_outgoing(),
@@ -64,7 +92,7 @@ fn tick_while_running<A: DebugAdapter>(debugger: &mut Debugger<A>, cpu: &mut Cpu
 fn get_stack_frames(
     adapter: &FakeDebugAdapter,
     debugger: &mut Debugger<FakeDebugAdapter>,
-    cpu: &Cpu<Ram>,
+    cpu: &mut Cpu<Ram>,
 ) -> Vec<StackFrame> {
     adapter.push_request(Request::StackTrace {});
     debugger.process_messages(cpu);
@@ -78,7 +106,7 @@ fn get_stack_frames(
 fn get_scopes(
     adapter: &FakeDebugAdapter,
     debugger: &mut Debugger<FakeDebugAdapter>,
-    cpu: &Cpu<Ram>,
+    cpu: &mut Cpu<Ram>,
     frame_id: i64,
 ) -> Vec<Scope> {
     adapter.push_request(Request::Scopes(ScopesArguments { frame_id }));
@@ -92,7 +120,7 @@ fn get_scopes(
 
 #[test]
 fn uses_sequence_numbers() {
-    let inspector = MockMachineInspector::new();
+    let mut machine = MockMachine::new();
     let adapter = FakeDebugAdapter::default();
     adapter.push_incoming(Ok(MessageEnvelope {
         seq: 5,
@@ -110,7 +138,7 @@ fn uses_sequence_numbers() {
     }));
     let mut debugger = Debugger::new(adapter.clone());
 
-    debugger.process_messages(&inspector);
+    debugger.process_messages(&mut machine);
 
     assert_matches!(
         adapter.pop_outgoing(),
@@ -140,7 +168,7 @@ fn uses_sequence_numbers() {
 
 #[test]
 fn initialization_sequence() {
-    let inspector = MockMachineInspector::new();
+    let mut machine = MockMachine::new();
     let adapter = FakeDebugAdapter::default();
     adapter.push_request(Request::Initialize(InitializeArguments {
         client_name: Some("Visual Studio Code".into()),
@@ -155,14 +183,23 @@ fn initialization_sequence() {
     adapter.push_request(Request::Threads {});
     let mut debugger = Debugger::new(adapter.clone());
 
-    debugger.process_messages(&inspector);
+    debugger.process_messages(&mut machine);
 
     assert_responded_with(
         &adapter,
         Response::Initialize(Capabilities {
+            supports_conditional_breakpoints: true,
+            supports_hit_conditional_breakpoints: true,
+            supports_data_breakpoints: true,
+            supports_data_breakpoint_bytes: true,
+            supports_function_breakpoints: true,
             supports_disassemble_request: true,
             supports_instruction_breakpoints: true,
             supports_read_memory_request: true,
+            supports_write_memory_request: true,
+            supports_set_variable: true,
+            supports_set_expression: true,
+            supports_evaluate_for_hovers: true,
         }),
     );
     assert_emitted(&adapter, Event::Initialized);
@@ -172,6 +209,7 @@ fn initialization_sequence() {
         Event::Stopped(StoppedEvent {
             thread_id: 1,
             reason: StopReason::Entry,
+            description: None,
             all_threads_stopped: true,
         }),
     );
@@ -209,7 +247,7 @@ fn stack_trace() {
     debugger.update(&cpu).unwrap();
 
     adapter.push_request(Request::StackTrace {});
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
     assert_responded_with(
         &adapter,
         Response::StackTrace(StackTraceResponse {
@@ -226,16 +264,16 @@ fn stack_trace() {
     assert_eq!(adapter.pop_outgoing(), None);
 
     adapter.push_request(Request::StepIn {});
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
     tick_while_running(&mut debugger, &mut cpu);
     adapter.push_request(Request::StepIn {});
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
     tick_while_running(&mut debugger, &mut cpu);
     purge_messages(&adapter);
     assert_eq!(cpu.reg_pc(), 0xF005);
 
     adapter.push_request(Request::StackTrace {});
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
     assert_responded_with(
         &adapter,
         Response::StackTrace(StackTraceResponse {
@@ -263,7 +301,7 @@ fn stack_trace() {
 
 #[test]
 fn disassembly() {
-    let cpu = cpu_with_code! {
+    let mut cpu = cpu_with_code! {
             lda 0x45
             sta 0xEA
     };
@@ -283,41 +321,44 @@ fn disassembly() {
         instruction_offset: None,
         instruction_count: 1,
     }));
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
 
     assert_responded_with(
         &adapter,
-        Response::Disassemble(DisassembleResponse {
+        Response::Disassemble(Some(DisassembleResponse {
             instructions: vec![
                 DisassembledInstruction {
                     address: "0xF000".to_string(),
                     instruction_bytes: "A5 45".to_string(),
                     instruction: "LDA $45".to_string(),
+                    symbol: None,
                 },
                 DisassembledInstruction {
                     address: "0xF002".to_string(),
                     instruction_bytes: "85 EA".to_string(),
                     instruction: "STA $EA".to_string(),
+                    symbol: None,
                 },
             ],
-        }),
+        })),
     );
     assert_responded_with(
         &adapter,
-        Response::Disassemble(DisassembleResponse {
+        Response::Disassemble(Some(DisassembleResponse {
             instructions: vec![DisassembledInstruction {
                 address: "0xF002".to_string(),
                 instruction_bytes: "85 EA".to_string(),
                 instruction: "STA $EA".to_string(),
+                symbol: None,
             }],
-        }),
+        })),
     );
     assert_eq!(adapter.pop_outgoing(), None);
 }
 
 #[test]
 fn disassembly_ambiguous() {
-    let cpu = cpu_with_code! {
+    let mut cpu = cpu_with_code! {
             lda 0x45
             sta 0xEA
             sta 0xAE
@@ -338,58 +379,64 @@ fn disassembly_ambiguous() {
         instruction_offset: Some(-1),
         instruction_count: 2,
     }));
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
 
     assert_responded_with(
         &adapter,
-        Response::Disassemble(DisassembleResponse {
+        Response::Disassemble(Some(DisassembleResponse {
             instructions: vec![
                 DisassembledInstruction {
                     address: "0xF000".to_string(),
                     instruction_bytes: "A5 45".to_string(),
                     instruction: "LDA $45".to_string(),
+                    symbol: None,
                 },
                 DisassembledInstruction {
                     address: "0xF002".to_string(),
                     instruction_bytes: "85".to_string(),
                     instruction: "".to_string(),
+                    symbol: None,
                 },
                 DisassembledInstruction {
                     address: "0xF003".to_string(),
                     instruction_bytes: "EA".to_string(),
                     instruction: "NOP".to_string(),
+                    symbol: None,
                 },
                 DisassembledInstruction {
                     address: "0xF004".to_string(),
                     instruction_bytes: "85 AE".to_string(),
                     instruction: "STA $AE".to_string(),
+                    symbol: None,
                 },
             ],
-        }),
+        })),
     );
     assert_responded_with(
         &adapter,
-        Response::Disassemble(DisassembleResponse {
+        Response::Disassemble(Some(DisassembleResponse {
             instructions: vec![
                 DisassembledInstruction {
                     address: "0xF002".to_string(),
                     instruction_bytes: "85 EA".to_string(),
                     instruction: "STA $EA".to_string(),
+                    symbol: None,
                 },
                 DisassembledInstruction {
                     address: "0xF004".to_string(),
                     instruction_bytes: "85 AE".to_string(),
                     instruction: "STA $AE".to_string(),
+                    symbol: None,
                 },
             ],
-        }),
+        })),
     );
     assert_eq!(adapter.pop_outgoing(), None);
 }
 
 #[test]
 fn read_memory() {
-    let cpu = cpu_with_program(&[0x8B, 0xAD, 0xF0, 0x0D]);
+    let mut cpu = cpu_with_program(&[0x8B, 0xAD, 0xF0, 0x0D]);
     let adapter = FakeDebugAdapter::default();
     let mut debugger = Debugger::new(adapter.clone());
     debugger.update(&cpu).unwrap();
@@ -404,30 +451,30 @@ fn read_memory() {
         offset: None,
         count: 2,
     }));
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
 
     assert_responded_with(
         &adapter,
-        Response::ReadMemory(ReadMemoryResponse {
+        Response::ReadMemory(Some(ReadMemoryResponse {
             address: "0xF000".to_string(),
             data: "i63wDQ==".to_string(),
             unreadable_bytes: 0,
-        }),
+        })),
     );
     assert_responded_with(
         &adapter,
-        Response::ReadMemory(ReadMemoryResponse {
+        Response::ReadMemory(Some(ReadMemoryResponse {
             address: "0xF001".to_string(),
             data: "rfA=".to_string(),
             unreadable_bytes: 0,
-        }),
+        })),
     );
     assert_eq!(adapter.pop_outgoing(), None);
 }
 
 #[test]
 fn read_memory_with_offset() {
-    let cpu = cpu_with_program(&[0x8B, 0xAD, 0xF0, 0x0D]);
+    let mut cpu = cpu_with_program(&[0x8B, 0xAD, 0xF0, 0x0D]);
     let adapter = FakeDebugAdapter::default();
     let mut debugger = Debugger::new(adapter.clone());
     debugger.update(&cpu).unwrap();
@@ -437,15 +484,15 @@ fn read_memory_with_offset() {
         offset: Some(-2),
         count: 2,
     }));
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
 
     assert_responded_with(
         &adapter,
-        Response::ReadMemory(ReadMemoryResponse {
+        Response::ReadMemory(Some(ReadMemoryResponse {
             address: "0xF001".to_string(),
             data: "rfA=".to_string(),
             unreadable_bytes: 0,
-        }),
+        })),
     );
     assert_eq!(adapter.pop_outgoing(), None);
 }
@@ -463,15 +510,278 @@ fn read_memory_truncates_after_last_bytes() {
         offset: Some(0),
         count: 10,
     }));
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
 
     assert_responded_with(
         &adapter,
-        Response::ReadMemory(ReadMemoryResponse {
+        Response::ReadMemory(Some(ReadMemoryResponse {
             address: "0xFFFE".to_string(),
             data: "8A0=".to_string(),
             unreadable_bytes: 8,
-        }),
+        })),
+    );
+    assert_eq!(adapter.pop_outgoing(), None);
+}
+
+#[test]
+fn write_memory() {
+    let mut cpu = cpu_with_program(&[0x8B, 0xAD, 0xF0, 0x0D]);
+    let adapter = FakeDebugAdapter::default();
+    let mut debugger = Debugger::new(adapter.clone());
+    debugger.update(&cpu).unwrap();
+
+    let write_memory = |memory_reference: &str, offset, allow_partial, data: &str| {
+        Request::WriteMemory(WriteMemoryArguments {
+            memory_reference: memory_reference.to_string(),
+            offset,
+            allow_partial,
+            data: data.to_string(),
+        })
+    };
+    adapter.push_request(write_memory("0xF000", None, None, "6uo="));
+    adapter.push_request(write_memory("0x0080", Some(2), None, "3q2+7w=="));
+    adapter.push_request(write_memory("0xFFFE", None, Some(true), "AQIDBA=="));
+    adapter.push_request(write_memory("0xFFFE", None, None, "BQYHCA=="));
+    adapter.push_request(write_memory("0x0080", None, None, "!!"));
+    debugger.process_messages(&mut cpu);
+
+    assert_responded_with(
+        &adapter,
+        Response::WriteMemory(Some(WriteMemoryResponse { bytes_written: 2 })),
+    );
+    assert_responded_with(
+        &adapter,
+        Response::WriteMemory(Some(WriteMemoryResponse { bytes_written: 4 })),
+    );
+    assert_responded_with(
+        &adapter,
+        Response::WriteMemory(Some(WriteMemoryResponse { bytes_written: 2 })),
+    );
+    assert_failed_with(
+        &adapter,
+        Response::WriteMemory(None),
+        "Address out of range: 65536",
+    );
+    assert_failed_with(
+        &adapter,
+        Response::WriteMemory(None),
+        "Invalid data: Invalid byte 33, offset 0.",
+    );
+    assert_eq!(adapter.pop_outgoing(), None);
+
+    assert_eq!(cpu.memory().bytes[0xF000..0xF004], [0xEA, 0xEA, 0xF0, 0x0D]);
+    assert_eq!(cpu.memory().bytes[0x0082..0x0086], [0xDE, 0xAD, 0xBE, 0xEF]);
+    assert_eq!(cpu.memory().bytes[0xFFFE..], [0x01, 0x02]);
+}
+
+#[test]
+fn write_memory_failure() {
+    // Only the first 4KiB of the address space can be poked.
+    let ram = Rc::new(RefCell::new(Ram::initialized_with(0xAA, 12)));
+    let mut memory = MemoryMap::new(0);
+    memory.mount(Mapping::read_write(0x0000..=0x0FFF, ram.clone()));
+    let mut cpu = Cpu::<MemoryMap>::new(Box::new(memory));
+    let adapter = FakeDebugAdapter::default();
+    let mut debugger = Debugger::new(adapter.clone());
+    debugger.update(&cpu).unwrap();
+
+    let write_memory = |memory_reference: &str, allow_partial| {
+        Request::WriteMemory(WriteMemoryArguments {
+            memory_reference: memory_reference.to_string(),
+            offset: None,
+            allow_partial,
+            data: "AQIDBA==".to_string(),
+        })
+    };
+    adapter.push_request(write_memory("0x0FFD", None));
+    adapter.push_request(write_memory("0x0FFE", Some(true)));
+    adapter.push_request(write_memory("F000", None));
+    debugger.process_messages(&mut cpu);
+
+    assert_failed_with(
+        &adapter,
+        Response::WriteMemory(None),
+        "Unable to write $04 to address $1000",
+    );
+    assert_responded_with(
+        &adapter,
+        Response::WriteMemory(Some(WriteMemoryResponse { bytes_written: 2 })),
+    );
+    assert_failed_with(
+        &adapter,
+        Response::WriteMemory(None),
+        "Invalid memory reference: F000",
+    );
+    assert_eq!(adapter.pop_outgoing(), None);
+
+    assert_eq!(ram.borrow().bytes[0x0FFC..], [0xAA, 0xAA, 0x01, 0x02]);
+}
+
+#[test]
+fn invalid_memory_references() {
+    let mut cpu = cpu_with_program(&[]);
+    let adapter = FakeDebugAdapter::default();
+    let mut debugger = Debugger::new(adapter.clone());
+    debugger.update(&cpu).unwrap();
+
+    adapter.push_request(Request::Disassemble(DisassembleArguments {
+        memory_reference: "0xF00X".to_string(),
+        offset: None,
+        instruction_offset: None,
+        instruction_count: 1,
+    }));
+    adapter.push_request(Request::ReadMemory(ReadMemoryArguments {
+        memory_reference: "".to_string(),
+        offset: None,
+        count: 1,
+    }));
+    debugger.process_messages(&mut cpu);
+
+    assert_failed_with(
+        &adapter,
+        Response::Disassemble(None),
+        "Invalid memory reference: 0xF00X",
+    );
+    assert_failed_with(
+        &adapter,
+        Response::ReadMemory(None),
+        "Invalid memory reference: ",
+    );
+    assert_eq!(adapter.pop_outgoing(), None);
+}
+
+#[test]
+fn set_variable() {
+    let mut cpu = cpu_with_program(&[]);
+    let adapter = FakeDebugAdapter::default();
+    let mut debugger = Debugger::new(adapter.clone());
+    debugger.update(&cpu).unwrap();
+
+    let set_variable = |variables_reference, name: &str, value: &str| {
+        Request::SetVariable(SetVariableArguments {
+            variables_reference,
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    };
+    adapter.push_request(set_variable(REGISTERS_VARIABLES_REFERENCE, "A", "$10"));
+    adapter.push_request(set_variable(REGISTERS_VARIABLES_REFERENCE, "X", "a + 3"));
+    adapter.push_request(set_variable(REGISTERS_VARIABLES_REFERENCE, "PC", "$F123"));
+    adapter.push_request(set_variable(REGISTERS_VARIABLES_REFERENCE, "FLAGS", "$81"));
+    adapter.push_request(set_variable(FLAGS_VARIABLES_REFERENCE, "C", "0"));
+    adapter.push_request(set_variable(REGISTERS_VARIABLES_REFERENCE, "Y", "256"));
+    adapter.push_request(set_variable(MEMORY_VARIABLES_REFERENCE, "SP", "0"));
+    debugger.process_messages(&mut cpu);
+
+    let set_variable_response = |value: &str| {
+        Response::SetVariable(Some(SetVariableResponse {
+            value: value.to_string(),
+        }))
+    };
+    assert_responded_with(&adapter, set_variable_response("$10"));
+    assert_responded_with(&adapter, set_variable_response("$13"));
+    assert_responded_with(&adapter, set_variable_response("$F123"));
+    assert_responded_with(&adapter, set_variable_response("N.-....C"));
+    assert_responded_with(&adapter, set_variable_response("0"));
+    assert_failed_with(
+        &adapter,
+        Response::SetVariable(None),
+        "Value out of range: 256",
+    );
+    assert_failed_with(&adapter, Response::SetVariable(None), "SP can't be changed");
+    assert_eq!(adapter.pop_outgoing(), None);
+
+    assert_eq!(cpu.reg_a(), 0x10);
+    assert_eq!(cpu.reg_x(), 0x13);
+    assert_eq!(cpu.reg_pc(), 0xF123);
+    assert_eq!(cpu.flags(), flags::N);
+}
+
+#[test]
+fn set_expression() {
+    let mut cpu = cpu_with_program(&[]);
+    let adapter = FakeDebugAdapter::default();
+    let mut debugger = Debugger::new(adapter.clone());
+    debugger.update(&cpu).unwrap();
+
+    let set_expression = |expression: &str, value: &str| {
+        Request::SetExpression(SetExpressionArguments {
+            expression: expression.to_string(),
+            value: value.to_string(),
+        })
+    };
+    adapter.push_request(set_expression("[$F000]", "$42"));
+    adapter.push_request(set_expression("sp", "[$F000] - 2"));
+    adapter.push_request(set_expression("z", "1"));
+    adapter.push_request(set_expression("x + 1", "5"));
+    debugger.process_messages(&mut cpu);
+
+    let set_expression_response = |value: &str| {
+        Response::SetExpression(Some(SetExpressionResponse {
+            value: value.to_string(),
+        }))
+    };
+    assert_responded_with(&adapter, set_expression_response("$42"));
+    assert_responded_with(&adapter, set_expression_response("$40"));
+    assert_responded_with(&adapter, set_expression_response("1"));
+    assert_failed_with(
+        &adapter,
+        Response::SetExpression(None),
+        "Only registers, flags, and memory can be assigned to",
+    );
+    assert_eq!(adapter.pop_outgoing(), None);
+
+    assert_eq!(cpu.memory().bytes[0xF000], 0x42);
+    assert_eq!(cpu.reg_sp(), 0x40);
+    assert_ne!(cpu.flags() & flags::Z, 0);
+}
+
+#[test]
+fn evaluate() {
+    let mut cpu = cpu_with_program(&[]);
+    cpu.set_reg_x(0x12);
+    cpu.mut_memory().bytes[0x80] = 0x12;
+    let adapter = FakeDebugAdapter::default();
+    let mut debugger = Debugger::new(adapter.clone());
+    debugger.symbols.insert("counter", 0x80);
+    debugger.update(&cpu).unwrap();
+
+    let evaluate = |expression: &str| {
+        Request::Evaluate(EvaluateArguments {
+            expression: expression.to_string(),
+            context: Some("watch".to_string()),
+        })
+    };
+    adapter.push_request(evaluate("x"));
+    adapter.push_request(evaluate("[counter] + 1"));
+    adapter.push_request(evaluate("{$FFFC}"));
+    adapter.push_request(evaluate("x - 1000"));
+    adapter.push_request(evaluate("x +"));
+    adapter.push_request(evaluate("[$10000]"));
+    debugger.process_messages(&mut cpu);
+
+    let evaluate_response = |result: &str| {
+        Response::Evaluate(Some(EvaluateResponse {
+            result: result.to_string(),
+            variables_reference: 0,
+        }))
+    };
+    assert_responded_with(&adapter, evaluate_response("$12 (18, %00010010)"));
+    assert_responded_with(&adapter, evaluate_response("$13 (19, %00010011)"));
+    assert_responded_with(
+        &adapter,
+        evaluate_response("$F000 (61440, %1111000000000000)"),
+    );
+    assert_responded_with(&adapter, evaluate_response("-982"));
+    assert_failed_with(
+        &adapter,
+        Response::Evaluate(None),
+        "Unexpected end of expression",
+    );
+    assert_failed_with(
+        &adapter,
+        Response::Evaluate(None),
+        "Address out of range: 65536",
     );
     assert_eq!(adapter.pop_outgoing(), None);
 }
@@ -506,23 +816,27 @@ fn variables() {
                 InstructionBreakpoint {
                     instruction_reference: "0xF008".to_string(),
                     offset: None,
+                    condition: None,
+                    hit_condition: None,
                 },
                 InstructionBreakpoint {
                     instruction_reference: "0xF011".to_string(),
                     offset: None,
+                    condition: None,
+                    hit_condition: None,
                 },
             ],
         },
     ));
     adapter.push_request(Request::Continue {});
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
     tick_while_running(&mut debugger, &mut cpu);
     purge_messages(&adapter);
     assert_eq!(cpu.reg_pc(), 0xF008);
 
-    let stack_frames = get_stack_frames(&adapter, &mut debugger, &cpu);
+    let stack_frames = get_stack_frames(&adapter, &mut debugger, &mut cpu);
     let frame_1_id = stack_frames[0].id;
-    let scopes = get_scopes(&adapter, &mut debugger, &cpu, frame_1_id);
+    let scopes = get_scopes(&adapter, &mut debugger, &mut cpu, frame_1_id);
     assert_eq!(scopes.len(), 2);
     assert_eq!(scopes[0].name, "Registers");
     assert_eq!(
@@ -539,7 +853,7 @@ fn variables() {
     adapter.push_request(Request::Variables(VariablesArguments {
         variables_reference: registers_reference,
     }));
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
     assert_responded_with(
         &adapter,
         Response::Variables(VariablesResponse {
@@ -577,7 +891,7 @@ fn variables() {
                 Variable {
                     name: "FLAGS".to_string(),
                     value: "..-.....".to_string(),
-                    variables_reference: 0,
+                    variables_reference: FLAGS_VARIABLES_REFERENCE,
                     memory_reference: None,
                 },
             ],
@@ -587,7 +901,7 @@ fn variables() {
     adapter.push_request(Request::Variables(VariablesArguments {
         variables_reference: memory_reference,
     }));
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
     assert_responded_with(
         &adapter,
         Response::Variables(VariablesResponse {
@@ -601,15 +915,15 @@ fn variables() {
     );
 
     adapter.push_request(Request::Continue {});
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
     tick_while_running(&mut debugger, &mut cpu);
     purge_messages(&adapter);
     assert_eq!(cpu.reg_pc(), 0xF011);
 
-    let stack_frames = get_stack_frames(&adapter, &mut debugger, &cpu);
+    let stack_frames = get_stack_frames(&adapter, &mut debugger, &mut cpu);
     assert_eq!(stack_frames.len(), 2);
     let frame_2_id = stack_frames[0].id;
-    let scopes = get_scopes(&adapter, &mut debugger, &cpu, frame_2_id);
+    let scopes = get_scopes(&adapter, &mut debugger, &mut cpu, frame_2_id);
     assert_eq!(scopes.len(), 2);
     assert_eq!(scopes[0].name, "Registers");
     assert_eq!(
@@ -624,7 +938,7 @@ fn variables() {
     adapter.push_request(Request::Variables(VariablesArguments {
         variables_reference: memory_reference,
     }));
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
     assert_responded_with(
         &adapter,
         Response::Variables(VariablesResponse {
@@ -640,7 +954,7 @@ fn variables() {
     adapter.push_request(Request::Variables(VariablesArguments {
         variables_reference: registers_reference,
     }));
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
     assert_responded_with(
         &adapter,
         Response::Variables(VariablesResponse {
@@ -678,7 +992,7 @@ fn variables() {
                 Variable {
                     name: "FLAGS".to_string(),
                     value: "..-...Z.".to_string(),
-                    variables_reference: 0,
+                    variables_reference: FLAGS_VARIABLES_REFERENCE,
                     memory_reference: None,
                 },
             ],
@@ -686,7 +1000,7 @@ fn variables() {
     );
 
     assert_eq!(stack_frames[1].id, frame_1_id);
-    let scopes = get_scopes(&adapter, &mut debugger, &cpu, frame_1_id);
+    let scopes = get_scopes(&adapter, &mut debugger, &mut cpu, frame_1_id);
     assert_eq!(scopes.len(), 1);
     assert_eq!(scopes[0].name, "Memory");
     let memory_reference = scopes[0].variables_reference;
@@ -694,7 +1008,7 @@ fn variables() {
     adapter.push_request(Request::Variables(VariablesArguments {
         variables_reference: memory_reference,
     }));
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
     assert_responded_with(
         &adapter,
         Response::Variables(VariablesResponse {
@@ -710,19 +1024,19 @@ fn variables() {
 
 #[test]
 fn continue_and_pause() {
-    let inspector = MockMachineInspector::new();
+    let mut machine = MockMachine::new();
     let adapter = FakeDebugAdapter::default();
     adapter.push_request(Request::Continue {});
     let mut debugger = Debugger::new(adapter.clone());
     assert!(debugger.stopped());
 
-    debugger.process_messages(&inspector);
+    debugger.process_messages(&mut machine);
 
     assert_responded_with(&adapter, Response::Continue {});
     assert!(!debugger.stopped());
 
     adapter.push_request(Request::Pause {});
-    debugger.process_messages(&inspector);
+    debugger.process_messages(&mut machine);
 
     assert_responded_with(&adapter, Response::Pause {});
     assert_emitted(
@@ -730,6 +1044,7 @@ fn continue_and_pause() {
         Event::Stopped(StoppedEvent {
             thread_id: 1,
             reason: StopReason::Pause,
+            description: None,
             all_threads_stopped: true,
         }),
     );
@@ -748,7 +1063,7 @@ fn step_in() {
     let mut debugger = Debugger::new(adapter.clone());
     debugger.update(&cpu).unwrap();
 
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
 
     assert_responded_with(&adapter, Response::StepIn {});
     assert!(!debugger.stopped());
@@ -765,6 +1080,7 @@ fn step_in() {
         Event::Stopped(StoppedEvent {
             thread_id: 1,
             reason: StopReason::Step,
+            description: None,
             all_threads_stopped: true,
         }),
     )
@@ -784,7 +1100,7 @@ fn next() {
     let mut debugger = Debugger::new(adapter.clone());
     debugger.update(&cpu).unwrap();
 
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
 
     purge_messages(&adapter);
     tick_while_running(&mut debugger, &mut cpu);
@@ -794,6 +1110,7 @@ fn next() {
         Event::Stopped(StoppedEvent {
             thread_id: 1,
             reason: StopReason::Step,
+            description: None,
             all_threads_stopped: true,
         }),
     );
@@ -816,13 +1133,13 @@ fn step_out() {
     adapter.push_request(Request::StepIn {});
     let mut debugger = Debugger::new(adapter.clone());
     debugger.update(&cpu).unwrap();
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
     tick_while_running(&mut debugger, &mut cpu);
     assert_eq!(cpu.reg_pc(), 0xF006);
 
     purge_messages(&adapter);
     adapter.push_request(Request::StepOut {});
-    debugger.process_messages(&cpu);
+    debugger.process_messages(&mut cpu);
     assert_responded_with(&adapter, Response::StepOut {});
     assert_eq!(adapter.pop_outgoing(), None);
 
@@ -833,6 +1150,7 @@ fn step_out() {
         Event::Stopped(StoppedEvent {
             thread_id: 1,
             reason: StopReason::Step,
+            description: None,
             all_threads_stopped: true,
         }),
     );
@@ -858,10 +1176,14 @@ fn instruction_breakpoints() {
                 InstructionBreakpoint {
                     instruction_reference: "0xF001".to_string(),
                     offset: None,
+                    condition: None,
+                    hit_condition: None,
                 },
                 InstructionBreakpoint {
                     instruction_reference: "0xEFFF".to_string(),
                     offset: Some(4), // Effective address: 0xF003
+                    condition: None,
+                    hit_condition: None,
                 },
             ],
         },
@@ -874,11 +1196,13 @@ fn instruction_breakpoints() {
             breakpoints: vec![
                 Breakpoint {
                     verified: true,
-                    instruction_reference: "0xF001".to_string(),
+                    message: None,
+                    instruction_reference: Some("0xF001".to_string()),
                 },
                 Breakpoint {
                     verified: true,
-                    instruction_reference: "0xF003".to_string(),
+                    message: None,
+                    instruction_reference: Some("0xF003".to_string()),
                 },
             ],
         }),
@@ -891,6 +1215,7 @@ fn instruction_breakpoints() {
         Event::Stopped(StoppedEvent {
             thread_id: 1,
             reason: StopReason::Breakpoint,
+            description: None,
             all_threads_stopped: true,
         }),
     );
@@ -906,20 +1231,339 @@ fn instruction_breakpoints() {
         Event::Stopped(StoppedEvent {
             thread_id: 1,
             reason: StopReason::Breakpoint,
+            description: None,
             all_threads_stopped: true,
         }),
     );
     assert_eq!(cpu.reg_pc(), 0xF003);
 }
 
+#[test]
+fn conditional_breakpoints() {
+    let mut cpu = cpu_with_code! {
+            ldx #0     // 0xF000
+        loop:
+            inx        // 0xF002
+            jmp loop   // 0xF003
+    };
+    let adapter = FakeDebugAdapter::default();
+    let mut debugger = Debugger::new(adapter.clone());
+    debugger.update(&cpu).unwrap();
+
+    let breakpoint = |condition: Option<&str>, hit_condition: Option<&str>| InstructionBreakpoint {
+        instruction_reference: "0xF003".to_string(),
+        offset: None,
+        condition: condition.map(str::to_string),
+        hit_condition: hit_condition.map(str::to_string),
+    };
+    adapter.push_request(Request::SetInstructionBreakpoints(
+        SetInstructionBreakpointsArguments {
+            breakpoints: vec![
+                breakpoint(Some("x > 2"), Some("%2")),
+                breakpoint(Some("X +"), None),
+                breakpoint(Some(""), Some("== Q")),
+                breakpoint(Some(""), Some("")),
+            ],
+        },
+    ));
+    adapter.push_request(Request::Continue {});
+    debugger.process_messages(&mut cpu);
+    let verified = |message: Option<&str>| Breakpoint {
+        verified: message.is_none(),
+        message: message.map(str::to_string),
+        instruction_reference: Some("0xF003".to_string()),
+    };
+    assert_responded_with(
+        &adapter,
+        Response::SetInstructionBreakpoints(SetInstructionBreakpointsResponse {
+            breakpoints: vec![
+                verified(None),
+                verified(Some("Unexpected end of expression")),
+                verified(Some("Invalid number: \"Q\"")),
+                verified(None),
+            ],
+        }),
+    );
+
+    // The last breakpoint is unconditional.
+    purge_messages(&adapter);
+    tick_while_running(&mut debugger, &mut cpu);
+    assert_eq!(cpu.reg_x(), 1);
+
+    adapter.push_request(Request::SetInstructionBreakpoints(
+        SetInstructionBreakpointsArguments {
+            breakpoints: vec![breakpoint(Some("x > 2"), Some("%2"))],
+        },
+    ));
+    adapter.push_request(Request::Continue {});
+    debugger.process_messages(&mut cpu);
+    purge_messages(&adapter);
+    tick_while_running(&mut debugger, &mut cpu);
+    assert_emitted(
+        &adapter,
+        Event::Stopped(StoppedEvent {
+            thread_id: 1,
+            reason: StopReason::Breakpoint,
+            description: None,
+            all_threads_stopped: true,
+        }),
+    );
+    assert_eq!(cpu.reg_x(), 4);
+
+    adapter.push_request(Request::Continue {});
+    debugger.process_messages(&mut cpu);
+    tick_while_running(&mut debugger, &mut cpu);
+    assert_eq!(cpu.reg_x(), 6);
+}
+
+#[test]
+fn data_breakpoints() {
+    let mut cpu = cpu_with_code! {
+            ldx #0     // 0xF000
+        loop:
+            inx        // 0xF002
+            stx 0x81   // 0xF003
+            jmp loop   // 0xF005
+    };
+    let adapter = FakeDebugAdapter::default();
+    let mut debugger = Debugger::new(adapter.clone());
+    cpu.set_bus_observer(Some(debugger.bus_observer()));
+    debugger.update(&cpu).unwrap();
+
+    let data_breakpoint_info =
+        |variables_reference: Option<i64>, name: &str, bytes: Option<i64>, as_address| {
+            Request::DataBreakpointInfo(DataBreakpointInfoArguments {
+                variables_reference,
+                name: name.to_string(),
+                bytes,
+                as_address,
+            })
+        };
+    let watchable = |range: &str| {
+        Response::DataBreakpointInfo(DataBreakpointInfoResponse {
+            data_id: Some(range.to_string()),
+            description: range.to_string(),
+            access_types: Some(vec![
+                DataBreakpointAccessType::Read,
+                DataBreakpointAccessType::Write,
+                DataBreakpointAccessType::ReadWrite,
+            ]),
+        })
+    };
+    // This is what VS Code sends when adding a data breakpoint at an address.
+    adapter.push_request(data_breakpoint_info(None, "0x0080", Some(2), Some(true)));
+    adapter.push_request(data_breakpoint_info(None, "$80", None, None));
+    adapter.push_request(data_breakpoint_info(None, "$90-$9F", None, None));
+    adapter.push_request(data_breakpoint_info(
+        Some(MEMORY_VARIABLES_REFERENCE),
+        "Memory",
+        Some(16),
+        None,
+    ));
+    adapter.push_request(data_breakpoint_info(None, "$FFFF", Some(2), Some(true)));
+    adapter.push_request(data_breakpoint_info(None, "$90-$9F", None, Some(true)));
+    adapter.push_request(data_breakpoint_info(
+        Some(REGISTERS_VARIABLES_REFERENCE),
+        "X",
+        None,
+        None,
+    ));
+    debugger.process_messages(&mut cpu);
+    assert_responded_with(&adapter, watchable("$0080-$0081"));
+    assert_responded_with(&adapter, watchable("$0080-$0080"));
+    assert_responded_with(&adapter, watchable("$0090-$009F"));
+    assert_responded_with(&adapter, watchable("$0000-$000F"));
+    assert_responded_with(
+        &adapter,
+        Response::DataBreakpointInfo(DataBreakpointInfoResponse {
+            data_id: None,
+            description: "Invalid number of bytes: 2".to_string(),
+            access_types: None,
+        }),
+    );
+    assert_responded_with(
+        &adapter,
+        Response::DataBreakpointInfo(DataBreakpointInfoResponse {
+            data_id: None,
+            description: "Invalid number: \"$90-$9F\"".to_string(),
+            access_types: None,
+        }),
+    );
+    assert_responded_with(
+        &adapter,
+        Response::DataBreakpointInfo(DataBreakpointInfoResponse {
+            data_id: None,
+            description: "Registers can't be watched".to_string(),
+            access_types: None,
+        }),
+    );
+
+    adapter.push_request(Request::SetDataBreakpoints(SetDataBreakpointsArguments {
+        breakpoints: vec![
+            DataBreakpoint {
+                data_id: "$0080-$0081".to_string(),
+                access_type: Some(DataBreakpointAccessType::Write),
+            },
+            DataBreakpoint {
+                data_id: "foo".to_string(),
+                access_type: None,
+            },
+        ],
+    }));
+    adapter.push_request(Request::Continue {});
+    debugger.process_messages(&mut cpu);
+    assert_responded_with(
+        &adapter,
+        Response::SetDataBreakpoints(SetDataBreakpointsResponse {
+            breakpoints: vec![
+                Breakpoint {
+                    verified: true,
+                    message: None,
+                    instruction_reference: None,
+                },
+                Breakpoint {
+                    verified: false,
+                    message: Some("Expected START-END, got \"foo\"".to_string()),
+                    instruction_reference: None,
+                },
+            ],
+        }),
+    );
+
+    purge_messages(&adapter);
+    tick_while_running(&mut debugger, &mut cpu);
+    assert_emitted(
+        &adapter,
+        Event::Stopped(StoppedEvent {
+            thread_id: 1,
+            reason: StopReason::DataBreakpoint,
+            description: Some("Write of $01 to $0081 at $F003".to_string()),
+            all_threads_stopped: true,
+        }),
+    );
+    assert_eq!(cpu.reg_pc(), 0xF005);
+
+    adapter.push_request(Request::Continue {});
+    debugger.process_messages(&mut cpu);
+    tick_while_running(&mut debugger, &mut cpu);
+    assert_eq!(cpu.reg_pc(), 0xF005);
+    assert_eq!(cpu.reg_x(), 2);
+}
+
+#[test]
+fn symbols() {
+    let mut cpu = cpu_with_code! {
+            jsr subroutine // 0xF000
+        loop:
+            jmp loop       // 0xF003
+        subroutine:
+            lda 0x80       // 0xF006
+            rts            // 0xF008
+    };
+    let adapter = FakeDebugAdapter::default();
+    let mut debugger = Debugger::new(adapter.clone());
+    let mut symbols = Symbols::new();
+    symbols.insert("start", 0xF000);
+    symbols.insert("subroutine", 0xF006);
+    symbols.insert("counter", 0x0080);
+    debugger.set_symbols(symbols);
+    debugger.update(&cpu).unwrap();
+
+    let function_breakpoint = |name: &str| FunctionBreakpoint {
+        name: name.to_string(),
+        condition: None,
+        hit_condition: None,
+    };
+    adapter.push_request(Request::SetFunctionBreakpoints(
+        SetFunctionBreakpointsArguments {
+            breakpoints: vec![
+                function_breakpoint("subroutine"),
+                function_breakpoint("foo"),
+            ],
+        },
+    ));
+    adapter.push_request(Request::Continue {});
+    debugger.process_messages(&mut cpu);
+    assert_responded_with(
+        &adapter,
+        Response::SetFunctionBreakpoints(SetFunctionBreakpointsResponse {
+            breakpoints: vec![
+                Breakpoint {
+                    verified: true,
+                    message: None,
+                    instruction_reference: Some("0xF006".to_string()),
+                },
+                Breakpoint {
+                    verified: false,
+                    message: Some("Unknown symbol: foo".to_string()),
+                    instruction_reference: None,
+                },
+            ],
+        }),
+    );
+
+    purge_messages(&adapter);
+    tick_while_running(&mut debugger, &mut cpu);
+    assert_emitted(
+        &adapter,
+        Event::Stopped(StoppedEvent {
+            thread_id: 1,
+            reason: StopReason::FunctionBreakpoint,
+            description: None,
+            all_threads_stopped: true,
+        }),
+    );
+    assert_eq!(cpu.reg_pc(), 0xF006);
+
+    let stack_frames = get_stack_frames(&adapter, &mut debugger, &mut cpu);
+    let frame_names: Vec<&str> = stack_frames
+        .iter()
+        .map(|frame| frame.name.as_str())
+        .collect();
+    assert_eq!(frame_names, ["subroutine", "start"]);
+
+    adapter.push_request(Request::Disassemble(DisassembleArguments {
+        memory_reference: "0xF000".to_string(),
+        offset: Some(0),
+        instruction_offset: Some(0),
+        instruction_count: 3,
+    }));
+    debugger.process_messages(&mut cpu);
+    assert_responded_with(
+        &adapter,
+        Response::Disassemble(Some(DisassembleResponse {
+            instructions: vec![
+                DisassembledInstruction {
+                    address: "0xF000".to_string(),
+                    instruction_bytes: "20 06 F0".to_string(),
+                    instruction: "JSR subroutine".to_string(),
+                    symbol: Some("start".to_string()),
+                },
+                DisassembledInstruction {
+                    address: "0xF003".to_string(),
+                    instruction_bytes: "4C 03 F0".to_string(),
+                    instruction: "JMP $F003".to_string(),
+                    symbol: None,
+                },
+                DisassembledInstruction {
+                    address: "0xF006".to_string(),
+                    instruction_bytes: "A5 80".to_string(),
+                    instruction: "LDA counter".to_string(),
+                    symbol: Some("subroutine".to_string()),
+                },
+            ],
+        })),
+    );
+}
+
 #[test]
 fn disconnects() {
-    let inspector = MockMachineInspector::new();
+    let mut machine = MockMachine::new();
     let adapter = FakeDebugAdapter::default();
     adapter.push_request(Request::Disconnect(None));
     adapter.expect_disconnect();
     let mut debugger = Debugger::new(adapter.clone());
-    debugger.process_messages(&inspector);
+    debugger.process_messages(&mut machine);
 
     assert_responded_with(&adapter, Response::Disconnect);
     assert!(adapter.disconnected());
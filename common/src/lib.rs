@@ -5,6 +5,8 @@ pub mod build_utils;
 pub mod colors;
 pub mod debugger;
 pub mod test_utils;
+pub mod trace;
+pub mod vcd;
 
 #[cfg(test)]
 #[macro_use]
@@ -1,7 +1,13 @@
 use crate::app::AppController;
 use image::DynamicImage;
+#[cfg(test)]
+use std::cell::RefCell;
 use std::fs::create_dir_all;
+#[cfg(test)]
+use std::io;
 use std::path::Path;
+#[cfg(test)]
+use std::rc::Rc;
 
 pub fn as_single_hex_digit(n: u8) -> char {
     if n <= 0x0f {
@@ -70,3 +76,30 @@ pub fn assert_current_frame(
     let expected_image = read_test_image(test_image_name);
     assert_images_equal(actual_image, expected_image, test_name, results_dir_path);
 }
+
+/// An output that can be examined after handing it over to a writer that takes
+/// ownership of it.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+#[cfg(test)]
+impl io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl SharedBuffer {
+    pub(crate) fn lines(&self) -> Vec<String> {
+        String::from_utf8(self.0.borrow().clone())
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+}
@@ -0,0 +1,286 @@
+//! Instruction trace logs in a format similar to the one used by nestest and
+//! Nintendulator. Each executed instruction is logged as a single line that
+//! contains the register values before executing it, which makes it easy to
+//! compare logs produced by different emulators.
+
+use crate::app::{parse_address, parse_number};
+use crate::debugger::disasm::{disassemble_opcodes, OpcodeSet};
+use crate::debugger::symbols::Symbols;
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
+use ya6502::cpu::MachineInspector;
+
+/// An inclusive range of addresses, specified as `START-END`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl AddressRange {
+    pub fn contains(&self, address: u16) -> bool {
+        (self.start..=self.end).contains(&address)
+    }
+}
+
+impl FromStr for AddressRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("Expected START-END, got {:?}", s))?;
+        Ok(Self {
+            start: parse_address(start)?,
+            end: parse_address(end)?,
+        })
+    }
+}
+
+/// A half-open range of CPU cycles, specified as `START-END`. Either end can be
+/// omitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CycleWindow {
+    pub start: u64,
+    pub end: Option<u64>,
+}
+
+impl CycleWindow {
+    fn contains(&self, cycle: u64) -> bool {
+        cycle >= self.start && !matches!(self.end, Some(end) if cycle >= end)
+    }
+}
+
+impl FromStr for CycleWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("Expected START-END, got {:?}", s))?;
+        Ok(Self {
+            start: if start.is_empty() {
+                0
+            } else {
+                parse_number(start)?
+            },
+            end: if end.is_empty() {
+                None
+            } else {
+                Some(parse_number(end)?)
+            },
+        })
+    }
+}
+
+/// Position of the video beam: TIA scanline and column, or VIC-II raster line
+/// and X coordinate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BeamPosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Writes instruction trace logs. Note that the CPU state is logged at the
+/// beginning of each instruction, so if an interrupt is serviced instead, the
+/// interrupted instruction gets logged twice: before the interrupt, and after
+/// returning from it.
+pub struct TraceWriter {
+    out: Box<dyn Write>,
+    address_ranges: Vec<AddressRange>,
+    cycle_window: CycleWindow,
+}
+
+impl TraceWriter {
+    /// Creates a trace writer. If `address_ranges` is not empty, only the
+    /// instructions located in one of these ranges are logged.
+    pub fn new(
+        out: Box<dyn Write>,
+        address_ranges: Vec<AddressRange>,
+        cycle_window: CycleWindow,
+    ) -> Self {
+        Self {
+            out,
+            address_ranges,
+            cycle_window,
+        }
+    }
+
+    /// Logs the instruction that is about to be executed, unless it's filtered
+    /// out. Should be called when the machine is at the beginning of an
+    /// instruction.
+    pub fn trace(
+        &mut self,
+        inspector: &impl MachineInspector,
+        beam_position: Option<BeamPosition>,
+    ) -> io::Result<()> {
+        let pc = inspector.reg_pc();
+        let cycles = inspector.cycles();
+        if !self.cycle_window.contains(cycles)
+            || !(self.address_ranges.is_empty()
+                || self.address_ranges.iter().any(|range| range.contains(pc)))
+        {
+            return Ok(());
+        }
+
+        // Symbols are deliberately not used here, and undocumented opcodes
+        // are decoded, so that the log can be compared with the ones produced
+        // by other emulators.
+        let instruction =
+            &disassemble_opcodes(inspector, &Symbols::new(), OpcodeSet::All, pc, pc, 0, 1)[0];
+        write!(
+            self.out,
+            "{:04X}  {:8}  {:30}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+            pc,
+            instruction.instruction_bytes,
+            instruction.instruction,
+            inspector.reg_a(),
+            inspector.reg_x(),
+            inspector.reg_y(),
+            inspector.flags(),
+            inspector.reg_sp(),
+        )?;
+        if let Some(BeamPosition { line, column }) = beam_position {
+            write!(self.out, " V:{:3} H:{:3}", line, column)?;
+        }
+        writeln!(self.out, " CYC:{}", cycles)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::SharedBuffer;
+    use ya6502::cpu_with_code;
+
+    #[test]
+    fn parses_filters() {
+        assert_eq!(
+            "$F000-$F0FF".parse(),
+            Ok(AddressRange {
+                start: 0xF000,
+                end: 0xF0FF
+            })
+        );
+        assert!("$F000".parse::<AddressRange>().is_err());
+        assert!("$F000-$10000".parse::<AddressRange>().is_err());
+
+        assert_eq!(
+            "100-200".parse(),
+            Ok(CycleWindow {
+                start: 100,
+                end: Some(200)
+            })
+        );
+        assert_eq!(
+            "100-".parse(),
+            Ok(CycleWindow {
+                start: 100,
+                end: None
+            })
+        );
+        assert_eq!(
+            "-0x200".parse(),
+            Ok(CycleWindow {
+                start: 0,
+                end: Some(0x200)
+            })
+        );
+    }
+
+    fn register_columns(cpu: &impl MachineInspector) -> String {
+        format!(
+            "A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+            cpu.reg_a(),
+            cpu.reg_x(),
+            cpu.reg_y(),
+            cpu.flags(),
+            cpu.reg_sp(),
+        )
+    }
+
+    #[test]
+    fn traces_instructions() {
+        let mut cpu = cpu_with_code! {
+                ldx #0x12
+                lda abs 0x1234
+                nop
+        };
+        let buffer = SharedBuffer::default();
+        let mut trace_writer =
+            TraceWriter::new(Box::new(buffer.clone()), vec![], CycleWindow::default());
+        let mut expected = vec![];
+
+        expected.push(format!(
+            "F000  A2 12     LDX #$12                        {} CYC:7",
+            register_columns(&cpu)
+        ));
+        trace_writer.trace(&cpu, None).unwrap();
+        cpu.step_instruction().unwrap();
+
+        expected.push(format!(
+            "F002  AD 34 12  LDA $1234                       {} V: 12 H:  3 CYC:9",
+            register_columns(&cpu)
+        ));
+        let beam_position = BeamPosition {
+            line: 12,
+            column: 3,
+        };
+        trace_writer.trace(&cpu, Some(beam_position)).unwrap();
+        cpu.step_instruction().unwrap();
+        cpu.step_instruction().unwrap();
+
+        expected.push(format!(
+            "F006  02        *HLT                            {} CYC:15",
+            register_columns(&cpu)
+        ));
+        trace_writer.trace(&cpu, None).unwrap();
+
+        itertools::assert_equal(buffer.lines(), expected);
+    }
+
+    #[test]
+    fn filters_instructions() {
+        let mut cpu = cpu_with_code! {
+                nop  // 7
+                nop  // 9
+                nop  // 11
+                nop  // 13
+                nop  // 15
+        };
+        let buffer = SharedBuffer::default();
+        let mut trace_writer = TraceWriter::new(
+            Box::new(buffer.clone()),
+            vec![
+                AddressRange {
+                    start: 0xF000,
+                    end: 0xF001,
+                },
+                AddressRange {
+                    start: 0xF003,
+                    end: 0xF004,
+                },
+            ],
+            CycleWindow {
+                start: 9,
+                end: Some(15),
+            },
+        );
+        for _ in 0..5 {
+            trace_writer.trace(&cpu, None).unwrap();
+            cpu.step_instruction().unwrap();
+        }
+
+        let traced_addresses = buffer
+            .lines()
+            .iter()
+            .map(|line| line[..4].to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(traced_addresses, ["F001", "F003"]);
+    }
+}
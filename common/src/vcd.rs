@@ -0,0 +1,499 @@
+//! Recording of cycle-exact bus traces as Value Change Dump (VCD) files, which
+//! can be viewed with waveform viewers, such as GTKWave. Each CPU cycle is one
+//! unit of time in the trace.
+
+use crate::app::{parse_address, parse_number};
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::io;
+use std::io::Write;
+use std::rc::Rc;
+use std::str::FromStr;
+use ya6502::cpu::bus::{BusAccess, BusAccessKind, BusObserver, BusOperation};
+use ya6502::cpu::variant::Variant;
+use ya6502::cpu::Cpu;
+use ya6502::memory::Memory;
+
+/// A value of a named signal emitted by one of the emulated chips.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signal {
+    /// Name of the chip, used as a scope in the VCD file.
+    pub scope: &'static str,
+    pub name: &'static str,
+    /// Number of bits.
+    pub width: u32,
+    pub value: u64,
+}
+
+impl Signal {
+    pub fn new(scope: &'static str, name: &'static str, width: u32, value: u64) -> Self {
+        Self {
+            scope,
+            name,
+            width,
+            value,
+        }
+    }
+
+    /// Creates a single-bit signal.
+    pub fn bit(scope: &'static str, name: &'static str, value: bool) -> Self {
+        Self::new(scope, name, 1, value as u64)
+    }
+}
+
+/// Returns the signals on the CPU input pins. Note that they are active high,
+/// just like the corresponding [`Cpu`] methods, so they don't reflect the
+/// actual voltage levels of IRQ and NMI lines.
+pub fn cpu_pin_signals<M: Memory + Debug, V: Variant>(cpu: &Cpu<M, V>) -> Vec<Signal> {
+    vec![
+        Signal::bit("cpu", "irq", cpu.irq_pin()),
+        Signal::bit("cpu", "nmi", cpu.nmi_pin()),
+        Signal::bit("cpu", "rdy", cpu.rdy_pin()),
+    ]
+}
+
+/// A condition that starts or stops recording a trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// Triggered at the beginning of a given CPU cycle.
+    Cycle(u64),
+    /// Triggered when the CPU fetches an opcode from a given address.
+    Pc(u16),
+}
+
+impl FromStr for Trigger {
+    type Err = String;
+
+    /// Parses either a cycle number, or an address prefixed with `pc:`.
+    /// Numbers can be given either as decimal, or hexadecimal ones prefixed
+    /// with `$` or `0x`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("pc:") {
+            Some(address) => parse_address(address).map(Trigger::Pc),
+            None => parse_number(s).map(Trigger::Cycle),
+        }
+    }
+}
+
+/// A minimal VCD writer. Signals are declared up front and referred to by
+/// their indices. Only the changes of signal values are written to the output.
+pub struct VcdWriter<W: Write> {
+    out: W,
+    widths: Vec<u32>,
+    values: Vec<Option<u64>>,
+    time: Option<u64>,
+}
+
+impl<W: Write> VcdWriter<W> {
+    /// Creates a writer and writes the VCD header that declares given signals.
+    /// Their values are ignored.
+    pub fn new(mut out: W, signals: &[Signal]) -> io::Result<Self> {
+        writeln!(out, "$version atari-emulator $end")?;
+        writeln!(out, "$timescale 1 us $end")?;
+        let mut scope = None;
+        for (index, signal) in signals.iter().enumerate() {
+            if scope != Some(signal.scope) {
+                if scope.is_some() {
+                    writeln!(out, "$upscope $end")?;
+                }
+                writeln!(out, "$scope module {} $end", signal.scope)?;
+                scope = Some(signal.scope);
+            }
+            writeln!(
+                out,
+                "$var wire {} {} {} $end",
+                signal.width,
+                identifier(index),
+                signal.name
+            )?;
+        }
+        if scope.is_some() {
+            writeln!(out, "$upscope $end")?;
+        }
+        writeln!(out, "$enddefinitions $end")?;
+        Ok(Self {
+            out,
+            widths: signals.iter().map(|signal| signal.width).collect(),
+            values: vec![None; signals.len()],
+            time: None,
+        })
+    }
+
+    /// Records a value of a signal with a given index at a given time, which
+    /// may not be earlier than the time of the previous change.
+    pub fn change(&mut self, time: u64, index: usize, value: u64) -> io::Result<()> {
+        if self.values[index] == Some(value) {
+            return Ok(());
+        }
+        self.set_time(time)?;
+        self.values[index] = Some(value);
+        match self.widths[index] {
+            1 => writeln!(self.out, "{}{}", value & 1, identifier(index)),
+            width => writeln!(
+                self.out,
+                "b{:0width$b} {}",
+                value,
+                identifier(index),
+                width = width as usize
+            ),
+        }
+    }
+
+    /// Marks the end of the trace at a given time and flushes the output.
+    pub fn finish(&mut self, time: u64) -> io::Result<()> {
+        self.set_time(time)?;
+        self.out.flush()
+    }
+
+    fn set_time(&mut self, time: u64) -> io::Result<()> {
+        if self.time != Some(time) {
+            writeln!(self.out, "#{}", time)?;
+            self.time = Some(time);
+        }
+        Ok(())
+    }
+}
+
+/// Returns a VCD identifier code for a signal with a given index. Identifiers
+/// are made of printable ASCII characters.
+fn identifier(mut index: usize) -> String {
+    const FIRST: u8 = b'!';
+    const COUNT: usize = (b'~' - b'!' + 1) as usize;
+    let mut identifier = String::new();
+    loop {
+        identifier.push((FIRST + (index % COUNT) as u8) as char);
+        index /= COUNT;
+        if index == 0 {
+            return identifier;
+        }
+        index -= 1;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RecorderState {
+    Waiting,
+    Recording,
+    Finished,
+}
+
+// Indices of the bus signals. Machine-specific signals follow them.
+const ADDRESS: usize = 0;
+const DATA: usize = 1;
+const READ: usize = 2;
+const SYNC: usize = 3;
+const BUS_SIGNALS: usize = 4;
+
+/// Records the CPU bus activity and machine-specific signals to a VCD file.
+/// The bus is recorded by observing the CPU memory accesses (see
+/// [`bus_observer`]); other signals need to be reported once per CPU cycle
+/// using [`BusRecorder::record_signals`].
+pub struct BusRecorder {
+    writer: VcdWriter<Box<dyn Write>>,
+    start: Option<Trigger>,
+    stop: Option<Trigger>,
+    state: RecorderState,
+    last_cycle: Option<u64>,
+    last_signals_cycle: Option<u64>,
+}
+
+impl BusRecorder {
+    /// Creates a recorder that writes to a given output. If there's no `start`
+    /// trigger, recording starts immediately. `signals` declare the
+    /// machine-specific signals that will be reported later.
+    pub fn new(
+        out: Box<dyn Write>,
+        start: Option<Trigger>,
+        stop: Option<Trigger>,
+        signals: &[Signal],
+    ) -> io::Result<Self> {
+        let bus_signals = [
+            Signal::new("cpu", "address", 16, 0),
+            Signal::new("cpu", "data", 8, 0),
+            Signal::bit("cpu", "rw", false),
+            Signal::bit("cpu", "sync", false),
+        ];
+        let all_signals: Vec<Signal> = bus_signals.iter().chain(signals).copied().collect();
+        Ok(Self {
+            writer: VcdWriter::new(out, &all_signals)?,
+            start,
+            stop,
+            state: if start.is_some() {
+                RecorderState::Waiting
+            } else {
+                RecorderState::Recording
+            },
+            last_cycle: None,
+            last_signals_cycle: None,
+        })
+    }
+
+    /// Records a single CPU memory access. The R/W line is high on reads, and
+    /// SYNC is high on opcode fetches.
+    pub fn record_access(&mut self, access: &BusAccess) -> io::Result<()> {
+        let sync = access.kind == BusAccessKind::OpcodeFetch;
+        self.update_state(access.cycle, sync.then_some(access.address))?;
+        if self.state != RecorderState::Recording {
+            return Ok(());
+        }
+        let read = access.operation == BusOperation::Read;
+        self.change(access.cycle, ADDRESS, access.address as u64)?;
+        self.change(access.cycle, DATA, access.value as u64)?;
+        self.change(access.cycle, READ, read as u64)?;
+        self.change(access.cycle, SYNC, sync as u64)
+    }
+
+    /// Returns `true` if signals haven't been yet recorded for a given cycle.
+    /// Used to avoid collecting them when it's not necessary.
+    pub fn needs_signals(&self, cycle: u64) -> bool {
+        self.state != RecorderState::Finished && self.last_signals_cycle != Some(cycle)
+    }
+
+    /// Records the values of machine-specific signals at the end of a given
+    /// cycle. They need to be given in the same order as in
+    /// [`BusRecorder::new`].
+    pub fn record_signals(&mut self, cycle: u64, signals: &[Signal]) -> io::Result<()> {
+        self.last_signals_cycle = Some(cycle);
+        self.update_state(cycle, None)?;
+        if self.state != RecorderState::Recording {
+            return Ok(());
+        }
+        for (index, signal) in signals.iter().enumerate() {
+            self.change(cycle, BUS_SIGNALS + index, signal.value)?;
+        }
+        Ok(())
+    }
+
+    fn update_state(&mut self, cycle: u64, pc: Option<u16>) -> io::Result<()> {
+        self.last_cycle = Some(cycle);
+        let triggered = |trigger| match trigger {
+            Some(Trigger::Cycle(trigger_cycle)) => cycle >= trigger_cycle,
+            Some(Trigger::Pc(address)) => pc == Some(address),
+            None => false,
+        };
+        match self.state {
+            RecorderState::Waiting if triggered(self.start) => {
+                self.state = RecorderState::Recording;
+            }
+            RecorderState::Recording if triggered(self.stop) => {
+                self.state = RecorderState::Finished;
+                self.writer.finish(cycle)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Writes a signal change. Once there's an error, the recording stops.
+    fn change(&mut self, cycle: u64, index: usize, value: u64) -> io::Result<()> {
+        let result = self.writer.change(cycle, index, value);
+        if result.is_err() {
+            self.state = RecorderState::Finished;
+        }
+        result
+    }
+
+    /// Flushes the output. Should be called after the machine stops, unless
+    /// the stop trigger has already finished the recording.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.state == RecorderState::Finished {
+            return Ok(());
+        }
+        self.state = RecorderState::Finished;
+        let time = self.last_cycle.map_or(0, |cycle| cycle + 1);
+        self.writer.finish(time)
+    }
+}
+
+impl Drop for BusRecorder {
+    fn drop(&mut self) {
+        // There's no way to report errors at this point.
+        let _ = self.finish();
+    }
+}
+
+/// Creates a CPU bus observer that feeds a given recorder. Errors are reported
+/// on the standard error stream.
+pub fn bus_observer(recorder: &Rc<RefCell<BusRecorder>>) -> Box<dyn BusObserver> {
+    let recorder = recorder.clone();
+    Box::new(move |access: &BusAccess| {
+        if let Err(e) = recorder.borrow_mut().record_access(access) {
+            eprintln!("Bus recorder error: {}", e);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::SharedBuffer;
+
+    /// Returns the recorded lines that follow the header.
+    fn value_changes(buffer: &SharedBuffer) -> Vec<String> {
+        buffer
+            .lines()
+            .into_iter()
+            .skip_while(|line| line != "$enddefinitions $end")
+            .skip(1)
+            .collect()
+    }
+
+    fn access(cycle: u64, address: u16, value: u8, kind: BusAccessKind) -> BusAccess {
+        BusAccess {
+            cycle,
+            operation: BusOperation::Read,
+            address,
+            value,
+            kind,
+        }
+    }
+
+    #[test]
+    fn parses_triggers() {
+        assert_eq!("1000".parse(), Ok(Trigger::Cycle(1000)));
+        assert_eq!("0x10".parse(), Ok(Trigger::Cycle(16)));
+        assert_eq!("pc:$F000".parse(), Ok(Trigger::Pc(0xF000)));
+        assert_eq!("pc:1024".parse(), Ok(Trigger::Pc(0x0400)));
+        assert!("pc:$10000".parse::<Trigger>().is_err());
+        assert!("pc".parse::<Trigger>().is_err());
+    }
+
+    #[test]
+    fn generates_identifiers() {
+        assert_eq!(identifier(0), "!");
+        assert_eq!(identifier(93), "~");
+        assert_eq!(identifier(94), "!!");
+        assert_eq!(identifier(95), "\"!");
+        assert_eq!(identifier(94 + 94 * 94), "!!!");
+    }
+
+    #[test]
+    fn writes_header_and_changes() {
+        let buffer = SharedBuffer::default();
+        let mut writer = VcdWriter::new(
+            buffer.clone(),
+            &[
+                Signal::new("tia", "column", 8, 0),
+                Signal::bit("tia", "vsync", false),
+                Signal::bit("cpu", "irq", false),
+            ],
+        )
+        .unwrap();
+        writer.change(0, 0, 3).unwrap();
+        writer.change(0, 1, 1).unwrap();
+        writer.change(1, 0, 3).unwrap();
+        writer.change(2, 0, 4).unwrap();
+        writer.finish(3).unwrap();
+
+        itertools::assert_equal(
+            buffer.lines(),
+            [
+                "$version atari-emulator $end",
+                "$timescale 1 us $end",
+                "$scope module tia $end",
+                "$var wire 8 ! column $end",
+                "$var wire 1 \" vsync $end",
+                "$upscope $end",
+                "$scope module cpu $end",
+                "$var wire 1 # irq $end",
+                "$upscope $end",
+                "$enddefinitions $end",
+                "#0",
+                "b00000011 !",
+                "1\"",
+                "#2",
+                "b00000100 !",
+                "#3",
+            ],
+        );
+    }
+
+    #[test]
+    fn records_bus_and_signals() {
+        let buffer = SharedBuffer::default();
+        let mut recorder = BusRecorder::new(
+            Box::new(buffer.clone()),
+            None,
+            None,
+            &[Signal::bit("cpu", "irq", false)],
+        )
+        .unwrap();
+        recorder
+            .record_access(&access(0, 0xF000, 0xEA, BusAccessKind::OpcodeFetch))
+            .unwrap();
+        recorder
+            .record_signals(0, &[Signal::bit("cpu", "irq", false)])
+            .unwrap();
+        recorder
+            .record_access(&BusAccess {
+                cycle: 1,
+                operation: BusOperation::Write,
+                address: 0x0080,
+                value: 0x12,
+                kind: BusAccessKind::Data,
+            })
+            .unwrap();
+        recorder
+            .record_signals(1, &[Signal::bit("cpu", "irq", true)])
+            .unwrap();
+        recorder.finish().unwrap();
+
+        itertools::assert_equal(
+            value_changes(&buffer),
+            [
+                "#0",
+                "b1111000000000000 !",
+                "b11101010 \"",
+                "1#",
+                "1$",
+                "0%",
+                "#1",
+                "b0000000010000000 !",
+                "b00010010 \"",
+                "0#",
+                "0$",
+                "1%",
+                "#2",
+            ],
+        );
+    }
+
+    #[test]
+    fn starts_and_stops_on_triggers() {
+        let buffer = SharedBuffer::default();
+        let mut recorder = BusRecorder::new(
+            Box::new(buffer.clone()),
+            Some(Trigger::Pc(0xF002)),
+            Some(Trigger::Cycle(4)),
+            &[],
+        )
+        .unwrap();
+        let fetches = [
+            access(0, 0xF000, 0xEA, BusAccessKind::OpcodeFetch),
+            access(1, 0xF001, 0xEA, BusAccessKind::Dummy),
+            access(2, 0xF002, 0xA9, BusAccessKind::OpcodeFetch),
+            access(3, 0xF003, 0x00, BusAccessKind::OperandFetch),
+            access(4, 0xF004, 0xEA, BusAccessKind::OpcodeFetch),
+        ];
+        for fetch in &fetches {
+            recorder.record_access(fetch).unwrap();
+        }
+        assert!(!recorder.needs_signals(5));
+
+        itertools::assert_equal(
+            value_changes(&buffer),
+            [
+                "#2",
+                "b1111000000000010 !",
+                "b10101001 \"",
+                "1#",
+                "1$",
+                "#3",
+                "b1111000000000011 !",
+                "b00000000 \"",
+                "0$",
+                "#4",
+            ],
+        );
+    }
+}
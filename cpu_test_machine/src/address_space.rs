@@ -0,0 +1,199 @@
+use std::fmt;
+use std::io;
+use ya6502::memory::{
+    Inspect, Memory, Poke, Ram, Read, ReadError, ReadResult, Write, WriteError, WriteResult,
+};
+
+/// Addresses of the memory-mapped I/O registers. Each of them is optional;
+/// an address that is not used by any register is just a regular RAM
+/// location.
+#[derive(Debug, Clone, Default)]
+pub struct IoConfig {
+    /// Writing to this address sends a byte to the output.
+    pub putchar: Option<u16>,
+    /// Reading from this address takes a byte from the input. At the end of
+    /// input, $FF is returned.
+    pub getchar: Option<u16>,
+    /// Writing to this address stops the simulation, using the written value
+    /// as the exit status.
+    pub exit: Option<u16>,
+    /// Bits of this register drive the interrupt lines: bit 0 is IRQ, and bit
+    /// 1 is NMI. A bit set to 1 means that the line is active.
+    pub interrupt: Option<u16>,
+}
+
+const IRQ_BIT: u8 = 1 << 0;
+const NMI_BIT: u8 = 1 << 1;
+
+/// A flat 64KiB RAM with a couple of I/O registers on top of it. All
+/// registers are shadowed by RAM, so inspecting them shows the last value that
+/// went through them.
+pub struct AddressSpace<I: io::Read, O: io::Write> {
+    pub ram: Ram,
+    pub input: I,
+    pub output: O,
+    io: IoConfig,
+    exit_code: Option<u8>,
+}
+
+impl<I: io::Read, O: io::Write> AddressSpace<I, O> {
+    pub fn new(ram: Ram, io: IoConfig, input: I, output: O) -> Self {
+        Self {
+            ram,
+            input,
+            output,
+            io,
+            exit_code: None,
+        }
+    }
+
+    /// Returns the value written to the exit register, if any.
+    pub fn exit_code(&self) -> Option<u8> {
+        self.exit_code
+    }
+
+    pub fn irq(&self) -> bool {
+        self.interrupt_register() & IRQ_BIT != 0
+    }
+
+    pub fn nmi(&self) -> bool {
+        self.interrupt_register() & NMI_BIT != 0
+    }
+
+    fn interrupt_register(&self) -> u8 {
+        match self.io.interrupt {
+            Some(address) => self.ram.bytes[address as usize],
+            None => 0,
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+impl<I: io::Read, O: io::Write> Inspect for AddressSpace<I, O> {
+    fn inspect(&self, address: u16) -> ReadResult {
+        self.ram.inspect(address)
+    }
+}
+
+impl<I: io::Read, O: io::Write> Read for AddressSpace<I, O> {
+    fn read(&mut self, address: u16) -> ReadResult {
+        if Some(address) == self.io.getchar {
+            // Make sure that the prompt, if any, is visible before waiting for
+            // the input.
+            self.output.flush().map_err(|_| ReadError { address })?;
+            let mut buffer = [0; 1];
+            let value = match self.input.read(&mut buffer) {
+                Ok(0) => 0xFF,
+                Ok(_) => buffer[0],
+                Err(_) => return Err(ReadError { address }),
+            };
+            self.ram.bytes[address as usize] = value;
+        }
+        self.ram.read(address)
+    }
+}
+
+impl<I: io::Read, O: io::Write> Write for AddressSpace<I, O> {
+    fn write(&mut self, address: u16, value: u8) -> WriteResult {
+        if Some(address) == self.io.putchar {
+            self.output
+                .write_all(&[value])
+                .map_err(|_| WriteError { address, value })?;
+        }
+        if Some(address) == self.io.exit {
+            self.exit_code = Some(value);
+        }
+        self.ram.write(address, value)
+    }
+}
+
+impl<I: io::Read, O: io::Write> Memory for AddressSpace<I, O> {}
+
+impl<I: io::Read, O: io::Write> Poke for AddressSpace<I, O> {
+    fn poke(&mut self, address: u16, value: u8) -> WriteResult {
+        self.ram.poke(address, value)
+    }
+}
+
+impl<I: io::Read, O: io::Write> fmt::Debug for AddressSpace<I, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AddressSpace")
+            .field("io", &self.io)
+            .field("exit_code", &self.exit_code)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address_space(input: &[u8]) -> AddressSpace<&[u8], Vec<u8>> {
+        AddressSpace::new(
+            Ram::new(16),
+            IoConfig {
+                putchar: Some(0xF001),
+                getchar: Some(0xF004),
+                exit: Some(0xF005),
+                interrupt: Some(0xBFFC),
+            },
+            input,
+            Vec::new(),
+        )
+    }
+
+    #[test]
+    fn ram() {
+        let mut space = address_space(b"");
+        space.write(0x0000, 0x12).unwrap();
+        space.write(0xFFFF, 0x34).unwrap();
+        assert_eq!(space.read(0x0000).unwrap(), 0x12);
+        assert_eq!(space.read(0xFFFF).unwrap(), 0x34);
+        assert_eq!(space.inspect(0x0000).unwrap(), 0x12);
+        assert_eq!(space.output, b"");
+        assert_eq!(space.exit_code(), None);
+    }
+
+    #[test]
+    fn console() {
+        let mut space = address_space(b"ok");
+        space.write(0xF001, b'H').unwrap();
+        space.write(0xF001, b'i').unwrap();
+        assert_eq!(space.output, b"Hi");
+        assert_eq!(space.inspect(0xF001).unwrap(), b'i');
+
+        assert_eq!(space.read(0xF004).unwrap(), b'o');
+        assert_eq!(space.inspect(0xF004).unwrap(), b'o');
+        assert_eq!(space.inspect(0xF004).unwrap(), b'o');
+        assert_eq!(space.read(0xF004).unwrap(), b'k');
+        assert_eq!(space.read(0xF004).unwrap(), 0xFF);
+    }
+
+    #[test]
+    fn exit() {
+        let mut space = address_space(b"");
+        space.write(0xF005, 3).unwrap();
+        assert_eq!(space.exit_code(), Some(3));
+        space.write(0xF005, 0).unwrap();
+        assert_eq!(space.exit_code(), Some(0));
+    }
+
+    #[test]
+    fn interrupts() {
+        let mut space = address_space(b"");
+        assert!(!space.irq());
+        assert!(!space.nmi());
+        space.write(0xBFFC, 0b01).unwrap();
+        assert!(space.irq());
+        assert!(!space.nmi());
+        space.write(0xBFFC, 0b10).unwrap();
+        assert!(!space.irq());
+        assert!(space.nmi());
+        space.poke(0xBFFC, 0b11).unwrap();
+        assert!(space.irq());
+        assert!(space.nmi());
+    }
+}
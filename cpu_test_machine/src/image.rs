@@ -0,0 +1,131 @@
+use crate::paravirt;
+use crate::paravirt::{load_sim65, Header};
+use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+use ya6502::loader::{load_intel_hex, load_o65, load_prg, load_raw, load_srec, Image};
+
+/// Format of the program file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Determine the format using the file contents or the file name
+    /// extension.
+    Auto,
+    Raw,
+    Prg,
+    IntelHex,
+    Srec,
+    O65,
+    Sim65,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Format::Auto),
+            "raw" => Ok(Format::Raw),
+            "prg" => Ok(Format::Prg),
+            "hex" => Ok(Format::IntelHex),
+            "srec" => Ok(Format::Srec),
+            "o65" => Ok(Format::O65),
+            "sim65" => Ok(Format::Sim65),
+            _ => Err(format!(
+                "Invalid format: {:?}; expected \"auto\", \"raw\", \"prg\", \"hex\", \
+                \"srec\", \"o65\", or \"sim65\"",
+                s
+            )),
+        }
+    }
+}
+
+impl Format {
+    /// Guesses the format of a given file. `sim65` programs are recognized by
+    /// their header; files with an unknown extension are treated as raw
+    /// binaries.
+    fn detect(path: &Path, bytes: &[u8]) -> Self {
+        if bytes.starts_with(paravirt::MAGIC) {
+            return Format::Sim65;
+        }
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("prg") => Format::Prg,
+            Some("hex" | "ihx") => Format::IntelHex,
+            Some("srec" | "s19" | "s28" | "s37" | "mot") => Format::Srec,
+            Some("o65") => Format::O65,
+            _ => Format::Raw,
+        }
+    }
+}
+
+/// A loaded program file.
+pub struct Program {
+    pub image: Image,
+    /// The header of a `sim65` program, if the file was one.
+    pub sim65_header: Option<Header>,
+}
+
+/// Reads a program file. The load address is used for raw binaries, and as a
+/// base address for relocatable o65 files; other formats specify their own
+/// addresses.
+pub fn load_program(
+    path: &str,
+    format: Format,
+    load_address: u16,
+) -> Result<Program, Box<dyn Error>> {
+    let bytes = std::fs::read(path)?;
+    let format = match format {
+        Format::Auto => Format::detect(Path::new(path), &bytes),
+        format => format,
+    };
+    let image = match format {
+        Format::Auto | Format::Raw => load_raw(&bytes, load_address)?,
+        Format::Prg => load_prg(&bytes)?,
+        Format::IntelHex => load_intel_hex(std::str::from_utf8(&bytes)?)?,
+        Format::Srec => load_srec(std::str::from_utf8(&bytes)?)?,
+        Format::O65 => load_o65(&bytes, load_address)?,
+        Format::Sim65 => {
+            let (image, header) = load_sim65(&bytes)?;
+            return Ok(Program {
+                image,
+                sim65_header: Some(header),
+            });
+        }
+    };
+    Ok(Program {
+        image,
+        sim65_header: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_format() {
+        assert_eq!("hex".parse(), Ok(Format::IntelHex));
+        assert_eq!("o65".parse(), Ok(Format::O65));
+        assert_eq!("sim65".parse(), Ok(Format::Sim65));
+        assert!("elf".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn detects_format() {
+        let detect = |path| Format::detect(Path::new(path), &[0x00, 0x02]);
+        assert_eq!(detect("test.PRG"), Format::Prg);
+        assert_eq!(detect("a/test.ihx"), Format::IntelHex);
+        assert_eq!(detect("test.s19"), Format::Srec);
+        assert_eq!(detect("test.o65"), Format::O65);
+        assert_eq!(detect("test.bin"), Format::Raw);
+        assert_eq!(detect("test"), Format::Raw);
+        assert_eq!(
+            Format::detect(Path::new("test"), b"sim65\x02\x00"),
+            Format::Sim65
+        );
+    }
+}
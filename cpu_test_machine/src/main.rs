@@ -1,35 +1,278 @@
+mod address_space;
+mod image;
+mod paravirt;
+
+use address_space::{AddressSpace, IoConfig};
 use clap::Parser;
+use image::{load_program, Format, Program};
+use paravirt::{CpuType, Paravirt};
+use std::cell::RefCell;
+use std::io::{Stdin, Stdout};
+use std::process;
+use std::rc::Rc;
 use std::time::Duration;
 
 use common::{
-    app::CommonCliArguments,
-    debugger::{adapter::TcpDebugAdapter, Debugger},
+    app::{parse_address, parse_number, CommonCliArguments},
+    debugger::{adapter::TcpDebugAdapter, symbols::Symbols, Debugger},
+    trace::TraceWriter,
+    vcd::{self, cpu_pin_signals, BusRecorder},
 };
 use ya6502::{
-    cpu::{Cpu, MachineInspector},
-    memory::Ram,
+    cpu::{
+        bus::{BusAccess, BusObserver},
+        variant::{Cmos65C02, Nmos6502, Variant},
+        Cpu, CpuError, MachineInspector,
+    },
+    memory::{Inspect, Ram},
 };
 
+/// Exit status used when the program fails a test or the CPU crashes.
+const FAILURE: i32 = 1;
+/// Exit status used when the program exceeds the cycle limit.
+const TIMEOUT: i32 = 2;
+
 #[derive(Parser)]
+#[clap(trailing_var_arg = true)]
 struct Args {
     #[clap(flatten)]
     common: CommonCliArguments,
-    test_file: String,
+
+    /// Program file format: "auto", "raw", "prg", "hex", "srec", "o65", or
+    /// "sim65". The "sim65" format also enables the sim65 paravirtualization
+    /// hooks.
+    #[clap(long, default_value = "auto")]
+    format: Format,
+    /// Address of a raw binary, or a base address of an o65 file.
+    #[clap(long, default_value = "0", parse(try_from_str = parse_address))]
+    load_address: u16,
+    /// Start address. If not specified, the entry point given in the program
+    /// file is used; if there isn't any, the CPU is reset.
+    #[clap(long, parse(try_from_str = parse_address))]
+    start: Option<u16>,
+
+    #[clap(long, parse(try_from_str = parse_address))]
+    reset_vector: Option<u16>,
+    #[clap(long, parse(try_from_str = parse_address))]
+    irq_vector: Option<u16>,
+    #[clap(long, parse(try_from_str = parse_address))]
+    nmi_vector: Option<u16>,
+
+    /// Address of the character output register.
+    #[clap(long, parse(try_from_str = parse_address))]
+    putchar: Option<u16>,
+    /// Address of the character input register.
+    #[clap(long, parse(try_from_str = parse_address))]
+    getchar: Option<u16>,
+    /// Address of a register that stops the program with the written value as
+    /// the exit status.
+    #[clap(long, parse(try_from_str = parse_address))]
+    exit: Option<u16>,
+    /// Address of a register that drives IRQ (bit 0) and NMI (bit 1) lines.
+    #[clap(long, parse(try_from_str = parse_address))]
+    interrupt_port: Option<u16>,
+
+    /// Stops the program after given number of cycles.
+    #[clap(long)]
+    max_cycles: Option<u64>,
+    /// The address of a trap that indicates success. Reaching any other trap
+    /// is considered a failure.
+    #[clap(long, parse(try_from_str = parse_address))]
+    success: Option<u16>,
+    /// A memory location that needs to hold a given value after reaching the
+    /// success trap, specified as ADDRESS=VALUE. Can be repeated.
+    #[clap(long, parse(try_from_str = parse_memory_check))]
+    expect: Vec<(u16, u8)>,
+
+    program_file: String,
+    /// Arguments passed to a sim65 program. Everything after the program file,
+    /// including options, is passed as is.
+    #[clap(multiple_values = true, allow_hyphen_values = true)]
+    program_args: Vec<String>,
+}
+
+fn parse_memory_check(s: &str) -> Result<(u16, u8), String> {
+    let (address, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected ADDRESS=VALUE, got {:?}", s))?;
+    let value = parse_number(value)?;
+    Ok((
+        parse_address(address)?,
+        u8::try_from(value).map_err(|_| format!("Value out of range: {:?}", s))?,
+    ))
+}
+
+type SimCpu<V> = Cpu<AddressSpace<Stdin, Stdout>, V>;
+
+/// The reason why the simulation stopped.
+enum Outcome {
+    /// The program wrote to the exit register.
+    Exit(u8),
+    /// The CPU reached an instruction that loops into itself.
+    Trap(u16),
+    CycleLimit,
+    Error(CpuError),
 }
 
 fn main() {
     let args = Args::parse();
 
-    let test_program = std::fs::read(args.test_file).expect("Unable to read the test file");
+    let program =
+        load_program(&args.program_file, args.format, args.load_address).unwrap_or_else(|e| {
+            eprintln!("Unable to load {}: {}", args.program_file, e);
+            process::exit(FAILURE);
+        });
+    let status = match program.sim65_header.as_ref().map(|header| header.cpu) {
+        Some(CpuType::Cmos65C02) => simulate::<Cmos65C02>(&args, &program),
+        _ => simulate::<Nmos6502>(&args, &program),
+    };
+    process::exit(status);
+}
 
-    let mut ram = Box::new(Ram::new(16));
-    ram.bytes[0x0000..=0xFFFF].copy_from_slice(&test_program);
-    let mut cpu = Cpu::new(ram);
-    cpu.jump_to(0x400);
+/// Sets up the machine, runs the program, and returns the exit status.
+fn simulate<V: Variant>(args: &Args, program: &Program) -> i32 {
+    let mut ram = Ram::with_power_on_state(&args.common.power_on, "RAM", 16);
+    program
+        .image
+        .write_to(&mut ram)
+        .expect("Unable to write the program to RAM");
+    for (address, vector) in [
+        (0xFFFA, args.nmi_vector),
+        (0xFFFC, args.reset_vector),
+        (0xFFFE, args.irq_vector),
+    ] {
+        if let Some(vector) = vector {
+            ram.bytes[address..address + 2].copy_from_slice(&vector.to_le_bytes());
+        }
+    }
 
+    let io = IoConfig {
+        putchar: args.putchar,
+        getchar: args.getchar,
+        exit: args.exit,
+        interrupt: args.interrupt_port,
+    };
+    let address_space = AddressSpace::new(ram, io, std::io::stdin(), std::io::stdout());
+    let mut cpu: SimCpu<V> =
+        Cpu::with_power_on_state(Box::new(address_space), &args.common.power_on);
+    if let Some(start) = args.start.or(program.image.entry) {
+        cpu.jump_to(start);
+    }
+    let paravirt = program.sim65_header.as_ref().map(|header| {
+        let program_args = std::iter::once(&args.program_file)
+            .chain(&args.program_args)
+            .cloned()
+            .collect();
+        Paravirt::new(header.stack_pointer, program_args)
+    });
+
+    let bus_recorder = match args.common.bus_recorder(&cpu_pin_signals(&cpu)) {
+        Ok(bus_recorder) => bus_recorder.map(|recorder| Rc::new(RefCell::new(recorder))),
+        Err(e) => {
+            eprintln!("Unable to create the bus trace file: {}", e);
+            return FAILURE;
+        }
+    };
+    if let Some(bus_recorder) = &bus_recorder {
+        cpu.set_bus_observer(Some(vcd::bus_observer(bus_recorder)));
+    }
+    let mut trace_writer = match args.common.trace_writer() {
+        Ok(trace_writer) => trace_writer,
+        Err(e) => {
+            eprintln!("Unable to create the trace log file: {}", e);
+            return FAILURE;
+        }
+    };
+    let symbols = match args.common.symbols() {
+        Ok(symbols) => symbols,
+        Err(e) => {
+            eprintln!("Unable to load the symbol files: {}", e);
+            return FAILURE;
+        }
+    };
+
+    let outcome = run(
+        &mut cpu,
+        args,
+        paravirt,
+        bus_recorder.as_deref(),
+        trace_writer.as_mut(),
+        symbols,
+    );
+    if let Err(e) = cpu.mut_memory().flush() {
+        eprintln!("Unable to write the output: {}", e);
+    }
+    if let Some(bus_recorder) = &bus_recorder {
+        if let Err(e) = bus_recorder.borrow_mut().finish() {
+            eprintln!("Unable to write the bus trace: {}", e);
+        }
+    }
+    if let Some(trace_writer) = &mut trace_writer {
+        if let Err(e) = trace_writer.flush() {
+            eprintln!("Unable to write the trace log: {}", e);
+        }
+    }
+    match outcome {
+        Outcome::Exit(code) => code as i32,
+        Outcome::Trap(pc) => match args.success {
+            None => {
+                println!("{}", &cpu);
+                0
+            }
+            Some(success) => {
+                if pc != success {
+                    eprintln!("Failure: trapped at ${:04X}", pc);
+                    eprintln!("{}", &cpu);
+                    FAILURE
+                } else if !memory_checks_pass(&cpu, &args.expect) {
+                    eprintln!("{}", &cpu);
+                    FAILURE
+                } else {
+                    eprintln!("Success after {} cycles", cpu.cycles());
+                    0
+                }
+            }
+        },
+        Outcome::CycleLimit => {
+            eprintln!("Cycle limit exceeded");
+            eprintln!("{}", &cpu);
+            TIMEOUT
+        }
+        Outcome::Error(e) => {
+            eprintln!("CPU error: {}", e);
+            eprintln!("{}", &cpu);
+            FAILURE
+        }
+    }
+}
+
+/// Runs the program until it stops. If the debugger is enabled, traps are not
+/// detected, so that the user can inspect them. Note that unless the debugger
+/// is enabled, the CPU pin signals are only recorded by the bus recorder after
+/// each instruction, since this is when the interrupt lines change anyway.
+fn run<V: Variant>(
+    cpu: &mut SimCpu<V>,
+    args: &Args,
+    mut paravirt: Option<Paravirt>,
+    bus_recorder: Option<&RefCell<BusRecorder>>,
+    mut trace_writer: Option<&mut TraceWriter>,
+    symbols: Symbols,
+) -> Outcome {
     let mut debugger = if args.common.debugger {
         let mut dbg = Debugger::new(TcpDebugAdapter::new(args.common.debugger_port));
-        if let Err(e) = dbg.update(&cpu) {
+        dbg.set_symbols(symbols);
+        // The debugger needs to observe the bus for data breakpoints, but the
+        // bus recorder may already be there.
+        let mut observer = dbg.bus_observer();
+        let observer: Box<dyn BusObserver> = match cpu.set_bus_observer(None) {
+            Some(mut recorder) => Box::new(move |access: &BusAccess| {
+                recorder.observe(access);
+                observer.observe(access);
+            }),
+            None => observer,
+        };
+        cpu.set_bus_observer(Some(observer));
+        if let Err(e) = dbg.update(&*cpu) {
             eprintln!("Debugger error: {}", e);
         }
         Some(dbg)
@@ -37,38 +280,109 @@ fn main() {
         None
     };
 
-    let mut prev_pc = 0;
-
     loop {
-        // println!("PC: ${:04X}", cpu.reg_pc());
         if let Some(debugger) = &mut debugger {
-            debugger.process_messages(&cpu);
+            debugger.process_messages(&mut *cpu);
             if !debugger.stopped() {
+                if cpu.at_instruction_start() {
+                    trace(&mut trace_writer, cpu);
+                }
                 if let Err(e) = cpu.tick() {
-                    eprintln!("CPU error: {}", e);
-                    eprintln!("{}", &cpu);
+                    // Just like in the other frontends, let the user inspect
+                    // the machine instead of ending the debugging session.
+                    if let Err(e) = debugger.stop_on_exception(e.to_string()) {
+                        eprintln!("Debugger error: {}", e);
+                    }
+                    continue;
                 }
-                if let Err(e) = debugger.update(&cpu) {
+                if let Err(e) = debugger.update(&*cpu) {
                     eprintln!("Debugger error: {}", e);
                 }
             } else {
                 // Yes, I know. Disgraceful. But it's so much easier than
                 // supporting blocking mode in the debugger adapter.
                 std::thread::sleep(Duration::from_millis(10));
+                continue;
             }
         } else {
-            if let Err(e) = cpu.tick() {
-                eprintln!("CPU error: {}", e);
-                eprintln!("{}", &cpu);
+            trace(&mut trace_writer, cpu);
+            let pc = cpu.reg_pc();
+            match cpu.step_instruction() {
+                Ok(step) if step.interrupt.is_none() && cpu.reg_pc() == pc => {
+                    return Outcome::Trap(pc)
+                }
+                Ok(_) => {}
+                Err(e) => return Outcome::Error(e),
             }
-            if cpu.at_instruction_start() {
-                let new_pc = cpu.reg_pc();
-                if new_pc == prev_pc {
-                    println!("{}", &cpu);
-                    return;
+        }
+
+        if let Some(paravirt) = &mut paravirt {
+            if cpu.at_instruction_start() && Paravirt::is_hook(cpu.reg_pc()) {
+                let mut regs = cpu.snapshot();
+                let exit_code = paravirt.call(&mut regs, &mut cpu.mut_memory().ram);
+                cpu.restore(&regs);
+                if let Some(code) = exit_code {
+                    return Outcome::Exit(code);
                 }
-                prev_pc = new_pc;
             }
         }
+
+        let memory = cpu.memory();
+        if let Some(code) = memory.exit_code() {
+            return Outcome::Exit(code);
+        }
+        let (irq, nmi) = (memory.irq(), memory.nmi());
+        if let Some(bus_recorder) = bus_recorder {
+            if let Err(e) = bus_recorder
+                .borrow_mut()
+                .record_signals(cpu.cycles() - 1, &cpu_pin_signals(cpu))
+            {
+                eprintln!("Bus recorder error: {}", e);
+            }
+        }
+        cpu.set_irq_pin(irq);
+        cpu.set_nmi_pin(nmi);
+        if matches!(args.max_cycles, Some(max_cycles) if cpu.cycles() >= max_cycles) {
+            return Outcome::CycleLimit;
+        }
+    }
+}
+
+/// Logs the instruction that the CPU is about to execute, if requested.
+fn trace<V: Variant>(trace_writer: &mut Option<&mut TraceWriter>, cpu: &SimCpu<V>) {
+    if let Some(trace_writer) = trace_writer {
+        if let Err(e) = trace_writer.trace(cpu, None) {
+            eprintln!("Trace writer error: {}", e);
+        }
+    }
+}
+
+/// Verifies that the memory contents match the expectations. Reports all
+/// mismatches.
+fn memory_checks_pass<V: Variant>(cpu: &SimCpu<V>, expectations: &[(u16, u8)]) -> bool {
+    let mut pass = true;
+    for (address, expected) in expectations {
+        let actual = cpu.memory().inspect(*address).unwrap();
+        if actual != *expected {
+            eprintln!(
+                "Failure: expected ${:02X} at ${:04X}, got ${:02X}",
+                expected, address, actual
+            );
+            pass = false;
+        }
+    }
+    pass
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_memory_checks() {
+        assert_eq!(parse_memory_check("$000B=0"), Ok((0x000B, 0x00)));
+        assert_eq!(parse_memory_check("512=$FF"), Ok((0x0200, 0xFF)));
+        assert!(parse_memory_check("$000B").is_err());
+        assert!(parse_memory_check("$000B=$100").is_err());
     }
 }
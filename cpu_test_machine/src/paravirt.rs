@@ -0,0 +1,507 @@
+//! An implementation of the paravirtualization interface of `sim65`, the
+//! simulator that comes with [cc65](https://cc65.github.io/). Programs
+//! compiled for the `sim6502` and `sim65c02` targets call subroutines at the
+//! top of the address space to access the host's files, command line
+//! arguments, and exit status. Instead of executing code at these addresses,
+//! the simulator performs the operation and returns from the subroutine.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Write};
+use ya6502::cpu::CpuState;
+use ya6502::loader::{load_raw, Image, LoadError, Segment};
+use ya6502::memory::Ram;
+
+pub const MAGIC: &[u8] = b"sim65";
+const VERSION: u8 = 2;
+const HEADER_LENGTH: usize = 12;
+
+/// Address of the first hook. The hooks follow in the order of the
+/// [`Hook`] enum.
+const PARAVIRT_BASE: u16 = 0xFFF4;
+
+enum Hook {
+    Open,
+    Close,
+    Read,
+    Write,
+    Args,
+    Exit,
+}
+
+const HOOKS: [Hook; 6] = [
+    Hook::Open,
+    Hook::Close,
+    Hook::Read,
+    Hook::Write,
+    Hook::Args,
+    Hook::Exit,
+];
+
+mod open_flags {
+    pub const READ: u16 = 0x01;
+    pub const WRITE: u16 = 0x02;
+    pub const CREATE: u16 = 0x10;
+    pub const TRUNCATE: u16 = 0x20;
+    pub const APPEND: u16 = 0x40;
+    pub const EXCLUSIVE: u16 = 0x80;
+}
+
+/// The value returned from the hooks in case of an error.
+const ERROR: u16 = 0xFFFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuType {
+    Nmos6502,
+    Cmos65C02,
+}
+
+/// Header of a `sim65` program file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub cpu: CpuType,
+    /// Zero page address of the C stack pointer.
+    pub stack_pointer: u8,
+    pub load_address: u16,
+    pub reset_address: u16,
+}
+
+/// Loads a program file produced by the cc65 linker for the `sim6502` and
+/// `sim65c02` targets. The reset vector is set to the program's entry point,
+/// so that the program starts after resetting the CPU, just like in `sim65`.
+pub fn load_sim65(bytes: &[u8]) -> Result<(Image, Header), LoadError> {
+    if bytes.len() < HEADER_LENGTH {
+        return Err(LoadError::Truncated);
+    }
+    if &bytes[..MAGIC.len()] != MAGIC {
+        return Err(LoadError::InvalidFormat("not a sim65 program"));
+    }
+    if bytes[5] != VERSION {
+        return Err(LoadError::Unsupported("sim65 program version other than 2"));
+    }
+    let header = Header {
+        cpu: match bytes[6] {
+            0 => CpuType::Nmos6502,
+            1 => CpuType::Cmos65C02,
+            // 6502X, an NMOS 6502 with unofficial opcodes, which our NMOS
+            // implementation supports anyway.
+            2 => CpuType::Nmos6502,
+            _ => return Err(LoadError::Unsupported("sim65 CPU type")),
+        },
+        stack_pointer: bytes[7],
+        load_address: u16::from_le_bytes([bytes[8], bytes[9]]),
+        reset_address: u16::from_le_bytes([bytes[10], bytes[11]]),
+    };
+    let mut image = load_raw(&bytes[HEADER_LENGTH..], header.load_address)?;
+    image.segments.push(Segment {
+        address: 0xFFFC,
+        bytes: header.reset_address.to_le_bytes().to_vec(),
+    });
+    Ok((image, header))
+}
+
+/// A host stream available to the simulated program.
+enum Stream {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(File),
+}
+
+impl Stream {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Stdin => io::stdin().read(buffer),
+            Stream::File(file) => file.read(buffer),
+            _ => Err(io::ErrorKind::Unsupported.into()),
+        }
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Stdout => io::stdout().write_all(buffer).map(|_| buffer.len()),
+            Stream::Stderr => io::stderr().write_all(buffer).map(|_| buffer.len()),
+            Stream::File(file) => file.write(buffer),
+            _ => Err(io::ErrorKind::Unsupported.into()),
+        }
+    }
+}
+
+pub struct Paravirt {
+    /// Zero page address of the C stack pointer.
+    stack_pointer: u8,
+    /// Program arguments, starting with the program name.
+    args: Vec<String>,
+    /// Open streams, indexed by file descriptors.
+    streams: Vec<Option<Stream>>,
+}
+
+impl Paravirt {
+    pub fn new(stack_pointer: u8, args: Vec<String>) -> Self {
+        Self {
+            stack_pointer,
+            args,
+            streams: vec![
+                Some(Stream::Stdin),
+                Some(Stream::Stdout),
+                Some(Stream::Stderr),
+            ],
+        }
+    }
+
+    /// Returns `true` if there's a hook at a given address.
+    pub fn is_hook(address: u16) -> bool {
+        address >= PARAVIRT_BASE && ((address - PARAVIRT_BASE) as usize) < HOOKS.len()
+    }
+
+    /// Executes the hook at the current program counter and returns from the
+    /// subroutine. Returns the exit status if the program wants to stop.
+    pub fn call(&mut self, regs: &mut CpuState, ram: &mut Ram) -> Option<u8> {
+        let result = match HOOKS[(regs.reg_pc - PARAVIRT_BASE) as usize] {
+            Hook::Open => self.open(regs, ram),
+            Hook::Close => self.close(regs),
+            Hook::Read => self.read(regs, ram),
+            Hook::Write => self.write(regs, ram),
+            Hook::Args => self.args(regs, ram),
+            Hook::Exit => return Some(regs.reg_a),
+        };
+        [regs.reg_a, regs.reg_x] = result.to_le_bytes();
+        let low = pop(regs, ram);
+        let high = pop(regs, ram);
+        regs.reg_pc = u16::from_le_bytes([low, high]).wrapping_add(1);
+        None
+    }
+
+    /// `int open(const char* name, int flags, ...)`. Since it's a variadic
+    /// function, the Y register holds the number of parameter bytes. The mode
+    /// parameter is ignored.
+    fn open(&mut self, regs: &CpuState, ram: &mut Ram) -> u16 {
+        self.pop_param(ram, (regs.reg_y as u16).wrapping_sub(4));
+        let flags = self.pop_param(ram, 2);
+        let name_address = self.pop_param(ram, 2);
+        let name = read_string(ram, name_address);
+
+        let file = OpenOptions::new()
+            .read(flags & open_flags::READ != 0)
+            .write(flags & open_flags::WRITE != 0)
+            .create(flags & open_flags::CREATE != 0)
+            .truncate(flags & open_flags::TRUNCATE != 0)
+            .append(flags & open_flags::APPEND != 0)
+            .create_new(flags & open_flags::EXCLUSIVE != 0)
+            .open(name);
+        let stream = match file {
+            Ok(file) => Some(Stream::File(file)),
+            Err(_) => return ERROR,
+        };
+        match self.streams.iter().position(Option::is_none) {
+            Some(fd) => {
+                self.streams[fd] = stream;
+                fd as u16
+            }
+            None => {
+                self.streams.push(stream);
+                (self.streams.len() - 1) as u16
+            }
+        }
+    }
+
+    /// `int close(int fd)`
+    fn close(&mut self, regs: &CpuState) -> u16 {
+        match self.streams.get_mut(ax(regs) as usize) {
+            Some(stream @ Some(_)) => {
+                *stream = None;
+                0
+            }
+            _ => ERROR,
+        }
+    }
+
+    /// `int read(int fd, void* buf, unsigned count)`
+    fn read(&mut self, regs: &CpuState, ram: &mut Ram) -> u16 {
+        let count = ax(regs);
+        let buffer_address = self.pop_param(ram, 2);
+        let fd = self.pop_param(ram, 2);
+        let mut buffer = vec![0; count as usize];
+        let length = match self.stream(fd).map(|stream| stream.read(&mut buffer)) {
+            Some(Ok(length)) => length,
+            _ => return ERROR,
+        };
+        for (offset, byte) in buffer[..length].iter().enumerate() {
+            ram.bytes[buffer_address.wrapping_add(offset as u16) as usize] = *byte;
+        }
+        length as u16
+    }
+
+    /// `int write(int fd, const void* buf, unsigned count)`
+    fn write(&mut self, regs: &CpuState, ram: &mut Ram) -> u16 {
+        let count = ax(regs);
+        let buffer_address = self.pop_param(ram, 2);
+        let fd = self.pop_param(ram, 2);
+        let buffer: Vec<u8> = (0..count)
+            .map(|offset| ram.bytes[buffer_address.wrapping_add(offset) as usize])
+            .collect();
+        match self.stream(fd).map(|stream| stream.write(&buffer)) {
+            Some(Ok(length)) => length as u16,
+            _ => ERROR,
+        }
+    }
+
+    /// Initializes `argc` and `argv`. The A and X registers hold the address
+    /// of `argv`; the argument strings and the array of pointers to them are
+    /// allocated on the C stack. Returns `argc`.
+    fn args(&mut self, regs: &CpuState, ram: &mut Ram) -> u16 {
+        let argv_address = ax(regs);
+        let mut sp = read_word(ram, self.stack_pointer as u16);
+        let mut pointer_address = sp.wrapping_sub((self.args.len() as u16 + 1) * 2);
+        write_word(ram, argv_address, pointer_address);
+        sp = pointer_address;
+        for arg in &self.args {
+            sp = sp.wrapping_sub(arg.len() as u16 + 1);
+            for (offset, byte) in arg.bytes().chain([0]).enumerate() {
+                ram.bytes[sp.wrapping_add(offset as u16) as usize] = byte;
+            }
+            write_word(ram, pointer_address, sp);
+            pointer_address = pointer_address.wrapping_add(2);
+        }
+        write_word(ram, pointer_address, 0);
+        write_word(ram, self.stack_pointer as u16, sp);
+        self.args.len() as u16
+    }
+
+    fn stream(&mut self, fd: u16) -> Option<&mut Stream> {
+        self.streams.get_mut(fd as usize)?.as_mut()
+    }
+
+    /// Reads a parameter from the C stack, and then moves the stack pointer by
+    /// a given number of bytes.
+    fn pop_param(&self, ram: &mut Ram, increment: u16) -> u16 {
+        let sp = read_word(ram, self.stack_pointer as u16);
+        write_word(ram, self.stack_pointer as u16, sp.wrapping_add(increment));
+        read_word(ram, sp)
+    }
+}
+
+/// Returns the 16-bit value passed in the A (low byte) and X (high byte)
+/// registers.
+fn ax(regs: &CpuState) -> u16 {
+    u16::from_le_bytes([regs.reg_a, regs.reg_x])
+}
+
+fn pop(regs: &mut CpuState, ram: &Ram) -> u8 {
+    regs.reg_sp = regs.reg_sp.wrapping_add(1);
+    ram.bytes[0x100 + regs.reg_sp as usize]
+}
+
+fn read_word(ram: &Ram, address: u16) -> u16 {
+    u16::from_le_bytes([
+        ram.bytes[address as usize],
+        ram.bytes[address.wrapping_add(1) as usize],
+    ])
+}
+
+fn write_word(ram: &mut Ram, address: u16, value: u16) {
+    let [low, high] = value.to_le_bytes();
+    ram.bytes[address as usize] = low;
+    ram.bytes[address.wrapping_add(1) as usize] = high;
+}
+
+/// Reads a zero-terminated string.
+fn read_string(ram: &Ram, address: u16) -> String {
+    let bytes: Vec<u8> = (0..=0xFFFF)
+        .map(|offset| ram.bytes[address.wrapping_add(offset) as usize])
+        .take_while(|byte| *byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ya6502::cpu::Cpu;
+
+    const STACK_POINTER: u8 = 0x02;
+    const STACK_TOP: u16 = 0xC000;
+
+    /// Prepares a call to a hook at a given address, made by a `JSR`
+    /// instruction at $0380, with given parameters pushed to the C stack.
+    fn call_setup(hook: u16, params: &[u16]) -> (CpuState, Ram) {
+        let mut ram = Ram::new(16);
+        let mut sp = STACK_TOP;
+        for param in params {
+            sp -= 2;
+            write_word(&mut ram, sp, *param);
+        }
+        write_word(&mut ram, STACK_POINTER as u16, sp);
+        ram.bytes[0x1FE..=0x1FF].copy_from_slice(&[0x82, 0x03]);
+        let mut regs = Cpu::<Ram>::new(Box::new(Ram::new(1))).snapshot();
+        regs.reg_pc = hook;
+        regs.reg_sp = 0xFD;
+        (regs, ram)
+    }
+
+    fn c_stack_pointer(ram: &Ram) -> u16 {
+        read_word(ram, STACK_POINTER as u16)
+    }
+
+    fn temp_file_name(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("ya6502-paravirt-{}-{}", std::process::id(), name))
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    fn sim65_header(cpu: u8) -> Vec<u8> {
+        let mut bytes = b"sim65".to_vec();
+        bytes.extend_from_slice(&[2, cpu, 0x02, 0x00, 0x02, 0x10, 0x02]);
+        bytes
+    }
+
+    #[test]
+    fn loads_program() {
+        let mut bytes = sim65_header(1);
+        bytes.extend_from_slice(&[0xEA, 0x60]);
+        let (image, header) = load_sim65(&bytes).unwrap();
+        assert_eq!(
+            header,
+            Header {
+                cpu: CpuType::Cmos65C02,
+                stack_pointer: 0x02,
+                load_address: 0x0200,
+                reset_address: 0x0210,
+            }
+        );
+        assert_eq!(
+            image.segments,
+            vec![
+                Segment {
+                    address: 0x0200,
+                    bytes: vec![0xEA, 0x60],
+                },
+                Segment {
+                    address: 0xFFFC,
+                    bytes: vec![0x10, 0x02],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn cpu_types() {
+        let cpu_type = |cpu| load_sim65(&sim65_header(cpu)).unwrap().1.cpu;
+        assert_eq!(cpu_type(0), CpuType::Nmos6502);
+        assert_eq!(cpu_type(1), CpuType::Cmos65C02);
+        assert_eq!(cpu_type(2), CpuType::Nmos6502);
+    }
+
+    #[test]
+    fn rejects_invalid_programs() {
+        assert_eq!(load_sim65(b"sim65"), Err(LoadError::Truncated));
+        assert_eq!(
+            load_sim65(b"sim66\x02\x00\x02\x00\x02\x00\x02"),
+            Err(LoadError::InvalidFormat("not a sim65 program"))
+        );
+        let mut bytes = sim65_header(0);
+        bytes[5] = 1;
+        assert_eq!(
+            load_sim65(&bytes),
+            Err(LoadError::Unsupported("sim65 program version other than 2"))
+        );
+        assert_eq!(
+            load_sim65(&sim65_header(3)),
+            Err(LoadError::Unsupported("sim65 CPU type"))
+        );
+    }
+
+    #[test]
+    fn hook_addresses() {
+        assert!(!Paravirt::is_hook(0xFFF3));
+        assert!(Paravirt::is_hook(0xFFF4));
+        assert!(Paravirt::is_hook(0xFFF9));
+        assert!(!Paravirt::is_hook(0xFFFA));
+    }
+
+    #[test]
+    fn exit() {
+        let mut paravirt = Paravirt::new(STACK_POINTER, vec![]);
+        let (mut regs, mut ram) = call_setup(0xFFF9, &[]);
+        regs.reg_a = 42;
+        assert_eq!(paravirt.call(&mut regs, &mut ram), Some(42));
+    }
+
+    #[test]
+    fn args() {
+        let mut paravirt = Paravirt::new(STACK_POINTER, vec!["prg".into(), "-v".into()]);
+        let (mut regs, mut ram) = call_setup(0xFFF8, &[]);
+        [regs.reg_a, regs.reg_x] = [0x00, 0x03];
+        assert_eq!(paravirt.call(&mut regs, &mut ram), None);
+
+        // Returns from the subroutine.
+        assert_eq!(regs.reg_pc, 0x0383);
+        assert_eq!(regs.reg_sp, 0xFF);
+        assert_eq!((regs.reg_a, regs.reg_x), (2, 0));
+
+        let argv = read_word(&ram, 0x0300);
+        assert_eq!(argv, STACK_TOP - 6);
+        assert_eq!(read_string(&ram, read_word(&ram, argv)), "prg");
+        assert_eq!(read_string(&ram, read_word(&ram, argv + 2)), "-v");
+        assert_eq!(read_word(&ram, argv + 4), 0);
+        assert_eq!(c_stack_pointer(&ram), argv - 7);
+    }
+
+    #[test]
+    fn files() {
+        let mut paravirt = Paravirt::new(STACK_POINTER, vec![]);
+        let file_name = temp_file_name("files");
+
+        // fd = open(name, O_WRONLY | O_CREAT | O_TRUNC)
+        let (mut regs, mut ram) = call_setup(0xFFF4, &[0x1000, 0x32]);
+        ram.bytes[0x1000..0x1000 + file_name.len()].copy_from_slice(file_name.as_bytes());
+        regs.reg_y = 4;
+        paravirt.call(&mut regs, &mut ram);
+        let fd = ax(&regs);
+        assert_eq!(fd, 3);
+        assert_eq!(c_stack_pointer(&ram), STACK_TOP);
+
+        // write(fd, buf, 5)
+        let (mut regs, mut ram) = call_setup(0xFFF7, &[fd, 0x2000]);
+        ram.bytes[0x2000..0x2005].copy_from_slice(b"hello");
+        [regs.reg_a, regs.reg_x] = [5, 0];
+        paravirt.call(&mut regs, &mut ram);
+        assert_eq!(ax(&regs), 5);
+        assert_eq!(c_stack_pointer(&ram), STACK_TOP);
+
+        // close(fd)
+        let (mut regs, mut ram) = call_setup(0xFFF5, &[]);
+        [regs.reg_a, regs.reg_x] = [fd as u8, 0];
+        paravirt.call(&mut regs, &mut ram);
+        assert_eq!(ax(&regs), 0);
+        let (mut regs, mut ram) = call_setup(0xFFF5, &[]);
+        [regs.reg_a, regs.reg_x] = [fd as u8, 0];
+        paravirt.call(&mut regs, &mut ram);
+        assert_eq!(ax(&regs), ERROR);
+
+        // fd = open(name, O_RDONLY, 0)
+        let (mut regs, mut ram) = call_setup(0xFFF4, &[0x1000, 0x01, 0x00]);
+        ram.bytes[0x1000..0x1000 + file_name.len()].copy_from_slice(file_name.as_bytes());
+        regs.reg_y = 6;
+        paravirt.call(&mut regs, &mut ram);
+        assert_eq!(ax(&regs), 3);
+        assert_eq!(c_stack_pointer(&ram), STACK_TOP);
+
+        // read(fd, buf, 10)
+        let (mut regs, mut ram) = call_setup(0xFFF6, &[fd, 0x2000]);
+        [regs.reg_a, regs.reg_x] = [10, 0];
+        paravirt.call(&mut regs, &mut ram);
+        assert_eq!(ax(&regs), 5);
+        assert_eq!(&ram.bytes[0x2000..0x2006], b"hello\0");
+
+        // Reading from a closed file fails.
+        let (mut regs, mut ram) = call_setup(0xFFF6, &[7, 0x2000]);
+        [regs.reg_a, regs.reg_x] = [10, 0];
+        paravirt.call(&mut regs, &mut ram);
+        assert_eq!(ax(&regs), ERROR);
+
+        std::fs::remove_file(file_name).unwrap();
+    }
+}
@@ -0,0 +1,286 @@
+//! Parsing and evaluation of expressions. The operator precedence follows
+//! ca65: unary operators bind the strongest, followed by multiplicative
+//! operators (including bitwise AND, XOR, and shifts), additive operators
+//! (including bitwise OR), comparisons, and finally logical operators.
+
+use super::lexer::Token;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(i64),
+    Symbol(String),
+    /// The current program counter (`*`).
+    ProgramCounter,
+    AnonymousReference(i32),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+/// Provides values of symbols while evaluating an expression. All methods
+/// return `None` if the value is not known (yet).
+pub trait Symbols {
+    fn symbol(&mut self, name: &str) -> Option<i64>;
+    fn program_counter(&self) -> Option<i64>;
+    fn anonymous_label(&mut self, offset: i32) -> Option<i64>;
+}
+
+const BINARY_OPERATORS: [&[&str]; 4] = [
+    &["&&", "||"],
+    &["=", "<>", "<", ">", "<=", ">="],
+    &["+", "-", "|"],
+    &["*", "/", "&", "^", "<<", ">>"],
+];
+
+/// A simple recursive descent parser that consumes tokens from a slice.
+pub struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tokens: &'a [Token]) -> Self {
+        Self {
+            tokens,
+            position: 0,
+        }
+    }
+
+    pub fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position)
+    }
+
+    pub fn peek_at(&self, offset: usize) -> Option<&'a Token> {
+        self.tokens.get(self.position + offset)
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn skip(&mut self, count: usize) {
+        self.position += count;
+    }
+
+    /// Goes back to a given position.
+    pub fn reset(&mut self, position: usize) {
+        self.position = position;
+    }
+
+    pub fn next(&mut self) -> Option<&'a Token> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.position >= self.tokens.len()
+    }
+
+    /// Consumes a given punctuation token, if it's the next one.
+    pub fn eat(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Punct(p)) if *p == punct) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn expect(&mut self, punct: &str) -> Result<(), String> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(format!("Expected '{}'", punct))
+        }
+    }
+
+    pub fn expect_end(&self) -> Result<(), String> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => Err(format!("Unexpected {}", describe(token))),
+        }
+    }
+
+    pub fn expression(&mut self) -> Result<Expr, String> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == BINARY_OPERATORS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(Token::Punct(op)) = self.peek() {
+            if !BINARY_OPERATORS[level].contains(op) {
+                break;
+            }
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Punct(op @ ("-" | "+" | "~" | "!" | "<" | ">" | "^"))) => {
+                Ok(Expr::Unary(op, Box::new(self.unary()?)))
+            }
+            Some(Token::Punct("(")) => {
+                let expr = self.expression()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Punct("*")) => Ok(Expr::ProgramCounter),
+            Some(Token::Number(n)) => Ok(Expr::Number(*n)),
+            // The `scrbyte` function from the `cbm` macro package.
+            Some(Token::Identifier(name))
+                if name == "scrbyte"
+                    && matches!(
+                        self.peek(),
+                        Some(Token::Number(_) | Token::String(_) | Token::Punct("("))
+                    ) =>
+            {
+                Ok(Expr::Unary("scrbyte", Box::new(self.unary()?)))
+            }
+            Some(Token::Identifier(name)) => Ok(Expr::Symbol(name.clone())),
+            Some(Token::AnonymousReference(offset)) => Ok(Expr::AnonymousReference(*offset)),
+            Some(Token::String(s)) if s.len() == 1 => Ok(Expr::Number(s.as_bytes()[0] as i64)),
+            Some(token) => Err(format!("Unexpected {}", describe(token))),
+            None => Err("Expression expected".to_string()),
+        }
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Identifier(name) => format!("'{}'", name),
+        Token::Directive(name) => format!("'.{}'", name),
+        Token::Number(n) => format!("'{}'", n),
+        Token::String(s) => format!("\"{}\"", s),
+        Token::AnonymousReference(_) => "anonymous label reference".to_string(),
+        Token::Punct(p) => format!("'{}'", p),
+    }
+}
+
+impl Expr {
+    /// Evaluates the expression. Returns `Ok(None)` if any of the symbols is
+    /// not known.
+    pub fn evaluate(&self, symbols: &mut impl Symbols) -> Result<Option<i64>, String> {
+        Ok(match self {
+            Expr::Number(n) => Some(*n),
+            Expr::Symbol(name) => symbols.symbol(name),
+            Expr::ProgramCounter => symbols.program_counter(),
+            Expr::AnonymousReference(offset) => symbols.anonymous_label(*offset),
+            Expr::Unary(op, operand) => operand.evaluate(symbols)?.map(|value| match *op {
+                "-" => -value,
+                "~" => !value,
+                "!" => (value == 0) as i64,
+                "<" => value & 0xFF,
+                ">" => (value >> 8) & 0xFF,
+                "^" => (value >> 16) & 0xFF,
+                "scrbyte" => screen_code(value as u8) as i64,
+                _ => value,
+            }),
+            Expr::Binary(op, left, right) => {
+                let (left, right) = match (left.evaluate(symbols)?, right.evaluate(symbols)?) {
+                    (Some(left), Some(right)) => (left, right),
+                    _ => return Ok(None),
+                };
+                Some(match *op {
+                    "&&" => (left != 0 && right != 0) as i64,
+                    "||" => (left != 0 || right != 0) as i64,
+                    "=" => (left == right) as i64,
+                    "<>" => (left != right) as i64,
+                    "<" => (left < right) as i64,
+                    ">" => (left > right) as i64,
+                    "<=" => (left <= right) as i64,
+                    ">=" => (left >= right) as i64,
+                    "+" => left.wrapping_add(right),
+                    "-" => left.wrapping_sub(right),
+                    "|" => left | right,
+                    "*" => left.wrapping_mul(right),
+                    "/" if right == 0 => return Err("Division by zero".to_string()),
+                    "/" => left / right,
+                    "&" => left & right,
+                    "^" => left ^ right,
+                    "<<" => left.wrapping_shl(right as u32),
+                    ">>" => left.wrapping_shr(right as u32),
+                    _ => unreachable!("Unknown operator {}", op),
+                })
+            }
+        })
+    }
+}
+
+/// Converts an ASCII character to a C64 screen code, the same way as ca65
+/// does it for the C64 target: lowercase letters become the unshifted ones,
+/// while uppercase letters become the shifted ones.
+pub fn screen_code(c: u8) -> u8 {
+    match c {
+        b'@' | b'['..=b'_' => c - 0x40,
+        b'a'..=b'z' => c - 0x60,
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::lexer::tokenize;
+    use std::collections::HashMap;
+
+    struct TestSymbols(HashMap<&'static str, i64>);
+
+    impl Symbols for TestSymbols {
+        fn symbol(&mut self, name: &str) -> Option<i64> {
+            self.0.get(name).copied()
+        }
+        fn program_counter(&self) -> Option<i64> {
+            Some(0xF010)
+        }
+        fn anonymous_label(&mut self, offset: i32) -> Option<i64> {
+            Some(0xF000 + offset as i64)
+        }
+    }
+
+    fn evaluate(source: &str) -> Result<Option<i64>, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser::new(&tokens);
+        let expr = parser.expression()?;
+        parser.expect_end()?;
+        expr.evaluate(&mut TestSymbols(HashMap::from([
+            ("Start", 0x1234),
+            ("Scope::Inner", 5),
+        ])))
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(evaluate("1 + 2 * 3"), Ok(Some(7)));
+        assert_eq!(evaluate("(1 + 2) * 3"), Ok(Some(9)));
+        assert_eq!(evaluate("1 | 2 & 3"), Ok(Some(3)));
+        assert_eq!(evaluate("10 - 2 - 3"), Ok(Some(5)));
+        assert_eq!(evaluate("(25 / 2) * 40 + 20 - (13 + 1) / 2"), Ok(Some(493)));
+        assert_eq!(evaluate("1 + 1 = 2 && 3 < 2"), Ok(Some(0)));
+        assert_eq!(evaluate("-2 * -3"), Ok(Some(6)));
+    }
+
+    #[test]
+    fn symbols_and_bytes() {
+        assert_eq!(evaluate("<Start"), Ok(Some(0x34)));
+        assert_eq!(evaluate(">Start + 1"), Ok(Some(0x13)));
+        assert_eq!(evaluate(">Start = >*"), Ok(Some(0)));
+        assert_eq!(evaluate("Scope::Inner << 4"), Ok(Some(0x50)));
+        assert_eq!(evaluate("* - :-"), Ok(Some(0x11)));
+        assert_eq!(evaluate("Unknown + 1"), Ok(None));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(evaluate("1 +").is_err());
+        assert!(evaluate("(1").is_err());
+        assert!(evaluate("1 2").is_err());
+        assert!(evaluate("1 / 0").is_err());
+    }
+}
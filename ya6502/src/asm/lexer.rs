@@ -0,0 +1,250 @@
+//! Splits assembly source lines into tokens.
+
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// A symbol, mnemonic, register, or macro name. Cheap local labels keep
+    /// their `@` prefix, and scoped names keep the `::` separators.
+    Identifier(String),
+    /// A control command, without the leading dot, in lowercase.
+    Directive(String),
+    Number(i64),
+    String(String),
+    /// A reference to an anonymous label: `:+` is 1, `:--` is -2, and so on.
+    AnonymousReference(i32),
+    /// Operators and punctuation.
+    Punct(&'static str),
+}
+
+/// A single line of the source code.
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub file: Rc<str>,
+    /// Line number, counted from 1.
+    pub number: usize,
+    pub text: Rc<str>,
+    pub tokens: Vec<Token>,
+}
+
+/// Punctuation, sorted so that longer operators are matched first.
+const PUNCTUATION: [&str; 25] = [
+    ":=", "<<", ">>", "<>", "<=", ">=", "&&", "||", "(", ")", ",", "#", ":", "=", "+", "-", "*",
+    "/", "&", "|", "^", "~", "!", "<", ">",
+];
+
+/// Splits the whole file into lines of tokens.
+pub fn tokenize_file(file: &str, source: &str) -> Result<Vec<Line>, (usize, String)> {
+    let file: Rc<str> = file.into();
+    source
+        .lines()
+        .enumerate()
+        .map(|(index, text)| {
+            Ok(Line {
+                file: file.clone(),
+                number: index + 1,
+                text: text.trim_end().into(),
+                tokens: tokenize(text).map_err(|message| (index + 1, message))?,
+            })
+        })
+        .collect()
+}
+
+/// Splits a single line into tokens, skipping the comment.
+pub fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == ';' {
+            break;
+        } else if c == '$' || c == '%' || c.is_ascii_digit() {
+            let (radix, start) = match c {
+                '$' => (16, i + 1),
+                '%' => (2, i + 1),
+                _ => (10, i),
+            };
+            let end = scan(&chars, start, |c| c.is_ascii_alphanumeric() || c == '_');
+            let digits: String = chars[start..end].iter().filter(|c| **c != '_').collect();
+            let value = i64::from_str_radix(&digits, radix)
+                .map_err(|_| format!("Invalid number: {}", String::from_iter(&chars[i..end])))?;
+            tokens.push(Token::Number(value));
+            i = end;
+        } else if c == '"' {
+            let end = scan(&chars, i + 1, |c| c != '"');
+            if end == chars.len() {
+                return Err("Unterminated string".to_string());
+            }
+            tokens.push(Token::String(chars[i + 1..end].iter().collect()));
+            i = end + 1;
+        } else if c == '\'' {
+            match chars.get(i + 1..i + 3) {
+                Some([c, '\'']) if c.is_ascii() => tokens.push(Token::Number(*c as i64)),
+                _ => return Err("Invalid character constant".to_string()),
+            }
+            i += 3;
+        } else if c == '.' && matches!(chars.get(i + 1), Some(c) if is_identifier_start(*c)) {
+            let end = scan(&chars, i + 1, is_identifier_char);
+            let name: String = chars[i + 1..end].iter().collect();
+            tokens.push(Token::Directive(name.to_lowercase()));
+            i = end;
+        } else if is_identifier_start(c) || c == '@' || starts_with(&chars[i..], "::") {
+            let mut end = i;
+            loop {
+                if starts_with(&chars[end..], "::") {
+                    end += 2;
+                }
+                if chars.get(end) == Some(&'@') {
+                    end += 1;
+                }
+                let word_end = scan(&chars, end, is_identifier_char);
+                if word_end == end {
+                    return Err(format!(
+                        "Invalid identifier: {}",
+                        String::from_iter(&chars[i..])
+                    ));
+                }
+                end = word_end;
+                if !starts_with(&chars[end..], "::") {
+                    break;
+                }
+            }
+            tokens.push(Token::Identifier(chars[i..end].iter().collect()));
+            i = end;
+        } else if c == ':' && matches!(chars.get(i + 1), Some('+' | '-')) {
+            let sign = chars[i + 1];
+            let end = scan(&chars, i + 1, |c| c == sign);
+            let count = (end - i - 1) as i32;
+            tokens.push(Token::AnonymousReference(if sign == '+' {
+                count
+            } else {
+                -count
+            }));
+            i = end;
+        } else if let Some(punct) = PUNCTUATION.iter().find(|p| starts_with(&chars[i..], p)) {
+            tokens.push(Token::Punct(punct));
+            i += punct.len();
+        } else {
+            return Err(format!("Unexpected character: {:?}", c));
+        }
+    }
+    Ok(tokens)
+}
+
+fn starts_with(chars: &[char], prefix: &str) -> bool {
+    chars.len() >= prefix.len() && prefix.chars().zip(chars).all(|(a, b)| a == *b)
+}
+
+fn scan(chars: &[char], start: usize, predicate: impl Fn(char) -> bool) -> usize {
+    chars[start..]
+        .iter()
+        .position(|c| !predicate(*c))
+        .map_or(chars.len(), |length| start + length)
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Token::*;
+
+    fn identifier(name: &str) -> Token {
+        Identifier(name.to_string())
+    }
+
+    #[test]
+    fn instructions() {
+        assert_eq!(
+            tokenize("Loop:   lda (Ptr),y  ; Comment").unwrap(),
+            [
+                identifier("Loop"),
+                Punct(":"),
+                identifier("lda"),
+                Punct("("),
+                identifier("Ptr"),
+                Punct(")"),
+                Punct(","),
+                identifier("y"),
+            ]
+        );
+        assert_eq!(
+            tokenize("  bne :-- ").unwrap(),
+            [identifier("bne"), AnonymousReference(-2)]
+        );
+        assert_eq!(
+            tokenize(": jmp :+").unwrap(),
+            [Punct(":"), identifier("jmp"), AnonymousReference(1)]
+        );
+    }
+
+    #[test]
+    fn numbers_and_strings() {
+        assert_eq!(
+            tokenize(".BYTE $fF, %0101_0000, 12, 'a', \"Hi; there\"").unwrap(),
+            [
+                Directive("byte".to_string()),
+                Number(0xFF),
+                Punct(","),
+                Number(0b0101_0000),
+                Punct(","),
+                Number(12),
+                Punct(","),
+                Number(b'a' as i64),
+                Punct(","),
+                String("Hi; there".to_string()),
+            ]
+        );
+        assert!(tokenize(".byte $").is_err());
+        assert!(tokenize(".byte 12a").is_err());
+        assert!(tokenize(".byte \"abc").is_err());
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(
+            tokenize("X := <(A << 2) >= >B <> *").unwrap(),
+            [
+                identifier("X"),
+                Punct(":="),
+                Punct("<"),
+                Punct("("),
+                identifier("A"),
+                Punct("<<"),
+                Number(2),
+                Punct(")"),
+                Punct(">="),
+                Punct(">"),
+                identifier("B"),
+                Punct("<>"),
+                Punct("*"),
+            ]
+        );
+    }
+
+    #[test]
+    fn scoped_and_local_names() {
+        assert_eq!(
+            tokenize("sta Vars::Chr + 1, @loop, ::Global").unwrap(),
+            [
+                identifier("sta"),
+                identifier("Vars::Chr"),
+                Punct("+"),
+                Number(1),
+                Punct(","),
+                identifier("@loop"),
+                Punct(","),
+                identifier("::Global"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,1255 @@
+//! A small native assembler for the subset of the ca65 syntax that is used by
+//! the test ROMs: labels (including cheap local `@labels` and anonymous `:`
+//! labels), constants, expressions, data directives, segments, `.org`,
+//! `.proc` and `.scope` blocks, `.repeat`, conditional assembly, and simple
+//! macros with parameters. Just like in ca65, each source file is a separate
+//! module, and only the symbols marked with `.export` or `.global` are visible
+//! in other modules. However, all modules are assembled together, so there's
+//! no separate linking step, and `.import` is accepted, but not needed.
+//! Instead of a linker configuration, the assembler takes a list of segments,
+//! which are laid out in the given order.
+//!
+//! The assembler keeps running passes over the code until the symbol values
+//! stop changing, so that forward references to zero page symbols can use the
+//! zero page addressing modes.
+
+mod expr;
+mod lexer;
+mod tests;
+
+use crate::cpu::instructions::{AddressingMode, NMOS_INSTRUCTIONS};
+use crate::loader::{Image, Segment};
+use expr::{screen_code, Expr, Parser, Symbols};
+use lexer::{tokenize_file, Line, Token};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Maximum number of passes before we give up on waiting for the symbol
+/// values to settle.
+const MAX_PASSES: usize = 10;
+/// Maximum nesting depth of includes and macro expansions.
+const MAX_DEPTH: usize = 64;
+
+const MACRO_DIRECTIVES: (&[&str], &[&str]) = (&["macro", "mac"], &["endmacro", "endmac"]);
+const REPEAT_DIRECTIVES: (&[&str], &[&str]) = (&["repeat", "rept"], &["endrepeat", "endrep"]);
+const IF_DIRECTIVES: (&[&str], &[&str]) = (&["if", "ifdef", "ifndef"], &["endif"]);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    /// Line number, counted from 1, or 0 if the error is not related to any
+    /// particular line.
+    pub line: usize,
+    pub message: String,
+}
+
+impl error::Error for AsmError {}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line > 0 {
+            write!(f, "{}:{}: {}", self.file, self.line, self.message)
+        } else {
+            write!(f, "{}: {}", self.file, self.message)
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, AsmError>;
+
+#[derive(Debug, Clone)]
+struct SegmentConfig {
+    name: String,
+    start: Option<u16>,
+    bss: bool,
+}
+
+/// Assembles programs. By default, there is only a single `CODE` segment that
+/// starts at address 0.
+#[derive(Debug, Clone, Default)]
+pub struct Assembler {
+    segments: Vec<SegmentConfig>,
+    include_dirs: Vec<PathBuf>,
+}
+
+impl Assembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a segment. If the start address is not given, the segment directly
+    /// follows the previous one.
+    pub fn with_segment(mut self, name: &str, start: Option<u16>) -> Self {
+        self.segments.push(SegmentConfig {
+            name: name.to_string(),
+            start,
+            bss: false,
+        });
+        self
+    }
+
+    /// Adds an uninitialized data segment, like `BSS` or `ZEROPAGE`. It can
+    /// only contain `.res` directives, and it's not a part of the resulting
+    /// image.
+    pub fn with_bss_segment(mut self, name: &str, start: Option<u16>) -> Self {
+        self.segments.push(SegmentConfig {
+            name: name.to_string(),
+            start,
+            bss: true,
+        });
+        self
+    }
+
+    /// Adds a directory to look for included files in. Files are first looked
+    /// up relative to the file that includes them.
+    pub fn with_include_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.include_dirs.push(dir.into());
+        self
+    }
+
+    /// Assembles given source files together.
+    pub fn assemble_files<P: AsRef<Path>>(&self, paths: &[P]) -> Result<Assembly> {
+        let mut files = HashMap::new();
+        let roots = paths
+            .iter()
+            .map(|path| load_file(&mut files, path.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+        self.assemble(&roots, &mut files)
+    }
+
+    /// Assembles a source code given as a string. The `name` is only used
+    /// for error messages and as a base for the included file paths.
+    pub fn assemble_source(&self, name: &str, source: &str) -> Result<Assembly> {
+        let lines = tokenize_file(name, source).map_err(|(line, message)| AsmError {
+            file: name.to_string(),
+            line,
+            message,
+        })?;
+        self.assemble(&[lines.into()], &mut HashMap::new())
+    }
+
+    fn assemble(&self, roots: &[Rc<[Line]>], files: &mut FileCache) -> Result<Assembly> {
+        let default_segments = [SegmentConfig {
+            name: "CODE".to_string(),
+            start: Some(0),
+            bss: false,
+        }];
+        let segments = if self.segments.is_empty() {
+            &default_segments[..]
+        } else {
+            &self.segments[..]
+        };
+
+        let mut previous = Tables::default();
+        for pass_number in 1..=MAX_PASSES {
+            let tables = {
+                let mut pass = Pass::new(self, segments, &previous, files);
+                for lines in roots {
+                    pass.process_file(lines)?;
+                }
+                if pass_number > 1 && pass.tables == previous {
+                    if let Some(error) = pass.errors.first() {
+                        return Err(error.clone());
+                    }
+                    return pass.into_assembly();
+                }
+                pass.tables
+            };
+            previous = tables;
+        }
+        Err(AsmError {
+            file: roots
+                .first()
+                .and_then(|lines| lines.first())
+                .map_or(String::new(), |line| line.file.to_string()),
+            line: 0,
+            message: "Symbol values don't settle".to_string(),
+        })
+    }
+}
+
+/// Assembles a single instruction located at a given address, which is useful
+/// for patching the code while debugging.
+pub fn assemble_instruction(source: &str, address: u16) -> Result<Vec<u8>> {
+    let assembly = Assembler::new()
+        .with_segment("CODE", Some(address))
+        .assemble_source("<instruction>", source)?;
+    Ok(assembly
+        .image
+        .segments
+        .into_iter()
+        .flat_map(|segment| segment.bytes)
+        .collect())
+}
+
+/// Result of a successful assembly.
+#[derive(Debug, Clone)]
+pub struct Assembly {
+    pub image: Image,
+    /// Addresses of all labels, by their fully qualified names.
+    pub labels: BTreeMap<String, u16>,
+    pub listing: Vec<ListingLine>,
+    /// Failed `.assert` directives with the `warning` action and `.warning`
+    /// directives.
+    pub warnings: Vec<AsmError>,
+}
+
+impl Assembly {
+    /// Returns the listing as text: each source line, along with its address
+    /// and the bytes that it produced.
+    pub fn listing(&self) -> String {
+        self.listing
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect()
+    }
+
+    /// Returns a symbol map in the VICE label format, sorted by address.
+    pub fn symbol_map(&self) -> String {
+        let mut labels: Vec<_> = self.labels.iter().collect();
+        labels.sort_by_key(|(name, address)| (**address, *name));
+        labels
+            .iter()
+            .map(|(name, address)| format!("al C:{:04X} .{}\n", address, name))
+            .collect()
+    }
+}
+
+/// A single line of the assembly listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListingLine {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub source: String,
+}
+
+impl fmt::Display for ListingLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let mut chunks = self.bytes.chunks(4);
+        let first = chunks.next().unwrap_or_default();
+        write!(
+            f,
+            "{:04X}  {:11}  {}",
+            self.address,
+            hex(first),
+            self.source
+        )?;
+        let mut address = self.address;
+        for chunk in chunks {
+            address = address.wrapping_add(4);
+            write!(f, "\n{:04X}  {}", address, hex(chunk))?;
+        }
+        Ok(())
+    }
+}
+
+type FileCache = HashMap<PathBuf, Rc<[Line]>>;
+
+fn load_file(files: &mut FileCache, path: &Path) -> Result<Rc<[Line]>> {
+    if let Some(lines) = files.get(path) {
+        return Ok(lines.clone());
+    }
+    let name = path.display().to_string();
+    let error = |line, message| AsmError {
+        file: name.clone(),
+        line,
+        message,
+    };
+    let source = fs::read_to_string(path).map_err(|e| error(0, e.to_string()))?;
+    let lines: Rc<[Line]> = tokenize_file(&name, &source)
+        .map_err(|(line, message)| error(line, message))?
+        .into();
+    files.insert(path.to_path_buf(), lines.clone());
+    Ok(lines)
+}
+
+/// Everything that needs to settle before the assembly is complete.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Tables {
+    /// Symbols defined in each module.
+    symbols: Vec<HashMap<String, i64>>,
+    exports: HashSet<String>,
+    anonymous_labels: Vec<i64>,
+    segment_sizes: Vec<u32>,
+}
+
+struct SegmentState {
+    pc: u32,
+    chunks: Vec<Segment>,
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Line>,
+}
+
+/// Forces a given size of an address operand.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum AddressSize {
+    ZeroPage,
+    Absolute,
+}
+
+enum Operand {
+    None,
+    Accumulator,
+    Immediate(Expr),
+    Indirect(Expr),
+    XIndirect(Expr),
+    IndirectY(Expr),
+    /// An address, optionally indexed with X or Y.
+    Direct(Expr, Option<char>, Option<AddressSize>),
+}
+
+/// State of a single assembly pass.
+struct Pass<'a> {
+    assembler: &'a Assembler,
+    segment_configs: &'a [SegmentConfig],
+    previous: &'a Tables,
+    files: &'a mut FileCache,
+    tables: Tables,
+    labels: BTreeMap<String, u16>,
+    segments: Vec<SegmentState>,
+    current_segment: usize,
+    scopes: Vec<String>,
+    anonymous_scopes: usize,
+    /// Name of the last non-local label, to which the cheap local labels
+    /// belong.
+    last_label: String,
+    macros: HashMap<String, Rc<Macro>>,
+    cbm_macros: bool,
+    /// Index of the module (source file) that is being processed.
+    module: usize,
+    depth: usize,
+    file: Rc<str>,
+    line_number: usize,
+    listing: Vec<ListingLine>,
+    /// Errors that may disappear once the symbol values settle.
+    errors: Vec<AsmError>,
+    warnings: Vec<AsmError>,
+}
+
+impl<'a> Pass<'a> {
+    fn new(
+        assembler: &'a Assembler,
+        segment_configs: &'a [SegmentConfig],
+        previous: &'a Tables,
+        files: &'a mut FileCache,
+    ) -> Self {
+        let mut next_start = 0;
+        let segments = segment_configs
+            .iter()
+            .enumerate()
+            .map(|(index, config)| {
+                let start = config.start.map_or(next_start, u32::from);
+                next_start = start + previous.segment_sizes.get(index).copied().unwrap_or(0);
+                SegmentState {
+                    pc: start,
+                    chunks: vec![],
+                }
+            })
+            .collect();
+        Self {
+            assembler,
+            segment_configs,
+            previous,
+            files,
+            tables: Tables {
+                segment_sizes: vec![0; segment_configs.len()],
+                ..Tables::default()
+            },
+            labels: BTreeMap::new(),
+            segments,
+            current_segment: 0,
+            scopes: vec![],
+            anonymous_scopes: 0,
+            last_label: String::new(),
+            macros: HashMap::new(),
+            cbm_macros: false,
+            module: 0,
+            depth: 0,
+            file: "".into(),
+            line_number: 0,
+            listing: vec![],
+            errors: vec![],
+            warnings: vec![],
+        }
+    }
+
+    fn into_assembly(self) -> Result<Assembly> {
+        let mut image = Image::default();
+        for (config, segment) in self.segment_configs.iter().zip(self.segments) {
+            if config.bss {
+                continue;
+            }
+            for chunk in segment.chunks {
+                image
+                    .add(chunk.address as u32, &chunk.bytes)
+                    .map_err(|e| AsmError {
+                        file: config.name.clone(),
+                        line: 0,
+                        message: e.to_string(),
+                    })?;
+            }
+        }
+        Ok(Assembly {
+            image,
+            labels: self.labels,
+            listing: self.listing,
+            warnings: self.warnings,
+        })
+    }
+
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError {
+            file: self.file.to_string(),
+            line: self.line_number,
+            message: message.into(),
+        }
+    }
+
+    /// Records an error that only gets reported if it persists after the
+    /// symbol values settle.
+    fn defer_error(&mut self, message: impl Into<String>) {
+        self.errors.push(self.error(message));
+    }
+
+    fn process_file(&mut self, lines: &[Line]) -> Result<()> {
+        self.module = self.tables.symbols.len();
+        self.tables.symbols.push(HashMap::new());
+        self.macros.clear();
+        self.cbm_macros = false;
+        self.scopes.clear();
+        self.last_label.clear();
+        self.current_segment = self.segment_index("CODE").unwrap_or(0);
+        self.process(lines)?;
+        if !self.scopes.is_empty() {
+            return Err(self.error("Missing .endscope or .endproc"));
+        }
+        Ok(())
+    }
+
+    fn process(&mut self, lines: &[Line]) -> Result<()> {
+        let mut index = 0;
+        while index < lines.len() {
+            index = self.process_line(lines, index)?;
+        }
+        Ok(())
+    }
+
+    /// Processes a line with a given index and returns the index of the next
+    /// line to process.
+    fn process_line(&mut self, lines: &[Line], index: usize) -> Result<usize> {
+        let line = &lines[index];
+        self.file = line.file.clone();
+        self.line_number = line.number;
+        self.listing.push(ListingLine {
+            address: self.pc() as u16,
+            bytes: vec![],
+            source: line.text.to_string(),
+        });
+
+        let mut parser = Parser::new(&line.tokens);
+        loop {
+            match (parser.peek(), parser.peek_at(1)) {
+                (Some(Token::Identifier(name)), Some(Token::Punct(":"))) => {
+                    self.define(name, self.pc() as i64, true)?;
+                    parser.skip(2);
+                }
+                (Some(Token::Punct(":")), _) => {
+                    let pc = self.pc() as i64;
+                    self.tables.anonymous_labels.push(pc);
+                    parser.skip(1);
+                }
+                _ => break,
+            }
+        }
+
+        match (parser.next(), parser.peek()) {
+            (None, _) => {}
+            (Some(Token::Identifier(name)), Some(Token::Punct(op @ ("=" | ":=")))) => {
+                parser.skip(1);
+                let value = self.expression(&mut parser)?;
+                parser.expect_end().map_err(|e| self.error(e))?;
+                if let Some(value) = value {
+                    self.define(name, value, *op == ":=")?;
+                }
+            }
+            (Some(Token::Directive(directive)), _) => {
+                return self.directive(directive, &mut parser, lines, index);
+            }
+            (Some(Token::Identifier(name)), _) => {
+                if let Some(macro_definition) = self.macros.get(name).cloned() {
+                    self.expand_macro(&macro_definition, &line.tokens[parser.position()..])?;
+                } else if self.cbm_macros && name == "scrcode" {
+                    self.scrcode(&mut parser)?;
+                } else {
+                    self.instruction(name, &mut parser)?;
+                }
+            }
+            (Some(_), _) => return Err(self.error("Instruction expected")),
+        }
+        Ok(index + 1)
+    }
+
+    fn directive(
+        &mut self,
+        directive: &str,
+        parser: &mut Parser,
+        lines: &[Line],
+        index: usize,
+    ) -> Result<usize> {
+        match directive {
+            "byte" | "byt" => self.data(parser, 1, false)?,
+            "word" | "addr" => self.data(parser, 2, false)?,
+            "dbyt" => self.data(parser, 2, true)?,
+            "asciiz" => {
+                self.data(parser, 1, false)?;
+                self.emit(&[0])?;
+            }
+            "res" => {
+                let count = self.expression(parser)?;
+                let fill = if parser.eat(",") {
+                    self.byte_expression(parser)?
+                } else {
+                    0
+                };
+                parser.expect_end().map_err(|e| self.error(e))?;
+                match count {
+                    Some(count) if (0..=0x10000).contains(&count) => {
+                        self.reserve(count as u32, fill)?
+                    }
+                    Some(_) => self.defer_error("Invalid .res size"),
+                    None => {}
+                }
+            }
+            "align" => {
+                let alignment = self.constant_expression(parser)?;
+                let fill = if parser.eat(",") {
+                    self.byte_expression(parser)?
+                } else {
+                    0
+                };
+                parser.expect_end().map_err(|e| self.error(e))?;
+                if !(1..=0x10000).contains(&alignment) {
+                    return Err(self.error("Invalid alignment"));
+                }
+                let pc = self.pc() as i64;
+                let padding = (alignment - pc % alignment) % alignment;
+                self.reserve(padding as u32, fill)?;
+            }
+            "org" => {
+                let address = self.expression(parser)?;
+                parser.expect_end().map_err(|e| self.error(e))?;
+                if let Some(address) = address {
+                    if !(0..=0xFFFF).contains(&address) {
+                        return Err(self.error("Address out of range"));
+                    }
+                    self.segments[self.current_segment].pc = address as u32;
+                }
+            }
+            "segment" => {
+                let name = self.string(parser)?;
+                parser.expect_end().map_err(|e| self.error(e))?;
+                self.switch_segment(&name)?;
+            }
+            "code" | "rodata" | "data" | "bss" | "zeropage" => {
+                parser.expect_end().map_err(|e| self.error(e))?;
+                self.switch_segment(&directive.to_uppercase())?;
+            }
+            "include" => {
+                let name = self.string(parser)?;
+                parser.expect_end().map_err(|e| self.error(e))?;
+                self.include(&name)?;
+            }
+            "proc" => {
+                let name = self.identifier(parser)?;
+                parser.expect_end().map_err(|e| self.error(e))?;
+                self.define(&name, self.pc() as i64, true)?;
+                self.scopes.push(name);
+            }
+            "scope" => {
+                let name = if parser.at_end() {
+                    self.anonymous_scopes += 1;
+                    format!("__scope{}", self.anonymous_scopes)
+                } else {
+                    self.identifier(parser)?
+                };
+                parser.expect_end().map_err(|e| self.error(e))?;
+                self.scopes.push(name);
+            }
+            "endproc" | "endscope" => {
+                parser.expect_end().map_err(|e| self.error(e))?;
+                if self.scopes.pop().is_none() {
+                    return Err(self.error(format!("Unexpected .{}", directive)));
+                }
+            }
+            "macro" | "mac" => {
+                let name = self.identifier(parser)?;
+                let mut parameters = vec![];
+                while !parser.at_end() {
+                    if !parameters.is_empty() {
+                        parser.expect(",").map_err(|e| self.error(e))?;
+                    }
+                    parameters.push(self.identifier(parser)?);
+                }
+                let end = self.find_block_end(lines, index, MACRO_DIRECTIVES)?;
+                let body = lines[index + 1..end.0].to_vec();
+                self.macros
+                    .insert(name, Rc::new(Macro { parameters, body }));
+                return Ok(end.0 + 1);
+            }
+            "repeat" | "rept" => {
+                let count = self.constant_expression(parser)?;
+                let variable = if parser.eat(",") {
+                    Some(self.identifier(parser)?)
+                } else {
+                    None
+                };
+                parser.expect_end().map_err(|e| self.error(e))?;
+                let end = self.find_block_end(lines, index, REPEAT_DIRECTIVES)?;
+                let body = &lines[index + 1..end.0];
+                for i in 0..count {
+                    match &variable {
+                        Some(variable) => {
+                            let replacement = [Token::Number(i)];
+                            let body = substitute(body, &[variable.as_str()], &[&replacement[..]]);
+                            self.process_nested(&body)?;
+                        }
+                        None => self.process_nested(body)?,
+                    }
+                }
+                return Ok(end.0 + 1);
+            }
+            "if" | "ifdef" | "ifndef" => {
+                let condition = match directive {
+                    "if" => self.constant_expression(parser)? != 0,
+                    _ => {
+                        let name = self.identifier(parser)?;
+                        let defined = self.is_defined(&name);
+                        defined == (directive == "ifdef")
+                    }
+                };
+                parser.expect_end().map_err(|e| self.error(e))?;
+                let (end, else_index) = self.find_block_end(lines, index, IF_DIRECTIVES)?;
+                let branch = match (condition, else_index) {
+                    (true, Some(else_index)) => &lines[index + 1..else_index],
+                    (true, None) => &lines[index + 1..end],
+                    (false, Some(else_index)) => &lines[else_index + 1..end],
+                    (false, None) => &[],
+                };
+                self.process(branch)?;
+                return Ok(end + 1);
+            }
+            "assert" => {
+                let value = self.expression(parser)?;
+                parser.expect(",").map_err(|e| self.error(e))?;
+                let action = self.identifier(parser)?.to_lowercase();
+                let message = if parser.eat(",") {
+                    self.string(parser)?
+                } else {
+                    "Assertion failed".to_string()
+                };
+                parser.expect_end().map_err(|e| self.error(e))?;
+                if value == Some(0) {
+                    match action.as_str() {
+                        "error" | "lderror" => self.defer_error(message),
+                        "warning" | "ldwarning" => self.warnings.push(self.error(message)),
+                        _ => return Err(self.error(format!("Unknown action: {}", action))),
+                    }
+                }
+            }
+            "error" => return Err(self.error(self.string(parser)?)),
+            "warning" => {
+                let message = self.string(parser)?;
+                self.warnings.push(self.error(message));
+            }
+            "macpack" => {
+                if self.identifier(parser)? == "cbm" {
+                    self.cbm_macros = true;
+                }
+            }
+            "export" | "exportzp" | "global" | "globalzp" => loop {
+                let name = self.identifier(parser)?;
+                self.tables.exports.insert(name);
+                if !parser.eat(",") {
+                    parser.expect_end().map_err(|e| self.error(e))?;
+                    break;
+                }
+            },
+            "import" | "importzp" | "setcpu" | "p02" | "debuginfo" | "list" | "listbytes"
+            | "smart" | "autoimport" => {}
+            "endmacro" | "endmac" | "endrepeat" | "endrep" | "else" | "endif" => {
+                return Err(self.error(format!("Unexpected .{}", directive)))
+            }
+            _ => return Err(self.error(format!("Unsupported directive: .{}", directive))),
+        }
+        Ok(index + 1)
+    }
+
+    /// Processes lines generated by a macro or a repeat block.
+    fn process_nested(&mut self, lines: &[Line]) -> Result<()> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error("Too many nested expansions"));
+        }
+        self.depth += 1;
+        let result = self.process(lines);
+        self.depth -= 1;
+        result
+    }
+
+    /// Finds the line that closes a block started at a given line. Returns
+    /// its index, along with the index of the `.else` line, if there is one.
+    fn find_block_end(
+        &self,
+        lines: &[Line],
+        start: usize,
+        (openers, closers): (&[&str], &[&str]),
+    ) -> Result<(usize, Option<usize>)> {
+        let mut depth = 0;
+        let mut else_index = None;
+        for (index, line) in lines.iter().enumerate().skip(start + 1) {
+            match line_directive(line) {
+                Some(directive) if openers.contains(&directive) => depth += 1,
+                Some(directive) if closers.contains(&directive) => {
+                    if depth == 0 {
+                        return Ok((index, else_index));
+                    }
+                    depth -= 1;
+                }
+                Some("else") if depth == 0 => else_index = Some(index),
+                _ => {}
+            }
+        }
+        Err(self.error(format!("Missing .{}", closers[0])))
+    }
+
+    fn expand_macro(&mut self, macro_definition: &Macro, arguments: &[Token]) -> Result<()> {
+        let mut arguments: Vec<&[Token]> = split_arguments(arguments);
+        if arguments.len() > macro_definition.parameters.len() {
+            return Err(self.error("Too many macro arguments"));
+        }
+        arguments.resize(macro_definition.parameters.len(), &[] as &[Token]);
+        let parameters: Vec<&str> = macro_definition
+            .parameters
+            .iter()
+            .map(String::as_str)
+            .collect();
+        let body = substitute(&macro_definition.body, &parameters, &arguments);
+        self.process_nested(&body)
+    }
+
+    fn include(&mut self, name: &str) -> Result<()> {
+        let current_dir = Path::new(&*self.file).parent().map(Path::to_path_buf);
+        let path = current_dir
+            .iter()
+            .chain(&self.assembler.include_dirs)
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+            .ok_or_else(|| self.error(format!("Unable to find file: {}", name)))?;
+        let lines = load_file(self.files, &path)?;
+        let (file, line_number) = (self.file.clone(), self.line_number);
+        self.process_nested(&lines)?;
+        self.file = file;
+        self.line_number = line_number;
+        Ok(())
+    }
+
+    fn instruction(&mut self, mnemonic: &str, parser: &mut Parser) -> Result<()> {
+        let mnemonic = mnemonic.to_uppercase();
+        if !NMOS_INSTRUCTIONS
+            .iter()
+            .any(|info| info.mnemonic == mnemonic)
+        {
+            return Err(self.error(format!("Unknown instruction: {}", mnemonic)));
+        }
+        let supports = |mode| opcode(&mnemonic, mode).is_some();
+
+        use AddressingMode::*;
+        let operand = self.operand(parser, supports(Indirect))?;
+        let (mode, value) = match operand {
+            Operand::None if supports(Implied) => (Implied, None),
+            Operand::None | Operand::Accumulator => (Accumulator, None),
+            Operand::Immediate(expr) => (Immediate, self.evaluate(&expr)?),
+            Operand::Indirect(expr) => (Indirect, self.evaluate(&expr)?),
+            Operand::XIndirect(expr) => (ZeroPageXIndirect, self.evaluate(&expr)?),
+            Operand::IndirectY(expr) => (ZeroPageIndirectY, self.evaluate(&expr)?),
+            Operand::Direct(expr, None, _) if supports(Relative) => {
+                (Relative, self.evaluate(&expr)?)
+            }
+            Operand::Direct(expr, index, size) => {
+                let value = self.evaluate(&expr)?;
+                let (zero_page, absolute) = match index {
+                    None => (ZeroPage, Absolute),
+                    Some('x') => (ZeroPageIndexedX, AbsoluteIndexedX),
+                    Some(_) => (ZeroPageIndexedY, AbsoluteIndexedY),
+                };
+                let fits_zero_page = matches!(value, Some(value) if (0..=0xFF).contains(&value));
+                let use_zero_page = match size {
+                    Some(size) => size == AddressSize::ZeroPage,
+                    None => supports(zero_page) && (fits_zero_page || !supports(absolute)),
+                };
+                (if use_zero_page { zero_page } else { absolute }, value)
+            }
+        };
+        let opcode = opcode(&mnemonic, mode)
+            .ok_or_else(|| self.error(format!("Unsupported addressing mode for {}", mnemonic)))?;
+
+        let operand_bytes = match mode.instruction_length() {
+            1 => vec![],
+            2 if mode == Relative => {
+                let offset = value.map_or(0, |target| target - (self.pc() as i64 + 2));
+                if !(-128..=127).contains(&offset) {
+                    self.defer_error("Branch out of range");
+                }
+                vec![offset as u8]
+            }
+            2 => {
+                let value = value.unwrap_or(0);
+                let range = if mode == Immediate {
+                    -128..=0xFF
+                } else {
+                    0..=0xFF
+                };
+                if !range.contains(&value) {
+                    self.defer_error("Range error");
+                }
+                vec![value as u8]
+            }
+            _ => {
+                let value = value.unwrap_or(0);
+                if !(0..=0xFFFF).contains(&value) {
+                    self.defer_error("Range error");
+                }
+                (value as u16).to_le_bytes().to_vec()
+            }
+        };
+        self.emit(&[opcode])?;
+        self.emit(&operand_bytes)
+    }
+
+    fn operand(&self, parser: &mut Parser, indirect: bool) -> Result<Operand> {
+        let start = parser.position();
+        match parser.peek() {
+            None => return Ok(Operand::None),
+            Some(Token::Identifier(name)) if name.eq_ignore_ascii_case("a") => {
+                parser.skip(1);
+                if parser.at_end() {
+                    return Ok(Operand::Accumulator);
+                }
+                parser.reset(start);
+            }
+            Some(Token::Punct("#")) => {
+                parser.skip(1);
+                let expr = parser.expression().map_err(|e| self.error(e))?;
+                parser.expect_end().map_err(|e| self.error(e))?;
+                return Ok(Operand::Immediate(expr));
+            }
+            Some(Token::Punct("(")) => {
+                parser.skip(1);
+                let expr = parser.expression().map_err(|e| self.error(e))?;
+                if parser.eat(",") {
+                    if index_register(parser) == Some('x') && parser.eat(")") && parser.at_end() {
+                        return Ok(Operand::XIndirect(expr));
+                    }
+                    return Err(self.error("Invalid indirect addressing"));
+                }
+                if parser.eat(")") {
+                    if parser.at_end() && indirect {
+                        return Ok(Operand::Indirect(expr));
+                    }
+                    if parser.eat(",") {
+                        if index_register(parser) == Some('y') && parser.at_end() {
+                            return Ok(Operand::IndirectY(expr));
+                        }
+                        return Err(self.error("Invalid indirect addressing"));
+                    }
+                }
+                parser.reset(start);
+            }
+            _ => {}
+        }
+
+        let size = match (parser.peek(), parser.peek_at(1)) {
+            (Some(Token::Identifier(prefix)), Some(Token::Punct(":"))) => {
+                let size = match prefix.to_lowercase().as_str() {
+                    "z" | "zp" => AddressSize::ZeroPage,
+                    "a" | "abs" => AddressSize::Absolute,
+                    _ => return Err(self.error(format!("Invalid address size: {}", prefix))),
+                };
+                parser.skip(2);
+                Some(size)
+            }
+            _ => None,
+        };
+        let expr = parser.expression().map_err(|e| self.error(e))?;
+        let index = if parser.eat(",") {
+            Some(index_register(parser).ok_or_else(|| self.error("Expected X or Y"))?)
+        } else {
+            None
+        };
+        parser.expect_end().map_err(|e| self.error(e))?;
+        Ok(Operand::Direct(expr, index, size))
+    }
+
+    /// Emits data items: expressions and strings.
+    fn data(&mut self, parser: &mut Parser, size: usize, big_endian: bool) -> Result<()> {
+        loop {
+            match parser.peek() {
+                Some(Token::String(s)) if size == 1 => {
+                    parser.skip(1);
+                    self.emit(s.as_bytes())?;
+                }
+                _ => {
+                    let value = self.expression(parser)?.unwrap_or(0);
+                    let range = if size == 1 {
+                        -0x80..=0xFF
+                    } else {
+                        -0x8000..=0xFFFF
+                    };
+                    if !range.contains(&value) {
+                        self.defer_error("Range error");
+                    }
+                    let bytes = (value as u16).to_le_bytes();
+                    match (size, big_endian) {
+                        (1, _) => self.emit(&bytes[..1])?,
+                        (_, false) => self.emit(&bytes)?,
+                        (_, true) => self.emit(&[bytes[1], bytes[0]])?,
+                    }
+                }
+            }
+            if !parser.eat(",") {
+                break;
+            }
+        }
+        parser.expect_end().map_err(|e| self.error(e))
+    }
+
+    /// Implements the `scrcode` macro from the `cbm` macro package, which
+    /// converts strings to C64 screen codes.
+    fn scrcode(&mut self, parser: &mut Parser) -> Result<()> {
+        loop {
+            match parser.peek() {
+                Some(Token::String(s)) => {
+                    parser.skip(1);
+                    let bytes: Vec<u8> = s.bytes().map(screen_code).collect();
+                    self.emit(&bytes)?;
+                }
+                _ => {
+                    let value = self.byte_expression(parser)?;
+                    self.emit(&[value])?;
+                }
+            }
+            if !parser.eat(",") {
+                break;
+            }
+        }
+        parser.expect_end().map_err(|e| self.error(e))
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<()> {
+        if self.segment_configs[self.current_segment].bss {
+            return Err(self.error("Data in an uninitialized segment"));
+        }
+        let segment = &mut self.segments[self.current_segment];
+        let pc = segment.pc;
+        match segment.chunks.last_mut() {
+            Some(chunk) if chunk.address as u32 + chunk.bytes.len() as u32 == pc => {
+                chunk.bytes.extend_from_slice(bytes)
+            }
+            _ => segment.chunks.push(Segment {
+                address: pc as u16,
+                bytes: bytes.to_vec(),
+            }),
+        }
+        self.advance(bytes.len() as u32);
+        if let Some(line) = self.listing.last_mut() {
+            line.bytes.extend_from_slice(bytes);
+        }
+        Ok(())
+    }
+
+    fn reserve(&mut self, count: u32, fill: u8) -> Result<()> {
+        if self.segment_configs[self.current_segment].bss {
+            self.advance(count);
+            Ok(())
+        } else {
+            self.emit(&vec![fill; count as usize])
+        }
+    }
+
+    fn advance(&mut self, count: u32) {
+        let segment = &mut self.segments[self.current_segment];
+        segment.pc += count;
+        self.tables.segment_sizes[self.current_segment] += count;
+        if segment.pc > 0x10000 {
+            self.defer_error("Program counter out of range");
+        }
+    }
+
+    fn pc(&self) -> u32 {
+        self.segments[self.current_segment].pc
+    }
+
+    fn segment_index(&self, name: &str) -> Option<usize> {
+        self.segment_configs
+            .iter()
+            .position(|config| config.name == name)
+    }
+
+    fn switch_segment(&mut self, name: &str) -> Result<()> {
+        self.current_segment = self
+            .segment_index(name)
+            .ok_or_else(|| self.error(format!("Unknown segment: {}", name)))?;
+        Ok(())
+    }
+
+    /// Defines a symbol in the current scope.
+    fn define(&mut self, name: &str, value: i64, label: bool) -> Result<()> {
+        let qualified = self.qualify(name);
+        if self.tables.symbols[self.module]
+            .insert(qualified.clone(), value)
+            .is_some()
+        {
+            return Err(self.error(format!("Symbol already defined: {}", name)));
+        }
+        if label {
+            self.labels.insert(qualified.clone(), value as u16);
+            if !name.starts_with('@') {
+                self.last_label = qualified;
+            }
+        }
+        Ok(())
+    }
+
+    fn qualify(&self, name: &str) -> String {
+        if name.starts_with('@') {
+            format!("{}{}", self.last_label, name)
+        } else if let Some(name) = name.strip_prefix("::") {
+            name.to_string()
+        } else {
+            self.scopes
+                .iter()
+                .map(String::as_str)
+                .chain([name])
+                .collect::<Vec<_>>()
+                .join("::")
+        }
+    }
+
+    /// Returns possible fully qualified names of a symbol referenced in the
+    /// current scope, starting with the innermost scope.
+    fn candidates(&self, name: &str) -> Vec<String> {
+        if name.starts_with('@') || name.starts_with("::") {
+            return vec![self.qualify(name)];
+        }
+        (0..=self.scopes.len())
+            .rev()
+            .map(|depth| {
+                self.scopes[..depth]
+                    .iter()
+                    .map(String::as_str)
+                    .chain([name])
+                    .collect::<Vec<_>>()
+                    .join("::")
+            })
+            .collect()
+    }
+
+    /// Looks up a symbol in the current module first, and then among the
+    /// symbols exported by other modules.
+    fn lookup(&self, name: &str) -> Option<i64> {
+        let local = self
+            .candidates(name)
+            .iter()
+            .find_map(|candidate| self.module_symbol(self.module, candidate));
+        let name = name.strip_prefix("::").unwrap_or(name);
+        if local.is_some()
+            || !(self.tables.exports.contains(name) || self.previous.exports.contains(name))
+        {
+            return local;
+        }
+        (0..self.tables.symbols.len().max(self.previous.symbols.len()))
+            .filter(|module| *module != self.module)
+            .find_map(|module| self.module_symbol(module, name))
+    }
+
+    /// Returns the value of a symbol defined in a given module, falling back
+    /// to the previous pass if it's not defined yet.
+    fn module_symbol(&self, module: usize, name: &str) -> Option<i64> {
+        [
+            self.tables.symbols.get(module),
+            self.previous.symbols.get(module),
+        ]
+        .into_iter()
+        .flatten()
+        .find_map(|symbols| symbols.get(name))
+        .copied()
+    }
+
+    fn is_defined(&self, name: &str) -> bool {
+        self.candidates(name)
+            .iter()
+            .any(|candidate| self.tables.symbols[self.module].contains_key(candidate))
+    }
+
+    fn evaluate(&mut self, expr: &Expr) -> Result<Option<i64>> {
+        expr.evaluate(self).map_err(|e| self.error(e))
+    }
+
+    fn expression(&mut self, parser: &mut Parser) -> Result<Option<i64>> {
+        let expr = parser.expression().map_err(|e| self.error(e))?;
+        self.evaluate(&expr)
+    }
+
+    /// Evaluates an expression that needs to be known right away.
+    fn constant_expression(&mut self, parser: &mut Parser) -> Result<i64> {
+        self.expression(parser)?
+            .ok_or_else(|| self.error("Constant expression expected"))
+    }
+
+    fn byte_expression(&mut self, parser: &mut Parser) -> Result<u8> {
+        let value = self.expression(parser)?.unwrap_or(0);
+        if !(-0x80..=0xFF).contains(&value) {
+            self.defer_error("Range error");
+        }
+        Ok(value as u8)
+    }
+
+    fn identifier(&self, parser: &mut Parser) -> Result<String> {
+        match parser.next() {
+            Some(Token::Identifier(name)) => Ok(name.clone()),
+            _ => Err(self.error("Identifier expected")),
+        }
+    }
+
+    fn string(&self, parser: &mut Parser) -> Result<String> {
+        match parser.next() {
+            Some(Token::String(s)) => Ok(s.clone()),
+            _ => Err(self.error("String expected")),
+        }
+    }
+}
+
+impl Symbols for Pass<'_> {
+    fn symbol(&mut self, name: &str) -> Option<i64> {
+        let value = self.lookup(name);
+        if value.is_none() {
+            self.defer_error(format!("Undefined symbol: {}", name));
+        }
+        value
+    }
+
+    fn program_counter(&self) -> Option<i64> {
+        Some(self.pc() as i64)
+    }
+
+    fn anonymous_label(&mut self, offset: i32) -> Option<i64> {
+        let defined = self.tables.anonymous_labels.len() as i64;
+        let value = if offset < 0 {
+            usize::try_from(defined + offset as i64)
+                .ok()
+                .and_then(|index| self.tables.anonymous_labels.get(index))
+        } else {
+            self.previous
+                .anonymous_labels
+                .get((defined + offset as i64 - 1) as usize)
+        }
+        .copied();
+        if value.is_none() {
+            self.defer_error("Anonymous label not found");
+        }
+        value
+    }
+}
+
+/// Returns the opcode of a given instruction. Documented opcodes take
+/// precedence over undocumented ones.
+fn opcode(mnemonic: &str, mode: AddressingMode) -> Option<u8> {
+    let find = |documented| {
+        NMOS_INSTRUCTIONS.iter().position(|info| {
+            info.mnemonic == mnemonic
+                && info.addressing_mode == mode
+                && info.documented == documented
+        })
+    };
+    find(true)
+        .or_else(|| find(false))
+        .map(|opcode| opcode as u8)
+}
+
+fn index_register(parser: &mut Parser) -> Option<char> {
+    match parser.next() {
+        Some(Token::Identifier(name)) if name.eq_ignore_ascii_case("x") => Some('x'),
+        Some(Token::Identifier(name)) if name.eq_ignore_ascii_case("y") => Some('y'),
+        _ => None,
+    }
+}
+
+/// Returns the directive of a given line, skipping its labels.
+fn line_directive(line: &Line) -> Option<&str> {
+    let mut tokens = &line.tokens[..];
+    loop {
+        match tokens {
+            [Token::Identifier(_), Token::Punct(":"), rest @ ..]
+            | [Token::Punct(":"), rest @ ..] => tokens = rest,
+            [Token::Directive(directive), ..] => return Some(directive),
+            _ => return None,
+        }
+    }
+}
+
+/// Splits macro arguments on commas that are not enclosed in parentheses.
+fn split_arguments(tokens: &[Token]) -> Vec<&[Token]> {
+    if tokens.is_empty() {
+        return vec![];
+    }
+    let mut arguments = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Punct("(") => depth += 1,
+            Token::Punct(")") => depth -= 1,
+            Token::Punct(",") if depth == 0 => {
+                arguments.push(&tokens[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    arguments.push(&tokens[start..]);
+    arguments
+}
+
+/// Replaces identifiers with given token sequences.
+fn substitute(lines: &[Line], names: &[&str], replacements: &[&[Token]]) -> Vec<Line> {
+    lines
+        .iter()
+        .map(|line| Line {
+            tokens: line
+                .tokens
+                .iter()
+                .flat_map(|token| match token {
+                    Token::Identifier(name) => match names.iter().position(|n| n == name) {
+                        Some(index) => replacements[index].to_vec(),
+                        None => vec![token.clone()],
+                    },
+                    _ => vec![token.clone()],
+                })
+                .collect(),
+            ..line.clone()
+        })
+        .collect()
+}
@@ -0,0 +1,9 @@
+.include "registers.inc"
+
+.export SetBackground
+
+.segment "CODE"
+.proc SetBackground
+            SetColor $0E
+            rts
+.endproc
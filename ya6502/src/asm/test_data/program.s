@@ -0,0 +1,17 @@
+.include "registers.inc"
+
+.import SetBackground
+
+.segment "ZEROPAGE"
+Counter:    .res 1
+
+.segment "CODE"
+Reset:      ldx #0
+            stx Counter
+:           jsr SetBackground
+            inc Counter
+            bne :-
+            jmp Reset
+
+.segment "VECTORS"
+            .word Reset, Reset, Reset
@@ -0,0 +1,578 @@
+#![cfg(test)]
+
+use super::*;
+use crate::cpu::Cpu;
+use crate::memory::Ram;
+use crate::test_utils::reset;
+use std::env;
+use std::ffi::OsStr;
+use std::process;
+use std::process::Command;
+
+fn test_data_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src/asm/test_data")
+        .join(name)
+}
+
+/// Assembles a source code at 0xF000 and returns the resulting bytes.
+fn assemble(source: &str) -> Vec<u8> {
+    let assembly = Assembler::new()
+        .with_segment("CODE", Some(0xF000))
+        .assemble_source("test.s", source)
+        .unwrap();
+    assembly
+        .image
+        .segments
+        .into_iter()
+        .flat_map(|segment| segment.bytes)
+        .collect()
+}
+
+fn assembly_error(source: &str) -> (usize, String) {
+    let error = Assembler::new()
+        .with_segment("CODE", Some(0xF000))
+        .assemble_source("test.s", source)
+        .unwrap_err();
+    (error.line, error.message)
+}
+
+#[test]
+fn addressing_modes() {
+    assert_eq!(
+        assemble(
+            "
+            nop
+            asl
+            asl a
+            lda #$12
+            lda $12
+            lda $12,x
+            ldx $12,Y
+            lda $1234
+            lda $1234,x
+            lda $1234,y
+            lda $12,y
+            lda ($12,x)
+            lda ($12),y
+            jmp ($1234)
+            jmp ($12 + 1) * 2
+            lda a:$12
+            LDA z:Later
+            lda ($12 + 1) * 2,x
+Later = $0034
+            "
+        ),
+        [
+            0xEA, //
+            0x0A, //
+            0x0A, //
+            0xA9, 0x12, //
+            0xA5, 0x12, //
+            0xB5, 0x12, //
+            0xB6, 0x12, //
+            0xAD, 0x34, 0x12, //
+            0xBD, 0x34, 0x12, //
+            0xB9, 0x34, 0x12, //
+            0xB9, 0x12, 0x00, //
+            0xA1, 0x12, //
+            0xB1, 0x12, //
+            0x6C, 0x34, 0x12, //
+            0x4C, 0x26, 0x00, //
+            0xAD, 0x12, 0x00, //
+            0xA5, 0x34, //
+            0xB5, 0x26, //
+        ]
+    );
+}
+
+#[test]
+fn labels_and_branches() {
+    assert_eq!(
+        assemble(
+            "
+Start:      ldx #3
+Loop:       dex
+            bne Loop
+            beq Forward
+            nop
+Forward:    jmp Start
+            "
+        ),
+        [0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0xF0, 0x01, 0xEA, 0x4C, 0x00, 0xF0]
+    );
+}
+
+#[test]
+fn zero_page_forward_references() {
+    assert_eq!(
+        assemble(
+            "
+            lda Var
+            sta Var + 1,x
+            lda Far
+Var = $80
+Far = $1234
+            "
+        ),
+        [0xA5, 0x80, 0x95, 0x81, 0xAD, 0x34, 0x12]
+    );
+}
+
+#[test]
+fn anonymous_and_local_labels() {
+    let assembly = Assembler::new()
+        .with_segment("CODE", Some(0xF000))
+        .assemble_source(
+            "test.s",
+            "
+First:      ldx #2
+@loop:      dex
+            bne @loop
+Second:     ldy #2
+@loop:      dey
+            bne @loop
+:           beq :+
+            bne :-
+:           rts
+            ",
+        )
+        .unwrap();
+    assert_eq!(
+        assembly.image.segments[0].bytes,
+        [
+            0xA2, 0x02, 0xCA, 0xD0, 0xFD, // First
+            0xA0, 0x02, 0x88, 0xD0, 0xFD, // Second
+            0xF0, 0x02, 0xD0, 0xFC, 0x60, // Anonymous
+        ]
+    );
+    assert_eq!(
+        assembly.labels,
+        BTreeMap::from([
+            ("First".to_string(), 0xF000),
+            ("First@loop".to_string(), 0xF002),
+            ("Second".to_string(), 0xF005),
+            ("Second@loop".to_string(), 0xF007),
+        ])
+    );
+}
+
+#[test]
+fn data() {
+    assert_eq!(
+        assemble(
+            r#"
+Table:      .byte 1, <Table, >Table, "AB", 'c'
+            .word Table, $1234
+            .dbyt $1234
+            .res 2, $FF
+            .asciiz "Hi"
+Size = * - Table
+            .byte Size, -1
+            "#
+        ),
+        [
+            0x01, 0x00, 0xF0, 0x41, 0x42, 0x63, // .byte
+            0x00, 0xF0, 0x34, 0x12, // .word
+            0x12, 0x34, // .dbyt
+            0xFF, 0xFF, // .res
+            0x48, 0x69, 0x00, // .asciiz
+            0x11, 0xFF, // .byte
+        ]
+    );
+}
+
+#[test]
+fn modules_and_includes() {
+    let assembly = Assembler::new()
+        .with_bss_segment("ZEROPAGE", Some(0x80))
+        .with_segment("CODE", Some(0xF000))
+        .with_segment("VECTORS", Some(0xFFFA))
+        .assemble_files(&[test_data_path("program.s"), test_data_path("library.s")])
+        .unwrap();
+    assert_eq!(
+        assembly.image,
+        Image {
+            segments: vec![
+                Segment {
+                    address: 0xF000,
+                    bytes: vec![
+                        0xA2, 0x00, 0x86, 0x80, 0x20, 0x0E, 0xF0, 0xE6, 0x80, 0xD0, 0xF9, 0x4C,
+                        0x00, 0xF0, // program.s
+                        0xA9, 0x0E, 0x85, 0x09, 0x60, // library.s
+                    ],
+                },
+                Segment {
+                    address: 0xFFFA,
+                    bytes: vec![0x00, 0xF0, 0x00, 0xF0, 0x00, 0xF0],
+                },
+            ],
+            entry: None,
+        }
+    );
+    assert_eq!(assembly.labels["Counter"], 0x80);
+    assert_eq!(assembly.labels["SetBackground"], 0xF00E);
+
+    let mut ram = Ram::new(16);
+    assembly.image.write_to(&mut ram).unwrap();
+    let mut cpu: Cpu<Ram> = Cpu::new(Box::new(ram));
+    reset(&mut cpu);
+    for _ in 0..7 {
+        cpu.step_instruction().unwrap();
+    }
+    assert_eq!(cpu.memory().bytes[0x09], 0x0E);
+    assert_eq!(cpu.memory().bytes[0x80], 1);
+}
+
+#[test]
+fn segment_layout_and_org() {
+    let assembly = Assembler::new()
+        .with_segment("RODATA", Some(0x1000))
+        .with_segment("CODE", None)
+        .assemble_source(
+            "test.s",
+            "
+            lda Data
+.rodata
+Data:       .byte 1, 2, 3
+.code
+            .org $2000
+            nop
+            ",
+        )
+        .unwrap();
+    assert_eq!(
+        assembly.image.segments,
+        [
+            Segment {
+                address: 0x1000,
+                bytes: vec![1, 2, 3, 0xAD, 0x00, 0x10],
+            },
+            Segment {
+                address: 0x2000,
+                bytes: vec![0xEA],
+            },
+        ]
+    );
+}
+
+#[test]
+fn scopes_and_procedures() {
+    let assembly = Assembler::new()
+        .with_segment("CODE", Some(0xF000))
+        .assemble_source(
+            "test.s",
+            "
+.proc Init
+            ldx #0
+Loop:       dex
+            bne Loop
+            rts
+.endproc
+.scope Vars
+Counter = $10
+.endscope
+Loop:       jsr Init
+            lda Vars::Counter
+            jmp Init::Loop
+            ",
+        )
+        .unwrap();
+    assert_eq!(
+        assembly.image.segments[0].bytes,
+        [
+            0xA2, 0x00, 0xCA, 0xD0, 0xFD, 0x60, // Init
+            0x20, 0x00, 0xF0, 0xA5, 0x10, 0x4C, 0x02, 0xF0,
+        ]
+    );
+    assert_eq!(
+        assembly.labels.keys().collect::<Vec<_>>(),
+        ["Init", "Init::Loop", "Loop"]
+    );
+}
+
+#[test]
+fn macros_repeats_and_conditionals() {
+    assert_eq!(
+        assemble(
+            "
+.macro Store Value, Address
+            lda #Value
+            sta Address
+.endmacro
+.macro Wait Count
+.scope
+            ldx #Count
+Loop:       dex
+            bne Loop
+.endscope
+.endmacro
+            Store 1, $10
+            Wait 1
+            Wait (1 + 1)
+            .repeat 3, i
+            .byte i * 2
+            .endrepeat
+DEBUG = 1
+.if DEBUG
+            nop
+.else
+            brk
+.endif
+.ifdef UNDEFINED
+            brk
+.endif
+.ifndef UNDEFINED
+            .byte $FF
+.endif
+            "
+        ),
+        [
+            0xA9, 0x01, 0x85, 0x10, // Store
+            0xA2, 0x01, 0xCA, 0xD0, 0xFD, // Wait 1
+            0xA2, 0x02, 0xCA, 0xD0, 0xFD, // Wait 2
+            0x00, 0x02, 0x04, // .repeat
+            0xEA, 0xFF, // Conditionals
+        ]
+    );
+}
+
+#[test]
+fn screen_codes() {
+    assert_eq!(
+        assemble(
+            r#"
+.macpack cbm
+            scrcode "hello, World@", 1
+            lda #scrbyte 'a'
+            .byte scrbyte('@') + 2
+            "#
+        ),
+        [8, 5, 12, 12, 15, 0x2C, 0x20, 0x57, 15, 18, 12, 4, 0, 1, 0xA9, 1, 2]
+    );
+}
+
+#[test]
+fn assertions() {
+    let assembly = Assembler::new()
+        .with_segment("CODE", Some(0xF000))
+        .assemble_source(
+            "test.s",
+            r#"
+            .assert * = $F000, error, "Wrong address"
+            nop
+            .assert >* = $F1, warning, "Wrong page"
+            "#,
+        )
+        .unwrap();
+    assert_eq!(
+        assembly.warnings,
+        [AsmError {
+            file: "test.s".to_string(),
+            line: 4,
+            message: "Wrong page".to_string(),
+        }]
+    );
+
+    assert_eq!(
+        assembly_error(".assert * = $F001, error, \"Wrong address\""),
+        (1, "Wrong address".to_string())
+    );
+}
+
+#[test]
+fn errors() {
+    let error = |message: &str| message.to_string();
+    assert_eq!(
+        assembly_error("lda #1\nlda Missing"),
+        (2, error("Undefined symbol: Missing"))
+    );
+    assert_eq!(
+        assembly_error("bne Far\n.res 200\nFar: nop"),
+        (1, error("Branch out of range"))
+    );
+    assert_eq!(
+        assembly_error("foo #1"),
+        (1, error("Unknown instruction: FOO"))
+    );
+    assert_eq!(
+        assembly_error("A: nop\nA: nop"),
+        (2, error("Symbol already defined: A"))
+    );
+    assert_eq!(
+        assembly_error("stx $1234,x"),
+        (1, error("Unsupported addressing mode for STX"))
+    );
+    assert_eq!(assembly_error("lda #$100"), (1, error("Range error")));
+    assert_eq!(assembly_error("lda ($10),x").0, 1);
+    assert_eq!(
+        assembly_error("nop\n.repeat 2\nnop"),
+        (2, error("Missing .endrepeat"))
+    );
+    assert_eq!(
+        assembly_error(".segment \"DATA\""),
+        (1, error("Unknown segment: DATA"))
+    );
+    assert_eq!(assembly_error("lda #1 +").0, 1);
+}
+
+#[test]
+fn listing_and_symbol_map() {
+    let assembly = Assembler::new()
+        .with_segment("CODE", Some(0xF000))
+        .assemble_source(
+            "test.s",
+            "Start:  lda #1   ; Load\n        .byte 1, 2, 3, 4, 5\nValue = 3\nEnd:",
+        )
+        .unwrap();
+    assert_eq!(
+        assembly.listing(),
+        "F000  A9 01        Start:  lda #1   ; Load\n\
+         F002  01 02 03 04          .byte 1, 2, 3, 4, 5\n\
+         F006  05\n\
+         F007               Value = 3\n\
+         F007               End:\n"
+    );
+    assert_eq!(
+        assembly.symbol_map(),
+        "al C:F000 .Start\n\
+         al C:F007 .End\n"
+    );
+}
+
+#[test]
+fn single_instructions() {
+    assert_eq!(
+        assemble_instruction("bne $1000", 0x1010),
+        Ok(vec![0xD0, 0xEE])
+    );
+    assert_eq!(assemble_instruction("lda ($10),y", 0), Ok(vec![0xB1, 0x10]));
+    assert!(assemble_instruction("lda", 0).is_err());
+}
+
+/// Assembles the test ROMs of the emulators both with our assembler and with
+/// cc65, and compares the results. Since the ROMs include the cc65 headers, the
+/// test requires the `CA65_INC` environment variable to point to the cc65
+/// `asminc` directory (ca65 itself uses it to look up the included files, too).
+#[test]
+#[ignore = "requires cc65 and the CA65_INC environment variable"]
+fn assembles_test_roms_like_cc65() {
+    let include_dir = PathBuf::from(
+        env::var("CA65_INC").expect("CA65_INC must point to the cc65 asminc directory"),
+    );
+    let workspace_dir = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+
+    // The segment layouts mirror the `build.cfg` linker configurations.
+    let atari_assembler = Assembler::new()
+        .with_bss_segment("ZEROPAGE", Some(0x0080))
+        .with_segment("RODATA", Some(0xF000))
+        .with_segment("CODE", None)
+        .with_segment("VECTORS", Some(0xFFFA))
+        .with_include_dir(&include_dir);
+    assert_test_roms_match_cc65(
+        &atari_assembler,
+        &workspace_dir.join("atari2600/src/test_roms"),
+        &[],
+        &[],
+        0xF000,
+    );
+
+    let c64_assembler = Assembler::new()
+        .with_bss_segment("ZEROPAGE", Some(0x0002))
+        .with_segment("CODE", Some(0xE000))
+        .with_segment("RODATA", None)
+        .with_segment("VECTORS", Some(0xFFFA))
+        .with_include_dir(&include_dir);
+    assert_test_roms_match_cc65(
+        &c64_assembler,
+        &workspace_dir.join("c64/src/test_roms"),
+        &["common.s"],
+        &["--target", "c64"],
+        0xE000,
+    );
+}
+
+/// Assembles each source file in a given directory, along with the `libraries`,
+/// and compares the result with the ROM image that starts at `origin`, as
+/// produced by ca65 and ld65.
+fn assert_test_roms_match_cc65(
+    assembler: &Assembler,
+    dir: &Path,
+    libraries: &[&str],
+    cc65_args: &[&str],
+    origin: u16,
+) {
+    // Use a separate output directory for each ROM set and test run, so that
+    // concurrent runs don't overwrite each other's files.
+    let out_dir =
+        env::temp_dir().join(format!("ya6502_test_roms_{}_{:04X}", process::id(), origin));
+    fs::create_dir_all(&out_dir).unwrap();
+    let libraries: Vec<PathBuf> = libraries.iter().map(|name| dir.join(name)).collect();
+    let mut programs: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some(OsStr::new("s")))
+        .filter(|path| !libraries.contains(path))
+        .collect();
+    programs.sort();
+
+    for program in &programs {
+        let sources = [vec![program.clone()], libraries.clone()].concat();
+        let objects: Vec<PathBuf> = sources
+            .iter()
+            .map(|source| {
+                let object = out_dir
+                    .join(source.file_name().unwrap())
+                    .with_extension("o");
+                run_cc65_tool(
+                    Command::new("ca65")
+                        .arg(source)
+                        .arg("-o")
+                        .arg(&object)
+                        .args(cc65_args),
+                );
+                object
+            })
+            .collect();
+        let rom_path = out_dir
+            .join(program.file_name().unwrap())
+            .with_extension("bin");
+        run_cc65_tool(
+            Command::new("ld65")
+                .args(&objects)
+                .arg("-C")
+                .arg(dir.join("build.cfg"))
+                .arg("-o")
+                .arg(&rom_path)
+                .args(cc65_args),
+        );
+        let expected = fs::read(&rom_path).unwrap();
+
+        let assembly = assembler
+            .assemble_files(&sources)
+            .unwrap_or_else(|e| panic!("{}", e));
+        let mut actual = vec![0; expected.len()];
+        for segment in &assembly.image.segments {
+            let start = (segment.address - origin) as usize;
+            let end = start + segment.bytes.len();
+            assert!(
+                end <= actual.len(),
+                "{}: segment at ${:04X} doesn't fit in the ROM",
+                program.display(),
+                segment.address,
+            );
+            actual[start..end].copy_from_slice(&segment.bytes);
+        }
+        assert!(
+            actual == expected,
+            "{} differs from the ld65 output",
+            program.display()
+        );
+    }
+    fs::remove_dir_all(&out_dir).unwrap();
+}
+
+fn run_cc65_tool(command: &mut Command) {
+    let status = command.status().expect("Unable to run a cc65 tool");
+    assert!(status.success(), "{:?} failed with {}", command, status);
+}
@@ -0,0 +1,69 @@
+use std::fmt;
+
+/// Direction of a bus access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusOperation {
+    Read,
+    Write,
+}
+
+/// The purpose for which the CPU accesses the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusAccessKind {
+    /// Fetching the first byte of an instruction.
+    OpcodeFetch,
+    /// Fetching the subsequent bytes of an instruction.
+    OperandFetch,
+    /// Reading an indirect address from the memory.
+    Pointer,
+    /// Reading or writing the actual instruction operand.
+    Data,
+    /// Pushing to or pulling from the stack.
+    Stack,
+    /// Reading a reset or interrupt vector.
+    Vector,
+    /// A dummy access whose result is ignored, or a dummy write performed by
+    /// read-modify-write instructions.
+    Dummy,
+}
+
+/// A single access to the memory bus, as reported to a [`BusObserver`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusAccess {
+    /// Number of the CPU cycle, counted from the moment the CPU was created.
+    pub cycle: u64,
+    pub operation: BusOperation,
+    /// The address, as seen on the address bus (with the variant's address
+    /// mask applied).
+    pub address: u16,
+    /// The value that has been read or written.
+    pub value: u8,
+    pub kind: BusAccessKind,
+}
+
+/// Receives notifications about every successful memory access made by the
+/// CPU. Useful for tracing, profiling, and debugging tools. Any
+/// `FnMut(&BusAccess)` closure is also an observer.
+pub trait BusObserver {
+    fn observe(&mut self, access: &BusAccess);
+}
+
+impl<F: FnMut(&BusAccess)> BusObserver for F {
+    fn observe(&mut self, access: &BusAccess) {
+        self(access)
+    }
+}
+
+/// An optional observer slot that can be embedded in a structure that derives
+/// `Debug`.
+#[derive(Default)]
+pub(crate) struct ObserverSlot(pub Option<Box<dyn BusObserver>>);
+
+impl fmt::Debug for ObserverSlot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "Some(BusObserver)"),
+            None => write!(f, "None"),
+        }
+    }
+}
@@ -0,0 +1,625 @@
+//! Static information about all 256 opcodes of the NMOS 6502, including the
+//! undocumented ones, and of the CMOS 65C02. It's meant to be used by tools,
+//! like disassemblers, tracers, and profilers, which can get the table that
+//! matches a given CPU using
+//! [`MachineInspector::instruction_set`](super::MachineInspector::instruction_set).
+//! The CPU microcode is verified against these tables, so that they never
+//! disagree on instruction lengths and timing.
+
+use super::opcodes::*;
+
+/// Describes where the instruction operand comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressingMode {
+    Accumulator,
+    Immediate,
+    Implied,
+    Relative,
+    Absolute,
+    ZeroPage,
+    Indirect,
+    AbsoluteIndexedX,
+    AbsoluteIndexedY,
+    ZeroPageIndexedX,
+    ZeroPageIndexedY,
+    ZeroPageXIndirect,
+    ZeroPageIndirectY,
+    /// The 65C02 `(zp)` mode.
+    ZeroPageIndirect,
+    /// The 65C02 `(abs,X)` mode, used only by JMP.
+    AbsoluteIndexedIndirect,
+}
+
+impl AddressingMode {
+    /// Returns the length of an instruction that uses this addressing mode,
+    /// including the opcode.
+    pub const fn instruction_length(self) -> u8 {
+        use AddressingMode::*;
+        match self {
+            Accumulator | Implied => 1,
+            Immediate | Relative | ZeroPage | ZeroPageIndexedX | ZeroPageIndexedY
+            | ZeroPageXIndirect | ZeroPageIndirectY | ZeroPageIndirect => 2,
+            Absolute | Indirect | AbsoluteIndexedX | AbsoluteIndexedY | AbsoluteIndexedIndirect => {
+                3
+            }
+        }
+    }
+}
+
+/// Describes what the instruction does with its memory operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessClass {
+    /// The instruction doesn't have a memory operand. It only performs
+    /// internal operations, accesses the stack, or changes the program flow.
+    Internal,
+    Read,
+    Write,
+    ReadModifyWrite,
+}
+
+/// Static information about a single opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstructionInfo {
+    pub mnemonic: &'static str,
+    pub addressing_mode: AddressingMode,
+    /// Number of bytes, including the opcode.
+    pub length: u8,
+    /// Number of cycles, not including any penalties. It's 0 for HLT, which
+    /// never finishes.
+    pub cycles: u8,
+    /// Whether the instruction takes an additional cycle if the effective
+    /// address crosses a page boundary. For branches, this means that the
+    /// branch takes one cycle more if it's taken, and yet another one if the
+    /// target is on a different page.
+    pub page_cross_penalty: bool,
+    pub documented: bool,
+    pub access_class: AccessClass,
+}
+
+impl InstructionInfo {
+    const fn with_cycles(self, cycles: u8) -> Self {
+        Self { cycles, ..self }
+    }
+
+    const fn undocumented(self) -> Self {
+        Self {
+            documented: false,
+            ..self
+        }
+    }
+}
+
+/// Information about all opcodes of a CPU, indexed by the opcode.
+pub type InstructionSet = [InstructionInfo; 256];
+
+/// Instruction set of the NMOS 6502 and its derivatives.
+pub static NMOS_INSTRUCTIONS: InstructionSet = nmos_instruction_table();
+
+/// Instruction set of the CMOS 65C02.
+pub static CMOS_INSTRUCTIONS: InstructionSet = cmos_instruction_table();
+
+/// Creates an instruction descriptor with the number of cycles and the page
+/// crossing penalty derived from the addressing mode and access class. This
+/// rule doesn't work for stack and program flow instructions, which need to
+/// have the number of cycles specified explicitly.
+const fn instruction(
+    mnemonic: &'static str,
+    addressing_mode: AddressingMode,
+    access_class: AccessClass,
+) -> InstructionInfo {
+    use AccessClass::*;
+    use AddressingMode::*;
+    let (cycles, page_cross_penalty) = match (addressing_mode, access_class) {
+        (Accumulator | Implied | Immediate | Relative, _) => {
+            (2, matches!(addressing_mode, Relative))
+        }
+        (ZeroPage, ReadModifyWrite) => (5, false),
+        (ZeroPage, _) => (3, false),
+        (ZeroPageIndexedX | ZeroPageIndexedY, ReadModifyWrite) => (6, false),
+        (ZeroPageIndexedX | ZeroPageIndexedY, _) => (4, false),
+        (Absolute, ReadModifyWrite) => (6, false),
+        (Absolute | Indirect, _) => (4, false),
+        (AbsoluteIndexedIndirect, _) => (6, false),
+        (AbsoluteIndexedX | AbsoluteIndexedY, Read) => (4, true),
+        (AbsoluteIndexedX | AbsoluteIndexedY, ReadModifyWrite) => (7, false),
+        (AbsoluteIndexedX | AbsoluteIndexedY, _) => (5, false),
+        (ZeroPageXIndirect, ReadModifyWrite) => (8, false),
+        (ZeroPageXIndirect, _) => (6, false),
+        (ZeroPageIndirectY, Read) => (5, true),
+        (ZeroPageIndirectY, ReadModifyWrite) => (8, false),
+        (ZeroPageIndirectY, _) => (6, false),
+        (ZeroPageIndirect, _) => (5, false),
+    };
+    InstructionInfo {
+        mnemonic,
+        addressing_mode,
+        length: addressing_mode.instruction_length(),
+        cycles,
+        page_cross_penalty,
+        documented: true,
+        access_class,
+    }
+}
+
+const fn nmos_instruction_table() -> InstructionSet {
+    use AccessClass::*;
+    use AddressingMode::*;
+    let mut table = [instruction("???", Implied, Internal); 256];
+
+    table[NOP as usize] = instruction("NOP", Implied, Internal);
+
+    table[LDA_IMM as usize] = instruction("LDA", Immediate, Read);
+    table[LDA_ZP as usize] = instruction("LDA", ZeroPage, Read);
+    table[LDA_ZP_X as usize] = instruction("LDA", ZeroPageIndexedX, Read);
+    table[LDA_ABS as usize] = instruction("LDA", Absolute, Read);
+    table[LDA_ABS_X as usize] = instruction("LDA", AbsoluteIndexedX, Read);
+    table[LDA_ABS_Y as usize] = instruction("LDA", AbsoluteIndexedY, Read);
+    table[LDA_X_INDIR as usize] = instruction("LDA", ZeroPageXIndirect, Read);
+    table[LDA_INDIR_Y as usize] = instruction("LDA", ZeroPageIndirectY, Read);
+
+    table[LDX_IMM as usize] = instruction("LDX", Immediate, Read);
+    table[LDX_ZP as usize] = instruction("LDX", ZeroPage, Read);
+    table[LDX_ZP_Y as usize] = instruction("LDX", ZeroPageIndexedY, Read);
+    table[LDX_ABS as usize] = instruction("LDX", Absolute, Read);
+    table[LDX_ABS_Y as usize] = instruction("LDX", AbsoluteIndexedY, Read);
+
+    table[LDY_IMM as usize] = instruction("LDY", Immediate, Read);
+    table[LDY_ZP as usize] = instruction("LDY", ZeroPage, Read);
+    table[LDY_ZP_X as usize] = instruction("LDY", ZeroPageIndexedX, Read);
+    table[LDY_ABS as usize] = instruction("LDY", Absolute, Read);
+    table[LDY_ABS_X as usize] = instruction("LDY", AbsoluteIndexedX, Read);
+
+    table[STA_ZP as usize] = instruction("STA", ZeroPage, Write);
+    table[STA_ZP_X as usize] = instruction("STA", ZeroPageIndexedX, Write);
+    table[STA_ABS as usize] = instruction("STA", Absolute, Write);
+    table[STA_ABS_X as usize] = instruction("STA", AbsoluteIndexedX, Write);
+    table[STA_ABS_Y as usize] = instruction("STA", AbsoluteIndexedY, Write);
+    table[STA_X_INDIR as usize] = instruction("STA", ZeroPageXIndirect, Write);
+    table[STA_INDIR_Y as usize] = instruction("STA", ZeroPageIndirectY, Write);
+
+    table[STX_ZP as usize] = instruction("STX", ZeroPage, Write);
+    table[STX_ZP_Y as usize] = instruction("STX", ZeroPageIndexedY, Write);
+    table[STX_ABS as usize] = instruction("STX", Absolute, Write);
+
+    table[STY_ZP as usize] = instruction("STY", ZeroPage, Write);
+    table[STY_ZP_X as usize] = instruction("STY", ZeroPageIndexedX, Write);
+    table[STY_ABS as usize] = instruction("STY", Absolute, Write);
+
+    table[AND_IMM as usize] = instruction("AND", Immediate, Read);
+    table[AND_ZP as usize] = instruction("AND", ZeroPage, Read);
+    table[AND_ZP_X as usize] = instruction("AND", ZeroPageIndexedX, Read);
+    table[AND_ABS as usize] = instruction("AND", Absolute, Read);
+    table[AND_ABS_X as usize] = instruction("AND", AbsoluteIndexedX, Read);
+    table[AND_ABS_Y as usize] = instruction("AND", AbsoluteIndexedY, Read);
+    table[AND_X_INDIR as usize] = instruction("AND", ZeroPageXIndirect, Read);
+    table[AND_INDIR_Y as usize] = instruction("AND", ZeroPageIndirectY, Read);
+
+    table[ORA_IMM as usize] = instruction("ORA", Immediate, Read);
+    table[ORA_ZP as usize] = instruction("ORA", ZeroPage, Read);
+    table[ORA_ZP_X as usize] = instruction("ORA", ZeroPageIndexedX, Read);
+    table[ORA_ABS as usize] = instruction("ORA", Absolute, Read);
+    table[ORA_ABS_X as usize] = instruction("ORA", AbsoluteIndexedX, Read);
+    table[ORA_ABS_Y as usize] = instruction("ORA", AbsoluteIndexedY, Read);
+    table[ORA_X_INDIR as usize] = instruction("ORA", ZeroPageXIndirect, Read);
+    table[ORA_INDIR_Y as usize] = instruction("ORA", ZeroPageIndirectY, Read);
+
+    table[EOR_IMM as usize] = instruction("EOR", Immediate, Read);
+    table[EOR_ZP as usize] = instruction("EOR", ZeroPage, Read);
+    table[EOR_ZP_X as usize] = instruction("EOR", ZeroPageIndexedX, Read);
+    table[EOR_ABS as usize] = instruction("EOR", Absolute, Read);
+    table[EOR_ABS_X as usize] = instruction("EOR", AbsoluteIndexedX, Read);
+    table[EOR_ABS_Y as usize] = instruction("EOR", AbsoluteIndexedY, Read);
+    table[EOR_X_INDIR as usize] = instruction("EOR", ZeroPageXIndirect, Read);
+    table[EOR_INDIR_Y as usize] = instruction("EOR", ZeroPageIndirectY, Read);
+
+    table[ASL_A as usize] = instruction("ASL", Accumulator, Internal);
+    table[ASL_ZP as usize] = instruction("ASL", ZeroPage, ReadModifyWrite);
+    table[ASL_ZP_X as usize] = instruction("ASL", ZeroPageIndexedX, ReadModifyWrite);
+    table[ASL_ABS as usize] = instruction("ASL", Absolute, ReadModifyWrite);
+    table[ASL_ABS_X as usize] = instruction("ASL", AbsoluteIndexedX, ReadModifyWrite);
+
+    table[LSR_A as usize] = instruction("LSR", Accumulator, Internal);
+    table[LSR_ZP as usize] = instruction("LSR", ZeroPage, ReadModifyWrite);
+    table[LSR_ZP_X as usize] = instruction("LSR", ZeroPageIndexedX, ReadModifyWrite);
+    table[LSR_ABS as usize] = instruction("LSR", Absolute, ReadModifyWrite);
+    table[LSR_ABS_X as usize] = instruction("LSR", AbsoluteIndexedX, ReadModifyWrite);
+
+    table[ROL_A as usize] = instruction("ROL", Accumulator, Internal);
+    table[ROL_ZP as usize] = instruction("ROL", ZeroPage, ReadModifyWrite);
+    table[ROL_ZP_X as usize] = instruction("ROL", ZeroPageIndexedX, ReadModifyWrite);
+    table[ROL_ABS as usize] = instruction("ROL", Absolute, ReadModifyWrite);
+    table[ROL_ABS_X as usize] = instruction("ROL", AbsoluteIndexedX, ReadModifyWrite);
+
+    table[ROR_A as usize] = instruction("ROR", Accumulator, Internal);
+    table[ROR_ZP as usize] = instruction("ROR", ZeroPage, ReadModifyWrite);
+    table[ROR_ZP_X as usize] = instruction("ROR", ZeroPageIndexedX, ReadModifyWrite);
+    table[ROR_ABS as usize] = instruction("ROR", Absolute, ReadModifyWrite);
+    table[ROR_ABS_X as usize] = instruction("ROR", AbsoluteIndexedX, ReadModifyWrite);
+
+    table[CMP_IMM as usize] = instruction("CMP", Immediate, Read);
+    table[CMP_ZP as usize] = instruction("CMP", ZeroPage, Read);
+    table[CMP_ZP_X as usize] = instruction("CMP", ZeroPageIndexedX, Read);
+    table[CMP_ABS as usize] = instruction("CMP", Absolute, Read);
+    table[CMP_ABS_X as usize] = instruction("CMP", AbsoluteIndexedX, Read);
+    table[CMP_ABS_Y as usize] = instruction("CMP", AbsoluteIndexedY, Read);
+    table[CMP_X_INDIR as usize] = instruction("CMP", ZeroPageXIndirect, Read);
+    table[CMP_INDIR_Y as usize] = instruction("CMP", ZeroPageIndirectY, Read);
+
+    table[CPX_IMM as usize] = instruction("CPX", Immediate, Read);
+    table[CPX_ZP as usize] = instruction("CPX", ZeroPage, Read);
+    table[CPX_ABS as usize] = instruction("CPX", Absolute, Read);
+
+    table[CPY_IMM as usize] = instruction("CPY", Immediate, Read);
+    table[CPY_ZP as usize] = instruction("CPY", ZeroPage, Read);
+    table[CPY_ABS as usize] = instruction("CPY", Absolute, Read);
+
+    table[BIT_ZP as usize] = instruction("BIT", ZeroPage, Read);
+    table[BIT_ABS as usize] = instruction("BIT", Absolute, Read);
+
+    table[ADC_IMM as usize] = instruction("ADC", Immediate, Read);
+    table[ADC_ZP as usize] = instruction("ADC", ZeroPage, Read);
+    table[ADC_ZP_X as usize] = instruction("ADC", ZeroPageIndexedX, Read);
+    table[ADC_ABS as usize] = instruction("ADC", Absolute, Read);
+    table[ADC_ABS_X as usize] = instruction("ADC", AbsoluteIndexedX, Read);
+    table[ADC_ABS_Y as usize] = instruction("ADC", AbsoluteIndexedY, Read);
+    table[ADC_X_INDIR as usize] = instruction("ADC", ZeroPageXIndirect, Read);
+    table[ADC_INDIR_Y as usize] = instruction("ADC", ZeroPageIndirectY, Read);
+
+    table[SBC_IMM as usize] = instruction("SBC", Immediate, Read);
+    table[SBC_ZP as usize] = instruction("SBC", ZeroPage, Read);
+    table[SBC_ZP_X as usize] = instruction("SBC", ZeroPageIndexedX, Read);
+    table[SBC_ABS as usize] = instruction("SBC", Absolute, Read);
+    table[SBC_ABS_X as usize] = instruction("SBC", AbsoluteIndexedX, Read);
+    table[SBC_ABS_Y as usize] = instruction("SBC", AbsoluteIndexedY, Read);
+    table[SBC_X_INDIR as usize] = instruction("SBC", ZeroPageXIndirect, Read);
+    table[SBC_INDIR_Y as usize] = instruction("SBC", ZeroPageIndirectY, Read);
+
+    table[INC_ZP as usize] = instruction("INC", ZeroPage, ReadModifyWrite);
+    table[INC_ZP_X as usize] = instruction("INC", ZeroPageIndexedX, ReadModifyWrite);
+    table[INC_ABS as usize] = instruction("INC", Absolute, ReadModifyWrite);
+    table[INC_ABS_X as usize] = instruction("INC", AbsoluteIndexedX, ReadModifyWrite);
+
+    table[DEC_ZP as usize] = instruction("DEC", ZeroPage, ReadModifyWrite);
+    table[DEC_ZP_X as usize] = instruction("DEC", ZeroPageIndexedX, ReadModifyWrite);
+    table[DEC_ABS as usize] = instruction("DEC", Absolute, ReadModifyWrite);
+    table[DEC_ABS_X as usize] = instruction("DEC", AbsoluteIndexedX, ReadModifyWrite);
+
+    table[INX as usize] = instruction("INX", Implied, Internal);
+    table[INY as usize] = instruction("INY", Implied, Internal);
+    table[DEX as usize] = instruction("DEX", Implied, Internal);
+    table[DEY as usize] = instruction("DEY", Implied, Internal);
+
+    table[TAX as usize] = instruction("TAX", Implied, Internal);
+    table[TAY as usize] = instruction("TAY", Implied, Internal);
+    table[TXA as usize] = instruction("TXA", Implied, Internal);
+    table[TYA as usize] = instruction("TYA", Implied, Internal);
+    table[TXS as usize] = instruction("TXS", Implied, Internal);
+    table[TSX as usize] = instruction("TSX", Implied, Internal);
+
+    table[PHP as usize] = instruction("PHP", Implied, Internal).with_cycles(3);
+    table[PHA as usize] = instruction("PHA", Implied, Internal).with_cycles(3);
+    table[PLP as usize] = instruction("PLP", Implied, Internal).with_cycles(4);
+    table[PLA as usize] = instruction("PLA", Implied, Internal).with_cycles(4);
+
+    table[SEI as usize] = instruction("SEI", Implied, Internal);
+    table[CLI as usize] = instruction("CLI", Implied, Internal);
+    table[SED as usize] = instruction("SED", Implied, Internal);
+    table[CLD as usize] = instruction("CLD", Implied, Internal);
+    table[SEC as usize] = instruction("SEC", Implied, Internal);
+    table[CLC as usize] = instruction("CLC", Implied, Internal);
+    table[CLV as usize] = instruction("CLV", Implied, Internal);
+
+    table[BEQ as usize] = instruction("BEQ", Relative, Internal);
+    table[BNE as usize] = instruction("BNE", Relative, Internal);
+    table[BCC as usize] = instruction("BCC", Relative, Internal);
+    table[BCS as usize] = instruction("BCS", Relative, Internal);
+    table[BPL as usize] = instruction("BPL", Relative, Internal);
+    table[BMI as usize] = instruction("BMI", Relative, Internal);
+    table[BVS as usize] = instruction("BVS", Relative, Internal);
+    table[BVC as usize] = instruction("BVC", Relative, Internal);
+
+    table[JMP_ABS as usize] = instruction("JMP", Absolute, Internal).with_cycles(3);
+    table[JMP_INDIR as usize] = instruction("JMP", Indirect, Internal).with_cycles(5);
+    table[JSR as usize] = instruction("JSR", Absolute, Internal).with_cycles(6);
+    table[RTS as usize] = instruction("RTS", Implied, Internal).with_cycles(6);
+    table[BRK as usize] = instruction("BRK", Implied, Internal).with_cycles(7);
+    table[RTI as usize] = instruction("RTI", Implied, Internal).with_cycles(6);
+
+    table[LAX_IMM as usize] = instruction("LAX", Immediate, Read).undocumented();
+    table[LAX_ZP as usize] = instruction("LAX", ZeroPage, Read).undocumented();
+    table[LAX_ZP_Y as usize] = instruction("LAX", ZeroPageIndexedY, Read).undocumented();
+    table[LAX_ABS as usize] = instruction("LAX", Absolute, Read).undocumented();
+    table[LAX_ABS_Y as usize] = instruction("LAX", AbsoluteIndexedY, Read).undocumented();
+    table[LAX_X_INDIR as usize] = instruction("LAX", ZeroPageXIndirect, Read).undocumented();
+    table[LAX_INDIR_Y as usize] = instruction("LAX", ZeroPageIndirectY, Read).undocumented();
+
+    table[SAX_ZP as usize] = instruction("SAX", ZeroPage, Write).undocumented();
+    table[SAX_ZP_Y as usize] = instruction("SAX", ZeroPageIndexedY, Write).undocumented();
+    table[SAX_ABS as usize] = instruction("SAX", Absolute, Write).undocumented();
+    table[SAX_X_INDIR as usize] = instruction("SAX", ZeroPageXIndirect, Write).undocumented();
+
+    table[DCP_ZP as usize] = instruction("DCP", ZeroPage, ReadModifyWrite).undocumented();
+    table[DCP_ZP_X as usize] = instruction("DCP", ZeroPageIndexedX, ReadModifyWrite).undocumented();
+    table[DCP_ABS as usize] = instruction("DCP", Absolute, ReadModifyWrite).undocumented();
+    table[DCP_ABS_X as usize] =
+        instruction("DCP", AbsoluteIndexedX, ReadModifyWrite).undocumented();
+    table[DCP_ABS_Y as usize] =
+        instruction("DCP", AbsoluteIndexedY, ReadModifyWrite).undocumented();
+    table[DCP_X_INDIR as usize] =
+        instruction("DCP", ZeroPageXIndirect, ReadModifyWrite).undocumented();
+    table[DCP_INDIR_Y as usize] =
+        instruction("DCP", ZeroPageIndirectY, ReadModifyWrite).undocumented();
+
+    table[ISC_ZP as usize] = instruction("ISC", ZeroPage, ReadModifyWrite).undocumented();
+    table[ISC_ZP_X as usize] = instruction("ISC", ZeroPageIndexedX, ReadModifyWrite).undocumented();
+    table[ISC_ABS as usize] = instruction("ISC", Absolute, ReadModifyWrite).undocumented();
+    table[ISC_ABS_X as usize] =
+        instruction("ISC", AbsoluteIndexedX, ReadModifyWrite).undocumented();
+    table[ISC_ABS_Y as usize] =
+        instruction("ISC", AbsoluteIndexedY, ReadModifyWrite).undocumented();
+    table[ISC_X_INDIR as usize] =
+        instruction("ISC", ZeroPageXIndirect, ReadModifyWrite).undocumented();
+    table[ISC_INDIR_Y as usize] =
+        instruction("ISC", ZeroPageIndirectY, ReadModifyWrite).undocumented();
+
+    table[SLO_ZP as usize] = instruction("SLO", ZeroPage, ReadModifyWrite).undocumented();
+    table[SLO_ZP_X as usize] = instruction("SLO", ZeroPageIndexedX, ReadModifyWrite).undocumented();
+    table[SLO_ABS as usize] = instruction("SLO", Absolute, ReadModifyWrite).undocumented();
+    table[SLO_ABS_X as usize] =
+        instruction("SLO", AbsoluteIndexedX, ReadModifyWrite).undocumented();
+    table[SLO_ABS_Y as usize] =
+        instruction("SLO", AbsoluteIndexedY, ReadModifyWrite).undocumented();
+    table[SLO_X_INDIR as usize] =
+        instruction("SLO", ZeroPageXIndirect, ReadModifyWrite).undocumented();
+    table[SLO_INDIR_Y as usize] =
+        instruction("SLO", ZeroPageIndirectY, ReadModifyWrite).undocumented();
+
+    table[RLA_ZP as usize] = instruction("RLA", ZeroPage, ReadModifyWrite).undocumented();
+    table[RLA_ZP_X as usize] = instruction("RLA", ZeroPageIndexedX, ReadModifyWrite).undocumented();
+    table[RLA_ABS as usize] = instruction("RLA", Absolute, ReadModifyWrite).undocumented();
+    table[RLA_ABS_X as usize] =
+        instruction("RLA", AbsoluteIndexedX, ReadModifyWrite).undocumented();
+    table[RLA_ABS_Y as usize] =
+        instruction("RLA", AbsoluteIndexedY, ReadModifyWrite).undocumented();
+    table[RLA_X_INDIR as usize] =
+        instruction("RLA", ZeroPageXIndirect, ReadModifyWrite).undocumented();
+    table[RLA_INDIR_Y as usize] =
+        instruction("RLA", ZeroPageIndirectY, ReadModifyWrite).undocumented();
+
+    table[SRE_ZP as usize] = instruction("SRE", ZeroPage, ReadModifyWrite).undocumented();
+    table[SRE_ZP_X as usize] = instruction("SRE", ZeroPageIndexedX, ReadModifyWrite).undocumented();
+    table[SRE_ABS as usize] = instruction("SRE", Absolute, ReadModifyWrite).undocumented();
+    table[SRE_ABS_X as usize] =
+        instruction("SRE", AbsoluteIndexedX, ReadModifyWrite).undocumented();
+    table[SRE_ABS_Y as usize] =
+        instruction("SRE", AbsoluteIndexedY, ReadModifyWrite).undocumented();
+    table[SRE_X_INDIR as usize] =
+        instruction("SRE", ZeroPageXIndirect, ReadModifyWrite).undocumented();
+    table[SRE_INDIR_Y as usize] =
+        instruction("SRE", ZeroPageIndirectY, ReadModifyWrite).undocumented();
+
+    table[RRA_ZP as usize] = instruction("RRA", ZeroPage, ReadModifyWrite).undocumented();
+    table[RRA_ZP_X as usize] = instruction("RRA", ZeroPageIndexedX, ReadModifyWrite).undocumented();
+    table[RRA_ABS as usize] = instruction("RRA", Absolute, ReadModifyWrite).undocumented();
+    table[RRA_ABS_X as usize] =
+        instruction("RRA", AbsoluteIndexedX, ReadModifyWrite).undocumented();
+    table[RRA_ABS_Y as usize] =
+        instruction("RRA", AbsoluteIndexedY, ReadModifyWrite).undocumented();
+    table[RRA_X_INDIR as usize] =
+        instruction("RRA", ZeroPageXIndirect, ReadModifyWrite).undocumented();
+    table[RRA_INDIR_Y as usize] =
+        instruction("RRA", ZeroPageIndirectY, ReadModifyWrite).undocumented();
+
+    table[ANC_IMM as usize] = instruction("ANC", Immediate, Read).undocumented();
+    table[ANC_IMM2 as usize] = instruction("ANC", Immediate, Read).undocumented();
+    table[ALR_IMM as usize] = instruction("ALR", Immediate, Read).undocumented();
+    table[ARR_IMM as usize] = instruction("ARR", Immediate, Read).undocumented();
+    table[SBX_IMM as usize] = instruction("SBX", Immediate, Read).undocumented();
+    table[SBC_IMM2 as usize] = instruction("SBC", Immediate, Read).undocumented();
+
+    table[NOP1 as usize] = instruction("NOP", Implied, Internal).undocumented();
+    table[NOP2 as usize] = instruction("NOP", Implied, Internal).undocumented();
+    table[NOP3 as usize] = instruction("NOP", Implied, Internal).undocumented();
+    table[NOP4 as usize] = instruction("NOP", Implied, Internal).undocumented();
+    table[NOP5 as usize] = instruction("NOP", Implied, Internal).undocumented();
+    table[NOP6 as usize] = instruction("NOP", Implied, Internal).undocumented();
+    table[NOP_IMM1 as usize] = instruction("NOP", Immediate, Read).undocumented();
+    table[NOP_IMM2 as usize] = instruction("NOP", Immediate, Read).undocumented();
+    table[NOP_IMM3 as usize] = instruction("NOP", Immediate, Read).undocumented();
+    table[NOP_IMM4 as usize] = instruction("NOP", Immediate, Read).undocumented();
+    table[NOP_IMM5 as usize] = instruction("NOP", Immediate, Read).undocumented();
+    table[NOP_ZP1 as usize] = instruction("NOP", ZeroPage, Read).undocumented();
+    table[NOP_ZP2 as usize] = instruction("NOP", ZeroPage, Read).undocumented();
+    table[NOP_ZP3 as usize] = instruction("NOP", ZeroPage, Read).undocumented();
+    table[NOP_ZP_X1 as usize] = instruction("NOP", ZeroPageIndexedX, Read).undocumented();
+    table[NOP_ZP_X2 as usize] = instruction("NOP", ZeroPageIndexedX, Read).undocumented();
+    table[NOP_ZP_X3 as usize] = instruction("NOP", ZeroPageIndexedX, Read).undocumented();
+    table[NOP_ZP_X4 as usize] = instruction("NOP", ZeroPageIndexedX, Read).undocumented();
+    table[NOP_ZP_X5 as usize] = instruction("NOP", ZeroPageIndexedX, Read).undocumented();
+    table[NOP_ZP_X6 as usize] = instruction("NOP", ZeroPageIndexedX, Read).undocumented();
+    table[NOP_ABS as usize] = instruction("NOP", Absolute, Read).undocumented();
+    table[NOP_ABS_X1 as usize] = instruction("NOP", AbsoluteIndexedX, Read).undocumented();
+    table[NOP_ABS_X2 as usize] = instruction("NOP", AbsoluteIndexedX, Read).undocumented();
+    table[NOP_ABS_X3 as usize] = instruction("NOP", AbsoluteIndexedX, Read).undocumented();
+    table[NOP_ABS_X4 as usize] = instruction("NOP", AbsoluteIndexedX, Read).undocumented();
+    table[NOP_ABS_X5 as usize] = instruction("NOP", AbsoluteIndexedX, Read).undocumented();
+    table[NOP_ABS_X6 as usize] = instruction("NOP", AbsoluteIndexedX, Read).undocumented();
+
+    table[ANE_IMM as usize] = instruction("ANE", Immediate, Read).undocumented();
+    table[LAS_ABS_Y as usize] = instruction("LAS", AbsoluteIndexedY, Read).undocumented();
+    table[SHA_ABS_Y as usize] = instruction("SHA", AbsoluteIndexedY, Write).undocumented();
+    table[SHA_INDIR_Y as usize] = instruction("SHA", ZeroPageIndirectY, Write).undocumented();
+    table[SHX_ABS_Y as usize] = instruction("SHX", AbsoluteIndexedY, Write).undocumented();
+    table[SHY_ABS_X as usize] = instruction("SHY", AbsoluteIndexedX, Write).undocumented();
+    table[TAS_ABS_Y as usize] = instruction("TAS", AbsoluteIndexedY, Write).undocumented();
+
+    let hlts = [
+        HLT1, HLT2, HLT3, HLT4, HLT5, HLT6, HLT7, HLT8, HLT9, HLT10, HLT11, HLT12,
+    ];
+    let mut i = 0;
+    while i < hlts.len() {
+        table[hlts[i] as usize] = instruction("HLT", Implied, Internal)
+            .with_cycles(0)
+            .undocumented();
+        i += 1;
+    }
+
+    table
+}
+
+const fn cmos_instruction_table() -> InstructionSet {
+    use AccessClass::*;
+    use AddressingMode::*;
+    let mut table = nmos_instruction_table();
+
+    // All undocumented NMOS opcodes are NOPs on 65C02. The ones from columns
+    // 3, 7, B, and F take a single cycle; the rest keep reading their operands
+    // like the NMOS NOPs do, unless they are replaced by new instructions
+    // below.
+    let mut opcode = 0;
+    while opcode < table.len() {
+        if opcode & 0b11 == 0b11 {
+            table[opcode] = instruction("NOP", Implied, Internal)
+                .with_cycles(1)
+                .undocumented();
+        }
+        opcode += 1;
+    }
+    let nops = [HLT1, HLT3, HLT5, HLT7];
+    let mut i = 0;
+    while i < nops.len() {
+        table[nops[i] as usize] = instruction("NOP", Immediate, Read).undocumented();
+        i += 1;
+    }
+    table[NOP_ABS_X3 as usize] = instruction("NOP", Absolute, Read)
+        .with_cycles(8)
+        .undocumented();
+    table[NOP_ABS_X5 as usize] = instruction("NOP", Absolute, Read).undocumented();
+    table[NOP_ABS_X6 as usize] = instruction("NOP", Absolute, Read).undocumented();
+
+    // BRA is always taken, so it takes 3 cycles, and one more when crossing a
+    // page boundary.
+    table[BRA as usize] = instruction("BRA", Relative, Internal).with_cycles(3);
+
+    table[PHX as usize] = instruction("PHX", Implied, Internal).with_cycles(3);
+    table[PHY as usize] = instruction("PHY", Implied, Internal).with_cycles(3);
+    table[PLX as usize] = instruction("PLX", Implied, Internal).with_cycles(4);
+    table[PLY as usize] = instruction("PLY", Implied, Internal).with_cycles(4);
+
+    table[STZ_ZP as usize] = instruction("STZ", ZeroPage, Write);
+    table[STZ_ZP_X as usize] = instruction("STZ", ZeroPageIndexedX, Write);
+    table[STZ_ABS as usize] = instruction("STZ", Absolute, Write);
+    table[STZ_ABS_X as usize] = instruction("STZ", AbsoluteIndexedX, Write);
+
+    table[TRB_ZP as usize] = instruction("TRB", ZeroPage, ReadModifyWrite);
+    table[TRB_ABS as usize] = instruction("TRB", Absolute, ReadModifyWrite);
+    table[TSB_ZP as usize] = instruction("TSB", ZeroPage, ReadModifyWrite);
+    table[TSB_ABS as usize] = instruction("TSB", Absolute, ReadModifyWrite);
+
+    table[ORA_ZP_INDIR as usize] = instruction("ORA", ZeroPageIndirect, Read);
+    table[AND_ZP_INDIR as usize] = instruction("AND", ZeroPageIndirect, Read);
+    table[EOR_ZP_INDIR as usize] = instruction("EOR", ZeroPageIndirect, Read);
+    table[ADC_ZP_INDIR as usize] = instruction("ADC", ZeroPageIndirect, Read);
+    table[STA_ZP_INDIR as usize] = instruction("STA", ZeroPageIndirect, Write);
+    table[LDA_ZP_INDIR as usize] = instruction("LDA", ZeroPageIndirect, Read);
+    table[CMP_ZP_INDIR as usize] = instruction("CMP", ZeroPageIndirect, Read);
+    table[SBC_ZP_INDIR as usize] = instruction("SBC", ZeroPageIndirect, Read);
+
+    table[BIT_IMM as usize] = instruction("BIT", Immediate, Read);
+    table[BIT_ZP_X as usize] = instruction("BIT", ZeroPageIndexedX, Read);
+    table[BIT_ABS_X as usize] = instruction("BIT", AbsoluteIndexedX, Read);
+
+    table[INC_A as usize] = instruction("INC", Accumulator, Internal);
+    table[DEC_A as usize] = instruction("DEC", Accumulator, Internal);
+
+    // Shifts and rotations with indexed addressing only take the additional
+    // cycle when crossing a page boundary.
+    let shifts = [ASL_ABS_X, LSR_ABS_X, ROL_ABS_X, ROR_ABS_X];
+    let mut i = 0;
+    while i < shifts.len() {
+        table[shifts[i] as usize] = InstructionInfo {
+            cycles: 6,
+            page_cross_penalty: true,
+            ..table[shifts[i] as usize]
+        };
+        i += 1;
+    }
+
+    // The page wrapping bug is fixed at the cost of an additional cycle.
+    table[JMP_INDIR as usize] = instruction("JMP", Indirect, Internal).with_cycles(6);
+    table[JMP_X_INDIR as usize] =
+        instruction("JMP", AbsoluteIndexedIndirect, Internal).with_cycles(6);
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_opcodes_defined() {
+        for (instruction_set, documented) in [(&NMOS_INSTRUCTIONS, 151), (&CMOS_INSTRUCTIONS, 178)]
+        {
+            assert!(instruction_set.iter().all(|info| info.mnemonic != "???"));
+            assert_eq!(
+                instruction_set
+                    .iter()
+                    .filter(|info| info.documented)
+                    .count(),
+                documented
+            );
+        }
+    }
+
+    #[test]
+    fn timing() {
+        let timing = |opcode: u8| {
+            let info = &NMOS_INSTRUCTIONS[opcode as usize];
+            (info.cycles, info.page_cross_penalty)
+        };
+        assert_eq!(timing(LDA_IMM), (2, false));
+        assert_eq!(timing(LDA_ABS_X), (4, true));
+        assert_eq!(timing(LDA_INDIR_Y), (5, true));
+        assert_eq!(timing(STA_ABS_X), (5, false));
+        assert_eq!(timing(STA_INDIR_Y), (6, false));
+        assert_eq!(timing(INC_ZP), (5, false));
+        assert_eq!(timing(INC_ABS_X), (7, false));
+        assert_eq!(timing(DCP_INDIR_Y), (8, false));
+        assert_eq!(timing(BNE), (2, true));
+        assert_eq!(timing(JSR), (6, false));
+        assert_eq!(timing(NOP_ABS_X1), (4, true));
+    }
+
+    #[test]
+    fn instruction_info() {
+        assert_eq!(
+            NMOS_INSTRUCTIONS[STA_ABS as usize],
+            InstructionInfo {
+                mnemonic: "STA",
+                addressing_mode: AddressingMode::Absolute,
+                length: 3,
+                cycles: 4,
+                page_cross_penalty: false,
+                documented: true,
+                access_class: AccessClass::Write,
+            }
+        );
+        assert_eq!(NMOS_INSTRUCTIONS[SLO_ZP as usize].mnemonic, "SLO");
+        assert!(!NMOS_INSTRUCTIONS[SLO_ZP as usize].documented);
+        assert_eq!(
+            CMOS_INSTRUCTIONS[LDA_ZP_INDIR as usize],
+            InstructionInfo {
+                mnemonic: "LDA",
+                addressing_mode: AddressingMode::ZeroPageIndirect,
+                length: 2,
+                cycles: 5,
+                page_cross_penalty: false,
+                documented: true,
+                access_class: AccessClass::Read,
+            }
+        );
+        assert_eq!(CMOS_INSTRUCTIONS[SLO_ZP as usize].mnemonic, "NOP");
+        assert_eq!(CMOS_INSTRUCTIONS[SLO_ZP as usize].length, 1);
+    }
+}
@@ -0,0 +1,979 @@
+//! Microcode of the CPU: for each opcode, a precomputed sequence of micro-ops,
+//! one per cycle. The CPU looks up the micro-op for the current cycle and
+//! executes it, so there are no closures or dynamic dispatch involved in
+//! running an instruction. Micro-ops that finish an instruction bring the CPU
+//! back to the ready state; some of them do it conditionally (for example, when
+//! indexing doesn't cross a page boundary), which shortens the sequence.
+
+use super::flags;
+use super::opcodes::*;
+
+/// Maximum number of cycles an instruction takes, not counting the opcode
+/// fetch.
+pub(super) const MAX_STEPS: usize = 7;
+
+/// A sequence of micro-ops that execute a single instruction, starting from the
+/// cycle that follows the opcode fetch.
+pub(super) type Steps = [MicroOp; MAX_STEPS];
+
+/// Microcode of the NMOS 6502, indexed by opcode.
+pub(super) static NMOS: [Steps; 256] = nmos_microcode();
+
+/// Microcode of the CMOS 65C02, indexed by opcode. Note that the single-cycle
+/// NOPs are handled while fetching the opcode, so they don't appear here.
+pub(super) static CMOS: [Steps; 256] = cmos_microcode();
+
+/// Selects an index register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Index {
+    X,
+    Y,
+}
+
+/// An operation performed on a value read from the memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Load {
+    Nop,
+    Lda,
+    Ldx,
+    Ldy,
+    And,
+    Ora,
+    Eor,
+    Adc,
+    Sbc,
+    Cmp,
+    Cpx,
+    Cpy,
+    Bit,
+    /// The 65C02 immediate BIT, which only affects the Z flag.
+    BitImmediate,
+    /// Pulls the flags from the stack (PLP).
+    Plp,
+    Lax,
+    /// The unstable immediate LAX.
+    LaxImmediate,
+    Anc,
+    Alr,
+    Arr,
+    Sbx,
+}
+
+/// An operation that modifies a value read from the memory (or the
+/// accumulator).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Modify {
+    Asl,
+    Lsr,
+    Rol,
+    Ror,
+    Inc,
+    Dec,
+    Tsb,
+    Trb,
+    Dcp,
+    Isc,
+    Slo,
+    Rla,
+    Sre,
+    Rra,
+}
+
+/// A value that gets written to the memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Source {
+    A,
+    X,
+    Y,
+    Zero,
+    /// The flags, as pushed by PHP.
+    Flags,
+    /// A AND X, as stored by SAX.
+    AAndX,
+}
+
+/// An operation that doesn't need any data from the memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Internal {
+    Nop,
+    Inx,
+    Iny,
+    Dex,
+    Dey,
+    Tax,
+    Tay,
+    Txa,
+    Tya,
+    Txs,
+    Tsx,
+    Sei,
+    Cli,
+    Sed,
+    Cld,
+    Sec,
+    Clc,
+    Clv,
+    /// Modifies the accumulator.
+    Accumulator(Modify),
+}
+
+/// A single cycle of an instruction. The names use the same registers as the
+/// CPU: `adl`/`adh` hold an effective address, `bal`/`bah` a base address to
+/// be indexed, and `ial`/`iah` an indirect address. Unless stated otherwise,
+/// indexed zero page addresses wrap around within the zero page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum MicroOp {
+    // Fetching the instruction operands.
+    FetchAdl,
+    FetchAdh,
+    FetchBal,
+    FetchBah,
+    FetchIal,
+    FetchIah,
+    /// Fetches a byte that follows the opcode and ignores it.
+    SkipOperand,
+    /// Same as [`MicroOp::SkipOperand`], but ignores read errors, too.
+    DummyFetch,
+
+    // Dummy reads whose results are ignored.
+    DummyReadPc,
+    DummyReadPreviousPc,
+    DummyReadBal,
+    DummyReadStack,
+    /// Reads from the stack and increments the stack pointer.
+    DummyPull,
+    /// Reads from the base address indexed without the page boundary fix.
+    DummyReadIndexed(Index),
+    /// Reads from `$FF` page at the offset stored in `adl`.
+    DummyReadHighPage,
+
+    // Reading indirect addresses.
+    ReadAdlZeroPageX,
+    ReadAdhZeroPageX,
+    ReadAdlZeroPageIndirect,
+    ReadAdhZeroPageIndirect,
+    ReadBalZeroPageIndirect,
+    ReadBahZeroPageIndirect,
+    ReadAdlIndirect,
+    ReadAdlIndexedIndirect,
+
+    // Final cycles of the read instructions.
+    LoadImmediate(Load),
+    LoadZeroPage(Load),
+    LoadZeroPageIndexed(Index, Load),
+    LoadAbsolute(Load),
+    /// Loads from the indexed address if it's on the same page as the base
+    /// address; otherwise, performs a dummy read and continues.
+    LoadIndexedSamePage(Index, Load),
+    /// Same as `LoadIndexedSamePage`, but on a page crossing, re-reads the
+    /// last operand byte instead of the unfixed address, as 65C02 does.
+    LoadIndexedSamePageCmos(Index, Load),
+    LoadIndexed(Index, Load),
+    Pull(Load),
+
+    // Final cycles of the write instructions.
+    StoreZeroPage(Source),
+    StoreZeroPageIndexed(Index, Source),
+    StoreAbsolute(Source),
+    StoreIndexed(Index, Source),
+    Push(Source),
+
+    // Read-modify-write instructions. The value being modified is kept in
+    // `tmp_data`.
+    ReadZeroPage,
+    /// Computes the effective address into `adl` and reads from it.
+    ReadZeroPageIndexed(Index),
+    ReadAbsolute,
+    ReadIndexed(Index),
+    /// Reads from the indexed address and skips the next micro-op if it's on
+    /// the same page as the base address; otherwise, re-reads the last operand
+    /// byte and continues. 65C02 only.
+    ReadIndexedSamePageCmos(Index),
+    DummyWriteZeroPage,
+    DummyWriteAbsolute,
+    DummyWriteIndexed(Index),
+    ModifyZeroPage(Modify),
+    ModifyAbsolute(Modify),
+    ModifyIndexed(Index, Modify),
+
+    /// Performs an internal operation, along with a dummy read.
+    Execute(Internal),
+
+    // Branches.
+    /// Fetches the offset and finishes unless the given flag has a given
+    /// value.
+    FetchBranchOffset {
+        flag: u8,
+        value: u8,
+    },
+    Branch,
+    BranchFixup,
+
+    // Jumps, subroutines, and interrupts.
+    Jump,
+    /// Finishes an indirect jump with the NMOS page wrapping bug.
+    JumpIndirectWrapped,
+    JumpIndirect,
+    JumpIndexedIndirect,
+    PushPch,
+    PushPcl,
+    PullPcl,
+    PullPch,
+    PullFlags,
+    ReturnFromSubroutine,
+    ReturnFromInterrupt,
+    /// Performs the rest of the BRK sequence, which is the same as handling
+    /// the IRQ.
+    Break,
+
+    /// Finishes the 65C02 8-cycle NOP.
+    DummyReadHighPageAndFinish,
+
+    Halt,
+    Unknown,
+}
+
+use Index::*;
+use MicroOp::*;
+
+/// Creates a micro-op sequence out of a slice.
+const fn steps(micro_ops: &[MicroOp]) -> Steps {
+    let mut result = [Unknown; MAX_STEPS];
+    let mut i = 0;
+    while i < micro_ops.len() {
+        result[i] = micro_ops[i];
+        i += 1;
+    }
+    result
+}
+
+const fn internal(operation: Internal) -> Steps {
+    steps(&[Execute(operation)])
+}
+
+const fn load_immediate(load: Load) -> Steps {
+    steps(&[LoadImmediate(load)])
+}
+
+const fn load_zero_page(load: Load) -> Steps {
+    steps(&[FetchAdl, LoadZeroPage(load)])
+}
+
+const fn load_zero_page_indexed(index: Index, load: Load) -> Steps {
+    steps(&[FetchBal, DummyReadBal, LoadZeroPageIndexed(index, load)])
+}
+
+const fn load_absolute(load: Load) -> Steps {
+    steps(&[FetchAdl, FetchAdh, LoadAbsolute(load)])
+}
+
+const fn load_absolute_indexed(index: Index, load: Load) -> Steps {
+    steps(&[
+        FetchBal,
+        FetchBah,
+        LoadIndexedSamePage(index, load),
+        LoadIndexed(index, load),
+    ])
+}
+
+const fn load_x_indirect(load: Load) -> Steps {
+    steps(&[
+        FetchBal,
+        DummyReadBal,
+        ReadAdlZeroPageX,
+        ReadAdhZeroPageX,
+        LoadAbsolute(load),
+    ])
+}
+
+const fn load_indirect_y(load: Load) -> Steps {
+    steps(&[
+        FetchIal,
+        ReadBalZeroPageIndirect,
+        ReadBahZeroPageIndirect,
+        LoadIndexedSamePage(Y, load),
+        LoadIndexed(Y, load),
+    ])
+}
+
+const fn load_zero_page_indirect(load: Load) -> Steps {
+    steps(&[
+        FetchIal,
+        ReadAdlZeroPageIndirect,
+        ReadAdhZeroPageIndirect,
+        LoadAbsolute(load),
+    ])
+}
+
+const fn store_zero_page(source: Source) -> Steps {
+    steps(&[FetchAdl, StoreZeroPage(source)])
+}
+
+const fn store_zero_page_indexed(index: Index, source: Source) -> Steps {
+    steps(&[FetchBal, DummyReadBal, StoreZeroPageIndexed(index, source)])
+}
+
+const fn store_absolute(source: Source) -> Steps {
+    steps(&[FetchAdl, FetchAdh, StoreAbsolute(source)])
+}
+
+const fn store_absolute_indexed(index: Index, source: Source) -> Steps {
+    steps(&[
+        FetchBal,
+        FetchBah,
+        DummyReadIndexed(index),
+        StoreIndexed(index, source),
+    ])
+}
+
+const fn store_x_indirect(source: Source) -> Steps {
+    steps(&[
+        FetchBal,
+        DummyReadBal,
+        ReadAdlZeroPageX,
+        ReadAdhZeroPageX,
+        StoreAbsolute(source),
+    ])
+}
+
+const fn store_indirect_y(source: Source) -> Steps {
+    steps(&[
+        FetchIal,
+        ReadBalZeroPageIndirect,
+        ReadBahZeroPageIndirect,
+        DummyReadIndexed(Y),
+        StoreIndexed(Y, source),
+    ])
+}
+
+const fn store_zero_page_indirect(source: Source) -> Steps {
+    steps(&[
+        FetchIal,
+        ReadAdlZeroPageIndirect,
+        ReadAdhZeroPageIndirect,
+        StoreAbsolute(source),
+    ])
+}
+
+const fn modify_zero_page(modify: Modify) -> Steps {
+    steps(&[
+        FetchAdl,
+        ReadZeroPage,
+        DummyWriteZeroPage,
+        ModifyZeroPage(modify),
+    ])
+}
+
+const fn modify_zero_page_x(modify: Modify) -> Steps {
+    steps(&[
+        FetchBal,
+        DummyReadBal,
+        ReadZeroPageIndexed(X),
+        DummyWriteZeroPage,
+        ModifyZeroPage(modify),
+    ])
+}
+
+const fn modify_absolute(modify: Modify) -> Steps {
+    steps(&[
+        FetchAdl,
+        FetchAdh,
+        ReadAbsolute,
+        DummyWriteAbsolute,
+        ModifyAbsolute(modify),
+    ])
+}
+
+const fn modify_absolute_indexed(index: Index, modify: Modify) -> Steps {
+    steps(&[
+        FetchBal,
+        FetchBah,
+        DummyReadIndexed(index),
+        ReadIndexed(index),
+        DummyWriteIndexed(index),
+        ModifyIndexed(index, modify),
+    ])
+}
+
+/// 65C02 variant of `modify_absolute_indexed` that only takes the additional
+/// cycle when crossing a page boundary.
+const fn modify_absolute_indexed_cmos(index: Index, modify: Modify) -> Steps {
+    steps(&[
+        FetchBal,
+        FetchBah,
+        ReadIndexedSamePageCmos(index),
+        ReadIndexed(index),
+        DummyWriteIndexed(index),
+        ModifyIndexed(index, modify),
+    ])
+}
+
+const fn modify_x_indirect(modify: Modify) -> Steps {
+    steps(&[
+        FetchBal,
+        DummyReadBal,
+        ReadAdlZeroPageX,
+        ReadAdhZeroPageX,
+        ReadAbsolute,
+        DummyWriteAbsolute,
+        ModifyAbsolute(modify),
+    ])
+}
+
+const fn modify_indirect_y(modify: Modify) -> Steps {
+    steps(&[
+        FetchIal,
+        ReadBalZeroPageIndirect,
+        ReadBahZeroPageIndirect,
+        DummyReadIndexed(Y),
+        ReadIndexed(Y),
+        DummyWriteIndexed(Y),
+        ModifyIndexed(Y, modify),
+    ])
+}
+
+/// Fills in all addressing modes of an undocumented read-modify-write
+/// instruction.
+const fn set_undocumented_modify(table: &mut [Steps; 256], opcodes: [u8; 7], modify: Modify) {
+    let [zp, zp_x, abs, abs_x, abs_y, x_indir, indir_y] = opcodes;
+    table[zp as usize] = modify_zero_page(modify);
+    table[zp_x as usize] = modify_zero_page_x(modify);
+    table[abs as usize] = modify_absolute(modify);
+    table[abs_x as usize] = modify_absolute_indexed(X, modify);
+    table[abs_y as usize] = modify_absolute_indexed(Y, modify);
+    table[x_indir as usize] = modify_x_indirect(modify);
+    table[indir_y as usize] = modify_indirect_y(modify);
+}
+
+const fn push(source: Source) -> Steps {
+    steps(&[DummyReadPc, Push(source)])
+}
+
+const fn pull(load: Load) -> Steps {
+    steps(&[DummyReadPc, DummyPull, Pull(load)])
+}
+
+const fn branch(flag: u8, value: u8) -> Steps {
+    steps(&[FetchBranchOffset { flag, value }, Branch, BranchFixup])
+}
+
+const fn nmos_microcode() -> [Steps; 256] {
+    use Internal::*;
+    use Load::*;
+    use Modify::*;
+    let mut table = [steps(&[Unknown]); 256];
+
+    table[NOP as usize] = internal(Internal::Nop);
+
+    table[LDA_IMM as usize] = load_immediate(Lda);
+    table[LDA_ZP as usize] = load_zero_page(Lda);
+    table[LDA_ZP_X as usize] = load_zero_page_indexed(X, Lda);
+    table[LDA_ABS as usize] = load_absolute(Lda);
+    table[LDA_ABS_X as usize] = load_absolute_indexed(X, Lda);
+    table[LDA_ABS_Y as usize] = load_absolute_indexed(Y, Lda);
+    table[LDA_X_INDIR as usize] = load_x_indirect(Lda);
+    table[LDA_INDIR_Y as usize] = load_indirect_y(Lda);
+
+    table[LDX_IMM as usize] = load_immediate(Ldx);
+    table[LDX_ZP as usize] = load_zero_page(Ldx);
+    table[LDX_ZP_Y as usize] = load_zero_page_indexed(Y, Ldx);
+    table[LDX_ABS as usize] = load_absolute(Ldx);
+    table[LDX_ABS_Y as usize] = load_absolute_indexed(Y, Ldx);
+
+    table[LDY_IMM as usize] = load_immediate(Ldy);
+    table[LDY_ZP as usize] = load_zero_page(Ldy);
+    table[LDY_ZP_X as usize] = load_zero_page_indexed(X, Ldy);
+    table[LDY_ABS as usize] = load_absolute(Ldy);
+    table[LDY_ABS_X as usize] = load_absolute_indexed(X, Ldy);
+
+    table[STA_ZP as usize] = store_zero_page(Source::A);
+    table[STA_ZP_X as usize] = store_zero_page_indexed(X, Source::A);
+    table[STA_ABS as usize] = store_absolute(Source::A);
+    table[STA_ABS_X as usize] = store_absolute_indexed(X, Source::A);
+    table[STA_ABS_Y as usize] = store_absolute_indexed(Y, Source::A);
+    table[STA_X_INDIR as usize] = store_x_indirect(Source::A);
+    table[STA_INDIR_Y as usize] = store_indirect_y(Source::A);
+
+    table[STX_ZP as usize] = store_zero_page(Source::X);
+    table[STX_ZP_Y as usize] = store_zero_page_indexed(Y, Source::X);
+    table[STX_ABS as usize] = store_absolute(Source::X);
+
+    table[STY_ZP as usize] = store_zero_page(Source::Y);
+    table[STY_ZP_X as usize] = store_zero_page_indexed(X, Source::Y);
+    table[STY_ABS as usize] = store_absolute(Source::Y);
+
+    table[AND_IMM as usize] = load_immediate(And);
+    table[AND_ZP as usize] = load_zero_page(And);
+    table[AND_ZP_X as usize] = load_zero_page_indexed(X, And);
+    table[AND_ABS as usize] = load_absolute(And);
+    table[AND_ABS_X as usize] = load_absolute_indexed(X, And);
+    table[AND_ABS_Y as usize] = load_absolute_indexed(Y, And);
+    table[AND_X_INDIR as usize] = load_x_indirect(And);
+    table[AND_INDIR_Y as usize] = load_indirect_y(And);
+
+    table[ORA_IMM as usize] = load_immediate(Ora);
+    table[ORA_ZP as usize] = load_zero_page(Ora);
+    table[ORA_ZP_X as usize] = load_zero_page_indexed(X, Ora);
+    table[ORA_ABS as usize] = load_absolute(Ora);
+    table[ORA_ABS_X as usize] = load_absolute_indexed(X, Ora);
+    table[ORA_ABS_Y as usize] = load_absolute_indexed(Y, Ora);
+    table[ORA_X_INDIR as usize] = load_x_indirect(Ora);
+    table[ORA_INDIR_Y as usize] = load_indirect_y(Ora);
+
+    table[EOR_IMM as usize] = load_immediate(Eor);
+    table[EOR_ZP as usize] = load_zero_page(Eor);
+    table[EOR_ZP_X as usize] = load_zero_page_indexed(X, Eor);
+    table[EOR_ABS as usize] = load_absolute(Eor);
+    table[EOR_ABS_X as usize] = load_absolute_indexed(X, Eor);
+    table[EOR_ABS_Y as usize] = load_absolute_indexed(Y, Eor);
+    table[EOR_X_INDIR as usize] = load_x_indirect(Eor);
+    table[EOR_INDIR_Y as usize] = load_indirect_y(Eor);
+
+    table[ASL_A as usize] = internal(Accumulator(Asl));
+    table[ASL_ZP as usize] = modify_zero_page(Asl);
+    table[ASL_ZP_X as usize] = modify_zero_page_x(Asl);
+    table[ASL_ABS as usize] = modify_absolute(Asl);
+    table[ASL_ABS_X as usize] = modify_absolute_indexed(X, Asl);
+
+    table[LSR_A as usize] = internal(Accumulator(Lsr));
+    table[LSR_ZP as usize] = modify_zero_page(Lsr);
+    table[LSR_ZP_X as usize] = modify_zero_page_x(Lsr);
+    table[LSR_ABS as usize] = modify_absolute(Lsr);
+    table[LSR_ABS_X as usize] = modify_absolute_indexed(X, Lsr);
+
+    table[ROL_A as usize] = internal(Accumulator(Rol));
+    table[ROL_ZP as usize] = modify_zero_page(Rol);
+    table[ROL_ZP_X as usize] = modify_zero_page_x(Rol);
+    table[ROL_ABS as usize] = modify_absolute(Rol);
+    table[ROL_ABS_X as usize] = modify_absolute_indexed(X, Rol);
+
+    table[ROR_A as usize] = internal(Accumulator(Ror));
+    table[ROR_ZP as usize] = modify_zero_page(Ror);
+    table[ROR_ZP_X as usize] = modify_zero_page_x(Ror);
+    table[ROR_ABS as usize] = modify_absolute(Ror);
+    table[ROR_ABS_X as usize] = modify_absolute_indexed(X, Ror);
+
+    table[CMP_IMM as usize] = load_immediate(Cmp);
+    table[CMP_ZP as usize] = load_zero_page(Cmp);
+    table[CMP_ZP_X as usize] = load_zero_page_indexed(X, Cmp);
+    table[CMP_ABS as usize] = load_absolute(Cmp);
+    table[CMP_ABS_X as usize] = load_absolute_indexed(X, Cmp);
+    table[CMP_ABS_Y as usize] = load_absolute_indexed(Y, Cmp);
+    table[CMP_X_INDIR as usize] = load_x_indirect(Cmp);
+    table[CMP_INDIR_Y as usize] = load_indirect_y(Cmp);
+
+    table[CPX_IMM as usize] = load_immediate(Cpx);
+    table[CPX_ZP as usize] = load_zero_page(Cpx);
+    table[CPX_ABS as usize] = load_absolute(Cpx);
+
+    table[CPY_IMM as usize] = load_immediate(Cpy);
+    table[CPY_ZP as usize] = load_zero_page(Cpy);
+    table[CPY_ABS as usize] = load_absolute(Cpy);
+
+    table[BIT_ZP as usize] = load_zero_page(Bit);
+    table[BIT_ABS as usize] = load_absolute(Bit);
+
+    table[ADC_IMM as usize] = load_immediate(Adc);
+    table[ADC_ZP as usize] = load_zero_page(Adc);
+    table[ADC_ZP_X as usize] = load_zero_page_indexed(X, Adc);
+    table[ADC_ABS as usize] = load_absolute(Adc);
+    table[ADC_ABS_X as usize] = load_absolute_indexed(X, Adc);
+    table[ADC_ABS_Y as usize] = load_absolute_indexed(Y, Adc);
+    table[ADC_X_INDIR as usize] = load_x_indirect(Adc);
+    table[ADC_INDIR_Y as usize] = load_indirect_y(Adc);
+
+    table[SBC_IMM as usize] = load_immediate(Sbc);
+    table[SBC_ZP as usize] = load_zero_page(Sbc);
+    table[SBC_ZP_X as usize] = load_zero_page_indexed(X, Sbc);
+    table[SBC_ABS as usize] = load_absolute(Sbc);
+    table[SBC_ABS_X as usize] = load_absolute_indexed(X, Sbc);
+    table[SBC_ABS_Y as usize] = load_absolute_indexed(Y, Sbc);
+    table[SBC_X_INDIR as usize] = load_x_indirect(Sbc);
+    table[SBC_INDIR_Y as usize] = load_indirect_y(Sbc);
+
+    table[INC_ZP as usize] = modify_zero_page(Inc);
+    table[INC_ZP_X as usize] = modify_zero_page_x(Inc);
+    table[INC_ABS as usize] = modify_absolute(Inc);
+    table[INC_ABS_X as usize] = modify_absolute_indexed(X, Inc);
+
+    table[DEC_ZP as usize] = modify_zero_page(Dec);
+    table[DEC_ZP_X as usize] = modify_zero_page_x(Dec);
+    table[DEC_ABS as usize] = modify_absolute(Dec);
+    table[DEC_ABS_X as usize] = modify_absolute_indexed(X, Dec);
+
+    table[INX as usize] = internal(Inx);
+    table[INY as usize] = internal(Iny);
+    table[DEX as usize] = internal(Dex);
+    table[DEY as usize] = internal(Dey);
+
+    table[TAX as usize] = internal(Tax);
+    table[TAY as usize] = internal(Tay);
+    table[TXA as usize] = internal(Txa);
+    table[TYA as usize] = internal(Tya);
+    table[TXS as usize] = internal(Txs);
+    table[TSX as usize] = internal(Tsx);
+
+    table[PHP as usize] = push(Source::Flags);
+    table[PLP as usize] = pull(Plp);
+    table[PHA as usize] = push(Source::A);
+    table[PLA as usize] = pull(Lda);
+
+    table[SEI as usize] = internal(Sei);
+    table[CLI as usize] = internal(Cli);
+    table[SED as usize] = internal(Sed);
+    table[CLD as usize] = internal(Cld);
+    table[SEC as usize] = internal(Sec);
+    table[CLC as usize] = internal(Clc);
+    table[CLV as usize] = internal(Clv);
+
+    table[BEQ as usize] = branch(flags::Z, flags::Z);
+    table[BNE as usize] = branch(flags::Z, 0);
+    table[BCC as usize] = branch(flags::C, 0);
+    table[BCS as usize] = branch(flags::C, flags::C);
+    table[BPL as usize] = branch(flags::N, 0);
+    table[BMI as usize] = branch(flags::N, flags::N);
+    table[BVS as usize] = branch(flags::V, flags::V);
+    table[BVC as usize] = branch(flags::V, 0);
+
+    table[JMP_ABS as usize] = steps(&[FetchAdl, Jump]);
+    table[JMP_INDIR as usize] = steps(&[FetchIal, FetchIah, ReadAdlIndirect, JumpIndirectWrapped]);
+    table[JSR as usize] = steps(&[FetchAdl, DummyReadStack, PushPch, PushPcl, Jump]);
+    table[RTS as usize] = steps(&[
+        DummyFetch,
+        DummyPull,
+        PullPcl,
+        PullPch,
+        ReturnFromSubroutine,
+    ]);
+    table[BRK as usize] = steps(&[SkipOperand, Break, Break, Break, Break, Break]);
+    table[RTI as usize] = steps(&[
+        DummyReadPc,
+        DummyPull,
+        PullFlags,
+        PullPcl,
+        ReturnFromInterrupt,
+    ]);
+
+    // Undocumented opcodes
+    table[LAX_IMM as usize] = load_immediate(LaxImmediate);
+    table[LAX_ZP as usize] = load_zero_page(Lax);
+    table[LAX_ZP_Y as usize] = load_zero_page_indexed(Y, Lax);
+    table[LAX_ABS as usize] = load_absolute(Lax);
+    table[LAX_ABS_Y as usize] = load_absolute_indexed(Y, Lax);
+    table[LAX_X_INDIR as usize] = load_x_indirect(Lax);
+    table[LAX_INDIR_Y as usize] = load_indirect_y(Lax);
+
+    table[SAX_ZP as usize] = store_zero_page(Source::AAndX);
+    table[SAX_ZP_Y as usize] = store_zero_page_indexed(Y, Source::AAndX);
+    table[SAX_ABS as usize] = store_absolute(Source::AAndX);
+    table[SAX_X_INDIR as usize] = store_x_indirect(Source::AAndX);
+
+    set_undocumented_modify(
+        &mut table,
+        [
+            DCP_ZP,
+            DCP_ZP_X,
+            DCP_ABS,
+            DCP_ABS_X,
+            DCP_ABS_Y,
+            DCP_X_INDIR,
+            DCP_INDIR_Y,
+        ],
+        Dcp,
+    );
+    set_undocumented_modify(
+        &mut table,
+        [
+            ISC_ZP,
+            ISC_ZP_X,
+            ISC_ABS,
+            ISC_ABS_X,
+            ISC_ABS_Y,
+            ISC_X_INDIR,
+            ISC_INDIR_Y,
+        ],
+        Isc,
+    );
+    set_undocumented_modify(
+        &mut table,
+        [
+            SLO_ZP,
+            SLO_ZP_X,
+            SLO_ABS,
+            SLO_ABS_X,
+            SLO_ABS_Y,
+            SLO_X_INDIR,
+            SLO_INDIR_Y,
+        ],
+        Slo,
+    );
+    set_undocumented_modify(
+        &mut table,
+        [
+            RLA_ZP,
+            RLA_ZP_X,
+            RLA_ABS,
+            RLA_ABS_X,
+            RLA_ABS_Y,
+            RLA_X_INDIR,
+            RLA_INDIR_Y,
+        ],
+        Rla,
+    );
+    set_undocumented_modify(
+        &mut table,
+        [
+            SRE_ZP,
+            SRE_ZP_X,
+            SRE_ABS,
+            SRE_ABS_X,
+            SRE_ABS_Y,
+            SRE_X_INDIR,
+            SRE_INDIR_Y,
+        ],
+        Sre,
+    );
+    set_undocumented_modify(
+        &mut table,
+        [
+            RRA_ZP,
+            RRA_ZP_X,
+            RRA_ABS,
+            RRA_ABS_X,
+            RRA_ABS_Y,
+            RRA_X_INDIR,
+            RRA_INDIR_Y,
+        ],
+        Rra,
+    );
+
+    table[ANC_IMM as usize] = load_immediate(Anc);
+    table[ANC_IMM2 as usize] = load_immediate(Anc);
+    table[ALR_IMM as usize] = load_immediate(Alr);
+    table[ARR_IMM as usize] = load_immediate(Arr);
+    table[SBX_IMM as usize] = load_immediate(Sbx);
+    table[SBC_IMM2 as usize] = load_immediate(Sbc);
+
+    // Undocumented NOPs. Note that except for the single-byte ones, they
+    // actually perform memory reads, just like the corresponding load
+    // instructions.
+    let nops = [NOP1, NOP2, NOP3, NOP4, NOP5, NOP6];
+    let mut i = 0;
+    while i < nops.len() {
+        table[nops[i] as usize] = internal(Internal::Nop);
+        i += 1;
+    }
+    let nops = [NOP_IMM1, NOP_IMM2, NOP_IMM3, NOP_IMM4, NOP_IMM5];
+    let mut i = 0;
+    while i < nops.len() {
+        table[nops[i] as usize] = load_immediate(Load::Nop);
+        i += 1;
+    }
+    let nops = [NOP_ZP1, NOP_ZP2, NOP_ZP3];
+    let mut i = 0;
+    while i < nops.len() {
+        table[nops[i] as usize] = load_zero_page(Load::Nop);
+        i += 1;
+    }
+    let nops = [
+        NOP_ZP_X1, NOP_ZP_X2, NOP_ZP_X3, NOP_ZP_X4, NOP_ZP_X5, NOP_ZP_X6,
+    ];
+    let mut i = 0;
+    while i < nops.len() {
+        table[nops[i] as usize] = load_zero_page_indexed(X, Load::Nop);
+        i += 1;
+    }
+    table[NOP_ABS as usize] = load_absolute(Load::Nop);
+    let nops = [
+        NOP_ABS_X1, NOP_ABS_X2, NOP_ABS_X3, NOP_ABS_X4, NOP_ABS_X5, NOP_ABS_X6,
+    ];
+    let mut i = 0;
+    while i < nops.len() {
+        table[nops[i] as usize] = load_absolute_indexed(X, Load::Nop);
+        i += 1;
+    }
+
+    let hlts = [
+        HLT1, HLT2, HLT3, HLT4, HLT5, HLT6, HLT7, HLT8, HLT9, HLT10, HLT11, HLT12,
+    ];
+    let mut i = 0;
+    while i < hlts.len() {
+        table[hlts[i] as usize] = steps(&[Halt]);
+        i += 1;
+    }
+
+    table
+}
+
+const fn cmos_microcode() -> [Steps; 256] {
+    use Internal::*;
+    use Load::*;
+    use Modify::*;
+    let mut table = nmos_microcode();
+
+    // Compare no flags, so that the condition is always met.
+    table[BRA as usize] = branch(0, 0);
+
+    table[PHX as usize] = push(Source::X);
+    table[PHY as usize] = push(Source::Y);
+    table[PLX as usize] = pull(Ldx);
+    table[PLY as usize] = pull(Ldy);
+
+    table[STZ_ZP as usize] = store_zero_page(Source::Zero);
+    table[STZ_ZP_X as usize] = store_zero_page_indexed(X, Source::Zero);
+    table[STZ_ABS as usize] = store_absolute(Source::Zero);
+    table[STZ_ABS_X as usize] = store_absolute_indexed(X, Source::Zero);
+
+    table[TRB_ZP as usize] = modify_zero_page(Trb);
+    table[TRB_ABS as usize] = modify_absolute(Trb);
+    table[TSB_ZP as usize] = modify_zero_page(Tsb);
+    table[TSB_ABS as usize] = modify_absolute(Tsb);
+
+    table[ORA_ZP_INDIR as usize] = load_zero_page_indirect(Ora);
+    table[AND_ZP_INDIR as usize] = load_zero_page_indirect(And);
+    table[EOR_ZP_INDIR as usize] = load_zero_page_indirect(Eor);
+    table[ADC_ZP_INDIR as usize] = load_zero_page_indirect(Adc);
+    table[STA_ZP_INDIR as usize] = store_zero_page_indirect(Source::A);
+    table[LDA_ZP_INDIR as usize] = load_zero_page_indirect(Lda);
+    table[CMP_ZP_INDIR as usize] = load_zero_page_indirect(Cmp);
+    table[SBC_ZP_INDIR as usize] = load_zero_page_indirect(Sbc);
+
+    table[BIT_IMM as usize] = load_immediate(BitImmediate);
+    table[BIT_ZP_X as usize] = load_zero_page_indexed(X, Bit);
+    table[BIT_ABS_X as usize] = load_absolute_indexed(X, Bit);
+
+    table[INC_A as usize] = internal(Accumulator(Inc));
+    table[DEC_A as usize] = internal(Accumulator(Dec));
+
+    // On page crossings, 65C02 doesn't read from the unfixed address; instead,
+    // it reads the last operand byte again.
+    let mut opcode = 0;
+    while opcode < table.len() {
+        let mut i = 0;
+        while i < MAX_STEPS {
+            if let LoadIndexedSamePage(index, load) = table[opcode][i] {
+                table[opcode][i] = LoadIndexedSamePageCmos(index, load);
+            }
+            i += 1;
+        }
+        opcode += 1;
+    }
+
+    // Shifts and rotations with indexed addressing only take the additional
+    // cycle when crossing a page boundary. INC and DEC always take 7 cycles.
+    table[ASL_ABS_X as usize] = modify_absolute_indexed_cmos(X, Asl);
+    table[LSR_ABS_X as usize] = modify_absolute_indexed_cmos(X, Lsr);
+    table[ROL_ABS_X as usize] = modify_absolute_indexed_cmos(X, Rol);
+    table[ROR_ABS_X as usize] = modify_absolute_indexed_cmos(X, Ror);
+
+    // Instead of wrapping around the page boundary when reading the address,
+    // 65C02 spends one more cycle to get it right.
+    table[JMP_INDIR as usize] = steps(&[
+        FetchIal,
+        FetchIah,
+        DummyReadPreviousPc,
+        ReadAdlIndirect,
+        JumpIndirect,
+    ]);
+    table[JMP_X_INDIR as usize] = steps(&[
+        FetchBal,
+        FetchBah,
+        DummyReadPreviousPc,
+        ReadAdlIndexedIndirect,
+        JumpIndexedIndirect,
+    ]);
+
+    // Unused 65C02 opcodes that behave differently than on NMOS. Single-byte
+    // NOPs are handled while fetching the opcode.
+    let nops = [HLT1, HLT3, HLT5, HLT7];
+    let mut i = 0;
+    while i < nops.len() {
+        table[nops[i] as usize] = load_immediate(Load::Nop);
+        i += 1;
+    }
+    table[NOP_ABS_X5 as usize] = load_absolute(Load::Nop);
+    table[NOP_ABS_X6 as usize] = load_absolute(Load::Nop);
+    // This one is weird: it reads from $FFxx for 5 cycles.
+    table[NOP_ABS_X3 as usize] = steps(&[
+        FetchAdl,
+        FetchAdh,
+        DummyReadHighPage,
+        DummyReadHighPage,
+        DummyReadHighPage,
+        DummyReadHighPage,
+        DummyReadHighPageAndFinish,
+    ]);
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::instructions::{
+        AddressingMode, InstructionSet, CMOS_INSTRUCTIONS, NMOS_INSTRUCTIONS,
+    };
+    use super::*;
+
+    /// Counts the cycles of an instruction, assuming that no shortcuts are
+    /// taken.
+    fn cycles(steps: &Steps) -> usize {
+        1 + steps.iter().take_while(|&&op| op != Unknown).count()
+    }
+
+    /// Returns the number of cycles that a given instruction takes in the
+    /// worst case, without the 65C02 decimal mode penalty.
+    fn expected_cycles(instruction_set: &InstructionSet, opcode: u8) -> usize {
+        let info = &instruction_set[opcode as usize];
+        if info.addressing_mode == AddressingMode::Relative {
+            // Branches are the odd ones: two cycles if not taken, three if
+            // taken, and four if crossing a page.
+            4
+        } else if info.page_cross_penalty {
+            // Conditional shortcuts make the instructions with a page crossing
+            // penalty shorter by one cycle.
+            info.cycles as usize + 1
+        } else {
+            info.cycles as usize
+        }
+    }
+
+    #[test]
+    fn matches_instruction_timing() {
+        for opcode in 0..=255u8 {
+            let info = &NMOS_INSTRUCTIONS[opcode as usize];
+            let steps = &NMOS[opcode as usize];
+            match steps[0] {
+                // HLT never finishes, and the unstable opcodes aren't
+                // emulated at all.
+                Halt | Unknown => continue,
+                _ => {}
+            }
+            assert_eq!(
+                cycles(steps),
+                expected_cycles(&NMOS_INSTRUCTIONS, opcode),
+                "{} (${:02X})",
+                info.mnemonic,
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn matches_cmos_instruction_timing() {
+        for opcode in 0..=255u8 {
+            let info = &CMOS_INSTRUCTIONS[opcode as usize];
+            if opcode & 0b11 == 0b11 {
+                // Single-cycle NOPs are handled while fetching the opcode.
+                assert_eq!(info.cycles, 1, "${:02X}", opcode);
+                continue;
+            }
+            assert_eq!(
+                cycles(&CMOS[opcode as usize]),
+                expected_cycles(&CMOS_INSTRUCTIONS, opcode),
+                "{} (${:02X})",
+                info.mnemonic,
+                opcode
+            );
+        }
+    }
+}
@@ -1,38 +1,68 @@
 mod bcd;
+pub mod bus;
 pub mod flags;
+pub mod instructions;
+mod microcode;
 pub mod opcodes;
+mod processor_tests;
 mod tests;
+pub mod variant;
 
 use crate::memory::Inspect;
-use crate::memory::{Memory, ReadError, ReadResult};
+use crate::memory::Poke;
+use crate::memory::{Memory, ReadError, ReadResult, WriteError, WriteResult};
+use crate::power_on::PowerOnState;
+use bus::{BusAccess, BusAccessKind, BusObserver, BusOperation, ObserverSlot};
 use flags::FlagRepresentation;
+use instructions::InstructionSet;
+use microcode::{Index, Internal, Load, MicroOp, Modify, Source};
 use mockall::automock;
+use mockall::mock;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 use std::error;
 use std::fmt;
 use std::fmt::Debug;
+use std::marker::PhantomData;
+use variant::{Nmos6502, Variant};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 enum SequenceState {
     Reset(u32),
     Ready,
     Opcode(u8, u32),
     Irq(u32),
     Nmi(u32),
+    /// An additional cycle taken by 65C02 after ADC and SBC in decimal mode.
+    DecimalFixup,
 }
 
 /// A 6502 CPU that operates on a given type of memory. A key to creating a
 /// working hardware implementation is to provide a `Memory` implementation
-/// specific to your particular hardware.
+/// specific to your particular hardware. The `V` parameter selects a
+/// particular [`Variant`] of the CPU; by default, it's an NMOS 6502.
 #[derive(Debug)]
-pub struct Cpu<M: Memory> {
+pub struct Cpu<M: Memory, V: Variant = Nmos6502> {
     memory: Box<M>,
+    variant: PhantomData<V>,
+    bus_observer: ObserverSlot,
 
     // Interrupt sensors.
     irq_pin: bool,
     nmi_pin: bool,
     nmi_buffer: bool,
     nmi_latch: bool,
+    // Results of polling the interrupts at the end of the last cycle, and the
+    // one before. The CPU decides whether to service an interrupt based on
+    // what it has seen in the second-to-last cycle of an instruction.
+    irq_polled: bool,
+    nmi_polled: bool,
+    irq_pending: bool,
+    nmi_pending: bool,
+
+    rdy_pin: bool,
+    // Whether the last cycle has been stalled because of the RDY pin.
+    stalled: bool,
 
     // Registers.
     reg_pc: u16,
@@ -46,6 +76,8 @@ pub struct Cpu<M: Memory> {
 
     // Number of cycle within execution of the current instruction.
     sequence_state: SequenceState,
+    // Address of the instruction currently being executed.
+    instruction_pc: u16,
     // Address
     adl: u8,
     adh: u8,
@@ -56,70 +88,184 @@ pub struct Cpu<M: Memory> {
     ial: u8,
     iah: u8,
     tmp_data: u8,
+
+    // Number of cycles executed since the CPU was created.
+    cycles: u64,
 }
 
-type TickResult = Result<(), Box<dyn error::Error>>;
+/// A complete snapshot of the internal CPU state, including the state of the
+/// instruction or interrupt sequence currently in progress. Restoring it using
+/// [`Cpu::restore`] brings the CPU back to exactly the same point of
+/// execution. Note that the state of memory is not a part of the snapshot; it's
+/// the caller's responsibility to save it separately.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CpuState {
+    pub reg_pc: u16,
+    pub reg_a: u8,
+    pub reg_x: u8,
+    pub reg_y: u8,
+    pub reg_sp: u8,
+    pub flags: u8,
+
+    pub irq_pin: bool,
+    pub nmi_pin: bool,
+    pub rdy_pin: bool,
+
+    pub cycles: u64,
+
+    // The rest is the internal state that shouldn't be tampered with.
+    nmi_buffer: bool,
+    nmi_latch: bool,
+    irq_polled: bool,
+    nmi_polled: bool,
+    irq_pending: bool,
+    nmi_pending: bool,
+    sequence_state: SequenceState,
+    instruction_pc: u16,
+    adl: u8,
+    adh: u8,
+    bal: u8,
+    bah: u8,
+    ial: u8,
+    iah: u8,
+    tmp_data: u8,
+}
 
-// enum CpuError {
-//     ReadError,
-//     WriteError,
-// }
+pub type TickResult = Result<(), CpuError>;
+
+/// An error that stops the CPU. Each variant carries the address of the
+/// instruction (or the interrupt sequence) that caused it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CpuError {
+    ReadError {
+        pc: u16,
+        error: ReadError,
+    },
+    WriteError {
+        pc: u16,
+        error: WriteError,
+    },
+    UnknownOpcode {
+        pc: u16,
+        opcode: u8,
+    },
+    /// The CPU executed one of the undocumented HLT opcodes, which freeze it
+    /// until the next reset.
+    Halted {
+        pc: u16,
+        opcode: u8,
+    },
+}
 
-#[derive(Debug, Clone)]
-struct UnknownOpcodeError {
-    pub opcode: u8,
-    pub address: u16,
+impl CpuError {
+    /// Returns the address of the instruction that caused the error.
+    pub fn pc(&self) -> u16 {
+        match self {
+            CpuError::ReadError { pc, .. }
+            | CpuError::WriteError { pc, .. }
+            | CpuError::UnknownOpcode { pc, .. }
+            | CpuError::Halted { pc, .. } => *pc,
+        }
+    }
 }
 
-impl error::Error for UnknownOpcodeError {}
+impl error::Error for CpuError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            CpuError::ReadError { error, .. } => Some(error),
+            CpuError::WriteError { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
 
-impl fmt::Display for UnknownOpcodeError {
+impl fmt::Display for CpuError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "Unknown opcode: ${:02X} at ${:04X}",
-            self.opcode, self.address
-        )
+        match self {
+            CpuError::ReadError { pc, error } => write!(f, "{} (PC: ${:04X})", error, pc),
+            CpuError::WriteError { pc, error } => write!(f, "{} (PC: ${:04X})", error, pc),
+            CpuError::UnknownOpcode { pc, opcode } => {
+                write!(f, "Unknown opcode: ${:02X} at ${:04X}", opcode, pc)
+            }
+            CpuError::Halted { pc, opcode } => {
+                write!(f, "CPU halted by opcode ${:02X} at ${:04X}", opcode, pc)
+            }
+        }
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct CpuHaltedError {
-    pub opcode: u8,
-    pub address: u16,
+/// A reason why the instruction sequence failed. It's turned into a
+/// [`CpuError`] once we attach the instruction address to it.
+enum Fault {
+    Read(ReadError),
+    Write(WriteError),
+    UnknownOpcode(u8),
+    Halted(u8),
 }
 
-impl error::Error for CpuHaltedError {}
+impl From<ReadError> for Fault {
+    fn from(error: ReadError) -> Self {
+        Fault::Read(error)
+    }
+}
 
-impl fmt::Display for CpuHaltedError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "CPU halted by opcode ${:02X} at ${:04X}",
-            self.opcode, self.address
-        )
+impl From<WriteError> for Fault {
+    fn from(error: WriteError) -> Self {
+        Fault::Write(error)
     }
 }
 
-// impl From<ReadError> for CpuError {
-//     fn from(err: ReadError) -> Self {
-//         CpuError::ReadError(err)
-//     }
-// }
+type SequenceResult = Result<(), Fault>;
+
+const NMI_VECTOR: u16 = 0xFFFA;
+const IRQ_VECTOR: u16 = 0xFFFE;
+
+/// A summary of what happened during [`Cpu::step_instruction`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    /// Number of CPU cycles consumed.
+    pub cycles: u32,
+    /// The interrupt that has been serviced (instead of executing an
+    /// instruction), if any.
+    pub interrupt: Option<Interrupt>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    Reset,
+    Irq,
+    Nmi,
+}
 
-impl<M: Memory + Debug> Cpu<M> {
+impl<M: Memory + Debug, V: Variant> Cpu<M, V> {
     /// Creates a new `CPU` that owns given `memory`. The newly created `CPU` is
     /// not yet ready for executing programs; it first needs to be reset using
-    /// the [`reset`](#method.reset) method.
+    /// the [`reset`](#method.reset) method. Just like the real hardware, the
+    /// CPU starts with random register values.
     pub fn new(memory: Box<M>) -> Self {
-        let mut rng = rand::thread_rng();
+        Self::with_power_on_state(memory, &PowerOnState::Random)
+    }
+
+    /// Same as [`new`](#method.new), but the initial register values are
+    /// determined by a given power-on policy.
+    pub fn with_power_on_state(memory: Box<M>, power_on_state: &PowerOnState) -> Self {
+        let mut rng = power_on_state.rng("CPU");
         Cpu {
             memory: memory,
+            variant: PhantomData,
+            bus_observer: ObserverSlot(None),
 
             irq_pin: false,
             nmi_pin: false,
             nmi_buffer: false,
             nmi_latch: false,
+            irq_polled: false,
+            nmi_polled: false,
+            irq_pending: false,
+            nmi_pending: false,
+
+            rdy_pin: true,
+            stalled: false,
 
             reg_pc: rng.gen(),
             reg_a: rng.gen(),
@@ -129,6 +275,7 @@ impl<M: Memory + Debug> Cpu<M> {
             flags: rng.gen::<u8>() & !flags::B | flags::UNUSED,
 
             sequence_state: SequenceState::Reset(0),
+            instruction_pc: 0,
             // adh: rng.gen(),
             adl: rng.gen(),
             adh: rng.gen(),
@@ -137,6 +284,8 @@ impl<M: Memory + Debug> Cpu<M> {
             ial: rng.gen(),
             iah: rng.gen(),
             tmp_data: rng.gen(),
+
+            cycles: 0,
         }
     }
 
@@ -154,6 +303,7 @@ impl<M: Memory + Debug> Cpu<M> {
     /// effectively resume program from this address.
     pub fn reset(&mut self) {
         self.sequence_state = SequenceState::Reset(0);
+        self.instruction_pc = self.reg_pc;
     }
 
     /// Controls whether IRQ has been received. Note that 6502 senses interrupts
@@ -170,11 +320,140 @@ impl<M: Memory + Debug> Cpu<M> {
         self.nmi_pin = nmi_pin;
     }
 
+    /// Controls the RDY line. When it's low (`false`), the CPU halts on the
+    /// next read cycle and keeps repeating it until the line goes high again.
+    /// Write cycles are not affected, so a write in progress always completes.
+    /// This is what the original NMOS chip does; 65C02 also halts on writes.
+    /// Just like the interrupt pins, the RDY line should be set before the
+    /// cycle that it's supposed to affect.
+    pub fn set_rdy_pin(&mut self, rdy_pin: bool) {
+        self.rdy_pin = rdy_pin;
+    }
+
+    pub fn irq_pin(&self) -> bool {
+        self.irq_pin
+    }
+
+    pub fn nmi_pin(&self) -> bool {
+        self.nmi_pin
+    }
+
+    pub fn rdy_pin(&self) -> bool {
+        self.rdy_pin
+    }
+
+    /// Captures the complete state of the CPU.
+    pub fn snapshot(&self) -> CpuState {
+        CpuState {
+            reg_pc: self.reg_pc,
+            reg_a: self.reg_a,
+            reg_x: self.reg_x,
+            reg_y: self.reg_y,
+            reg_sp: self.reg_sp,
+            flags: self.flags,
+
+            irq_pin: self.irq_pin,
+            nmi_pin: self.nmi_pin,
+            rdy_pin: self.rdy_pin,
+
+            cycles: self.cycles,
+
+            nmi_buffer: self.nmi_buffer,
+            nmi_latch: self.nmi_latch,
+            irq_polled: self.irq_polled,
+            nmi_polled: self.nmi_polled,
+            irq_pending: self.irq_pending,
+            nmi_pending: self.nmi_pending,
+            sequence_state: self.sequence_state,
+            instruction_pc: self.instruction_pc,
+            adl: self.adl,
+            adh: self.adh,
+            bal: self.bal,
+            bah: self.bah,
+            ial: self.ial,
+            iah: self.iah,
+            tmp_data: self.tmp_data,
+        }
+    }
+
+    /// Restores the CPU state previously captured using
+    /// [`snapshot`](#method.snapshot). The state should come from a CPU of the
+    /// same variant.
+    pub fn restore(&mut self, state: &CpuState) {
+        self.reg_pc = state.reg_pc;
+        self.reg_a = state.reg_a;
+        self.reg_x = state.reg_x;
+        self.reg_y = state.reg_y;
+        self.reg_sp = state.reg_sp;
+        self.flags = state.flags;
+
+        self.irq_pin = state.irq_pin;
+        self.nmi_pin = state.nmi_pin;
+        self.rdy_pin = state.rdy_pin;
+
+        self.cycles = state.cycles;
+
+        self.nmi_buffer = state.nmi_buffer;
+        self.nmi_latch = state.nmi_latch;
+        self.irq_polled = state.irq_polled;
+        self.nmi_polled = state.nmi_polled;
+        self.irq_pending = state.irq_pending;
+        self.nmi_pending = state.nmi_pending;
+        self.sequence_state = state.sequence_state;
+        self.instruction_pc = state.instruction_pc;
+        self.adl = state.adl;
+        self.adh = state.adh;
+        self.bal = state.bal;
+        self.bah = state.bah;
+        self.ial = state.ial;
+        self.iah = state.iah;
+        self.tmp_data = state.tmp_data;
+    }
+
+    /// Installs an observer that gets notified about every memory access
+    /// performed by the CPU, or removes it if `None` is given. Returns the
+    /// previously installed observer. When there's no observer, the only
+    /// overhead is checking for its presence. A read that gets repeated while
+    /// the CPU is stalled by the RDY line is only reported once.
+    pub fn set_bus_observer(
+        &mut self,
+        observer: Option<Box<dyn BusObserver>>,
+    ) -> Option<Box<dyn BusObserver>> {
+        std::mem::replace(&mut self.bus_observer.0, observer)
+    }
+
     pub fn jump_to(&mut self, address: u16) {
         self.reg_pc = address;
         self.sequence_state = SequenceState::Ready;
     }
 
+    /// Runs the CPU until it reaches the beginning of the next instruction.
+    /// Normally, this means executing a single instruction, but if the CPU is
+    /// about to service an interrupt (or is being reset), only the interrupt
+    /// sequence is performed, and the CPU stops at the first instruction of the
+    /// interrupt handler. If called in the middle of an instruction, it
+    /// finishes this instruction. Note that this function doesn't return as
+    /// long as the CPU is stalled by the RDY line.
+    pub fn step_instruction(&mut self) -> Result<Step, CpuError> {
+        let mut step = Step {
+            cycles: 0,
+            interrupt: None,
+        };
+        loop {
+            self.tick()?;
+            step.cycles += 1;
+            match self.sequence_state {
+                SequenceState::Reset(_) => step.interrupt = Some(Interrupt::Reset),
+                SequenceState::Irq(_) => step.interrupt = Some(Interrupt::Irq),
+                SequenceState::Nmi(_) => step.interrupt = Some(Interrupt::Nmi),
+                _ => {}
+            }
+            if self.at_sequence_start() {
+                return Ok(step);
+            }
+        }
+    }
+
     /// Performs a single CPU cycle.
     pub fn tick(&mut self) -> TickResult {
         // Detect transition on the NMI pin.
@@ -183,692 +462,68 @@ impl<M: Memory + Debug> Cpu<M> {
         }
         self.nmi_buffer = self.nmi_pin;
 
+        self.stalled = false;
+        if self.rdy_pin {
+            return self.tick_sequence().map_err(|fault| self.cpu_error(fault));
+        }
+
+        // We don't know in advance whether the current cycle is going to read
+        // or write, so we just try to perform it. If the CPU attempts to read
+        // from the memory, the read still happens on the bus, but the cycle is
+        // rolled back, so that it gets repeated once the RDY line goes high.
+        let state = self.snapshot();
+        let result = self.tick_sequence();
+        if !self.stalled {
+            return result.map_err(|fault| self.cpu_error(fault));
+        }
+        self.restore(&state);
+        self.cycles += 1;
+        Ok(())
+    }
+
+    fn cpu_error(&self, fault: Fault) -> CpuError {
+        let pc = self.instruction_pc;
+        match fault {
+            Fault::Read(error) => CpuError::ReadError { pc, error },
+            Fault::Write(error) => CpuError::WriteError { pc, error },
+            Fault::UnknownOpcode(opcode) => CpuError::UnknownOpcode { pc, opcode },
+            Fault::Halted(opcode) => CpuError::Halted { pc, opcode },
+        }
+    }
+
+    /// Performs a single step of the instruction or interrupt sequence.
+    fn tick_sequence(&mut self) -> SequenceResult {
         match self.sequence_state {
             // Fetching the opcode. A small trick: at first, we use 0 for
             // subcycle number, and it will later get increased to 1. Funny
             // thing, returning from here with subcycle set to 1 is slower than
             // waiting for 0 to be increased. Benchmarked!
             SequenceState::Ready => {
-                if self.nmi_latch {
+                self.instruction_pc = self.reg_pc;
+                if self.nmi_pending {
                     self.nmi_latch = false;
                     self.phantom_read(self.reg_pc);
                     self.sequence_state = SequenceState::Nmi(0);
-                } else if self.irq_pin && self.flags & flags::I == 0 {
+                } else if self.irq_pending {
                     self.phantom_read(self.reg_pc);
                     self.sequence_state = SequenceState::Irq(0);
                 } else {
-                    self.sequence_state = SequenceState::Opcode(self.consume_program_byte()?, 0);
+                    let opcode = self.read(self.reg_pc, BusAccessKind::OpcodeFetch)?;
+                    self.reg_pc = self.reg_pc.wrapping_add(1);
+                    // On 65C02, all the opcodes from columns 3, 7, B, and F
+                    // are single-cycle NOPs, so we just stay ready to fetch
+                    // the next opcode.
+                    if !V::CMOS || opcode & 0b11 != 0b11 {
+                        self.sequence_state = SequenceState::Opcode(opcode, 0);
+                    }
                 }
             }
-
-            // List ALL the opcodes!
-            SequenceState::Opcode(opcodes::NOP, _) => {
-                self.tick_simple_internal_operation(&mut |_| {})?;
-            }
-
-            SequenceState::Opcode(opcodes::LDA_IMM, _) => {
-                self.tick_load_immediate(&mut |me, value| me.set_reg_a(value))?;
-            }
-            SequenceState::Opcode(opcodes::LDA_ZP, _) => {
-                self.tick_load_zero_page(&mut |me, value| me.set_reg_a(value))?;
-            }
-            SequenceState::Opcode(opcodes::LDA_ZP_X, _) => {
-                self.tick_load_zero_page_indexed(self.reg_x, &mut |me, value| me.set_reg_a(value))?;
-            }
-            SequenceState::Opcode(opcodes::LDA_ABS, _) => {
-                self.tick_load_absolute(&mut |me, value| me.set_reg_a(value))?;
-            }
-            SequenceState::Opcode(opcodes::LDA_ABS_X, _) => {
-                self.tick_load_absolute_indexed(self.reg_x, &mut |me, value| me.set_reg_a(value))?;
-            }
-            SequenceState::Opcode(opcodes::LDA_ABS_Y, _) => {
-                self.tick_load_absolute_indexed(self.reg_y, &mut |me, value| me.set_reg_a(value))?;
-            }
-            SequenceState::Opcode(opcodes::LDA_X_INDIR, _) => {
-                self.tick_load_x_indirect(&mut |me, value| me.set_reg_a(value))?;
-            }
-            SequenceState::Opcode(opcodes::LDA_INDIR_Y, _) => {
-                self.tick_load_indirect_y(&mut |me, value| me.set_reg_a(value))?;
-            }
-
-            SequenceState::Opcode(opcodes::LDX_IMM, _) => {
-                self.tick_load_immediate(&mut |me, value| me.set_reg_x(value))?;
-            }
-            SequenceState::Opcode(opcodes::LDX_ZP, _) => {
-                self.tick_load_zero_page(&mut |me, value| me.set_reg_x(value))?;
-            }
-            SequenceState::Opcode(opcodes::LDX_ZP_Y, _) => {
-                self.tick_load_zero_page_indexed(self.reg_y, &mut |me, value| me.set_reg_x(value))?;
-            }
-            SequenceState::Opcode(opcodes::LDX_ABS, _) => {
-                self.tick_load_absolute(&mut |me, value| me.set_reg_x(value))?;
-            }
-            SequenceState::Opcode(opcodes::LDX_ABS_Y, _) => {
-                self.tick_load_absolute_indexed(self.reg_y, &mut |me, value| me.set_reg_x(value))?;
-            }
-
-            SequenceState::Opcode(opcodes::LDY_IMM, _) => {
-                self.tick_load_immediate(&mut |me, value| me.set_reg_y(value))?;
-            }
-            SequenceState::Opcode(opcodes::LDY_ZP, _) => {
-                self.tick_load_zero_page(&mut |me, value| me.set_reg_y(value))?;
-            }
-            SequenceState::Opcode(opcodes::LDY_ZP_X, _) => {
-                self.tick_load_zero_page_indexed(self.reg_x, &mut |me, value| me.set_reg_y(value))?;
-            }
-            SequenceState::Opcode(opcodes::LDY_ABS, _) => {
-                self.tick_load_absolute(&mut |me, value| me.set_reg_y(value))?;
-            }
-            SequenceState::Opcode(opcodes::LDY_ABS_X, _) => {
-                self.tick_load_absolute_indexed(self.reg_x, &mut |me, value| me.set_reg_y(value))?;
-            }
-
-            SequenceState::Opcode(opcodes::STA_ZP, _) => {
-                self.tick_store_zero_page(self.reg_a)?;
-            }
-            SequenceState::Opcode(opcodes::STA_ZP_X, _) => {
-                self.tick_store_zero_page_indexed(self.reg_x, self.reg_a)?;
-            }
-            SequenceState::Opcode(opcodes::STA_ABS, _) => {
-                self.tick_store_abs(self.reg_a)?;
-            }
-            SequenceState::Opcode(opcodes::STA_ABS_X, _) => {
-                self.tick_store_abs_indexed(self.reg_x, self.reg_a)?;
-            }
-            SequenceState::Opcode(opcodes::STA_ABS_Y, _) => {
-                self.tick_store_abs_indexed(self.reg_y, self.reg_a)?;
-            }
-            SequenceState::Opcode(opcodes::STA_X_INDIR, _) => {
-                self.tick_store_x_indirect(self.reg_a)?;
-            }
-            SequenceState::Opcode(opcodes::STA_INDIR_Y, _) => {
-                self.tick_store_indirect_y(self.reg_a)?;
-            }
-
-            SequenceState::Opcode(opcodes::STX_ZP, _) => {
-                self.tick_store_zero_page(self.reg_x)?;
-            }
-            SequenceState::Opcode(opcodes::STX_ZP_Y, _) => {
-                self.tick_store_zero_page_indexed(self.reg_y, self.reg_x)?;
-            }
-            SequenceState::Opcode(opcodes::STX_ABS, _) => {
-                self.tick_store_abs(self.reg_x)?;
-            }
-
-            SequenceState::Opcode(opcodes::STY_ZP, _) => {
-                self.tick_store_zero_page(self.reg_y)?;
-            }
-            SequenceState::Opcode(opcodes::STY_ZP_X, _) => {
-                self.tick_store_zero_page_indexed(self.reg_x, self.reg_y)?;
-            }
-            SequenceState::Opcode(opcodes::STY_ABS, _) => {
-                self.tick_store_abs(self.reg_y)?;
-            }
-
-            SequenceState::Opcode(opcodes::AND_IMM, _) => {
-                self.tick_load_immediate(&mut |me, value| me.set_reg_a(me.reg_a & value))?;
-            }
-            SequenceState::Opcode(opcodes::AND_ZP, _) => {
-                self.tick_load_zero_page(&mut |me, value| me.set_reg_a(me.reg_a & value))?;
-            }
-            SequenceState::Opcode(opcodes::AND_ZP_X, _) => {
-                self.tick_load_zero_page_indexed(self.reg_x, &mut |me, value| {
-                    me.set_reg_a(me.reg_a & value)
-                })?;
-            }
-            SequenceState::Opcode(opcodes::AND_ABS, _) => {
-                self.tick_load_absolute(&mut |me, value| me.set_reg_a(me.reg_a & value))?;
-            }
-            SequenceState::Opcode(opcodes::AND_ABS_X, _) => {
-                self.tick_load_absolute_indexed(self.reg_x, &mut |me, value| {
-                    me.set_reg_a(me.reg_a & value)
-                })?;
-            }
-            SequenceState::Opcode(opcodes::AND_ABS_Y, _) => {
-                self.tick_load_absolute_indexed(self.reg_y, &mut |me, value| {
-                    me.set_reg_a(me.reg_a & value)
-                })?;
-            }
-            SequenceState::Opcode(opcodes::AND_X_INDIR, _) => {
-                self.tick_load_x_indirect(&mut |me, value| me.set_reg_a(me.reg_a & value))?;
-            }
-            SequenceState::Opcode(opcodes::AND_INDIR_Y, _) => {
-                self.tick_load_indirect_y(&mut |me, value| me.set_reg_a(me.reg_a & value))?;
-            }
-
-            SequenceState::Opcode(opcodes::ORA_IMM, _) => {
-                self.tick_load_immediate(&mut |me, value| me.set_reg_a(me.reg_a | value))?;
-            }
-            SequenceState::Opcode(opcodes::ORA_ZP, _) => {
-                self.tick_load_zero_page(&mut |me, value| me.set_reg_a(me.reg_a | value))?;
-            }
-            SequenceState::Opcode(opcodes::ORA_ZP_X, _) => {
-                self.tick_load_zero_page_indexed(self.reg_x, &mut |me, value| {
-                    me.set_reg_a(me.reg_a | value)
-                })?;
-            }
-            SequenceState::Opcode(opcodes::ORA_ABS, _) => {
-                self.tick_load_absolute(&mut |me, value| me.set_reg_a(me.reg_a | value))?;
-            }
-            SequenceState::Opcode(opcodes::ORA_ABS_X, _) => {
-                self.tick_load_absolute_indexed(self.reg_x, &mut |me, value| {
-                    me.set_reg_a(me.reg_a | value)
-                })?;
-            }
-            SequenceState::Opcode(opcodes::ORA_ABS_Y, _) => {
-                self.tick_load_absolute_indexed(self.reg_y, &mut |me, value| {
-                    me.set_reg_a(me.reg_a | value)
-                })?;
-            }
-            SequenceState::Opcode(opcodes::ORA_X_INDIR, _) => {
-                self.tick_load_x_indirect(&mut |me, value| me.set_reg_a(me.reg_a | value))?;
-            }
-            SequenceState::Opcode(opcodes::ORA_INDIR_Y, _) => {
-                self.tick_load_indirect_y(&mut |me, value| me.set_reg_a(me.reg_a | value))?;
-            }
-
-            SequenceState::Opcode(opcodes::EOR_IMM, _) => {
-                self.tick_load_immediate(&mut |me, value| me.set_reg_a(me.reg_a ^ value))?;
-            }
-            SequenceState::Opcode(opcodes::EOR_ZP, _) => {
-                self.tick_load_zero_page(&mut |me, value| me.set_reg_a(me.reg_a ^ value))?;
-            }
-            SequenceState::Opcode(opcodes::EOR_ZP_X, _) => {
-                self.tick_load_zero_page_indexed(self.reg_x, &mut |me, value| {
-                    me.set_reg_a(me.reg_a ^ value)
-                })?;
-            }
-            SequenceState::Opcode(opcodes::EOR_ABS, _) => {
-                self.tick_load_absolute(&mut |me, value| me.set_reg_a(me.reg_a ^ value))?;
-            }
-            SequenceState::Opcode(opcodes::EOR_ABS_X, _) => {
-                self.tick_load_absolute_indexed(self.reg_x, &mut |me, value| {
-                    me.set_reg_a(me.reg_a ^ value)
-                })?;
-            }
-            SequenceState::Opcode(opcodes::EOR_ABS_Y, _) => {
-                self.tick_load_absolute_indexed(self.reg_y, &mut |me, value| {
-                    me.set_reg_a(me.reg_a ^ value)
-                })?;
-            }
-            SequenceState::Opcode(opcodes::EOR_X_INDIR, _) => {
-                self.tick_load_x_indirect(&mut |me, value| me.set_reg_a(me.reg_a ^ value))?;
-            }
-            SequenceState::Opcode(opcodes::EOR_INDIR_Y, _) => {
-                self.tick_load_indirect_y(&mut |me, value| me.set_reg_a(me.reg_a ^ value))?;
-            }
-
-            SequenceState::Opcode(opcodes::ASL_A, _) => {
-                self.tick_simple_internal_operation(&mut |me| {
-                    let shifted = me.shift_left(me.reg_a);
-                    me.set_reg_a(shifted);
-                })?;
-            }
-            SequenceState::Opcode(opcodes::ASL_ZP, _) => {
-                self.tick_load_modify_store_zero_page(&mut |me, value| me.shift_left(value))?;
-            }
-            SequenceState::Opcode(opcodes::ASL_ZP_X, _) => {
-                self.tick_load_modify_store_zero_page_x(&mut |me, value| me.shift_left(value))?;
-            }
-            SequenceState::Opcode(opcodes::ASL_ABS, _) => {
-                self.tick_load_modify_store_absolute(&mut |me, value| me.shift_left(value))?;
-            }
-            SequenceState::Opcode(opcodes::ASL_ABS_X, _) => {
-                self.tick_load_modify_store_absolute_indexed(self.reg_x, &mut |me, value| {
-                    me.shift_left(value)
-                })?;
-            }
-
-            SequenceState::Opcode(opcodes::LSR_A, _) => {
-                self.tick_simple_internal_operation(&mut |me| {
-                    let shifted = me.shift_right(me.reg_a);
-                    me.set_reg_a(shifted);
-                })?;
-            }
-            SequenceState::Opcode(opcodes::LSR_ZP, _) => {
-                self.tick_load_modify_store_zero_page(&mut |me, value| me.shift_right(value))?;
-            }
-            SequenceState::Opcode(opcodes::LSR_ZP_X, _) => {
-                self.tick_load_modify_store_zero_page_x(&mut |me, value| me.shift_right(value))?;
-            }
-            SequenceState::Opcode(opcodes::LSR_ABS, _) => {
-                self.tick_load_modify_store_absolute(&mut |me, value| me.shift_right(value))?;
-            }
-            SequenceState::Opcode(opcodes::LSR_ABS_X, _) => {
-                self.tick_load_modify_store_absolute_indexed(self.reg_x, &mut |me, value| {
-                    me.shift_right(value)
-                })?;
-            }
-
-            SequenceState::Opcode(opcodes::ROL_A, _) => {
-                self.tick_simple_internal_operation(&mut |me| {
-                    let rotated = me.rotate_left(me.reg_a);
-                    me.set_reg_a(rotated);
-                })?;
-            }
-            SequenceState::Opcode(opcodes::ROL_ZP, _) => {
-                self.tick_load_modify_store_zero_page(&mut |me, value| me.rotate_left(value))?;
-            }
-            SequenceState::Opcode(opcodes::ROL_ZP_X, _) => {
-                self.tick_load_modify_store_zero_page_x(&mut |me, value| me.rotate_left(value))?;
-            }
-            SequenceState::Opcode(opcodes::ROL_ABS, _) => {
-                self.tick_load_modify_store_absolute(&mut |me, value| me.rotate_left(value))?;
-            }
-            SequenceState::Opcode(opcodes::ROL_ABS_X, _) => {
-                self.tick_load_modify_store_absolute_indexed(self.reg_x, &mut |me, value| {
-                    me.rotate_left(value)
-                })?;
-            }
-
-            SequenceState::Opcode(opcodes::ROR_A, _) => {
-                self.tick_simple_internal_operation(&mut |me| {
-                    let rotated = me.rotate_right(me.reg_a);
-                    me.set_reg_a(rotated);
-                })?;
-            }
-            SequenceState::Opcode(opcodes::ROR_ZP, _) => {
-                self.tick_load_modify_store_zero_page(&mut |me, value| me.rotate_right(value))?;
-            }
-            SequenceState::Opcode(opcodes::ROR_ZP_X, _) => {
-                self.tick_load_modify_store_zero_page_x(&mut |me, value| me.rotate_right(value))?;
-            }
-            SequenceState::Opcode(opcodes::ROR_ABS, _) => {
-                self.tick_load_modify_store_absolute(&mut |me, value| me.rotate_right(value))?;
-            }
-            SequenceState::Opcode(opcodes::ROR_ABS_X, _) => {
-                self.tick_load_modify_store_absolute_indexed(self.reg_x, &mut |me, value| {
-                    me.rotate_right(value)
-                })?;
-            }
-
-            SequenceState::Opcode(opcodes::CMP_IMM, _) => {
-                self.tick_compare_immediate(self.reg_a)?;
-            }
-            SequenceState::Opcode(opcodes::CMP_ZP, _) => {
-                self.tick_compare_zero_page(self.reg_a)?;
-            }
-            SequenceState::Opcode(opcodes::CMP_ZP_X, _) => {
-                self.tick_compare_zero_page_x(self.reg_a)?;
-            }
-            SequenceState::Opcode(opcodes::CMP_ABS, _) => {
-                self.tick_compare_absolute(self.reg_a)?;
-            }
-            SequenceState::Opcode(opcodes::CMP_ABS_X, _) => {
-                self.tick_compare_absolute_indexed(self.reg_x, self.reg_a)?;
-            }
-            SequenceState::Opcode(opcodes::CMP_ABS_Y, _) => {
-                self.tick_compare_absolute_indexed(self.reg_y, self.reg_a)?;
-            }
-            SequenceState::Opcode(opcodes::CMP_X_INDIR, _) => {
-                self.tick_compare_x_indirect(self.reg_a)?;
-            }
-            SequenceState::Opcode(opcodes::CMP_INDIR_Y, _) => {
-                self.tick_compare_indirect_y(self.reg_a)?;
-            }
-
-            SequenceState::Opcode(opcodes::CPX_IMM, _) => {
-                self.tick_compare_immediate(self.reg_x)?;
-            }
-            SequenceState::Opcode(opcodes::CPX_ZP, _) => {
-                self.tick_compare_zero_page(self.reg_x)?;
-            }
-            SequenceState::Opcode(opcodes::CPX_ABS, _) => {
-                self.tick_compare_absolute(self.reg_x)?;
-            }
-
-            SequenceState::Opcode(opcodes::CPY_IMM, _) => {
-                self.tick_compare_immediate(self.reg_y)?;
-            }
-            SequenceState::Opcode(opcodes::CPY_ZP, _) => {
-                self.tick_compare_zero_page(self.reg_y)?;
-            }
-            SequenceState::Opcode(opcodes::CPY_ABS, _) => {
-                self.tick_compare_absolute(self.reg_y)?;
-            }
-
-            SequenceState::Opcode(opcodes::BIT_ZP, _) => {
-                self.tick_load_zero_page(&mut |me, value| me.test_bits(value))?;
-            }
-            SequenceState::Opcode(opcodes::BIT_ABS, _) => {
-                self.tick_load_absolute(&mut |me, value| me.test_bits(value))?;
-            }
-
-            SequenceState::Opcode(opcodes::ADC_IMM, _) => {
-                self.tick_load_immediate(&mut |me, value| {
-                    let sum = me.add_with_carry(me.reg_a, value);
-                    me.set_reg_a(sum);
-                })?;
-            }
-            SequenceState::Opcode(opcodes::ADC_ZP, _) => {
-                self.tick_load_zero_page(&mut |me, value| {
-                    let sum = me.add_with_carry(me.reg_a, value);
-                    me.set_reg_a(sum);
-                })?;
-            }
-            SequenceState::Opcode(opcodes::ADC_ZP_X, _) => {
-                self.tick_load_zero_page_indexed(self.reg_x, &mut |me, value| {
-                    let sum = me.add_with_carry(me.reg_a, value);
-                    me.set_reg_a(sum);
-                })?;
-            }
-            SequenceState::Opcode(opcodes::ADC_ABS, _) => {
-                self.tick_load_absolute(&mut |me, value| {
-                    let sum = me.add_with_carry(me.reg_a, value);
-                    me.set_reg_a(sum);
-                })?;
-            }
-            SequenceState::Opcode(opcodes::ADC_ABS_X, _) => {
-                self.tick_load_absolute_indexed(self.reg_x, &mut |me, value| {
-                    let sum = me.add_with_carry(me.reg_a, value);
-                    me.set_reg_a(sum);
-                })?;
-            }
-            SequenceState::Opcode(opcodes::ADC_ABS_Y, _) => {
-                self.tick_load_absolute_indexed(self.reg_y, &mut |me, value| {
-                    let sum = me.add_with_carry(me.reg_a, value);
-                    me.set_reg_a(sum);
-                })?;
-            }
-            SequenceState::Opcode(opcodes::ADC_X_INDIR, _) => {
-                self.tick_load_x_indirect(&mut |me, value| {
-                    let sum = me.add_with_carry(me.reg_a, value);
-                    me.set_reg_a(sum);
-                })?;
-            }
-            SequenceState::Opcode(opcodes::ADC_INDIR_Y, _) => {
-                self.tick_load_indirect_y(&mut |me, value| {
-                    let sum = me.add_with_carry(me.reg_a, value);
-                    me.set_reg_a(sum);
-                })?;
-            }
-
-            SequenceState::Opcode(opcodes::SBC_IMM, _) => {
-                self.tick_load_immediate(&mut |me, value| {
-                    let diff = me.sub_with_carry(me.reg_a, value);
-                    me.set_reg_a(diff);
-                })?;
-            }
-            SequenceState::Opcode(opcodes::SBC_ZP, _) => {
-                self.tick_load_zero_page(&mut |me, value| {
-                    let diff = me.sub_with_carry(me.reg_a, value);
-                    me.set_reg_a(diff);
-                })?;
-            }
-            SequenceState::Opcode(opcodes::SBC_ZP_X, _) => {
-                self.tick_load_zero_page_indexed(self.reg_x, &mut |me, value| {
-                    let diff = me.sub_with_carry(me.reg_a, value);
-                    me.set_reg_a(diff);
-                })?;
-            }
-            SequenceState::Opcode(opcodes::SBC_ABS, _) => {
-                self.tick_load_absolute(&mut |me, value| {
-                    let diff = me.sub_with_carry(me.reg_a, value);
-                    me.set_reg_a(diff);
-                })?;
-            }
-            SequenceState::Opcode(opcodes::SBC_ABS_X, _) => {
-                self.tick_load_absolute_indexed(self.reg_x, &mut |me, value| {
-                    let diff = me.sub_with_carry(me.reg_a, value);
-                    me.set_reg_a(diff);
-                })?;
-            }
-            SequenceState::Opcode(opcodes::SBC_ABS_Y, _) => {
-                self.tick_load_absolute_indexed(self.reg_y, &mut |me, value| {
-                    let diff = me.sub_with_carry(me.reg_a, value);
-                    me.set_reg_a(diff);
-                })?;
-            }
-            SequenceState::Opcode(opcodes::SBC_X_INDIR, _) => {
-                self.tick_load_x_indirect(&mut |me, value| {
-                    let diff = me.sub_with_carry(me.reg_a, value);
-                    me.set_reg_a(diff);
-                })?;
-            }
-            SequenceState::Opcode(opcodes::SBC_INDIR_Y, _) => {
-                self.tick_load_indirect_y(&mut |me, value| {
-                    let diff = me.sub_with_carry(me.reg_a, value);
-                    me.set_reg_a(diff);
-                })?;
-            }
-
-            SequenceState::Opcode(opcodes::INC_ZP, _) => {
-                self.tick_load_modify_store_zero_page(&mut |me, val| me.inc(val))?;
-            }
-            SequenceState::Opcode(opcodes::INC_ZP_X, _) => {
-                self.tick_load_modify_store_zero_page_x(&mut |me, val| me.inc(val))?;
-            }
-            SequenceState::Opcode(opcodes::INC_ABS, _) => {
-                self.tick_load_modify_store_absolute(&mut |me, val| me.inc(val))?;
-            }
-            SequenceState::Opcode(opcodes::INC_ABS_X, _) => {
-                self.tick_load_modify_store_absolute_indexed(self.reg_x, &mut |me, val| {
-                    me.inc(val)
-                })?;
-            }
-
-            SequenceState::Opcode(opcodes::DEC_ZP, _) => {
-                self.tick_load_modify_store_zero_page(&mut |me, val| me.dec(val))?;
-            }
-            SequenceState::Opcode(opcodes::DEC_ZP_X, _) => {
-                self.tick_load_modify_store_zero_page_x(&mut |me, val| me.dec(val))?;
-            }
-            SequenceState::Opcode(opcodes::DEC_ABS, _) => {
-                self.tick_load_modify_store_absolute(&mut |me, val| me.dec(val))?;
-            }
-            SequenceState::Opcode(opcodes::DEC_ABS_X, _) => {
-                self.tick_load_modify_store_absolute_indexed(self.reg_x, &mut |me, val| {
-                    me.dec(val)
-                })?;
-            }
-
-            SequenceState::Opcode(opcodes::INX, _) => {
-                self.tick_simple_internal_operation(&mut |me| {
-                    me.set_reg_x(me.reg_x.wrapping_add(1))
-                })?;
-            }
-            SequenceState::Opcode(opcodes::INY, _) => {
-                self.tick_simple_internal_operation(&mut |me| {
-                    me.set_reg_y(me.reg_y.wrapping_add(1))
-                })?;
-            }
-            SequenceState::Opcode(opcodes::DEX, _) => {
-                self.tick_simple_internal_operation(&mut |me| {
-                    me.set_reg_x(me.reg_x.wrapping_sub(1))
-                })?;
-            }
-            SequenceState::Opcode(opcodes::DEY, _) => {
-                self.tick_simple_internal_operation(&mut |me| {
-                    me.set_reg_y(me.reg_y.wrapping_sub(1))
-                })?;
-            }
-
-            SequenceState::Opcode(opcodes::TAX, _) => {
-                self.tick_simple_internal_operation(&mut |me| me.set_reg_x(me.reg_a))?;
-            }
-            SequenceState::Opcode(opcodes::TAY, _) => {
-                self.tick_simple_internal_operation(&mut |me| me.set_reg_y(me.reg_a))?;
-            }
-            SequenceState::Opcode(opcodes::TXA, _) => {
-                self.tick_simple_internal_operation(&mut |me| me.set_reg_a(me.reg_x))?;
-            }
-            SequenceState::Opcode(opcodes::TYA, _) => {
-                self.tick_simple_internal_operation(&mut |me| me.set_reg_a(me.reg_y))?;
-            }
-            SequenceState::Opcode(opcodes::TXS, _) => {
-                self.tick_simple_internal_operation(&mut |me| me.reg_sp = me.reg_x)?;
-            }
-            SequenceState::Opcode(opcodes::TSX, _) => {
-                self.tick_simple_internal_operation(&mut |me| me.set_reg_x(me.reg_sp))?;
-            }
-
-            SequenceState::Opcode(opcodes::PHP, _) => {
-                self.tick_push(self.flags | flags::PUSHED)?;
-            }
-            SequenceState::Opcode(opcodes::PLP, _) => {
-                self.tick_pull(&mut |me, value| me.flags = value & !flags::PUSHED)?;
-            }
-            SequenceState::Opcode(opcodes::PHA, _) => {
-                self.tick_push(self.reg_a)?;
-            }
-            SequenceState::Opcode(opcodes::PLA, _) => {
-                self.tick_pull(&mut |me, value| me.set_reg_a(value))?;
-            }
-
-            SequenceState::Opcode(opcodes::SEI, _) => {
-                self.tick_simple_internal_operation(&mut |me| me.flags |= flags::I)?;
-            }
-            SequenceState::Opcode(opcodes::CLI, _) => {
-                self.tick_simple_internal_operation(&mut |me| me.flags &= !flags::I)?;
-            }
-            SequenceState::Opcode(opcodes::SED, _) => {
-                self.tick_simple_internal_operation(&mut |me| me.flags |= flags::D)?;
-            }
-            SequenceState::Opcode(opcodes::CLD, _) => {
-                self.tick_simple_internal_operation(&mut |me| me.flags &= !flags::D)?;
-            }
-            SequenceState::Opcode(opcodes::SEC, _) => {
-                self.tick_simple_internal_operation(&mut |me| me.flags |= flags::C)?;
-            }
-            SequenceState::Opcode(opcodes::CLC, _) => {
-                self.tick_simple_internal_operation(&mut |me| me.flags &= !flags::C)?;
-            }
-            SequenceState::Opcode(opcodes::CLV, _) => {
-                self.tick_simple_internal_operation(&mut |me| me.flags &= !flags::V)?;
-            }
-
-            SequenceState::Opcode(opcodes::BEQ, _) => {
-                self.tick_branch_if_flag(flags::Z, flags::Z)?;
-            }
-            SequenceState::Opcode(opcodes::BNE, _) => {
-                self.tick_branch_if_flag(flags::Z, 0)?;
-            }
-            SequenceState::Opcode(opcodes::BCC, _) => {
-                self.tick_branch_if_flag(flags::C, 0)?;
-            }
-            SequenceState::Opcode(opcodes::BCS, _) => {
-                self.tick_branch_if_flag(flags::C, flags::C)?;
-            }
-            SequenceState::Opcode(opcodes::BPL, _) => {
-                self.tick_branch_if_flag(flags::N, 0)?;
-            }
-            SequenceState::Opcode(opcodes::BMI, _) => {
-                self.tick_branch_if_flag(flags::N, flags::N)?;
-            }
-            SequenceState::Opcode(opcodes::BVS, _) => {
-                self.tick_branch_if_flag(flags::V, flags::V)?;
-            }
-            SequenceState::Opcode(opcodes::BVC, _) => {
-                self.tick_branch_if_flag(flags::V, 0)?;
-            }
-
-            SequenceState::Opcode(opcodes::JMP_ABS, subcycle) => match subcycle {
-                1 => self.adl = self.consume_program_byte()?,
-                _ => {
-                    self.adh = self.memory.read(self.reg_pc)?;
-                    self.reg_pc = self.address();
-                    self.sequence_state = SequenceState::Ready;
-                }
-            },
-            SequenceState::Opcode(opcodes::JMP_INDIR, subcycle) => match subcycle {
-                1 => self.ial = self.consume_program_byte()?,
-                2 => self.iah = self.consume_program_byte()?,
-                3 => self.adl = self.memory.read(u16::from_le_bytes([self.ial, self.iah]))?,
-                _ => {
-                    self.adh = self
-                        .memory
-                        .read(u16::from_le_bytes([self.ial.wrapping_add(1), self.iah]))?;
-                    self.reg_pc = self.address();
-                    self.sequence_state = SequenceState::Ready;
-                }
-            },
-
-            SequenceState::Opcode(opcodes::JSR, subcycle) => match subcycle {
-                1 => self.adl = self.consume_program_byte()?,
-                2 => {
-                    self.phantom_read(self.stack_pointer());
-                }
-                3 => {
-                    self.memory
-                        .write(self.stack_pointer(), (self.reg_pc >> 8) as u8)?;
-                    self.reg_sp = self.reg_sp.wrapping_sub(1);
-                }
-                4 => {
-                    self.memory.write(self.stack_pointer(), self.reg_pc as u8)?;
-                    self.reg_sp = self.reg_sp.wrapping_sub(1);
-                }
-                _ => {
-                    self.adh = self.memory.read(self.reg_pc)?;
-                    self.reg_pc = self.address();
-                    self.sequence_state = SequenceState::Ready;
-                }
-            },
-            SequenceState::Opcode(opcodes::RTS, subcycle) => match subcycle {
-                1 => {
-                    let _ = self.consume_program_byte();
-                }
-                2 => {
-                    self.phantom_read(self.stack_pointer());
-                    self.reg_sp = self.reg_sp.wrapping_add(1);
-                }
-                3 => {
-                    self.reg_pc =
-                        self.reg_pc & 0xFF00 | self.memory.read(self.stack_pointer())? as u16;
-                    self.reg_sp = self.reg_sp.wrapping_add(1);
-                }
-                4 => {
-                    self.reg_pc =
-                        self.reg_pc & 0xFF | ((self.memory.read(self.stack_pointer())? as u16) << 8)
-                }
-                _ => {
-                    let _ = self.consume_program_byte();
-                    self.sequence_state = SequenceState::Ready;
-                }
-            },
-
-            SequenceState::Opcode(opcodes::BRK, subcycle) => match subcycle {
-                1 => {
-                    self.consume_program_byte()?;
-                }
-                _ => self.tick_interrupt_sequence(subcycle, 0xFFFE, flags::PUSHED)?,
-            },
-            SequenceState::Opcode(opcodes::RTI, subcycle) => match subcycle {
-                1 => self.phantom_read(self.reg_pc),
-                2 => {
-                    self.phantom_read(self.stack_pointer());
-                    self.reg_sp = self.reg_sp.wrapping_add(1);
-                }
-                3 => {
-                    self.flags = self.memory.read(self.stack_pointer())?;
-                    self.reg_sp = self.reg_sp.wrapping_add(1);
-                }
-                4 => {
-                    self.reg_pc =
-                        self.reg_pc & 0xFF00 | self.memory.read(self.stack_pointer())? as u16;
-                    self.reg_sp = self.reg_sp.wrapping_add(1);
-                }
-                _ => {
-                    self.reg_pc = self.reg_pc & 0xFF
-                        | ((self.memory.read(self.stack_pointer())? as u16) << 8);
-                    self.sequence_state = SequenceState::Ready;
-                }
-            },
-
-            // Unofficial opcodes
-            SequenceState::Opcode(opcodes::HLT1, _) => {
-                return Err(Box::new(CpuHaltedError {
-                    opcode: opcodes::HLT1,
-                    address: self.reg_pc.wrapping_sub(1),
-                }));
+            SequenceState::DecimalFixup => {
+                self.phantom_read(self.reg_pc);
+                self.sequence_state = SequenceState::Ready;
             }
 
-            // Oh no, we don't support it! (Yet.)
-            SequenceState::Opcode(other_opcode, _) => {
-                return Err(Box::new(UnknownOpcodeError {
-                    opcode: other_opcode,
-                    address: self.reg_pc.wrapping_sub(1),
-                }));
-            }
+            SequenceState::Opcode(opcode, subcycle) => self.tick_microcode(opcode, subcycle)?,
 
             // Reset sequence.
             SequenceState::Reset(subcycle) => match subcycle {
@@ -878,22 +533,33 @@ impl<M: Memory + Debug> Cpu<M> {
                     self.phantom_read(self.stack_pointer());
                     self.reg_sp = self.reg_sp.wrapping_sub(1);
                 }
-                5 => self.reg_pc = self.reg_pc & 0xFF00 | (self.memory.read(0xFFFC)? as u16),
+                5 => {
+                    self.reg_pc =
+                        self.reg_pc & 0xFF00 | (self.read(0xFFFC, BusAccessKind::Vector)? as u16)
+                }
                 _ => {
-                    self.reg_pc = self.reg_pc & 0xFF | ((self.memory.read(0xFFFD)? as u16) << 8);
+                    self.reg_pc = self.reg_pc & 0xFF
+                        | ((self.read(0xFFFD, BusAccessKind::Vector)? as u16) << 8);
                     self.sequence_state = SequenceState::Ready;
                     self.flags |= flags::I;
+                    if V::CMOS {
+                        self.flags &= !flags::D;
+                    }
+                    self.irq_polled = false;
+                    self.nmi_polled = false;
                 }
             },
 
             SequenceState::Irq(subcycle) => {
-                self.tick_interrupt_sequence(subcycle, 0xFFFE, flags::UNUSED)?
+                self.tick_interrupt_sequence(subcycle, IRQ_VECTOR, flags::UNUSED)?
             }
             SequenceState::Nmi(subcycle) => {
-                self.tick_interrupt_sequence(subcycle, 0xFFFA, flags::UNUSED)?
+                self.tick_interrupt_sequence(subcycle, NMI_VECTOR, flags::UNUSED)?
             }
         }
 
+        self.poll_interrupts();
+
         // Now move on to the next subcycle.
         match self.sequence_state {
             SequenceState::Opcode(opcode, subcycle) => {
@@ -908,429 +574,236 @@ impl<M: Memory + Debug> Cpu<M> {
             SequenceState::Nmi(subcycle) => self.sequence_state = SequenceState::Nmi(subcycle + 1),
             _ => {}
         };
+        self.cycles += 1;
         Ok(())
     }
 
-    fn tick_simple_internal_operation(
-        &mut self,
-        operation: &mut dyn FnMut(&mut Self),
-    ) -> Result<(), ReadError> {
-        self.phantom_read(self.reg_pc);
-        operation(self);
-        self.sequence_state = SequenceState::Ready;
-        Ok(())
-    }
-
-    fn tick_load_immediate(
-        &mut self,
-        load: &mut dyn FnMut(&mut Self, u8),
-    ) -> Result<(), ReadError> {
-        let value = self.consume_program_byte()?;
-        load(self, value);
-        self.sequence_state = SequenceState::Ready;
-        Ok(())
-    }
-
-    fn tick_load_zero_page(
-        &mut self,
-        load: &mut dyn FnMut(&mut Self, u8),
-    ) -> Result<(), ReadError> {
-        match self.sequence_state {
-            SequenceState::Opcode(_, 1) => self.adl = self.consume_program_byte()?,
-            _ => {
-                let value = self.memory.read(self.adl as u16)?;
-                load(self, value);
-                self.sequence_state = SequenceState::Ready;
-            }
-        };
-        Ok(())
-    }
-
-    fn tick_load_zero_page_indexed(
-        &mut self,
-        index: u8,
-        load: &mut dyn FnMut(&mut Self, u8),
-    ) -> Result<(), ReadError> {
-        match self.sequence_state {
-            SequenceState::Opcode(_, 1) => self.bal = self.consume_program_byte()?,
-            SequenceState::Opcode(_, 2) => self.phantom_read(self.bal as u16),
-            _ => {
-                let value = self.memory.read(self.bal.wrapping_add(index) as u16)?;
-                load(self, value);
-                self.sequence_state = SequenceState::Ready;
-            }
-        };
-        Ok(())
-    }
-
-    fn tick_load_absolute(&mut self, load: &mut dyn FnMut(&mut Self, u8)) -> Result<(), ReadError> {
-        match self.sequence_state {
-            SequenceState::Opcode(_, 1) => self.adl = self.consume_program_byte()?,
-            SequenceState::Opcode(_, 2) => self.adh = self.consume_program_byte()?,
-            _ => {
-                let value = self.memory.read(self.address())?;
-                load(self, value);
-                self.sequence_state = SequenceState::Ready;
-            }
-        };
-        Ok(())
-    }
-
-    fn tick_load_absolute_indexed(
-        &mut self,
-        index: u8,
-        load: &mut dyn FnMut(&mut Self, u8),
-    ) -> Result<(), ReadError> {
-        match self.sequence_state {
-            SequenceState::Opcode(_, 1) => self.bal = self.consume_program_byte()?,
-            SequenceState::Opcode(_, 2) => self.bah = self.consume_program_byte()?,
-            SequenceState::Opcode(_, 3) => {
-                let (adl, carry) = self.bal.overflowing_add(index);
-                let address = u16::from_le_bytes([adl, self.bah]);
-                if carry {
-                    self.phantom_read(address);
-                } else {
-                    let value = self.memory.read(address)?;
-                    load(self, value);
-                    self.sequence_state = SequenceState::Ready;
-                }
-            }
-            _ => {
-                let value = self
-                    .memory
-                    .read(self.base_address().wrapping_add(index as u16))?;
-                load(self, value);
-                self.sequence_state = SequenceState::Ready;
-            }
+    /// Performs a single cycle of an instruction by executing the micro-op
+    /// that the microcode table specifies for this cycle.
+    fn tick_microcode(&mut self, opcode: u8, subcycle: u32) -> SequenceResult {
+        let microcode = if V::CMOS {
+            &microcode::CMOS
+        } else {
+            &microcode::NMOS
         };
-        Ok(())
-    }
-
-    fn tick_load_x_indirect(
-        &mut self,
-        load: &mut dyn FnMut(&mut Self, u8),
-    ) -> Result<(), ReadError> {
-        match self.sequence_state {
-            SequenceState::Opcode(_, 1) => self.bal = self.consume_program_byte()?,
-            SequenceState::Opcode(_, 2) => self.phantom_read(self.bal as u16),
-            SequenceState::Opcode(_, 3) => {
-                self.adl = self.memory.read(self.bal.wrapping_add(self.reg_x) as u16)?;
-            }
-            SequenceState::Opcode(_, 4) => {
-                self.adh = self
-                    .memory
-                    .read(self.bal.wrapping_add(self.reg_x).wrapping_add(1) as u16)?;
+        match microcode[opcode as usize][subcycle as usize - 1] {
+            MicroOp::FetchAdl => self.adl = self.consume_program_byte()?,
+            MicroOp::FetchAdh => self.adh = self.consume_program_byte()?,
+            MicroOp::FetchBal => self.bal = self.consume_program_byte()?,
+            MicroOp::FetchBah => self.bah = self.consume_program_byte()?,
+            MicroOp::FetchIal => self.ial = self.consume_program_byte()?,
+            MicroOp::FetchIah => self.iah = self.consume_program_byte()?,
+            MicroOp::SkipOperand => {
+                self.consume_program_byte()?;
+            }
+            MicroOp::DummyFetch => {
+                let _ = self.consume_program_byte();
+            }
+
+            MicroOp::DummyReadPc => self.phantom_read(self.reg_pc),
+            MicroOp::DummyReadPreviousPc => self.phantom_read(self.reg_pc.wrapping_sub(1)),
+            MicroOp::DummyReadBal => self.phantom_read(self.bal as u16),
+            MicroOp::DummyReadStack => self.phantom_read(self.stack_pointer()),
+            MicroOp::DummyPull => {
+                self.phantom_read(self.stack_pointer());
+                self.reg_sp = self.reg_sp.wrapping_add(1);
             }
-            _ => {
-                let value = self.memory.read(self.address())?;
-                load(self, value);
-                self.sequence_state = SequenceState::Ready;
+            MicroOp::DummyReadIndexed(index) => {
+                let index = self.index_register(index);
+                self.phantom_read(u16::from_le_bytes([self.bal.wrapping_add(index), self.bah]));
             }
-        }
-        Ok(())
-    }
+            MicroOp::DummyReadHighPage => self.phantom_read(0xFF00 | self.adl as u16),
 
-    fn tick_load_indirect_y(
-        &mut self,
-        load: &mut dyn FnMut(&mut Self, u8),
-    ) -> Result<(), ReadError> {
-        match self.sequence_state {
-            SequenceState::Opcode(_, 1) => self.ial = self.consume_program_byte()?,
-            SequenceState::Opcode(_, 2) => self.bal = self.memory.read(self.ial as u16)?,
-            SequenceState::Opcode(_, 3) => {
-                self.bah = self.memory.read(self.ial.wrapping_add(1) as u16)?
-            }
-            SequenceState::Opcode(_, 4) => {
-                let (adl, carry) = self.bal.overflowing_add(self.reg_y);
-                let address = u16::from_le_bytes([adl, self.bah]);
-                if carry {
-                    self.phantom_read(address);
-                } else {
-                    let value = self.memory.read(address)?;
-                    load(self, value);
-                    self.sequence_state = SequenceState::Ready;
-                }
+            MicroOp::ReadAdlZeroPageX => {
+                self.adl = self.read(
+                    self.bal.wrapping_add(self.reg_x) as u16,
+                    BusAccessKind::Pointer,
+                )?;
             }
-            _ => {
-                let value = self
-                    .memory
-                    .read(self.base_address().wrapping_add(self.reg_y as u16))?;
-                load(self, value);
-                self.sequence_state = SequenceState::Ready;
+            MicroOp::ReadAdhZeroPageX => {
+                self.adh = self.read(
+                    self.bal.wrapping_add(self.reg_x).wrapping_add(1) as u16,
+                    BusAccessKind::Pointer,
+                )?;
             }
-        }
-        Ok(())
-    }
-
-    fn tick_store_zero_page(&mut self, value: u8) -> TickResult {
-        match self.sequence_state {
-            SequenceState::Opcode(_, 1) => self.adl = self.consume_program_byte()?,
-            _ => {
-                self.memory.write(self.adl as u16, value)?;
-                self.sequence_state = SequenceState::Ready;
+            MicroOp::ReadAdlZeroPageIndirect => {
+                self.adl = self.read(self.ial as u16, BusAccessKind::Pointer)?
             }
-        };
-        Ok(())
-    }
-
-    fn tick_store_zero_page_indexed(&mut self, index: u8, value: u8) -> TickResult {
-        match self.sequence_state {
-            SequenceState::Opcode(_, 1) => self.bal = self.consume_program_byte()?,
-            SequenceState::Opcode(_, 2) => self.phantom_read(self.bal as u16),
-            _ => {
-                self.memory
-                    .write((self.bal.wrapping_add(index)) as u16, value)?;
-                self.sequence_state = SequenceState::Ready;
+            MicroOp::ReadAdhZeroPageIndirect => {
+                self.adh = self.read(self.ial.wrapping_add(1) as u16, BusAccessKind::Pointer)?
             }
-        };
-        Ok(())
-    }
-
-    fn tick_store_abs(&mut self, value: u8) -> TickResult {
-        match self.sequence_state {
-            SequenceState::Opcode(_, 1) => self.adl = self.consume_program_byte()?,
-            SequenceState::Opcode(_, 2) => self.adh = self.consume_program_byte()?,
-            _ => {
-                self.memory.write(self.address(), value)?;
-                self.sequence_state = SequenceState::Ready;
+            MicroOp::ReadBalZeroPageIndirect => {
+                self.bal = self.read(self.ial as u16, BusAccessKind::Pointer)?
             }
-        }
-        Ok(())
-    }
-
-    fn tick_store_abs_indexed(&mut self, index: u8, value: u8) -> TickResult {
-        match self.sequence_state {
-            SequenceState::Opcode(_, 1) => self.bal = self.consume_program_byte()?,
-            SequenceState::Opcode(_, 2) => self.bah = self.consume_program_byte()?,
-            SequenceState::Opcode(_, 3) => {
-                self.phantom_read(u16::from_le_bytes([self.bal.wrapping_add(index), self.bah]));
+            MicroOp::ReadBahZeroPageIndirect => {
+                self.bah = self.read(self.ial.wrapping_add(1) as u16, BusAccessKind::Pointer)?
             }
-            _ => {
-                self.memory
-                    .write(self.base_address().wrapping_add(index as u16), value)?;
-                self.sequence_state = SequenceState::Ready;
+            MicroOp::ReadAdlIndirect => {
+                self.adl = self.read(
+                    u16::from_le_bytes([self.ial, self.iah]),
+                    BusAccessKind::Pointer,
+                )?
+            }
+            MicroOp::ReadAdlIndexedIndirect => {
+                self.adl = self.read(
+                    self.base_address().wrapping_add(self.reg_x as u16),
+                    BusAccessKind::Pointer,
+                )?
             }
-        }
-        Ok(())
-    }
 
-    fn tick_store_x_indirect(&mut self, value: u8) -> TickResult {
-        match self.sequence_state {
-            SequenceState::Opcode(_, 1) => self.bal = self.consume_program_byte()?,
-            SequenceState::Opcode(_, 2) => self.phantom_read(self.bal as u16),
-            SequenceState::Opcode(_, 3) => {
-                self.adl = self.memory.read(self.bal.wrapping_add(self.reg_x) as u16)?;
+            MicroOp::LoadImmediate(load) => {
+                let value = self.consume_program_byte()?;
+                self.sequence_state = SequenceState::Ready;
+                self.execute_load(load, value);
             }
-            SequenceState::Opcode(_, 4) => {
-                self.adh = self
-                    .memory
-                    .read(self.bal.wrapping_add(self.reg_x).wrapping_add(1) as u16)?;
+            MicroOp::LoadZeroPage(load) => {
+                let value = self.read(self.adl as u16, BusAccessKind::Data)?;
+                self.sequence_state = SequenceState::Ready;
+                self.execute_load(load, value);
             }
-            _ => {
-                self.memory.write(self.address(), value)?;
+            MicroOp::LoadZeroPageIndexed(index, load) => {
+                let address = self.bal.wrapping_add(self.index_register(index)) as u16;
+                let value = self.read(address, BusAccessKind::Data)?;
                 self.sequence_state = SequenceState::Ready;
+                self.execute_load(load, value);
             }
-        }
-        Ok(())
-    }
-
-    fn tick_store_indirect_y(&mut self, value: u8) -> TickResult {
-        match self.sequence_state {
-            SequenceState::Opcode(_, 1) => self.ial = self.consume_program_byte()?,
-            SequenceState::Opcode(_, 2) => self.bal = self.memory.read(self.ial as u16)?,
-            SequenceState::Opcode(_, 3) => {
-                self.bah = self.memory.read(self.ial.wrapping_add(1) as u16)?
+            MicroOp::LoadAbsolute(load) => {
+                let value = self.read(self.address(), BusAccessKind::Data)?;
+                self.sequence_state = SequenceState::Ready;
+                self.execute_load(load, value);
+            }
+            MicroOp::LoadIndexedSamePage(index, load) => {
+                let (adl, carry) = self.bal.overflowing_add(self.index_register(index));
+                let address = u16::from_le_bytes([adl, self.bah]);
+                if carry {
+                    self.phantom_read(address);
+                } else {
+                    let value = self.read(address, BusAccessKind::Data)?;
+                    self.sequence_state = SequenceState::Ready;
+                    self.execute_load(load, value);
+                }
             }
-            SequenceState::Opcode(_, 4) => {
-                self.phantom_read(u16::from_le_bytes([
-                    self.bal.wrapping_add(self.reg_y),
-                    self.bah,
-                ]));
+            MicroOp::LoadIndexedSamePageCmos(index, load) => {
+                let (adl, carry) = self.bal.overflowing_add(self.index_register(index));
+                if carry {
+                    self.phantom_read(self.reg_pc.wrapping_sub(1));
+                } else {
+                    let address = u16::from_le_bytes([adl, self.bah]);
+                    let value = self.read(address, BusAccessKind::Data)?;
+                    self.sequence_state = SequenceState::Ready;
+                    self.execute_load(load, value);
+                }
             }
-            _ => {
-                self.memory
-                    .write(self.base_address().wrapping_add(self.reg_y as u16), value)?;
+            MicroOp::LoadIndexed(index, load) => {
+                let value = self.read(self.indexed_address(index), BusAccessKind::Data)?;
                 self.sequence_state = SequenceState::Ready;
+                self.execute_load(load, value);
+            }
+            MicroOp::Pull(load) => {
+                let value = self.read(self.stack_pointer(), BusAccessKind::Stack)?;
+                self.sequence_state = SequenceState::Ready;
+                self.execute_load(load, value);
             }
-        }
-        Ok(())
-    }
 
-    fn tick_load_modify_store_zero_page(
-        &mut self,
-        operation: &mut dyn FnMut(&mut Self, u8) -> u8,
-    ) -> TickResult {
-        match self.sequence_state {
-            SequenceState::Opcode(_, 1) => self.adl = self.consume_program_byte()?,
-            SequenceState::Opcode(_, 2) => self.tmp_data = self.memory.read(self.adl as u16)?,
-            SequenceState::Opcode(_, 3) => {
-                // A rare case of a "phantom write". Since we write the same
-                // data, it doesn't really matter (that much), but we need to
-                // simulate it anyway.
-                self.memory.write(self.adl as u16, self.tmp_data)?;
+            MicroOp::StoreZeroPage(source) => {
+                let value = self.source_value(source);
+                self.write(self.adl as u16, value, BusAccessKind::Data)?;
+                self.sequence_state = SequenceState::Ready;
             }
-            _ => {
-                let result = operation(self, self.tmp_data);
-                self.memory.write(self.adl as u16, result)?;
+            MicroOp::StoreZeroPageIndexed(index, source) => {
+                let address = self.bal.wrapping_add(self.index_register(index)) as u16;
+                let value = self.source_value(source);
+                self.write(address, value, BusAccessKind::Data)?;
                 self.sequence_state = SequenceState::Ready;
             }
-        }
-        Ok(())
-    }
-
-    fn tick_load_modify_store_zero_page_x(
-        &mut self,
-        operation: &mut dyn FnMut(&mut Self, u8) -> u8,
-    ) -> TickResult {
-        match self.sequence_state {
-            SequenceState::Opcode(_, 1) => self.bal = self.consume_program_byte()?,
-            SequenceState::Opcode(_, 2) => self.phantom_read(self.bal as u16),
-            SequenceState::Opcode(_, 3) => {
-                self.adl = self.bal.wrapping_add(self.reg_x);
-                self.tmp_data = self.memory.read(self.adl as u16)?;
+            MicroOp::StoreAbsolute(source) => {
+                let value = self.source_value(source);
+                self.write(self.address(), value, BusAccessKind::Data)?;
+                self.sequence_state = SequenceState::Ready;
             }
-            SequenceState::Opcode(_, 4) => {
-                // Phantom write.
-                self.memory.write(self.adl as u16, self.tmp_data)?;
+            MicroOp::StoreIndexed(index, source) => {
+                let value = self.source_value(source);
+                self.write(self.indexed_address(index), value, BusAccessKind::Data)?;
+                self.sequence_state = SequenceState::Ready;
             }
-            _ => {
-                let result = operation(self, self.tmp_data);
-                self.memory.write(self.adl as u16, result)?;
+            MicroOp::Push(source) => {
+                let value = self.source_value(source);
+                self.write(self.stack_pointer(), value, BusAccessKind::Stack)?;
+                self.reg_sp = self.reg_sp.wrapping_sub(1);
                 self.sequence_state = SequenceState::Ready;
             }
-        }
-        Ok(())
-    }
 
-    fn tick_load_modify_store_absolute(
-        &mut self,
-        operation: &mut dyn FnMut(&mut Self, u8) -> u8,
-    ) -> TickResult {
-        match self.sequence_state {
-            SequenceState::Opcode(_, 1) => self.adl = self.consume_program_byte()?,
-            SequenceState::Opcode(_, 2) => self.adh = self.consume_program_byte()?,
-            SequenceState::Opcode(_, 3) => {
-                self.tmp_data = self.memory.read(self.address())?;
+            MicroOp::ReadZeroPage => {
+                self.tmp_data = self.read(self.adl as u16, BusAccessKind::Data)?;
             }
-            SequenceState::Opcode(_, 4) => {
-                // Phantom write.
-                self.memory.write(self.address(), self.tmp_data)?;
+            MicroOp::ReadZeroPageIndexed(index) => {
+                self.adl = self.bal.wrapping_add(self.index_register(index));
+                self.tmp_data = self.read(self.adl as u16, BusAccessKind::Data)?;
             }
-            _ => {
-                let result = operation(self, self.tmp_data);
-                self.memory.write(self.address(), result)?;
-                self.sequence_state = SequenceState::Ready;
+            MicroOp::ReadAbsolute => {
+                self.tmp_data = self.read(self.address(), BusAccessKind::Data)?;
             }
-        }
-        Ok(())
-    }
-
-    fn tick_load_modify_store_absolute_indexed(
-        &mut self,
-        index: u8,
-        operation: &mut dyn FnMut(&mut Self, u8) -> u8,
-    ) -> TickResult {
-        match self.sequence_state {
-            SequenceState::Opcode(_, 1) => self.bal = self.consume_program_byte()?,
-            SequenceState::Opcode(_, 2) => self.bah = self.consume_program_byte()?,
-            SequenceState::Opcode(_, 3) => {
-                self.phantom_read(u16::from_le_bytes([self.bal.wrapping_add(index), self.bah]));
+            MicroOp::ReadIndexed(index) => {
+                self.tmp_data = self.read(self.indexed_address(index), BusAccessKind::Data)?;
             }
-            SequenceState::Opcode(_, 4) => {
-                self.tmp_data = self
-                    .memory
-                    .read(self.base_address().wrapping_add(index as u16))?;
+            MicroOp::ReadIndexedSamePageCmos(index) => {
+                let (adl, carry) = self.bal.overflowing_add(self.index_register(index));
+                if carry {
+                    self.phantom_read(self.reg_pc.wrapping_sub(1));
+                } else {
+                    let address = u16::from_le_bytes([adl, self.bah]);
+                    self.tmp_data = self.read(address, BusAccessKind::Data)?;
+                    // Skip the `ReadIndexed` step.
+                    self.sequence_state = SequenceState::Opcode(opcode, subcycle + 1);
+                }
             }
-            SequenceState::Opcode(_, 5) => {
-                // Phantom write.
-                self.memory.write(
-                    self.base_address().wrapping_add(index as u16),
-                    self.tmp_data,
-                )?;
+            // A rare case of a "phantom write". Since we write the same data,
+            // it doesn't really matter (that much), but we need to simulate it
+            // anyway.
+            MicroOp::DummyWriteZeroPage => self.phantom_write(self.adl as u16)?,
+            MicroOp::DummyWriteAbsolute => self.phantom_write(self.address())?,
+            MicroOp::DummyWriteIndexed(index) => self.phantom_write(self.indexed_address(index))?,
+            MicroOp::ModifyZeroPage(modify) => {
+                let result = self.execute_modify(modify, self.tmp_data);
+                self.write(self.adl as u16, result, BusAccessKind::Data)?;
+                self.sequence_state = SequenceState::Ready;
             }
-            _ => {
-                let result = operation(self, self.tmp_data);
-                self.memory
-                    .write(self.base_address().wrapping_add(index as u16), result)?;
+            MicroOp::ModifyAbsolute(modify) => {
+                let result = self.execute_modify(modify, self.tmp_data);
+                self.write(self.address(), result, BusAccessKind::Data)?;
                 self.sequence_state = SequenceState::Ready;
             }
-        }
-        Ok(())
-    }
-
-    fn tick_compare_immediate(&mut self, register: u8) -> Result<(), ReadError> {
-        self.tick_load_immediate(&mut |me, value| me.compare(register, value))
-    }
-
-    fn tick_compare_zero_page(&mut self, register: u8) -> Result<(), ReadError> {
-        self.tick_load_zero_page(&mut |me, value| me.compare(register, value))
-    }
-
-    fn tick_compare_zero_page_x(&mut self, register: u8) -> Result<(), ReadError> {
-        self.tick_load_zero_page_indexed(self.reg_x, &mut |me, value| me.compare(register, value))
-    }
-
-    fn tick_compare_absolute(&mut self, register: u8) -> Result<(), ReadError> {
-        self.tick_load_absolute(&mut |me, value| me.compare(register, value))
-    }
-
-    fn tick_compare_absolute_indexed(&mut self, index: u8, register: u8) -> Result<(), ReadError> {
-        self.tick_load_absolute_indexed(index, &mut |me, value| me.compare(register, value))
-    }
-
-    fn tick_compare_x_indirect(&mut self, register: u8) -> Result<(), ReadError> {
-        self.tick_load_x_indirect(&mut |me, value| me.compare(register, value))
-    }
-
-    fn tick_compare_indirect_y(&mut self, register: u8) -> Result<(), ReadError> {
-        self.tick_load_indirect_y(&mut |me, value| me.compare(register, value))
-    }
-
-    fn tick_push(&mut self, value: u8) -> TickResult {
-        match self.sequence_state {
-            SequenceState::Opcode(_, 1) => self.phantom_read(self.reg_pc),
-            _ => {
-                self.memory.write(self.stack_pointer(), value)?;
-                self.reg_sp = self.reg_sp.wrapping_sub(1);
+            MicroOp::ModifyIndexed(index, modify) => {
+                let result = self.execute_modify(modify, self.tmp_data);
+                self.write(self.indexed_address(index), result, BusAccessKind::Data)?;
                 self.sequence_state = SequenceState::Ready;
             }
-        };
-        Ok(())
-    }
 
-    fn tick_pull(&mut self, load: &mut dyn FnMut(&mut Self, u8)) -> Result<(), ReadError> {
-        match self.sequence_state {
-            SequenceState::Opcode(_, 1) => self.phantom_read(self.reg_pc),
-            SequenceState::Opcode(_, 2) => {
-                self.phantom_read(self.stack_pointer());
-                self.reg_sp = self.reg_sp.wrapping_add(1);
-            }
-            _ => {
-                let value = self.memory.read(self.stack_pointer())?;
-                load(self, value);
+            MicroOp::Execute(operation) => {
+                self.phantom_read(self.reg_pc);
                 self.sequence_state = SequenceState::Ready;
+                self.execute_internal(operation);
             }
-        };
-        Ok(())
-    }
 
-    fn tick_branch_if_flag(&mut self, flag: u8, value: u8) -> Result<(), ReadError> {
-        match self.sequence_state {
-            // TODO: handle additional cycle when crossing page boundaries
-            SequenceState::Opcode(_, 1) => {
+            MicroOp::FetchBranchOffset { flag, value } => {
                 self.adl = self.consume_program_byte()?;
                 if self.flags & flag != value {
                     // Condition not met; don't branch.
                     self.sequence_state = SequenceState::Ready;
                 }
             }
-            SequenceState::Opcode(_, 2) => {
+            MicroOp::Branch => {
                 let new_pc = self.reg_pc.wrapping_add(self.adl as i8 as u16);
                 if new_pc & 0xFF00 == self.reg_pc & 0xFF00 {
                     // No page boundary crossed. Do a phantom read of the
-                    // computed address and skip the next cycle.
+                    // computed address and skip the next cycle. Since this
+                    // cycle doesn't poll the interrupts, an interrupt that
+                    // has just been detected gets delayed until after the next
+                    // instruction.
                     self.phantom_read(self.reg_pc);
                     self.sequence_state = SequenceState::Ready;
+                    self.irq_polled &= self.irq_pending;
+                    self.nmi_polled &= self.nmi_pending;
                 } else {
                     self.phantom_read((new_pc & 0x00FF) | (self.reg_pc & 0xFF00));
                     // Page boundary crossed. Do a phantom read of a
@@ -1339,44 +812,319 @@ impl<M: Memory + Debug> Cpu<M> {
                 }
                 self.reg_pc = new_pc;
             }
-            _ => {
+            MicroOp::BranchFixup => {
                 self.phantom_read(self.reg_pc);
                 self.sequence_state = SequenceState::Ready;
             }
-        };
+
+            MicroOp::Jump => {
+                self.adh = self.read(self.reg_pc, BusAccessKind::OperandFetch)?;
+                self.reg_pc = self.address();
+                self.sequence_state = SequenceState::Ready;
+            }
+            MicroOp::JumpIndirectWrapped => {
+                self.adh = self.read(
+                    u16::from_le_bytes([self.ial.wrapping_add(1), self.iah]),
+                    BusAccessKind::Pointer,
+                )?;
+                self.reg_pc = self.address();
+                self.sequence_state = SequenceState::Ready;
+            }
+            MicroOp::JumpIndirect => {
+                self.adh = self.read(
+                    u16::from_le_bytes([self.ial, self.iah]).wrapping_add(1),
+                    BusAccessKind::Pointer,
+                )?;
+                self.reg_pc = self.address();
+                self.sequence_state = SequenceState::Ready;
+            }
+            MicroOp::JumpIndexedIndirect => {
+                self.adh = self.read(
+                    self.base_address()
+                        .wrapping_add(self.reg_x as u16)
+                        .wrapping_add(1),
+                    BusAccessKind::Pointer,
+                )?;
+                self.reg_pc = self.address();
+                self.sequence_state = SequenceState::Ready;
+            }
+            MicroOp::PushPch => {
+                self.write(
+                    self.stack_pointer(),
+                    (self.reg_pc >> 8) as u8,
+                    BusAccessKind::Stack,
+                )?;
+                self.reg_sp = self.reg_sp.wrapping_sub(1);
+            }
+            MicroOp::PushPcl => {
+                self.write(
+                    self.stack_pointer(),
+                    self.reg_pc as u8,
+                    BusAccessKind::Stack,
+                )?;
+                self.reg_sp = self.reg_sp.wrapping_sub(1);
+            }
+            MicroOp::PullPcl => {
+                self.reg_pc = self.reg_pc & 0xFF00
+                    | self.read(self.stack_pointer(), BusAccessKind::Stack)? as u16;
+                self.reg_sp = self.reg_sp.wrapping_add(1);
+            }
+            MicroOp::PullPch => {
+                self.reg_pc = self.reg_pc & 0xFF
+                    | ((self.read(self.stack_pointer(), BusAccessKind::Stack)? as u16) << 8);
+            }
+            MicroOp::PullFlags => {
+                self.flags = self.read(self.stack_pointer(), BusAccessKind::Stack)?;
+                self.reg_sp = self.reg_sp.wrapping_add(1);
+            }
+            MicroOp::ReturnFromSubroutine => {
+                let _ = self.consume_program_byte();
+                self.sequence_state = SequenceState::Ready;
+            }
+            MicroOp::ReturnFromInterrupt => {
+                self.reg_pc = self.reg_pc & 0xFF
+                    | ((self.read(self.stack_pointer(), BusAccessKind::Stack)? as u16) << 8);
+                self.sequence_state = SequenceState::Ready;
+            }
+            MicroOp::Break => self.tick_interrupt_sequence(subcycle, IRQ_VECTOR, flags::PUSHED)?,
+
+            MicroOp::DummyReadHighPageAndFinish => {
+                self.phantom_read(0xFF00 | self.adl as u16);
+                self.sequence_state = SequenceState::Ready;
+            }
+
+            MicroOp::Halt => return Err(Fault::Halted(opcode)),
+            // Oh no, we don't support it! (Yet.)
+            MicroOp::Unknown => return Err(Fault::UnknownOpcode(opcode)),
+        }
         Ok(())
     }
 
-    fn tick_interrupt_sequence(&mut self, subcycle: u32, vector: u16, flag_mask: u8) -> TickResult {
+    /// Applies an operation to a value that has just been read from the
+    /// memory.
+    fn execute_load(&mut self, load: Load, value: u8) {
+        match load {
+            Load::Nop => {}
+            Load::Lda => self.set_reg_a(value),
+            Load::Ldx => self.set_reg_x(value),
+            Load::Ldy => self.set_reg_y(value),
+            Load::And => self.set_reg_a(self.reg_a & value),
+            Load::Ora => self.set_reg_a(self.reg_a | value),
+            Load::Eor => self.set_reg_a(self.reg_a ^ value),
+            Load::Adc => self.adc(value),
+            Load::Sbc => self.sbc(value),
+            Load::Cmp => self.compare(self.reg_a, value),
+            Load::Cpx => self.compare(self.reg_x, value),
+            Load::Cpy => self.compare(self.reg_y, value),
+            Load::Bit => self.test_bits(value),
+            Load::BitImmediate => self.test_bits_z(value),
+            Load::Plp => self.flags = value & !flags::PUSHED,
+            Load::Lax => self.load_a_x(value),
+            Load::LaxImmediate => {
+                // This one is unstable on real hardware, as the result depends
+                // on the chip and temperature. We use the "magic" constant
+                // 0xEE, which is what most of the reference emulators do.
+                let result = (self.reg_a | 0xEE) & value;
+                self.reg_x = result;
+                self.set_reg_a(result);
+            }
+            Load::Anc => {
+                self.set_reg_a(self.reg_a & value);
+                // Carry is copied from the N flag (bit 7 of the result).
+                self.flags = self.flags & !flags::C | (self.reg_a >> 7);
+            }
+            Load::Alr => {
+                let shifted = self.shift_right(self.reg_a & value);
+                self.set_reg_a(shifted);
+            }
+            Load::Arr => self.arr(value),
+            Load::Sbx => {
+                let a_and_x = self.reg_a & self.reg_x;
+                self.compare(a_and_x, value);
+                self.reg_x = a_and_x.wrapping_sub(value);
+            }
+        }
+    }
+
+    /// Applies a read-modify-write operation to a value and returns the result.
+    fn execute_modify(&mut self, modify: Modify, value: u8) -> u8 {
+        match modify {
+            Modify::Asl => self.shift_left(value),
+            Modify::Lsr => self.shift_right(value),
+            Modify::Rol => self.rotate_left(value),
+            Modify::Ror => self.rotate_right(value),
+            Modify::Inc => self.inc(value),
+            Modify::Dec => self.dec(value),
+            Modify::Tsb => self.tsb(value),
+            Modify::Trb => self.trb(value),
+            Modify::Dcp => self.dcp(value),
+            Modify::Isc => self.isc(value),
+            Modify::Slo => self.slo(value),
+            Modify::Rla => self.rla(value),
+            Modify::Sre => self.sre(value),
+            Modify::Rra => self.rra(value),
+        }
+    }
+
+    fn execute_internal(&mut self, operation: Internal) {
+        match operation {
+            Internal::Nop => {}
+            Internal::Inx => self.set_reg_x(self.reg_x.wrapping_add(1)),
+            Internal::Iny => self.set_reg_y(self.reg_y.wrapping_add(1)),
+            Internal::Dex => self.set_reg_x(self.reg_x.wrapping_sub(1)),
+            Internal::Dey => self.set_reg_y(self.reg_y.wrapping_sub(1)),
+            Internal::Tax => self.set_reg_x(self.reg_a),
+            Internal::Tay => self.set_reg_y(self.reg_a),
+            Internal::Txa => self.set_reg_a(self.reg_x),
+            Internal::Tya => self.set_reg_a(self.reg_y),
+            Internal::Txs => self.reg_sp = self.reg_x,
+            Internal::Tsx => self.set_reg_x(self.reg_sp),
+            Internal::Sei => self.flags |= flags::I,
+            Internal::Cli => self.flags &= !flags::I,
+            Internal::Sed => self.flags |= flags::D,
+            Internal::Cld => self.flags &= !flags::D,
+            Internal::Sec => self.flags |= flags::C,
+            Internal::Clc => self.flags &= !flags::C,
+            Internal::Clv => self.flags &= !flags::V,
+            Internal::Accumulator(modify) => {
+                let result = self.execute_modify(modify, self.reg_a);
+                self.set_reg_a(result);
+            }
+        }
+    }
+
+    /// Returns a value to be stored in the memory.
+    fn source_value(&self, source: Source) -> u8 {
+        match source {
+            Source::A => self.reg_a,
+            Source::X => self.reg_x,
+            Source::Y => self.reg_y,
+            Source::Zero => 0,
+            Source::Flags => self.flags | flags::PUSHED,
+            Source::AAndX => self.reg_a & self.reg_x,
+        }
+    }
+
+    fn tick_interrupt_sequence(
+        &mut self,
+        subcycle: u32,
+        vector: u16,
+        flag_mask: u8,
+    ) -> SequenceResult {
         match subcycle {
             1 => self.phantom_read(self.reg_pc),
             2 => {
-                self.memory
-                    .write(self.stack_pointer(), (self.reg_pc >> 8) as u8)?;
+                self.write(
+                    self.stack_pointer(),
+                    (self.reg_pc >> 8) as u8,
+                    BusAccessKind::Stack,
+                )?;
                 self.reg_sp = self.reg_sp.wrapping_sub(1);
             }
             3 => {
-                self.memory.write(self.stack_pointer(), self.reg_pc as u8)?;
+                self.write(
+                    self.stack_pointer(),
+                    self.reg_pc as u8,
+                    BusAccessKind::Stack,
+                )?;
                 self.reg_sp = self.reg_sp.wrapping_sub(1);
             }
             4 => {
-                self.memory
-                    .write(self.stack_pointer(), self.flags | flag_mask)?;
+                self.write(
+                    self.stack_pointer(),
+                    self.flags | flag_mask,
+                    BusAccessKind::Stack,
+                )?;
                 self.reg_sp = self.reg_sp.wrapping_sub(1);
+                self.flags |= flags::I;
+                if V::CMOS {
+                    self.flags &= !flags::D;
+                }
+                // An NMI detected before pushing the flags hijacks the BRK or
+                // IRQ sequence: the CPU fetches the NMI vector instead.
+                if vector != NMI_VECTOR && self.nmi_polled {
+                    self.nmi_latch = false;
+                    self.sequence_state = SequenceState::Nmi(subcycle);
+                }
+            }
+            5 => {
+                self.reg_pc =
+                    self.reg_pc & 0xFF00 | (self.read(vector, BusAccessKind::Vector)? as u16)
             }
-            5 => self.reg_pc = self.reg_pc & 0xFF00 | (self.memory.read(vector)? as u16),
             _ => {
-                self.reg_pc = self.reg_pc & 0xFF | ((self.memory.read(vector + 1)? as u16) << 8);
+                self.reg_pc = self.reg_pc & 0xFF
+                    | ((self.read(vector + 1, BusAccessKind::Vector)? as u16) << 8);
                 self.sequence_state = SequenceState::Ready;
-                self.flags |= flags::I;
+                // The first instruction of the handler is always executed
+                // before servicing another interrupt.
+                self.irq_polled = false;
+                self.nmi_polled = false;
             }
         }
         Ok(())
     }
 
+    /// Polls the interrupt lines at the end of a cycle. An IRQ is only
+    /// detected if it's not masked with the I flag, so that changing the flag
+    /// in the last cycle of CLI, SEI, or PLP affects the interrupts only after
+    /// the next instruction.
+    fn poll_interrupts(&mut self) {
+        self.irq_pending = self.irq_polled;
+        self.nmi_pending = self.nmi_polled;
+        self.irq_polled = self.irq_pin && self.flags & flags::I == 0;
+        self.nmi_polled = self.nmi_latch;
+    }
+
+    /// Reads a byte from the memory, putting the address on the address bus.
+    /// The `kind` argument is only used to notify the bus observer.
+    fn read(&mut self, address: u16, kind: BusAccessKind) -> ReadResult {
+        let address = address & V::ADDRESS_MASK;
+        if !self.rdy_pin {
+            self.stalled = true;
+        }
+        let value = self.memory.read(address)?;
+        // A stalled cycle is going to be repeated, so the observer only gets
+        // notified once the read actually completes.
+        if !self.stalled {
+            self.notify_bus_observer(BusOperation::Read, address, value, kind);
+        }
+        Ok(value)
+    }
+
+    /// Writes a byte to the memory, putting the address on the address bus.
+    /// The `kind` argument is only used to notify the bus observer.
+    fn write(&mut self, address: u16, value: u8, kind: BusAccessKind) -> WriteResult {
+        let address = address & V::ADDRESS_MASK;
+        if V::CMOS && !self.rdy_pin {
+            self.stalled = true;
+            return Ok(());
+        }
+        self.memory.write(address, value)?;
+        self.notify_bus_observer(BusOperation::Write, address, value, kind);
+        Ok(())
+    }
+
+    fn notify_bus_observer(
+        &mut self,
+        operation: BusOperation,
+        address: u16,
+        value: u8,
+        kind: BusAccessKind,
+    ) {
+        if let Some(observer) = &mut self.bus_observer.0 {
+            observer.observe(&BusAccess {
+                cycle: self.cycles,
+                operation,
+                address,
+                value,
+                kind,
+            });
+        }
+    }
+
     /// Reads one byte from the program and advances the program counter.
     fn consume_program_byte(&mut self) -> ReadResult {
-        let result = self.memory.read(self.reg_pc)?;
+        let result = self.read(self.reg_pc, BusAccessKind::OperandFetch)?;
         self.reg_pc = self.reg_pc.wrapping_add(1);
         return Ok(result);
     }
@@ -1386,7 +1134,18 @@ impl<M: Memory + Debug> Cpu<M> {
     /// we don't use the result value, we don't even care if it was a read
     /// error.
     fn phantom_read(&mut self, address: u16) {
-        let _ = self.memory.read(address);
+        let _ = self.read(address, BusAccessKind::Dummy);
+    }
+
+    /// Performs the dummy cycle of a read-modify-write instruction. NMOS
+    /// writes back the unmodified value, while 65C02 just reads it again.
+    fn phantom_write(&mut self, address: u16) -> WriteResult {
+        if V::CMOS {
+            self.phantom_read(address);
+            Ok(())
+        } else {
+            self.write(address, self.tmp_data, BusAccessKind::Dummy)
+        }
     }
 
     fn set_reg_a(&mut self, value: u8) {
@@ -1424,16 +1183,28 @@ impl<M: Memory + Debug> Cpu<M> {
     }
 
     /// Calculates lhs+rhs+C, updates the C and V flags, and returns the result.
-    /// The V flag is not set in BCD mode, which is not how the real CPU works,
-    /// but it's undefined anyway.
+    /// The V flag is not set in BCD mode on NMOS, which is not how the real CPU
+    /// works, but it's undefined anyway. 65C02 sets it in a documented way.
     fn add_with_carry(&mut self, lhs: u8, rhs: u8) -> u8 {
-        if self.flags & flags::D != 0 {
-            let (result, carry) = bcd::bcd_add(lhs, rhs, self.flags & flags::C != 0);
+        if V::DECIMAL_MODE && self.flags & flags::D != 0 {
+            let carry_in = self.flags & flags::C != 0;
+            let (result, carry) = bcd::bcd_add(lhs, rhs, carry_in);
             self.flags = if carry {
                 self.flags | flags::C
             } else {
                 self.flags & !flags::C
             };
+            if V::CMOS {
+                // The overflow is detected after adjusting the lower digit,
+                // but before adjusting the upper one.
+                let mut low = (lhs & 0x0F) + (rhs & 0x0F) + carry_in as u8;
+                if low >= 0x0A {
+                    low = ((low + 0x06) & 0x0F) + 0x10;
+                }
+                let sum = (lhs & 0xF0) as i8 as i16 + (rhs & 0xF0) as i8 as i16 + low as i16;
+                let overflow = !(-128..=127).contains(&sum);
+                self.flags = (self.flags & !flags::V) | if overflow { flags::V } else { 0 };
+            }
             return result;
         }
 
@@ -1459,15 +1230,22 @@ impl<M: Memory + Debug> Cpu<M> {
     }
 
     /// Calculates lhs-rhs-(1-C), updates the C and V flags, and returns the
-    /// result.
+    /// result. See [`Cpu::add_with_carry`] for notes on the V flag in BCD mode.
     fn sub_with_carry(&mut self, lhs: u8, rhs: u8) -> u8 {
-        if self.flags & flags::D != 0 {
-            let (result, borrow) = bcd::bcd_sub(lhs, rhs, self.flags & flags::C == 0);
+        if V::DECIMAL_MODE && self.flags & flags::D != 0 {
+            let borrow_in = self.flags & flags::C == 0;
+            let (result, borrow) = bcd::bcd_sub(lhs, rhs, borrow_in);
             self.flags = if borrow {
                 self.flags & !flags::C
             } else {
                 self.flags | flags::C
             };
+            if V::CMOS {
+                // 65C02 sets the V flag just like in binary mode.
+                let diff = lhs as i8 as i16 - rhs as i8 as i16 - borrow_in as i16;
+                let overflow = !(-128..=127).contains(&diff);
+                self.flags = (self.flags & !flags::V) | if overflow { flags::V } else { 0 };
+            }
             return result;
         }
 
@@ -1492,6 +1270,28 @@ impl<M: Memory + Debug> Cpu<M> {
         return unsigned_diff;
     }
 
+    /// Adds the value to the accumulator (ADC). In decimal mode, 65C02 takes an
+    /// additional cycle to do that. Note that this works only because the
+    /// micro-ops apply their operations after marking the instruction as
+    /// finished.
+    fn adc(&mut self, value: u8) {
+        let sum = self.add_with_carry(self.reg_a, value);
+        self.set_reg_a(sum);
+        if V::CMOS && self.flags & flags::D != 0 {
+            self.sequence_state = SequenceState::DecimalFixup;
+        }
+    }
+
+    /// Subtracts the value from the accumulator (SBC). See [`Cpu::adc`] for
+    /// notes on timing.
+    fn sbc(&mut self, value: u8) {
+        let diff = self.sub_with_carry(self.reg_a, value);
+        self.set_reg_a(diff);
+        if V::CMOS && self.flags & flags::D != 0 {
+            self.sequence_state = SequenceState::DecimalFixup;
+        }
+    }
+
     fn shift_left(&mut self, value: u8) -> u8 {
         let carry = (value & (1 << 7)) >> 7;
         let new_value = value << 1;
@@ -1544,6 +1344,117 @@ impl<M: Memory + Debug> Cpu<M> {
         result
     }
 
+    /// Tests the value against the accumulator and sets the bits that are set
+    /// in the accumulator (TSB).
+    fn tsb(&mut self, value: u8) -> u8 {
+        self.test_bits_z(value);
+        value | self.reg_a
+    }
+
+    /// Tests the value against the accumulator and clears the bits that are
+    /// set in the accumulator (TRB).
+    fn trb(&mut self, value: u8) -> u8 {
+        self.test_bits_z(value);
+        value & !self.reg_a
+    }
+
+    /// Sets the Z flag if the value has no bits in common with the
+    /// accumulator. Doesn't touch any other flags.
+    fn test_bits_z(&mut self, value: u8) {
+        self.flags = self.flags & !flags::Z | if value & self.reg_a == 0 { flags::Z } else { 0 };
+    }
+
+    /// Loads the same value to both A and X registers (LAX).
+    fn load_a_x(&mut self, value: u8) {
+        self.reg_x = value;
+        self.set_reg_a(value);
+    }
+
+    /// Decrements the value and compares it with the accumulator (DCP).
+    fn dcp(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_sub(1);
+        self.compare(self.reg_a, result);
+        result
+    }
+
+    /// Increments the value and subtracts it from the accumulator (ISC).
+    fn isc(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_add(1);
+        let diff = self.sub_with_carry(self.reg_a, result);
+        self.set_reg_a(diff);
+        result
+    }
+
+    /// Shifts the value left and ORs it with the accumulator (SLO).
+    fn slo(&mut self, value: u8) -> u8 {
+        let result = self.shift_left(value);
+        self.set_reg_a(self.reg_a | result);
+        result
+    }
+
+    /// Rotates the value left and ANDs it with the accumulator (RLA).
+    fn rla(&mut self, value: u8) -> u8 {
+        let result = self.rotate_left(value);
+        self.set_reg_a(self.reg_a & result);
+        result
+    }
+
+    /// Shifts the value right and XORs it with the accumulator (SRE).
+    fn sre(&mut self, value: u8) -> u8 {
+        let result = self.shift_right(value);
+        self.set_reg_a(self.reg_a ^ result);
+        result
+    }
+
+    /// Rotates the value right and adds it to the accumulator, using the
+    /// carry from the rotation (RRA).
+    fn rra(&mut self, value: u8) -> u8 {
+        let result = self.rotate_right(value);
+        let sum = self.add_with_carry(self.reg_a, result);
+        self.set_reg_a(sum);
+        result
+    }
+
+    /// ANDs the accumulator with the value and rotates it right (ARR). The
+    /// flags are set in a peculiar way, since the operation is performed
+    /// partially by the adder circuit; in decimal mode, the result also gets
+    /// a BCD fixup.
+    fn arr(&mut self, value: u8) {
+        let and_result = self.reg_a & value;
+        let prev_carry = self.flags & flags::C;
+        let mut result = (and_result >> 1) | (prev_carry << 7);
+        self.update_flags_nz(result);
+        if !V::DECIMAL_MODE || self.flags & flags::D == 0 {
+            let bit_6 = (result >> 6) & 1;
+            let bit_5 = (result >> 5) & 1;
+            self.flags = (self.flags & !(flags::C | flags::V))
+                | if bit_6 != 0 { flags::C } else { 0 }
+                | if bit_6 ^ bit_5 != 0 { flags::V } else { 0 };
+        } else {
+            let overflow = (and_result ^ result) & 0b0100_0000 != 0;
+            let low_nibble = and_result & 0x0F;
+            let high_nibble = and_result >> 4;
+            if low_nibble + (low_nibble & 1) > 5 {
+                result = (result & 0xF0) | (result.wrapping_add(6) & 0x0F);
+            }
+            let carry = high_nibble + (high_nibble & 1) > 5;
+            if carry {
+                result = result.wrapping_add(0x60);
+            }
+            self.flags = (self.flags & !(flags::C | flags::V))
+                | if carry { flags::C } else { 0 }
+                | if overflow { flags::V } else { 0 };
+        }
+        self.reg_a = result;
+    }
+
+    /// Returns `true` if the next cycle starts a new instruction or interrupt
+    /// sequence. Same as [`MachineInspector::at_instruction_start`], but
+    /// doesn't require the memory to implement `Inspect`.
+    fn at_sequence_start(&self) -> bool {
+        self.sequence_state == SequenceState::Ready && !self.stalled
+    }
+
     fn stack_pointer(&self) -> u16 {
         0x100 | self.reg_sp as u16
     }
@@ -1558,6 +1469,19 @@ impl<M: Memory + Debug> Cpu<M> {
         u16::from_le_bytes([self.bal, self.bah])
     }
 
+    /// Returns the base address indexed with a given register.
+    fn indexed_address(&self, index: Index) -> u16 {
+        self.base_address()
+            .wrapping_add(self.index_register(index) as u16)
+    }
+
+    fn index_register(&self, index: Index) -> u8 {
+        match index {
+            Index::X => self.reg_x,
+            Index::Y => self.reg_y,
+        }
+    }
+
     pub fn ticks(&mut self, n_ticks: u32) -> TickResult {
         for _ in 0..n_ticks {
             self.tick()?;
@@ -1566,7 +1490,7 @@ impl<M: Memory + Debug> Cpu<M> {
     }
 }
 
-impl<M: Memory> fmt::Display for Cpu<M> {
+impl<M: Memory, V: Variant> fmt::Display for Cpu<M, V> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
         writeln!(
             f,
@@ -1593,9 +1517,14 @@ pub trait MachineInspector {
     fn flags(&self) -> u8;
     fn at_instruction_start(&self) -> bool;
     fn inspect_memory(&self, address: u16) -> u8;
+    /// Returns the total number of CPU cycles executed since the machine was
+    /// created.
+    fn cycles(&self) -> u64;
+    /// Returns information about the instructions supported by the CPU.
+    fn instruction_set(&self) -> &'static InstructionSet;
 }
 
-impl<M: Memory + Inspect> MachineInspector for Cpu<M> {
+impl<M: Memory + Inspect, V: Variant> MachineInspector for Cpu<M, V> {
     fn reg_pc(&self) -> u16 {
         self.reg_pc
     }
@@ -1621,10 +1550,100 @@ impl<M: Memory + Inspect> MachineInspector for Cpu<M> {
     }
 
     fn at_instruction_start(&self) -> bool {
-        self.sequence_state == SequenceState::Ready
+        // If the CPU got stalled, it has already been at the start of the
+        // instruction before the stall.
+        self.sequence_state == SequenceState::Ready && !self.stalled
+    }
+
+    fn cycles(&self) -> u64 {
+        self.cycles
     }
 
     fn inspect_memory(&self, address: u16) -> u8 {
         self.memory.inspect(address).unwrap_or(0xFF)
     }
+
+    fn instruction_set(&self) -> &'static InstructionSet {
+        V::instruction_set()
+    }
+}
+
+/// An interface for modifying machine's internal state for debugging purposes.
+/// It's a mutable counterpart of [`MachineInspector`].
+#[automock]
+pub trait MachineEditor {
+    /// Changes a byte in the memory without triggering any side effects. See
+    /// [`Poke::poke`] for details.
+    fn poke_memory(&mut self, address: u16, value: u8) -> WriteResult;
+    /// Changes the program counter. If the CPU is in the middle of an
+    /// instruction, the instruction is abandoned, and the execution continues
+    /// at the new address.
+    fn set_reg_pc(&mut self, value: u16);
+    fn set_reg_a(&mut self, value: u8);
+    fn set_reg_x(&mut self, value: u8);
+    fn set_reg_y(&mut self, value: u8);
+    fn set_reg_sp(&mut self, value: u8);
+    /// Changes the flag register. The B and unused bits are ignored, since
+    /// they don't exist in the actual register.
+    fn set_flags(&mut self, value: u8);
+}
+
+impl<M: Memory + Poke, V: Variant> MachineEditor for Cpu<M, V> {
+    fn poke_memory(&mut self, address: u16, value: u8) -> WriteResult {
+        self.memory.poke(address, value)
+    }
+
+    fn set_reg_pc(&mut self, value: u16) {
+        self.reg_pc = value;
+        self.sequence_state = SequenceState::Ready;
+    }
+
+    fn set_reg_a(&mut self, value: u8) {
+        self.reg_a = value;
+    }
+
+    fn set_reg_x(&mut self, value: u8) {
+        self.reg_x = value;
+    }
+
+    fn set_reg_y(&mut self, value: u8) {
+        self.reg_y = value;
+    }
+
+    fn set_reg_sp(&mut self, value: u8) {
+        self.reg_sp = value;
+    }
+
+    fn set_flags(&mut self, value: u8) {
+        self.flags = value & !flags::PUSHED;
+    }
+}
+
+mock! {
+    /// A machine that can be both inspected and edited. Useful for testing
+    /// debuggers.
+    pub Machine {}
+
+    impl MachineInspector for Machine {
+        fn reg_pc(&self) -> u16;
+        fn reg_a(&self) -> u8;
+        fn reg_x(&self) -> u8;
+        fn reg_y(&self) -> u8;
+        fn reg_sp(&self) -> u8;
+        fn flags(&self) -> u8;
+        fn at_instruction_start(&self) -> bool;
+        fn inspect_memory(&self, address: u16) -> u8;
+        fn cycles(&self) -> u64;
+        fn instruction_set(&self) -> &'static InstructionSet;
+    }
+
+    impl MachineEditor for Machine {
+        fn poke_memory(&mut self, address: u16, value: u8) -> WriteResult;
+        fn set_reg_pc(&mut self, value: u16);
+        fn set_reg_a(&mut self, value: u8);
+        fn set_reg_x(&mut self, value: u8);
+        fn set_reg_y(&mut self, value: u8);
+        fn set_reg_sp(&mut self, value: u8);
+        fn set_flags(&mut self, value: u8);
+    }
 }
@@ -177,4 +177,163 @@ pub const RTS: u8 = 0x60;
 pub const BRK: u8 = 0x00;
 pub const RTI: u8 = 0x40;
 
+// Undocumented NMOS opcodes. Note that the names of some of these vary
+// between sources (e.g. SBX is also known as AXS, and ISC as ISB).
+
+pub const LAX_IMM: u8 = 0xAB; // Unstable; see `Cpu::tick` for details.
+pub const LAX_ZP: u8 = 0xA7;
+pub const LAX_ZP_Y: u8 = 0xB7;
+pub const LAX_ABS: u8 = 0xAF;
+pub const LAX_ABS_Y: u8 = 0xBF;
+pub const LAX_X_INDIR: u8 = 0xA3;
+pub const LAX_INDIR_Y: u8 = 0xB3;
+
+pub const SAX_ZP: u8 = 0x87;
+pub const SAX_ZP_Y: u8 = 0x97;
+pub const SAX_ABS: u8 = 0x8F;
+pub const SAX_X_INDIR: u8 = 0x83;
+
+pub const DCP_ZP: u8 = 0xC7;
+pub const DCP_ZP_X: u8 = 0xD7;
+pub const DCP_ABS: u8 = 0xCF;
+pub const DCP_ABS_X: u8 = 0xDF;
+pub const DCP_ABS_Y: u8 = 0xDB;
+pub const DCP_X_INDIR: u8 = 0xC3;
+pub const DCP_INDIR_Y: u8 = 0xD3;
+
+pub const ISC_ZP: u8 = 0xE7;
+pub const ISC_ZP_X: u8 = 0xF7;
+pub const ISC_ABS: u8 = 0xEF;
+pub const ISC_ABS_X: u8 = 0xFF;
+pub const ISC_ABS_Y: u8 = 0xFB;
+pub const ISC_X_INDIR: u8 = 0xE3;
+pub const ISC_INDIR_Y: u8 = 0xF3;
+
+pub const SLO_ZP: u8 = 0x07;
+pub const SLO_ZP_X: u8 = 0x17;
+pub const SLO_ABS: u8 = 0x0F;
+pub const SLO_ABS_X: u8 = 0x1F;
+pub const SLO_ABS_Y: u8 = 0x1B;
+pub const SLO_X_INDIR: u8 = 0x03;
+pub const SLO_INDIR_Y: u8 = 0x13;
+
+pub const RLA_ZP: u8 = 0x27;
+pub const RLA_ZP_X: u8 = 0x37;
+pub const RLA_ABS: u8 = 0x2F;
+pub const RLA_ABS_X: u8 = 0x3F;
+pub const RLA_ABS_Y: u8 = 0x3B;
+pub const RLA_X_INDIR: u8 = 0x23;
+pub const RLA_INDIR_Y: u8 = 0x33;
+
+pub const SRE_ZP: u8 = 0x47;
+pub const SRE_ZP_X: u8 = 0x57;
+pub const SRE_ABS: u8 = 0x4F;
+pub const SRE_ABS_X: u8 = 0x5F;
+pub const SRE_ABS_Y: u8 = 0x5B;
+pub const SRE_X_INDIR: u8 = 0x43;
+pub const SRE_INDIR_Y: u8 = 0x53;
+
+pub const RRA_ZP: u8 = 0x67;
+pub const RRA_ZP_X: u8 = 0x77;
+pub const RRA_ABS: u8 = 0x6F;
+pub const RRA_ABS_X: u8 = 0x7F;
+pub const RRA_ABS_Y: u8 = 0x7B;
+pub const RRA_X_INDIR: u8 = 0x63;
+pub const RRA_INDIR_Y: u8 = 0x73;
+
+pub const ANC_IMM: u8 = 0x0B;
+pub const ANC_IMM2: u8 = 0x2B;
+pub const ALR_IMM: u8 = 0x4B;
+pub const ARR_IMM: u8 = 0x6B;
+pub const SBX_IMM: u8 = 0xCB;
+pub const SBC_IMM2: u8 = 0xEB;
+
+pub const NOP1: u8 = 0x1A;
+pub const NOP2: u8 = 0x3A;
+pub const NOP3: u8 = 0x5A;
+pub const NOP4: u8 = 0x7A;
+pub const NOP5: u8 = 0xDA;
+pub const NOP6: u8 = 0xFA;
+pub const NOP_IMM1: u8 = 0x80;
+pub const NOP_IMM2: u8 = 0x82;
+pub const NOP_IMM3: u8 = 0x89;
+pub const NOP_IMM4: u8 = 0xC2;
+pub const NOP_IMM5: u8 = 0xE2;
+pub const NOP_ZP1: u8 = 0x04;
+pub const NOP_ZP2: u8 = 0x44;
+pub const NOP_ZP3: u8 = 0x64;
+pub const NOP_ZP_X1: u8 = 0x14;
+pub const NOP_ZP_X2: u8 = 0x34;
+pub const NOP_ZP_X3: u8 = 0x54;
+pub const NOP_ZP_X4: u8 = 0x74;
+pub const NOP_ZP_X5: u8 = 0xD4;
+pub const NOP_ZP_X6: u8 = 0xF4;
+pub const NOP_ABS: u8 = 0x0C;
+pub const NOP_ABS_X1: u8 = 0x1C;
+pub const NOP_ABS_X2: u8 = 0x3C;
+pub const NOP_ABS_X3: u8 = 0x5C;
+pub const NOP_ABS_X4: u8 = 0x7C;
+pub const NOP_ABS_X5: u8 = 0xDC;
+pub const NOP_ABS_X6: u8 = 0xFC;
+
+// Highly unstable opcodes that are not emulated by `Cpu`. They are only
+// defined here for completeness.
+
+pub const ANE_IMM: u8 = 0x8B;
+pub const LAS_ABS_Y: u8 = 0xBB;
+pub const SHA_ABS_Y: u8 = 0x9F;
+pub const SHA_INDIR_Y: u8 = 0x93;
+pub const SHX_ABS_Y: u8 = 0x9E;
+pub const SHY_ABS_X: u8 = 0x9C;
+pub const TAS_ABS_Y: u8 = 0x9B;
+
 pub const HLT1: u8 = 0x02;
+pub const HLT2: u8 = 0x12;
+pub const HLT3: u8 = 0x22;
+pub const HLT4: u8 = 0x32;
+pub const HLT5: u8 = 0x42;
+pub const HLT6: u8 = 0x52;
+pub const HLT7: u8 = 0x62;
+pub const HLT8: u8 = 0x72;
+pub const HLT9: u8 = 0x92;
+pub const HLT10: u8 = 0xB2;
+pub const HLT11: u8 = 0xD2;
+pub const HLT12: u8 = 0xF2;
+
+// Opcodes added by 65C02. Note that many of them reuse the numbers of
+// undocumented NMOS opcodes.
+
+pub const BRA: u8 = 0x80;
+
+pub const PHX: u8 = 0xDA;
+pub const PHY: u8 = 0x5A;
+pub const PLX: u8 = 0xFA;
+pub const PLY: u8 = 0x7A;
+
+pub const STZ_ZP: u8 = 0x64;
+pub const STZ_ZP_X: u8 = 0x74;
+pub const STZ_ABS: u8 = 0x9C;
+pub const STZ_ABS_X: u8 = 0x9E;
+
+pub const TRB_ZP: u8 = 0x14;
+pub const TRB_ABS: u8 = 0x1C;
+pub const TSB_ZP: u8 = 0x04;
+pub const TSB_ABS: u8 = 0x0C;
+
+pub const ORA_ZP_INDIR: u8 = 0x12;
+pub const AND_ZP_INDIR: u8 = 0x32;
+pub const EOR_ZP_INDIR: u8 = 0x52;
+pub const ADC_ZP_INDIR: u8 = 0x72;
+pub const STA_ZP_INDIR: u8 = 0x92;
+pub const LDA_ZP_INDIR: u8 = 0xB2;
+pub const CMP_ZP_INDIR: u8 = 0xD2;
+pub const SBC_ZP_INDIR: u8 = 0xF2;
+
+pub const BIT_IMM: u8 = 0x89;
+pub const BIT_ZP_X: u8 = 0x34;
+pub const BIT_ABS_X: u8 = 0x3C;
+
+pub const INC_A: u8 = 0x1A;
+pub const DEC_A: u8 = 0x3A;
+
+pub const JMP_X_INDIR: u8 = 0x7C;
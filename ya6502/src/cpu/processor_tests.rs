@@ -0,0 +1,230 @@
+#![cfg(test)]
+
+//! A harness for running the
+//! [ProcessorTests](https://github.com/SingleStepTests/65x02) suite, which
+//! describes the initial and final state, as well as the complete bus activity,
+//! of a single instruction. Since the suite is huge, it's not a part of this
+//! repository. To run it, clone the suite and point the `PROCESSOR_TESTS_DIR`
+//! environment variable to the checkout directory (the one that contains
+//! `6502`, `nes6502`, and other subdirectories). Otherwise, the corresponding
+//! tests are skipped.
+
+use super::bus::{BusAccess, BusOperation};
+use super::variant::{Cmos65C02, Nmos6502, Ricoh2A03, Variant};
+use super::{flags, Cpu, CpuError};
+use crate::memory::Ram;
+use crate::power_on::PowerOnState;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+#[derive(Deserialize)]
+struct TestCase {
+    name: String,
+    initial: TestState,
+    #[serde(rename = "final")]
+    final_state: TestState,
+    cycles: Vec<(u16, u8, String)>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct TestState {
+    pc: u16,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+/// Outcome of running a single test file.
+#[derive(Debug, PartialEq)]
+enum FileResult {
+    Passed,
+    /// The opcode is not supported by the emulator.
+    Skipped,
+    Failed(Vec<String>),
+}
+
+/// Creates a CPU ready to execute an instruction in a given state.
+fn cpu_with_state<V: Variant>(state: &TestState) -> Cpu<Ram, V> {
+    let mut memory = Box::new(Ram::new(16));
+    for (address, value) in &state.ram {
+        memory.bytes[*address as usize] = *value;
+    }
+    let mut cpu = Cpu::with_power_on_state(memory, &PowerOnState::Zero);
+    cpu.jump_to(state.pc);
+    cpu.reg_sp = state.s;
+    cpu.reg_a = state.a;
+    cpu.reg_x = state.x;
+    cpu.reg_y = state.y;
+    cpu.flags = state.p & !flags::B | flags::UNUSED;
+    return cpu;
+}
+
+/// Runs a single test case and returns an error message if it fails.
+fn run_test_case<V: Variant>(test_case: &TestCase) -> Result<(), String> {
+    let mut cpu = cpu_with_state::<V>(&test_case.initial);
+    let accesses = Rc::new(RefCell::new(Vec::new()));
+    let accesses_clone = accesses.clone();
+    cpu.set_bus_observer(Some(Box::new(move |access: &BusAccess| {
+        accesses_clone.borrow_mut().push(*access)
+    })));
+    if let Err(e) = cpu.step_instruction() {
+        return Err(e.to_string());
+    }
+
+    // B and the unused bit don't really exist in the register, so we don't
+    // care about their values.
+    let flag_mask = !(flags::B | flags::UNUSED);
+    let mut expected = test_case.final_state.clone();
+    expected.p &= flag_mask;
+    let actual = TestState {
+        pc: cpu.reg_pc,
+        s: cpu.reg_sp,
+        a: cpu.reg_a,
+        x: cpu.reg_x,
+        y: cpu.reg_y,
+        p: cpu.flags & flag_mask,
+        ram: expected
+            .ram
+            .iter()
+            .map(|(address, _)| (*address, cpu.memory.bytes[*address as usize]))
+            .collect(),
+    };
+    if actual != expected {
+        return Err(format!("expected {:?}, got {:?}", expected, actual));
+    }
+
+    let actual_cycles: Vec<(u16, u8, String)> = accesses
+        .borrow()
+        .iter()
+        .map(|access| {
+            let operation = match access.operation {
+                BusOperation::Read => "read",
+                BusOperation::Write => "write",
+            };
+            (access.address, access.value, operation.to_string())
+        })
+        .collect();
+    if actual_cycles != test_case.cycles {
+        return Err(format!(
+            "expected cycles {:?}, got {:?}",
+            test_case.cycles, actual_cycles
+        ));
+    }
+    Ok(())
+}
+
+/// Checks whether the emulator supports the opcode tested by a given test case
+/// by executing it on a separate CPU.
+fn opcode_supported<V: Variant>(test_case: &TestCase) -> bool {
+    let mut cpu = cpu_with_state::<V>(&test_case.initial);
+    !matches!(
+        cpu.step_instruction(),
+        Err(CpuError::UnknownOpcode { .. } | CpuError::Halted { .. })
+    )
+}
+
+/// Runs all test cases from a given file. If the emulator doesn't support the
+/// opcode, the file is skipped.
+fn run_test_file<V: Variant>(path: &Path) -> FileResult {
+    let test_cases: Vec<TestCase> =
+        serde_json::from_slice(&fs::read(path).unwrap()).expect("Unable to parse test file");
+    if !opcode_supported::<V>(&test_cases[0]) {
+        return FileResult::Skipped;
+    }
+    let mut failures = vec![];
+    for test_case in &test_cases {
+        if let Err(message) = run_test_case::<V>(test_case) {
+            failures.push(format!("{}: {}", test_case.name, message));
+        }
+    }
+    return if failures.is_empty() {
+        FileResult::Passed
+    } else {
+        FileResult::Failed(failures)
+    };
+}
+
+/// Runs all test files from a given subdirectory of the test suite, provided
+/// that the `PROCESSOR_TESTS_DIR` environment variable is set.
+fn run_test_suite<V: Variant>(subdirectory: &str) {
+    let root = match env::var("PROCESSOR_TESTS_DIR") {
+        Ok(root) => PathBuf::from(root),
+        Err(_) => {
+            eprintln!("PROCESSOR_TESTS_DIR not set, skipping ProcessorTests");
+            return;
+        }
+    };
+    let mut paths: Vec<PathBuf> = fs::read_dir(root.join(subdirectory).join("v1"))
+        .expect("Unable to read the test directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+
+    let mut failed_files = vec![];
+    for path in &paths {
+        match run_test_file::<V>(path) {
+            FileResult::Passed => {}
+            FileResult::Skipped => eprintln!("Skipped {}", path.display()),
+            FileResult::Failed(failures) => {
+                eprintln!(
+                    "{}: {} failures, first: {}",
+                    path.display(),
+                    failures.len(),
+                    failures[0]
+                );
+                failed_files.push(path);
+            }
+        }
+    }
+    assert!(
+        failed_files.is_empty(),
+        "{} of {} files failed",
+        failed_files.len(),
+        paths.len()
+    );
+}
+
+#[test]
+fn harness() {
+    let path = Path::new("src")
+        .join("cpu")
+        .join("test_data")
+        .join("processor_tests");
+    assert_eq!(
+        run_test_file::<Nmos6502>(&path.join("passing.json")),
+        FileResult::Passed
+    );
+    assert_eq!(
+        run_test_file::<Nmos6502>(&path.join("halt.json")),
+        FileResult::Skipped
+    );
+    match run_test_file::<Nmos6502>(&path.join("failing.json")) {
+        FileResult::Failed(failures) => assert_eq!(failures.len(), 2),
+        other => panic!("Expected failures, got {:?}", other),
+    }
+}
+
+#[test]
+fn nmos_6502() {
+    run_test_suite::<Nmos6502>("6502");
+}
+
+#[test]
+fn ricoh_2a03() {
+    run_test_suite::<Ricoh2A03>("nes6502");
+}
+
+#[test]
+fn cmos_65c02() {
+    // Note: we use the Synertek variant of the tests, since it doesn't
+    // include the Rockwell and WDC extensions.
+    run_test_suite::<Cmos65C02>("synertek65c02");
+}
@@ -5,11 +5,17 @@ extern crate test;
 use super::*;
 use crate::cpu_with_code;
 use crate::memory::Ram;
+use crate::power_on::PowerOnState;
+use crate::test_utils::cpu_variant_with_program;
 use crate::test_utils::cpu_with_program;
 use crate::test_utils::reset;
+use bus::{BusAccess, BusAccessKind, BusOperation};
+use std::cell::RefCell;
+use std::rc::Rc;
 use test::Bencher;
+use variant::{Cmos65C02, Mos6507, Ricoh2A03};
 
-fn reversed_stack(cpu: &Cpu<Ram>) -> Vec<u8> {
+fn reversed_stack<V: Variant>(cpu: &Cpu<Ram, V>) -> Vec<u8> {
     cpu.memory.bytes[(cpu.stack_pointer() as usize + 1)..=0x1FF]
         .iter()
         .copied()
@@ -1432,7 +1438,7 @@ fn branching_across_pages_adds_one_cpu_cycle() {
             20,
         ],
     ));
-    let mut cpu = Cpu::new(memory);
+    let mut cpu: Cpu<Ram> = Cpu::new(memory);
     reset(&mut cpu);
     cpu.ticks(8).unwrap();
     assert_ne!(cpu.memory.bytes[20], 10);
@@ -1592,7 +1598,7 @@ fn pc_wrapping() {
         ],
     ));
     memory.bytes[0..2].copy_from_slice(&[opcodes::STA_ZP, 20]);
-    let mut cpu = Cpu::new(memory);
+    let mut cpu: Cpu<Ram> = Cpu::new(memory);
     reset(&mut cpu);
     cpu.ticks(8).unwrap();
     assert_eq!(cpu.memory.bytes[20], 10);
@@ -1613,7 +1619,7 @@ fn pc_wrapping_during_branch() {
         ],
     ));
     memory.bytes[2..4].copy_from_slice(&[opcodes::STA_ZP, 20]);
-    let mut cpu = Cpu::new(memory);
+    let mut cpu: Cpu<Ram> = Cpu::new(memory);
     reset(&mut cpu);
     cpu.ticks(9).unwrap();
     assert_eq!(cpu.memory.bytes[20], 10);
@@ -1700,16 +1706,20 @@ fn irq() {
     // been triggered.
     assert_eq!(cpu.memory.bytes[10..=14], [2, 0, 0, 0, 0]);
 
+    // The IRQ line is polled before the last cycle of each instruction, so
+    // the INC instruction that has just started will finish first.
     cpu.set_irq_pin(true);
-    cpu.ticks(7 + 29).unwrap();
+    cpu.ticks(5 + 7 + 29 - 2).unwrap();
+    // Turn off the IRQ line before RTI polls it, expecting no more
+    // interrupts.
+    cpu.set_irq_pin(false);
+    cpu.ticks(2).unwrap();
     // No B flag expected on the stack this time.
     assert_eq!(cpu.memory.bytes[0x1FD], flags::UNUSED);
-    assert_eq!(cpu.memory.bytes[10..=14], [2, 2, 0, 0, 0]);
+    assert_eq!(cpu.memory.bytes[10..=14], [3, 3, 0, 0, 0]);
 
-    // Turn off the IRQ line, expecting no interrupts.
-    cpu.set_irq_pin(false);
-    cpu.ticks(3 * 8).unwrap();
-    assert_eq!(cpu.memory.bytes[10..=14], [5, 2, 0, 0, 0]);
+    cpu.ticks(3 + 2 * 8).unwrap();
+    assert_eq!(cpu.memory.bytes[10..=14], [5, 3, 0, 0, 0]);
 
     // Turn the IRQ line back on for twice as long as before, triggering two
     // consecutive interrupts. To make it more fun, trigger the interrupt in the
@@ -1718,7 +1728,7 @@ fn irq() {
     cpu.ticks(2).unwrap();
     cpu.set_irq_pin(true);
     cpu.ticks(3 + 2 * (7 + 29)).unwrap();
-    assert_eq!(cpu.memory.bytes[10..=14], [6, 2, 6, 6, 0]);
+    assert_eq!(cpu.memory.bytes[10..=14], [6, 3, 6, 6, 0]);
 }
 
 #[test]
@@ -1737,7 +1747,8 @@ fn irq_right_after_init_pushes_b_flag_unset() {
     cpu.mut_memory().bytes[0xFFFE..=0xFFFF].copy_from_slice(&[0x07, 0xF0]);
     cpu.ticks(2 + 2 + 2).unwrap();
     cpu.set_irq_pin(true);
-    cpu.ticks(7).unwrap();
+    // The interrupt is polled too late to stop the JMP instruction.
+    cpu.ticks(3 + 7).unwrap();
     let flags = cpu.memory.bytes[0x01FD];
     assert_eq!(flags & flags::UNUSED, flags::UNUSED);
     assert_eq!(flags & flags::B, 0);
@@ -1752,17 +1763,17 @@ fn nmi() {
     assert_eq!(cpu.memory.bytes[10..=15], [2, 0, 0, 0, 0, 0]);
 
     cpu.set_nmi_pin(true);
-    cpu.ticks(7 + 29).unwrap();
-    assert_eq!(cpu.memory.bytes[10..=15], [2, 2, 0, 0, 0, 0]);
+    cpu.ticks(5 + 7 + 29).unwrap();
+    assert_eq!(cpu.memory.bytes[10..=15], [3, 3, 0, 0, 0, 0]);
 
     // Since NMI is edge-triggered, this shouldn't result in another interrupt.
-    cpu.ticks(3 * 8).unwrap();
-    assert_eq!(cpu.memory.bytes[10..=15], [5, 2, 0, 0, 0, 0]);
+    cpu.ticks(3 + 2 * 8).unwrap();
+    assert_eq!(cpu.memory.bytes[10..=15], [5, 3, 0, 0, 0, 0]);
 
     // Release the NMI flag for a while.
     cpu.set_nmi_pin(false);
     cpu.ticks(2 * 8).unwrap();
-    assert_eq!(cpu.memory.bytes[10..=15], [7, 2, 0, 0, 0, 0]);
+    assert_eq!(cpu.memory.bytes[10..=15], [7, 3, 0, 0, 0, 0]);
 
     // Trigger another interrupt; this time with a very short signal, in the
     // middle of processing the INC instruction.
@@ -1770,8 +1781,8 @@ fn nmi() {
     cpu.set_nmi_pin(true);
     cpu.ticks(1).unwrap();
     cpu.set_nmi_pin(false);
-    cpu.ticks(7 + 29 - 2).unwrap();
-    assert_eq!(cpu.memory.bytes[10..=15], [8, 2, 8, 0, 0, 0]);
+    cpu.ticks(3 + 7 + 29).unwrap();
+    assert_eq!(cpu.memory.bytes[10..=15], [8, 3, 8, 0, 0, 0]);
 }
 
 #[test]
@@ -1828,15 +1839,16 @@ fn i_flag_inside_interrupt_handlers() {
     cpu.mut_memory().bytes[10..21].copy_from_slice(&[
         0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
     ]);
+    // Hold the IRQ line long enough for it to be polled at the end of PHP.
     cpu.ticks(3 + 11 + 22 + 16).unwrap();
     cpu.set_irq_pin(true);
-    cpu.tick().unwrap();
+    cpu.ticks(2).unwrap();
     cpu.set_irq_pin(false);
-    cpu.ticks(7 - 1 + 22 + 16).unwrap();
+    cpu.ticks(1 + 7 + 22 + 13).unwrap();
     cpu.set_nmi_pin(true);
     cpu.tick().unwrap();
     cpu.set_nmi_pin(false);
-    cpu.ticks(7 - 1 + 22 + 16).unwrap();
+    cpu.ticks(2 + 7 + 22 + 13).unwrap();
 
     itertools::assert_equal(
         cpu.memory().bytes[10..=15].iter().map(|p| p & flags::I),
@@ -1844,6 +1856,211 @@ fn i_flag_inside_interrupt_handlers() {
     );
 }
 
+#[test]
+fn irq_polled_before_last_cycle() {
+    // An IRQ asserted before the last cycle of an instruction is serviced
+    // right after this instruction; otherwise, another instruction gets
+    // executed first.
+    for (delay, expected_x) in [(0, 0), (2, 0), (3, 1)] {
+        let mut cpu = cpu_with_code! {
+                ldx #0
+                cli
+                lda abs 0x1234 // 4 cycles
+                inx
+                inx
+            interrupt:         // 0xF008
+                stx 20
+            loop:
+                jmp loop
+        };
+        cpu.mut_memory().bytes[0xFFFE..=0xFFFF].copy_from_slice(&[0x08, 0xF0]);
+        cpu.ticks(2 + 2 + delay).unwrap();
+        cpu.set_irq_pin(true);
+        cpu.ticks(4 - delay + 2 + 7 + 3).unwrap();
+        assert_eq!(cpu.memory.bytes[20], expected_x, "delay: {}", delay);
+    }
+}
+
+#[test]
+fn irq_latency_after_cli_and_plp() {
+    // Both CLI and PLP change the I flag in their last cycle, so one more
+    // instruction gets executed before servicing a pending IRQ.
+    let mut cpu = cpu_with_code! {
+            ldx #0
+            cli
+            inx
+            inx
+        interrupt:  // 0xF005
+            stx 20
+        loop:
+            jmp loop
+    };
+    cpu.mut_memory().bytes[0xFFFE..=0xFFFF].copy_from_slice(&[0x05, 0xF0]);
+    cpu.set_irq_pin(true);
+    cpu.ticks(2 + 2 + 2 + 7 + 3).unwrap();
+    assert_eq!(cpu.memory.bytes[20], 1);
+
+    let mut cpu = cpu_with_code! {
+            lda #0
+            pha
+            ldx #0
+            plp
+            inx
+            inx
+        interrupt:  // 0xF008
+            stx 20
+        loop:
+            jmp loop
+    };
+    cpu.mut_memory().bytes[0xFFFE..=0xFFFF].copy_from_slice(&[0x08, 0xF0]);
+    cpu.set_irq_pin(true);
+    cpu.ticks(2 + 3 + 2 + 4 + 2 + 7 + 3).unwrap();
+    assert_eq!(cpu.memory.bytes[20], 1);
+}
+
+#[test]
+fn irq_taken_right_after_sei() {
+    // SEI sets the I flag too late to prevent an IRQ polled while it's being
+    // executed. The interrupt handler sees the I flag set on the stack.
+    let mut cpu = cpu_with_code! {
+            ldx #0xFF
+            txs
+            ldx #0
+            cli
+            sei
+            inx
+        interrupt:  // 0xF008
+            stx 20
+        loop:
+            jmp loop
+    };
+    cpu.mut_memory().bytes[0xFFFE..=0xFFFF].copy_from_slice(&[0x08, 0xF0]);
+    cpu.ticks(2 + 2 + 2 + 2).unwrap();
+    cpu.set_irq_pin(true);
+    cpu.ticks(2 + 7 + 3).unwrap();
+    assert_eq!(cpu.memory.bytes[20], 0);
+    assert_eq!(cpu.memory.bytes[0x1FE..=0x1FF], [0x07, 0xF0]);
+    assert_eq!(cpu.memory.bytes[0x1FD] & flags::I, flags::I);
+}
+
+#[test]
+fn taken_branch_delays_interrupts() {
+    // A taken branch that doesn't cross a page boundary doesn't poll the
+    // interrupts in its last cycle. An interrupt that arrives in the middle of
+    // such a branch is only serviced after the next instruction.
+    for (delay, expected_x) in [(0, 0), (1, 1)] {
+        let mut cpu = cpu_with_code! {
+                ldx #0
+                cli
+                lda #1
+                bne next    // 3 cycles
+            next:
+                inx
+                inx
+            interrupt:      // 0xF009
+                stx 20
+            loop:
+                jmp loop
+        };
+        cpu.mut_memory().bytes[0xFFFE..=0xFFFF].copy_from_slice(&[0x09, 0xF0]);
+        cpu.ticks(2 + 2 + 2 + delay).unwrap();
+        cpu.set_irq_pin(true);
+        cpu.ticks(3 - delay + 2 + 2 + 7 + 3).unwrap();
+        assert_eq!(cpu.memory.bytes[20], expected_x, "delay: {}", delay);
+    }
+
+    // This doesn't happen when crossing a page boundary.
+    let mut memory = Box::new(Ram::with_test_program_at(
+        0xF0F7,
+        &[
+            opcodes::LDX_IMM,
+            0,
+            opcodes::CLI,
+            opcodes::LDA_IMM,
+            1,
+            opcodes::BNE, // 4 cycles
+            2,
+            opcodes::HLT1,
+            opcodes::HLT1,
+            opcodes::INX, // 0xF100
+            opcodes::INX,
+            opcodes::STX_ZP, // 0xF102
+            20,
+            opcodes::JMP_ABS,
+            0x04,
+            0xF1,
+        ],
+    ));
+    memory.bytes[0xFFFE..=0xFFFF].copy_from_slice(&[0x02, 0xF1]);
+    let mut cpu: Cpu<Ram> = Cpu::new(memory);
+    reset(&mut cpu);
+    cpu.ticks(2 + 2 + 2 + 1).unwrap();
+    cpu.set_irq_pin(true);
+    cpu.ticks(3 + 7 + 3).unwrap();
+    assert_eq!(cpu.memory.bytes[20], 0);
+}
+
+fn cpu_with_hijacking_test_code() -> Cpu<Ram> {
+    let mut cpu = cpu_with_code! {
+            ldx #0xFF
+            txs
+            ldy #1
+            brk
+            nop         // Skipped
+        loop:           // 0xF007
+            jmp loop
+
+        irq:            // 0xF00A
+            sty 20
+            jmp loop
+        nmi:            // 0xF00F
+            sty 21
+            jmp loop
+    };
+    cpu.mut_memory().bytes[0xFFFA..=0xFFFF].copy_from_slice(&[0x0F, 0xF0, 0x00, 0xF0, 0x0A, 0xF0]);
+    cpu
+}
+
+#[test]
+fn nmi_hijacks_brk() {
+    // An NMI that arrives before BRK pushes the flags causes the CPU to jump
+    // to the NMI handler instead, with the B flag still pushed on the stack.
+    for delay in 0..=3 {
+        let mut cpu = cpu_with_hijacking_test_code();
+        cpu.ticks(2 + 2 + 2 + delay).unwrap();
+        cpu.set_nmi_pin(true);
+        cpu.ticks(7 - delay + 3).unwrap();
+        assert_eq!(cpu.memory.bytes[20..=21], [0, 1], "delay: {}", delay);
+        assert_eq!(cpu.memory.bytes[0x1FE..=0x1FF], [0x07, 0xF0]);
+        assert_eq!(cpu.memory.bytes[0x1FD] & flags::B, flags::B);
+    }
+
+    // Otherwise, the NMI is serviced after the first instruction of the BRK
+    // handler.
+    let mut cpu = cpu_with_hijacking_test_code();
+    cpu.ticks(2 + 2 + 2 + 4).unwrap();
+    cpu.set_nmi_pin(true);
+    cpu.ticks(3 + 3 + 7 + 3).unwrap();
+    assert_eq!(cpu.memory.bytes[20..=21], [1, 1]);
+    assert_eq!(cpu.memory.bytes[0x1FB..=0x1FC], [0x0C, 0xF0]);
+}
+
+#[test]
+fn nmi_hijacks_irq() {
+    for delay in 0..=3 {
+        let mut cpu = cpu_with_hijacking_test_code();
+        cpu.mut_memory().bytes[0xF005] = opcodes::CLI;
+        cpu.ticks(2 + 2 + 2 + 2).unwrap();
+        cpu.set_irq_pin(true);
+        cpu.ticks(2 + delay).unwrap();
+        cpu.set_nmi_pin(true);
+        cpu.ticks(7 - delay + 3).unwrap();
+        assert_eq!(cpu.memory.bytes[20..=21], [0, 1], "delay: {}", delay);
+        assert_eq!(cpu.memory.bytes[0x1FE..=0x1FF], [0x07, 0xF0]);
+        assert_eq!(cpu.memory.bytes[0x1FD] & flags::B, 0);
+    }
+}
+
 #[test]
 fn reports_instruction_start() {
     let mut cpu = cpu_with_code! {
@@ -1878,6 +2095,1455 @@ fn reports_instruction_start() {
     assert_eq!(cpu.reg_pc(), 0xF006);
 }
 
+#[test]
+fn lax() {
+    let mut cpu = cpu_with_program(&[
+        opcodes::LAX_ZP,
+        0x10,
+        // 3 cycles
+        opcodes::STX_ZP,
+        0x20,
+        // 3 cycles
+        opcodes::LDY_IMM,
+        1,
+        // 2 cycles
+        opcodes::LAX_ZP_Y,
+        0x10,
+        // 4 cycles
+        opcodes::STA_ZP,
+        0x21,
+        // 3 cycles
+        opcodes::LAX_ABS,
+        0x34,
+        0x12,
+        // 4 cycles
+        opcodes::STX_ZP,
+        0x22,
+        // 3 cycles
+        opcodes::LAX_ABS_Y,
+        0xFF,
+        0x12,
+        // 5 cycles (crossing a page)
+        opcodes::STA_ZP,
+        0x23,
+        // 3 cycles
+        opcodes::LDX_IMM,
+        2,
+        // 2 cycles
+        opcodes::LAX_X_INDIR,
+        0x30,
+        // 6 cycles
+        opcodes::STX_ZP,
+        0x24,
+        // 3 cycles
+        opcodes::LAX_INDIR_Y,
+        0x34,
+        // 5 cycles
+        opcodes::STA_ZP,
+        0x25,
+        // 3 cycles
+        opcodes::LAX_IMM,
+        0x0F,
+        // 2 cycles
+        opcodes::STX_ZP,
+        0x26,
+        // 3 cycles
+    ]);
+    cpu.mut_memory().bytes[0x10..=0x11].copy_from_slice(&[0x11, 0x22]);
+    cpu.mut_memory().bytes[0x32..=0x35].copy_from_slice(&[0x40, 0x12, 0x50, 0x12]);
+    cpu.mut_memory().bytes[0x1234] = 0x33;
+    cpu.mut_memory().bytes[0x1300] = 0x44;
+    cpu.mut_memory().bytes[0x1240] = 0x55;
+    cpu.mut_memory().bytes[0x1251] = 0x66;
+
+    cpu.ticks(53).unwrap();
+    assert_eq!(
+        cpu.memory.bytes[0x20..=0x25],
+        [0x11, 0x22, 0x33, 0x44, 0x55, 0x66]
+    );
+    assert_ne!(cpu.memory.bytes[0x26], 0x0E);
+    cpu.ticks(1).unwrap();
+    // LAX #imm is unstable; we assume A = X = (A | 0xEE) & imm.
+    assert_eq!(cpu.memory.bytes[0x26], 0x0E);
+}
+
+#[test]
+fn sax() {
+    let mut cpu = cpu_with_program(&[
+        opcodes::LDA_IMM,
+        0b1100_1100,
+        // 2 cycles
+        opcodes::LDX_IMM,
+        0b1010_1010,
+        // 2 cycles
+        opcodes::LDY_IMM,
+        1,
+        // 2 cycles
+        opcodes::SAX_ZP,
+        0x20,
+        // 3 cycles
+        opcodes::SAX_ZP_Y,
+        0x20,
+        // 4 cycles
+        opcodes::SAX_ABS,
+        0x34,
+        0x12,
+        // 4 cycles
+        opcodes::SAX_X_INDIR,
+        0x30,
+        // 6 cycles
+    ]);
+    cpu.mut_memory().bytes[0xDA..=0xDB].copy_from_slice(&[0x40, 0x12]);
+
+    cpu.ticks(23).unwrap();
+    assert_eq!(cpu.memory.bytes[0x20..=0x21], [0b1000_1000, 0b1000_1000]);
+    assert_eq!(cpu.memory.bytes[0x1234], 0b1000_1000);
+    assert_eq!(cpu.memory.bytes[0x1240], 0b1000_1000);
+    assert_eq!(cpu.reg_a, 0b1100_1100);
+    assert_eq!(cpu.reg_x, 0b1010_1010);
+}
+
+#[test]
+fn dcp() {
+    // Since all the read-modify-write undocumented instructions share the
+    // addressing mode implementation, this test also verifies the timing of
+    // all these modes.
+    let mut cpu = cpu_with_program(&[
+        opcodes::LDX_IMM,
+        0xFE,
+        // 2 cycles
+        opcodes::TXS,
+        // 2 cycles
+        opcodes::PLP,
+        // 4 cycles
+        opcodes::LDA_IMM,
+        0x0F,
+        // 2 cycles
+        opcodes::LDX_IMM,
+        1,
+        // 2 cycles
+        opcodes::LDY_IMM,
+        2,
+        // 2 cycles
+        opcodes::DCP_ZP,
+        0x10,
+        // 5 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::DCP_ZP_X,
+        0x10,
+        // 6 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::DCP_ABS,
+        0x34,
+        0x12,
+        // 6 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::DCP_ABS_X,
+        0x34,
+        0x12,
+        // 7 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::DCP_ABS_Y,
+        0x34,
+        0x12,
+        // 7 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::DCP_X_INDIR,
+        0x20,
+        // 8 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::DCP_INDIR_Y,
+        0x30,
+        // 8 cycles
+        opcodes::PHP,
+        // 3 cycles
+    ]);
+    cpu.mut_memory().bytes[0x10..=0x11].copy_from_slice(&[0x10, 0x11]);
+    cpu.mut_memory().bytes[0x21..=0x22].copy_from_slice(&[0x40, 0x12]);
+    cpu.mut_memory().bytes[0x30..=0x31].copy_from_slice(&[0x50, 0x12]);
+    cpu.mut_memory().bytes[0x1234..=0x1236].copy_from_slice(&[0x00, 0x05, 0x0F]);
+    cpu.mut_memory().bytes[0x1240] = 0x10;
+    cpu.mut_memory().bytes[0x1252] = 0x80;
+
+    cpu.ticks(14 + 8 + 9 + 9 + 10 + 10 + 11 + 11).unwrap();
+    assert_eq!(cpu.memory.bytes[0x10..=0x11], [0x0F, 0x10]);
+    assert_eq!(cpu.memory.bytes[0x1234..=0x1236], [0xFF, 0x04, 0x0E]);
+    assert_eq!(cpu.memory.bytes[0x1240], 0x0F);
+    assert_eq!(cpu.memory.bytes[0x1252], 0x7F);
+    assert_eq!(cpu.reg_a, 0x0F);
+    assert_eq!(
+        reversed_stack(&cpu),
+        [
+            flags::PUSHED | flags::Z | flags::C,
+            flags::PUSHED | flags::N,
+            flags::PUSHED,
+            flags::PUSHED | flags::C,
+            flags::PUSHED | flags::C,
+            flags::PUSHED | flags::Z | flags::C,
+            flags::PUSHED | flags::N,
+        ]
+    );
+}
+
+#[test]
+fn isc() {
+    let mut cpu = cpu_with_program(&[
+        opcodes::LDX_IMM,
+        0xFE,
+        // 2 cycles
+        opcodes::TXS,
+        // 2 cycles
+        opcodes::PLP,
+        // 4 cycles
+        opcodes::SEC,
+        // 2 cycles
+        opcodes::LDA_IMM,
+        0x10,
+        // 2 cycles
+        opcodes::ISC_ZP,
+        0x10,
+        // 5 cycles
+        opcodes::STA_ZP,
+        0x20,
+        // 3 cycles
+        opcodes::ISC_ABS,
+        0x11,
+        0x00,
+        // 6 cycles
+        opcodes::PHP,
+        // 3 cycles
+    ]);
+    cpu.mut_memory().bytes[0x10..=0x11].copy_from_slice(&[0x04, 0x0A]);
+
+    cpu.ticks(8 + 4 + 8 + 9).unwrap();
+    assert_eq!(cpu.memory.bytes[0x10..=0x11], [0x05, 0x0B]);
+    assert_eq!(cpu.memory.bytes[0x20], 0x0B);
+    assert_eq!(cpu.reg_a, 0);
+    assert_eq!(reversed_stack(&cpu), [flags::PUSHED | flags::Z | flags::C]);
+}
+
+#[test]
+fn slo_rla_sre_rra() {
+    let mut cpu = cpu_with_program(&[
+        opcodes::LDX_IMM,
+        0xFE,
+        // 2 cycles
+        opcodes::TXS,
+        // 2 cycles
+        opcodes::PLP,
+        // 4 cycles
+        opcodes::LDA_IMM,
+        0b0000_0001,
+        // 2 cycles
+        opcodes::SLO_ZP,
+        0x10,
+        // 5 cycles
+        opcodes::STA_ZP,
+        0x20,
+        // 3 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::LDA_IMM,
+        0b1111_0000,
+        // 2 cycles
+        opcodes::CLC,
+        // 2 cycles
+        opcodes::RLA_ZP,
+        0x11,
+        // 5 cycles
+        opcodes::STA_ZP,
+        0x21,
+        // 3 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::LDA_IMM,
+        0b1111_0000,
+        // 2 cycles
+        opcodes::SRE_ZP,
+        0x12,
+        // 5 cycles
+        opcodes::STA_ZP,
+        0x22,
+        // 3 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::LDA_IMM,
+        0x10,
+        // 2 cycles
+        opcodes::CLC,
+        // 2 cycles
+        opcodes::RRA_ZP,
+        0x13,
+        // 5 cycles
+        opcodes::STA_ZP,
+        0x23,
+        // 3 cycles
+        opcodes::PHP,
+        // 3 cycles
+    ]);
+    cpu.mut_memory().bytes[0x10..=0x13].copy_from_slice(&[
+        0b1000_0010,
+        0b1100_0001,
+        0b0000_0011,
+        0b0000_0101,
+    ]);
+
+    cpu.ticks(8 + 13 + 15 + 13 + 15).unwrap();
+    assert_eq!(
+        cpu.memory.bytes[0x10..=0x13],
+        [0b0000_0100, 0b1000_0010, 0b0000_0001, 0b0000_0010]
+    );
+    assert_eq!(
+        cpu.memory.bytes[0x20..=0x23],
+        [0b0000_0101, 0b1000_0000, 0b1111_0001, 0x13]
+    );
+    assert_eq!(
+        reversed_stack(&cpu),
+        [
+            flags::PUSHED | flags::C,
+            flags::PUSHED | flags::N | flags::C,
+            flags::PUSHED | flags::N | flags::C,
+            flags::PUSHED,
+        ]
+    );
+}
+
+#[test]
+fn anc_alr() {
+    let mut cpu = cpu_with_program(&[
+        opcodes::LDX_IMM,
+        0xFE,
+        // 2 cycles
+        opcodes::TXS,
+        // 2 cycles
+        opcodes::PLP,
+        // 4 cycles
+        opcodes::LDA_IMM,
+        0xF0,
+        // 2 cycles
+        opcodes::ANC_IMM,
+        0x8F,
+        // 2 cycles
+        opcodes::STA_ZP,
+        0x20,
+        // 3 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::ANC_IMM2,
+        0x0F,
+        // 2 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::LDA_IMM,
+        0xFF,
+        // 2 cycles
+        opcodes::ALR_IMM,
+        0b0000_0011,
+        // 2 cycles
+        opcodes::STA_ZP,
+        0x21,
+        // 3 cycles
+        opcodes::PHP,
+        // 3 cycles
+    ]);
+
+    cpu.ticks(8 + 10 + 5 + 10).unwrap();
+    assert_eq!(cpu.memory.bytes[0x20..=0x21], [0x80, 0x01]);
+    assert_eq!(
+        reversed_stack(&cpu),
+        [
+            flags::PUSHED | flags::N | flags::C,
+            flags::PUSHED | flags::Z,
+            flags::PUSHED | flags::C,
+        ]
+    );
+}
+
+#[test]
+fn arr() {
+    let mut cpu = cpu_with_program(&[
+        opcodes::LDX_IMM,
+        0xFE,
+        // 2 cycles
+        opcodes::TXS,
+        // 2 cycles
+        opcodes::PLP,
+        // 4 cycles
+        opcodes::SEC,
+        // 2 cycles
+        opcodes::LDA_IMM,
+        0xFF,
+        // 2 cycles
+        opcodes::ARR_IMM,
+        0b1100_0000,
+        // 2 cycles
+        opcodes::STA_ZP,
+        0x20,
+        // 3 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::CLC,
+        // 2 cycles
+        opcodes::LDA_IMM,
+        0xFF,
+        // 2 cycles
+        opcodes::ARR_IMM,
+        0b0100_0000,
+        // 2 cycles
+        opcodes::STA_ZP,
+        0x21,
+        // 3 cycles
+        opcodes::PHP,
+        // 3 cycles
+        // Decimal mode
+        opcodes::SED,
+        // 2 cycles
+        opcodes::CLC,
+        // 2 cycles
+        opcodes::LDA_IMM,
+        0xFF,
+        // 2 cycles
+        opcodes::ARR_IMM,
+        0x05,
+        // 2 cycles
+        opcodes::STA_ZP,
+        0x22,
+        // 3 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::LDA_IMM,
+        0xFF,
+        // 2 cycles
+        opcodes::ARR_IMM,
+        0x50,
+        // 2 cycles
+        opcodes::STA_ZP,
+        0x23,
+        // 3 cycles
+        opcodes::PHP,
+        // 3 cycles
+    ]);
+
+    cpu.ticks(8 + 12 + 12 + 14 + 10).unwrap();
+    assert_eq!(cpu.memory.bytes[0x20..=0x23], [0xE0, 0x20, 0x08, 0x88]);
+    assert_eq!(
+        reversed_stack(&cpu),
+        [
+            flags::PUSHED | flags::N | flags::C,
+            flags::PUSHED | flags::V,
+            flags::PUSHED | flags::D,
+            flags::PUSHED | flags::D | flags::V | flags::C,
+        ]
+    );
+}
+
+#[test]
+fn sbx() {
+    let mut cpu = cpu_with_program(&[
+        opcodes::LDX_IMM,
+        0xFE,
+        // 2 cycles
+        opcodes::TXS,
+        // 2 cycles
+        opcodes::PLP,
+        // 4 cycles
+        opcodes::SED,
+        // 2 cycles (shouldn't matter)
+        opcodes::LDA_IMM,
+        0xF0,
+        // 2 cycles
+        opcodes::LDX_IMM,
+        0x3C,
+        // 2 cycles
+        opcodes::SBX_IMM,
+        0x10,
+        // 2 cycles
+        opcodes::STX_ZP,
+        0x20,
+        // 3 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::SBX_IMM,
+        0x30,
+        // 2 cycles
+        opcodes::STX_ZP,
+        0x21,
+        // 3 cycles
+        opcodes::PHP,
+        // 3 cycles
+    ]);
+
+    cpu.ticks(8 + 14 + 8).unwrap();
+    assert_eq!(cpu.memory.bytes[0x20..=0x21], [0x20, 0xF0]);
+    assert_eq!(cpu.reg_a, 0xF0);
+    assert_eq!(
+        reversed_stack(&cpu),
+        [
+            flags::PUSHED | flags::D | flags::C,
+            flags::PUSHED | flags::D | flags::N,
+        ]
+    );
+}
+
+#[test]
+fn undocumented_sbc_immediate() {
+    let mut cpu = cpu_with_program(&[
+        opcodes::SEC,
+        // 2 cycles
+        opcodes::CLD,
+        // 2 cycles
+        opcodes::LDA_IMM,
+        0x10,
+        // 2 cycles
+        opcodes::SBC_IMM2,
+        0x01,
+        // 2 cycles
+        opcodes::STA_ZP,
+        0x20,
+        // 3 cycles
+    ]);
+    cpu.ticks(11).unwrap();
+    assert_eq!(cpu.memory.bytes[0x20], 0x0F);
+}
+
+#[test]
+fn undocumented_nops() {
+    let cases: [(&[u8], &[u8], u32); 7] = [
+        (
+            &[
+                opcodes::NOP1,
+                opcodes::NOP2,
+                opcodes::NOP3,
+                opcodes::NOP4,
+                opcodes::NOP5,
+                opcodes::NOP6,
+            ],
+            &[],
+            2,
+        ),
+        (
+            &[
+                opcodes::NOP_IMM1,
+                opcodes::NOP_IMM2,
+                opcodes::NOP_IMM3,
+                opcodes::NOP_IMM4,
+                opcodes::NOP_IMM5,
+            ],
+            &[0xFF],
+            2,
+        ),
+        (
+            &[opcodes::NOP_ZP1, opcodes::NOP_ZP2, opcodes::NOP_ZP3],
+            &[0x10],
+            3,
+        ),
+        (
+            &[
+                opcodes::NOP_ZP_X1,
+                opcodes::NOP_ZP_X2,
+                opcodes::NOP_ZP_X3,
+                opcodes::NOP_ZP_X4,
+                opcodes::NOP_ZP_X5,
+                opcodes::NOP_ZP_X6,
+            ],
+            &[0x10],
+            4,
+        ),
+        (&[opcodes::NOP_ABS], &[0x34, 0x12], 4),
+        (
+            &[
+                opcodes::NOP_ABS_X1,
+                opcodes::NOP_ABS_X2,
+                opcodes::NOP_ABS_X3,
+                opcodes::NOP_ABS_X4,
+                opcodes::NOP_ABS_X5,
+                opcodes::NOP_ABS_X6,
+            ],
+            &[0x34, 0x12],
+            4,
+        ),
+        (
+            &[
+                opcodes::NOP_ABS_X1,
+                opcodes::NOP_ABS_X2,
+                opcodes::NOP_ABS_X3,
+                opcodes::NOP_ABS_X4,
+                opcodes::NOP_ABS_X5,
+                opcodes::NOP_ABS_X6,
+            ],
+            &[0xFF, 0x12],
+            // Crossing a page
+            5,
+        ),
+    ];
+    for (nop_opcodes, operands, cycles) in cases {
+        for opcode in nop_opcodes {
+            let mut program = vec![opcodes::LDA_IMM, 0x42, opcodes::LDX_IMM, 1, *opcode];
+            program.extend_from_slice(operands);
+            program.extend_from_slice(&[opcodes::STA_ZP, 0x20, opcodes::STX_ZP, 0x21]);
+            let mut cpu = cpu_with_program(&program);
+
+            cpu.ticks(4 + cycles + 2).unwrap();
+            assert_ne!(cpu.memory.bytes[0x20], 0x42, "opcode ${:02X}", opcode);
+            cpu.ticks(1 + 3).unwrap();
+            assert_eq!(
+                cpu.memory.bytes[0x20..=0x21],
+                [0x42, 1],
+                "opcode ${:02X}",
+                opcode
+            );
+        }
+    }
+}
+
+#[test]
+fn halting() {
+    for opcode in [
+        opcodes::HLT1,
+        opcodes::HLT2,
+        opcodes::HLT3,
+        opcodes::HLT4,
+        opcodes::HLT5,
+        opcodes::HLT6,
+        opcodes::HLT7,
+        opcodes::HLT8,
+        opcodes::HLT9,
+        opcodes::HLT10,
+        opcodes::HLT11,
+        opcodes::HLT12,
+    ] {
+        let mut cpu = cpu_with_program(&[opcodes::NOP, opcode]);
+        cpu.ticks(3).unwrap();
+        assert_eq!(
+            cpu.tick().unwrap_err(),
+            CpuError::Halted { opcode, pc: 0xF001 }
+        );
+    }
+}
+
+#[test]
+fn reports_unknown_opcode() {
+    let mut cpu = cpu_with_program(&[opcodes::NOP, opcodes::ANE_IMM, 0x00]);
+    cpu.ticks(3).unwrap();
+    let error = cpu.tick().unwrap_err();
+    assert_eq!(
+        error,
+        CpuError::UnknownOpcode {
+            opcode: opcodes::ANE_IMM,
+            pc: 0xF001,
+        }
+    );
+    assert_eq!(error.to_string(), "Unknown opcode: $8B at $F001");
+}
+
+#[test]
+fn cmos_stack_and_stz() {
+    let mut cpu = cpu_variant_with_program::<Cmos65C02>(&[
+        opcodes::LDX_IMM,
+        0x11,
+        // 2 cycles
+        opcodes::LDY_IMM,
+        0x22,
+        // 2 cycles
+        opcodes::PHX,
+        // 3 cycles
+        opcodes::PHY,
+        // 3 cycles
+        opcodes::STZ_ZP,
+        0x10,
+        // 3 cycles
+        opcodes::STZ_ZP_X,
+        0x10,
+        // 4 cycles
+        opcodes::STZ_ABS,
+        0x34,
+        0x12,
+        // 4 cycles
+        opcodes::STZ_ABS_X,
+        0x30,
+        0x12,
+        // 5 cycles
+        opcodes::PLX,
+        // 4 cycles
+        opcodes::PLY,
+        // 4 cycles
+        opcodes::STX_ZP,
+        0x30,
+        // 3 cycles
+        opcodes::STY_ZP,
+        0x31,
+        // 3 cycles
+    ]);
+    cpu.mut_memory().bytes[0x10] = 0xFF;
+    cpu.mut_memory().bytes[0x21] = 0xFF;
+    cpu.mut_memory().bytes[0x1234] = 0xFF;
+    cpu.mut_memory().bytes[0x1241] = 0xFF;
+
+    cpu.ticks(39).unwrap();
+    assert_eq!(cpu.memory.bytes[0x10], 0);
+    assert_eq!(cpu.memory.bytes[0x21], 0);
+    assert_eq!(cpu.memory.bytes[0x1234], 0);
+    assert_eq!(cpu.memory.bytes[0x1241], 0);
+    assert_eq!(cpu.memory.bytes[0x30], 0x22);
+    assert_ne!(cpu.memory.bytes[0x31], 0x11);
+    cpu.ticks(1).unwrap();
+    assert_eq!(cpu.memory.bytes[0x31], 0x11);
+}
+
+#[test]
+fn cmos_zero_page_indirect() {
+    let mut cpu = cpu_variant_with_program::<Cmos65C02>(&[
+        opcodes::LDX_IMM,
+        0xFE,
+        // 2 cycles
+        opcodes::TXS,
+        // 2 cycles
+        opcodes::PLP,
+        // 4 cycles
+        opcodes::LDA_ZP_INDIR,
+        0x30,
+        // 5 cycles
+        opcodes::EOR_IMM,
+        0xFF,
+        // 2 cycles
+        opcodes::STA_ZP_INDIR,
+        0x32,
+        // 5 cycles
+        opcodes::AND_ZP_INDIR,
+        0x34,
+        // 5 cycles
+        opcodes::ORA_ZP_INDIR,
+        0x30,
+        // 5 cycles
+        opcodes::STA_ZP,
+        0x10,
+        // 3 cycles
+        opcodes::CMP_ZP_INDIR,
+        0x32,
+        // 5 cycles
+        opcodes::PHP,
+        // 3 cycles
+    ]);
+    cpu.mut_memory().bytes[0x30..=0x35].copy_from_slice(&[0x00, 0x12, 0x00, 0x13, 0x00, 0x14]);
+    cpu.mut_memory().bytes[0x1200] = 0x0F;
+    cpu.mut_memory().bytes[0x1400] = 0x3C;
+
+    cpu.ticks(41).unwrap();
+    assert_eq!(cpu.memory.bytes[0x1300], 0xF0);
+    assert_eq!(cpu.memory.bytes[0x10], 0x3F);
+    assert_eq!(reversed_stack(&cpu), [flags::PUSHED]);
+}
+
+#[test]
+fn cmos_trb_tsb() {
+    let mut cpu = cpu_variant_with_program::<Cmos65C02>(&[
+        opcodes::LDX_IMM,
+        0xFE,
+        // 2 cycles
+        opcodes::TXS,
+        // 2 cycles
+        opcodes::PLP,
+        // 4 cycles
+        opcodes::LDA_IMM,
+        0b1100_1100,
+        // 2 cycles
+        opcodes::TSB_ZP,
+        0x10,
+        // 5 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::TRB_ABS,
+        0x34,
+        0x12,
+        // 6 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::TRB_ZP,
+        0x11,
+        // 5 cycles
+        opcodes::TSB_ABS,
+        0x35,
+        0x12,
+        // 6 cycles
+    ]);
+    cpu.mut_memory().bytes[0x10..=0x11].copy_from_slice(&[0b1010_1010, 0b1111_0000]);
+    cpu.mut_memory().bytes[0x1234..=0x1235].copy_from_slice(&[0b0011_0011, 0b0000_1111]);
+
+    cpu.ticks(38).unwrap();
+    assert_eq!(cpu.memory.bytes[0x10], 0b1110_1110);
+    assert_eq!(cpu.memory.bytes[0x11], 0b0011_0000);
+    assert_eq!(cpu.memory.bytes[0x1234], 0b0011_0011);
+    assert_eq!(cpu.memory.bytes[0x1235], 0b1100_1111);
+    assert_eq!(
+        reversed_stack(&cpu),
+        [
+            flags::PUSHED | flags::N,
+            flags::PUSHED | flags::N | flags::Z
+        ]
+    );
+}
+
+#[test]
+fn cmos_bit() {
+    let mut cpu = cpu_variant_with_program::<Cmos65C02>(&[
+        opcodes::LDX_IMM,
+        0xFE,
+        // 2 cycles
+        opcodes::TXS,
+        // 2 cycles
+        opcodes::LDA_IMM,
+        0x0F,
+        // 2 cycles
+        opcodes::PLP,
+        // 4 cycles
+        opcodes::BIT_IMM,
+        0xF0,
+        // 2 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::BIT_ZP_X,
+        0x12,
+        // 4 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::BIT_ABS_X,
+        0x02,
+        0x12,
+        // 5 cycles (crossing a page)
+        opcodes::PHP,
+        // 3 cycles
+    ]);
+    cpu.mut_memory().bytes[0x1FF] = flags::N | flags::V;
+    cpu.mut_memory().bytes[0x10] = 0x40;
+    cpu.mut_memory().bytes[0x1300] = 0x81;
+
+    cpu.ticks(30).unwrap();
+    assert_eq!(
+        reversed_stack(&cpu),
+        [
+            flags::PUSHED | flags::N | flags::V | flags::Z,
+            flags::PUSHED | flags::V | flags::Z,
+            flags::PUSHED | flags::N,
+        ]
+    );
+}
+
+#[test]
+fn cmos_inc_dec_accumulator() {
+    let mut cpu = cpu_variant_with_program::<Cmos65C02>(&[
+        opcodes::LDA_IMM,
+        0xFF,
+        // 2 cycles
+        opcodes::INC_A,
+        // 2 cycles
+        opcodes::STA_ZP,
+        0x10,
+        // 3 cycles
+        opcodes::DEC_A,
+        // 2 cycles
+        opcodes::STA_ZP,
+        0x11,
+        // 3 cycles
+    ]);
+    cpu.mut_memory().bytes[0x10] = 0xAA;
+
+    cpu.ticks(12).unwrap();
+    assert_eq!(cpu.memory.bytes[0x10..=0x11], [0x00, 0xFF]);
+    assert_eq!(cpu.flags & (flags::N | flags::Z), flags::N);
+}
+
+#[test]
+fn cmos_bra() {
+    let mut cpu = cpu_variant_with_program::<Cmos65C02>(&[
+        opcodes::LDA_IMM,
+        1,
+        // 2 cycles
+        opcodes::BRA,
+        2,
+        // 3 cycles
+        opcodes::LDA_IMM,
+        2,
+        opcodes::STA_ZP,
+        0x10,
+        // 3 cycles
+    ]);
+
+    cpu.ticks(7).unwrap();
+    assert_ne!(cpu.memory.bytes[0x10], 1);
+    cpu.ticks(1).unwrap();
+    assert_eq!(cpu.memory.bytes[0x10], 1);
+}
+
+#[test]
+fn cmos_jmp() {
+    let mut cpu = cpu_variant_with_program::<Cmos65C02>(&[
+        opcodes::LDX_IMM,
+        2,
+        // 2 cycles
+        opcodes::JMP_INDIR,
+        0xFF,
+        0x12,
+        // 6 cycles
+    ]);
+    // NMOS would take the high byte of the address from $1200.
+    cpu.mut_memory().bytes[0x1200] = 0xE0;
+    cpu.mut_memory().bytes[0x12FF..=0x1300].copy_from_slice(&[0x00, 0xF1]);
+    cpu.mut_memory().bytes[0x1236..=0x1237].copy_from_slice(&[0x00, 0xF2]);
+    cpu.mut_memory().bytes[0xF100..=0xF102].copy_from_slice(&[opcodes::JMP_X_INDIR, 0x34, 0x12]);
+    // 6 cycles
+    cpu.mut_memory().bytes[0xF200..=0xF203].copy_from_slice(&[
+        opcodes::LDA_IMM,
+        0x42,
+        // 2 cycles
+        opcodes::STA_ZP,
+        0x10,
+        // 3 cycles
+    ]);
+
+    cpu.ticks(18).unwrap();
+    assert_ne!(cpu.memory.bytes[0x10], 0x42);
+    cpu.ticks(1).unwrap();
+    assert_eq!(cpu.memory.bytes[0x10], 0x42);
+}
+
+#[test]
+fn cmos_decimal_mode() {
+    let mut cpu = cpu_variant_with_program::<Cmos65C02>(&[
+        opcodes::SED,
+        // 2 cycles
+        opcodes::CLC,
+        // 2 cycles
+        opcodes::LDA_IMM,
+        0x19,
+        // 2 cycles
+        opcodes::ADC_IMM,
+        0x01,
+        // 3 cycles
+        opcodes::STA_ZP,
+        0x10,
+        // 3 cycles
+        opcodes::SBC_ZP_INDIR,
+        0x20,
+        // 6 cycles
+        opcodes::STA_ZP,
+        0x11,
+        // 3 cycles
+        opcodes::BRK,
+        // 7 cycles
+    ]);
+    cpu.mut_memory().bytes[0x20..=0x21].copy_from_slice(&[0x34, 0x12]);
+    cpu.mut_memory().bytes[0x1234] = 0x05;
+    cpu.mut_memory().bytes[0xFFFE..=0xFFFF].copy_from_slice(&[0x00, 0xF1]);
+
+    cpu.ticks(11).unwrap();
+    assert_ne!(cpu.memory.bytes[0x10], 0x20);
+    cpu.ticks(1).unwrap();
+    assert_eq!(cpu.memory.bytes[0x10], 0x20);
+    cpu.ticks(8).unwrap();
+    assert_ne!(cpu.memory.bytes[0x11], 0x14);
+    cpu.ticks(1).unwrap();
+    assert_eq!(cpu.memory.bytes[0x11], 0x14);
+    assert_ne!(cpu.flags & flags::D, 0);
+    cpu.ticks(7).unwrap();
+    assert_eq!(cpu.reg_pc, 0xF100);
+    assert_eq!(cpu.flags & flags::D, 0);
+}
+
+#[test]
+fn cmos_decimal_mode_flags() {
+    let mut cpu = cpu_variant_with_program::<Cmos65C02>(&[
+        opcodes::LDX_IMM,
+        0xFF,
+        // 2 cycles
+        opcodes::TXS,
+        // 2 cycles
+        opcodes::SED,
+        // 2 cycles
+        opcodes::SEC,
+        // 2 cycles
+        opcodes::LDA_IMM,
+        0x79,
+        // 2 cycles
+        opcodes::ADC_IMM,
+        0x00,
+        // 3 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::CLC,
+        // 2 cycles
+        opcodes::LDA_IMM,
+        0x99,
+        // 2 cycles
+        opcodes::ADC_IMM,
+        0x01,
+        // 3 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::SEC,
+        // 2 cycles
+        opcodes::LDA_IMM,
+        0x80,
+        // 2 cycles
+        opcodes::SBC_IMM,
+        0x01,
+        // 3 cycles
+        opcodes::PHP,
+        // 3 cycles
+        opcodes::LDA_IMM,
+        0x01,
+        // 2 cycles
+        opcodes::SBC_IMM,
+        0x01,
+        // 3 cycles
+        opcodes::PHP,
+        // 3 cycles
+    ]);
+
+    cpu.ticks(44).unwrap();
+    let result_flags = flags::N | flags::V | flags::Z | flags::C;
+    assert_eq!(
+        reversed_stack(&cpu)
+            .iter()
+            .map(|flags| flags & result_flags)
+            .collect::<Vec<_>>(),
+        [
+            // 0x79 + 0x00 + 1 = 0x80
+            flags::N | flags::V,
+            // 0x99 + 0x01 = 0x00, carry; V cleared
+            flags::Z | flags::C,
+            // 0x80 - 0x01 = 0x79
+            flags::V | flags::C,
+            // 0x01 - 0x01 = 0x00
+            flags::Z | flags::C,
+        ]
+    );
+}
+
+#[test]
+fn cmos_unused_opcodes() {
+    let mut cpu = cpu_variant_with_program::<Cmos65C02>(&[
+        opcodes::LDA_IMM,
+        0x42,
+        // 2 cycles
+        opcodes::LAX_ZP,
+        // 1 cycle
+        opcodes::SBX_IMM,
+        // 1 cycle
+        opcodes::HLT1,
+        0xFF,
+        // 2 cycles
+        opcodes::NOP_ABS_X3,
+        0x34,
+        0x12,
+        // 8 cycles
+        opcodes::NOP_ABS_X5,
+        0xFF,
+        0x12,
+        // 4 cycles
+        opcodes::NOP_ZP2,
+        0x10,
+        // 3 cycles
+        opcodes::STA_ZP,
+        0x10,
+        // 3 cycles
+    ]);
+
+    cpu.ticks(23).unwrap();
+    assert_ne!(cpu.memory.bytes[0x10], 0x42);
+    cpu.ticks(1).unwrap();
+    assert_eq!(cpu.memory.bytes[0x10], 0x42);
+}
+
+#[test]
+fn cmos_indexed_addressing() {
+    let mut cpu = cpu_variant_with_program::<Cmos65C02>(&[
+        opcodes::LDX_IMM,
+        0x02,
+        // 2 cycles
+        opcodes::LDA_ABS_X,
+        0xFF,
+        0x12,
+        // 5 cycles (crossing a page)
+        opcodes::ASL_ABS_X,
+        0x00,
+        0x13,
+        // 6 cycles
+        opcodes::ROR_ABS_X,
+        0xFF,
+        0x12,
+        // 7 cycles (crossing a page)
+    ]);
+    cpu.mut_memory().bytes[0x1301] = 0x42;
+    cpu.mut_memory().bytes[0x1302] = 0x11;
+    let accesses = Rc::new(RefCell::new(Vec::new()));
+    let accesses_clone = accesses.clone();
+    cpu.set_bus_observer(Some(Box::new(move |access: &BusAccess| {
+        accesses_clone
+            .borrow_mut()
+            .push((access.operation, access.address, access.kind))
+    })));
+    cpu.ticks(2 + 5 + 6 + 7).unwrap();
+
+    use BusAccessKind::*;
+    use BusOperation::*;
+    assert_eq!(
+        accesses.borrow()[2..],
+        [
+            (Read, 0xF002, OpcodeFetch),
+            (Read, 0xF003, OperandFetch),
+            (Read, 0xF004, OperandFetch),
+            // NMOS would read from $1201 here.
+            (Read, 0xF004, Dummy),
+            (Read, 0x1301, Data),
+            (Read, 0xF005, OpcodeFetch),
+            (Read, 0xF006, OperandFetch),
+            (Read, 0xF007, OperandFetch),
+            (Read, 0x1302, Data),
+            (Read, 0x1302, Dummy),
+            (Write, 0x1302, Data),
+            (Read, 0xF008, OpcodeFetch),
+            (Read, 0xF009, OperandFetch),
+            (Read, 0xF00A, OperandFetch),
+            (Read, 0xF00A, Dummy),
+            (Read, 0x1301, Data),
+            (Read, 0x1301, Dummy),
+            (Write, 0x1301, Data),
+        ]
+    );
+    assert_eq!(cpu.reg_a, 0x42);
+    assert_eq!(cpu.memory.bytes[0x1301..=0x1302], [0x21, 0x22]);
+}
+
+#[test]
+fn ricoh_2a03_ignores_decimal_mode() {
+    let mut cpu = cpu_variant_with_program::<Ricoh2A03>(&[
+        opcodes::SED,
+        // 2 cycles
+        opcodes::CLC,
+        // 2 cycles
+        opcodes::LDA_IMM,
+        0x19,
+        // 2 cycles
+        opcodes::ADC_IMM,
+        0x01,
+        // 2 cycles
+        opcodes::STA_ZP,
+        0x10,
+        // 3 cycles
+    ]);
+
+    cpu.ticks(11).unwrap();
+    assert_eq!(cpu.memory.bytes[0x10], 0x1A);
+}
+
+#[test]
+fn mos_6507_has_13_bit_address_bus() {
+    let mut memory = Box::new(Ram::with_test_program_at(
+        0x1000,
+        &[
+            opcodes::LDA_IMM,
+            0x42,
+            // 2 cycles
+            opcodes::STA_ABS,
+            0x80,
+            0x20,
+            // 4 cycles
+        ],
+    ));
+    // The reset vector is seen at $1FFC, and the CPU is going to use the
+    // address as is, so the program will appear to run at $F000.
+    memory.bytes[0x1FFC..=0x1FFD].copy_from_slice(&[0x00, 0xF0]);
+    let mut cpu: Cpu<Ram, Mos6507> = Cpu::new(memory);
+    reset(&mut cpu);
+
+    cpu.ticks(6).unwrap();
+    assert_eq!(cpu.reg_pc, 0xF005);
+    assert_eq!(cpu.memory.bytes[0x0080], 0x42);
+    assert_eq!(cpu.memory.bytes[0x2080], 0x00);
+}
+
+#[test]
+fn snapshot_and_restore() {
+    fn new_cpu() -> Cpu<Ram> {
+        let mut cpu = cpu_with_code! {
+                cli
+                ldx #0
+            loop:
+                lda 0x1234,x
+                sta 0x40,x
+                inc 0x50
+                inx
+                jmp loop
+        };
+        cpu.mut_memory().bytes[0xF100] = opcodes::RTI;
+        cpu.mut_memory().bytes[0xFFFA..=0xFFFF].copy_from_slice(&[0x00, 0xF1, 0, 0, 0x00, 0xF1]);
+        return cpu;
+    }
+    fn tick(cpu: &mut Cpu<Ram>, cycle: u32) {
+        cpu.set_irq_pin((30..40).contains(&cycle));
+        cpu.set_nmi_pin((60..62).contains(&cycle));
+        cpu.tick().unwrap();
+    }
+
+    // Take a snapshot at every possible cycle, including the middle of
+    // instructions and interrupt sequences, restore it on another CPU, and
+    // make sure that both CPUs stay in sync afterwards.
+    for snapshot_cycle in 0..100 {
+        let mut cpu = new_cpu();
+        for cycle in 0..snapshot_cycle {
+            tick(&mut cpu, cycle);
+        }
+        let serialized = serde_json::to_string(&cpu.snapshot()).unwrap();
+        let mut restored_cpu = new_cpu();
+        restored_cpu.ticks(5).unwrap();
+        restored_cpu
+            .mut_memory()
+            .bytes
+            .copy_from_slice(&cpu.memory.bytes);
+        restored_cpu.restore(&serde_json::from_str(&serialized).unwrap());
+        assert_eq!(restored_cpu.snapshot(), cpu.snapshot());
+
+        for cycle in snapshot_cycle..snapshot_cycle + 50 {
+            tick(&mut cpu, cycle);
+            tick(&mut restored_cpu, cycle);
+            assert_eq!(
+                restored_cpu.snapshot(),
+                cpu.snapshot(),
+                "Snapshot taken at cycle {}, diverged at cycle {}",
+                snapshot_cycle,
+                cycle
+            );
+            assert_eq!(restored_cpu.memory.bytes, cpu.memory.bytes);
+        }
+    }
+}
+
+#[test]
+fn deterministic_power_on_state() {
+    let power_on = |power_on_state| {
+        Cpu::<Ram>::with_power_on_state(Box::new(Ram::new(16)), &power_on_state).snapshot()
+    };
+    assert_eq!(
+        power_on(PowerOnState::Seeded(3)),
+        power_on(PowerOnState::Seeded(3))
+    );
+    let state = power_on(PowerOnState::Zero);
+    assert_eq!(
+        [state.reg_a, state.reg_x, state.reg_y, state.reg_sp],
+        [0, 0, 0, 0]
+    );
+    assert_eq!(state.reg_pc, 0);
+    assert_eq!(state.flags, flags::UNUSED);
+}
+
+#[test]
+fn bus_observer() {
+    let mut cpu = cpu_with_program(&[
+        opcodes::LDA_ABS,
+        0x34,
+        0x12,
+        opcodes::STA_ZP,
+        0x40,
+        opcodes::PHA,
+        opcodes::INC_ZP,
+        0x50,
+    ]);
+    cpu.reg_sp = 0xFD;
+    cpu.mut_memory().bytes[0x1234] = 0x56;
+    cpu.mut_memory().bytes[0x50] = 0x07;
+    let accesses = Rc::new(RefCell::new(Vec::new()));
+    let accesses_clone = accesses.clone();
+    cpu.set_bus_observer(Some(Box::new(move |access: &BusAccess| {
+        accesses_clone.borrow_mut().push(*access)
+    })));
+    cpu.ticks(4 + 3 + 3 + 5).unwrap();
+
+    use BusAccessKind::*;
+    use BusOperation::*;
+    let access = |cycle, operation, address, value, kind| BusAccess {
+        cycle,
+        operation,
+        address,
+        value,
+        kind,
+    };
+    assert_eq!(
+        *accesses.borrow(),
+        vec![
+            access(7, Read, 0xF000, opcodes::LDA_ABS, OpcodeFetch),
+            access(8, Read, 0xF001, 0x34, OperandFetch),
+            access(9, Read, 0xF002, 0x12, OperandFetch),
+            access(10, Read, 0x1234, 0x56, Data),
+            access(11, Read, 0xF003, opcodes::STA_ZP, OpcodeFetch),
+            access(12, Read, 0xF004, 0x40, OperandFetch),
+            access(13, Write, 0x0040, 0x56, Data),
+            access(14, Read, 0xF005, opcodes::PHA, OpcodeFetch),
+            access(15, Read, 0xF006, opcodes::INC_ZP, Dummy),
+            access(16, Write, 0x01FD, 0x56, Stack),
+            access(17, Read, 0xF006, opcodes::INC_ZP, OpcodeFetch),
+            access(18, Read, 0xF007, 0x50, OperandFetch),
+            access(19, Read, 0x0050, 0x07, Data),
+            access(20, Write, 0x0050, 0x07, Dummy),
+            access(21, Write, 0x0050, 0x08, Data),
+        ]
+    );
+    assert_eq!(cpu.cycles(), 22);
+
+    // Removing the observer stops the notifications.
+    assert!(cpu.set_bus_observer(None).is_some());
+    reset(&mut cpu);
+    cpu.ticks(4).unwrap();
+    assert_eq!(accesses.borrow().len(), 15);
+}
+
+#[test]
+fn step_instruction() {
+    let mut cpu = cpu_with_program(&[opcodes::NOP, opcodes::LDA_ABS_X, 0xFF, 0x12, opcodes::NOP]);
+    cpu.mut_memory().bytes[0xFFFE..=0xFFFF].copy_from_slice(&[0x00, 0xF1]);
+    cpu.reg_x = 1;
+    cpu.flags &= !flags::I;
+    let cycles_before = cpu.cycles();
+
+    let step = |cycles, interrupt| Step { cycles, interrupt };
+    assert_eq!(cpu.step_instruction().unwrap(), step(2, None));
+    assert_eq!(cpu.step_instruction().unwrap(), step(5, None));
+    cpu.set_irq_pin(true);
+    // The interrupt is taken after the instruction during which it was
+    // polled.
+    assert_eq!(cpu.step_instruction().unwrap(), step(2, None));
+    assert_eq!(
+        cpu.step_instruction().unwrap(),
+        step(7, Some(Interrupt::Irq))
+    );
+    assert_eq!(cpu.reg_pc, 0xF100);
+    assert_eq!(cpu.cycles() - cycles_before, 2 + 5 + 2 + 7);
+
+    cpu.reset();
+    assert_eq!(
+        cpu.step_instruction().unwrap(),
+        step(7, Some(Interrupt::Reset))
+    );
+    assert_eq!(cpu.reg_pc, 0xF000);
+}
+
+#[test]
+fn poke_memory() {
+    let mut cpu = cpu_with_program(&[opcodes::LDA_ZP, 0x40]);
+    cpu.poke_memory(0x0040, 0x2A).unwrap();
+    cpu.poke_memory(0xF001, 0x41).unwrap();
+    cpu.poke_memory(0x0041, 0x3B).unwrap();
+    reset(&mut cpu);
+    cpu.step_instruction().unwrap();
+    assert_eq!(cpu.reg_a, 0x3B);
+    assert_eq!(cpu.inspect_memory(0x0040), 0x2A);
+}
+
+#[test]
+fn edit_registers() {
+    let mut cpu = cpu_with_code! {
+            lda #1      // 0xF000
+            nop         // 0xF002
+            nop         // 0xF003
+            inx         // 0xF004
+    };
+    cpu.ticks(1).unwrap();
+    // We're in the middle of LDA, but changing PC abandons it.
+    cpu.set_reg_pc(0xF004);
+    cpu.set_reg_a(0x12);
+    cpu.set_reg_x(0x34);
+    cpu.set_reg_y(0x56);
+    cpu.set_reg_sp(0x78);
+    cpu.set_flags(0xFF);
+    assert_eq!(cpu.reg_a(), 0x12);
+    assert_eq!(cpu.reg_y(), 0x56);
+    assert_eq!(cpu.reg_sp(), 0x78);
+    assert_eq!(cpu.flags(), !flags::PUSHED);
+
+    cpu.step_instruction().unwrap();
+    assert_eq!(cpu.reg_pc(), 0xF005);
+    assert_eq!(cpu.reg_x(), 0x35);
+}
+
+#[test]
+fn rdy_halts_on_reads() {
+    let mut cpu = cpu_with_program(&[
+        opcodes::STA_ZP,
+        0x40,
+        opcodes::INC_ZP,
+        0x41,
+        opcodes::LDA_ZP,
+        0x41,
+    ]);
+    cpu.reg_a = 5;
+    cpu.ticks(2).unwrap();
+
+    // The STA write cycle completes, but the next opcode fetch gets stalled.
+    cpu.set_rdy_pin(false);
+    cpu.tick().unwrap();
+    assert_eq!(cpu.memory.bytes[0x40], 5);
+    assert!(cpu.at_instruction_start());
+    cpu.ticks(10).unwrap();
+    assert_eq!(cpu.reg_pc, 0xF002);
+    assert!(!cpu.at_instruction_start());
+
+    // Both INC write cycles complete while RDY is low.
+    cpu.set_rdy_pin(true);
+    cpu.ticks(3).unwrap();
+    cpu.set_rdy_pin(false);
+    cpu.ticks(10).unwrap();
+    assert_eq!(cpu.memory.bytes[0x41], 1);
+    assert_eq!(cpu.reg_pc, 0xF004);
+
+    cpu.set_rdy_pin(true);
+    cpu.ticks(3).unwrap();
+    assert_eq!(cpu.reg_a, 1);
+    assert_eq!(cpu.cycles(), 7 + 3 + 10 + 3 + 10 + 3);
+}
+
+#[test]
+fn rdy_stalls_notify_bus_observer_once() {
+    let mut cpu = cpu_with_program(&[opcodes::LDA_ZP, 0x41]);
+    cpu.memory.bytes[0x41] = 8;
+    let hits = Rc::new(RefCell::new(Vec::new()));
+    let hits_clone = hits.clone();
+    cpu.set_bus_observer(Some(Box::new(move |access: &BusAccess| {
+        if access.address == 0x41 {
+            hits_clone.borrow_mut().push(access.cycle);
+        }
+    })));
+    cpu.ticks(2).unwrap();
+
+    // The operand read is repeated while RDY is low, but it's only reported
+    // once, in the cycle when it completes.
+    cpu.set_rdy_pin(false);
+    cpu.ticks(10).unwrap();
+    assert!(hits.borrow().is_empty());
+    cpu.set_rdy_pin(true);
+    cpu.tick().unwrap();
+    assert_eq!(cpu.reg_a, 8);
+    assert_eq!(*hits.borrow(), [cpu.cycles() - 1]);
+}
+
+#[test]
+fn cmos_rdy_halts_on_writes() {
+    let mut cpu = cpu_variant_with_program::<Cmos65C02>(&[opcodes::STA_ZP, 0x40]);
+    cpu.reg_a = 5;
+    cpu.memory.bytes[0x40] = 0;
+    cpu.ticks(2).unwrap();
+    cpu.set_rdy_pin(false);
+    cpu.ticks(10).unwrap();
+    assert_eq!(cpu.memory.bytes[0x40], 0);
+    cpu.set_rdy_pin(true);
+    cpu.tick().unwrap();
+    assert_eq!(cpu.memory.bytes[0x40], 5);
+}
+
 #[bench]
 fn benchmark(b: &mut Bencher) {
     let mut cpu = cpu_with_code! {
@@ -1898,3 +3564,52 @@ fn benchmark(b: &mut Bencher) {
         cpu.ticks(1000).unwrap();
     });
 }
+
+/// A program that exercises most addressing modes, as well as subroutine calls,
+/// stack operations, and branches.
+fn addressing_modes_benchmark_program() -> Vec<u8> {
+    assemble6502!({
+        start: 0xF000,
+        code: {
+                ldx #0
+                ldy #1
+                lda #0x80
+                sta 0x10
+                sta 0x11
+            loop:
+                lda (0x10),y
+                sta (0x10,x)
+                inc 0x20,x
+                ror 0x21
+                jsr sub
+                dey
+                bne loop
+                iny
+                jmp loop
+            sub:
+                ora 0x30,x
+                pha
+                pla
+                rts
+        }
+    })
+    .to_vec()
+}
+
+#[bench]
+fn benchmark_addressing_modes(b: &mut Bencher) {
+    let mut cpu = cpu_with_program(&addressing_modes_benchmark_program());
+    b.iter(|| {
+        reset(&mut cpu);
+        cpu.ticks(1000).unwrap();
+    });
+}
+
+#[bench]
+fn benchmark_addressing_modes_cmos(b: &mut Bencher) {
+    let mut cpu = cpu_variant_with_program::<Cmos65C02>(&addressing_modes_benchmark_program());
+    b.iter(|| {
+        reset(&mut cpu);
+        cpu.ticks(1000).unwrap();
+    });
+}
@@ -0,0 +1,79 @@
+use super::instructions::{InstructionSet, CMOS_INSTRUCTIONS, NMOS_INSTRUCTIONS};
+use std::fmt::Debug;
+
+/// Selects the instruction set and hardware quirks of a particular member of
+/// the 6502 family. Variants are zero-sized types used as a type parameter of
+/// [`Cpu`](super::Cpu), so all the checks are resolved at compile time and
+/// don't cost anything in the emulation loop.
+pub trait Variant: Debug {
+    /// A mask applied to every address put on the address bus. Chips with a
+    /// reduced number of address pins simply don't see the upper bits.
+    const ADDRESS_MASK: u16 = 0xFFFF;
+
+    /// Whether setting the D flag actually enables the decimal mode in ADC and
+    /// SBC instructions.
+    const DECIMAL_MODE: bool = true;
+
+    /// Whether the CPU is a CMOS 65C02, which adds some instructions, turns
+    /// all the undocumented NMOS opcodes into NOPs, and fixes a couple of
+    /// NMOS bugs.
+    const CMOS: bool = false;
+
+    /// Returns information about the instructions supported by this variant.
+    fn instruction_set() -> &'static InstructionSet {
+        &NMOS_INSTRUCTIONS
+    }
+}
+
+/// The original NMOS 6502, including its undocumented opcodes.
+#[derive(Debug)]
+pub struct Nmos6502;
+
+impl Variant for Nmos6502 {}
+
+/// A 6502 in a smaller package, used by Atari 2600. It only has 13 address
+/// lines, and therefore sees only 8KiB of address space.
+#[derive(Debug)]
+pub struct Mos6507;
+
+impl Variant for Mos6507 {
+    const ADDRESS_MASK: u16 = 0x1FFF;
+}
+
+/// A 6502 with a built-in I/O port, used by Commodore 64. Since the port is
+/// mapped to the memory at addresses $0000 and $0001, it's up to the `Memory`
+/// implementation to emulate it; as far as instruction execution goes, this
+/// chip is identical to [`Nmos6502`].
+#[derive(Debug)]
+pub struct Mos6510;
+
+impl Variant for Mos6510 {}
+
+/// A 6502 core built into the NES CPU. The D flag can be set and cleared, but
+/// it doesn't affect the arithmetic.
+#[derive(Debug)]
+pub struct Ricoh2A03;
+
+impl Variant for Ricoh2A03 {
+    const DECIMAL_MODE: bool = false;
+}
+
+/// The original CMOS 65C02. Compared to NMOS, it adds BRA, PHX/PHY, PLX/PLY,
+/// STZ, TRB, TSB, the `(zp)` addressing mode, and some new addressing modes
+/// for BIT, INC, DEC, and JMP. JMP `(abs)` doesn't wrap within a page anymore,
+/// interrupts clear the D flag, and ADC/SBC in decimal mode take one
+/// additional cycle. Unused opcodes are NOPs of various lengths.
+///
+/// Note that bit manipulation instructions (RMB, SMB, BBR, BBS) added by
+/// Rockwell, as well as WDC's WAI and STP, are not supported; these opcodes
+/// are executed as single-cycle NOPs.
+#[derive(Debug)]
+pub struct Cmos65C02;
+
+impl Variant for Cmos65C02 {
+    const CMOS: bool = true;
+
+    fn instruction_set() -> &'static InstructionSet {
+        &CMOS_INSTRUCTIONS
+    }
+}
@@ -6,6 +6,9 @@
 #[no_link]
 extern crate rustasm6502;
 
+pub mod asm;
 pub mod cpu;
+pub mod loader;
 pub mod memory;
+pub mod power_on;
 pub mod test_utils;
@@ -0,0 +1,157 @@
+use super::{decode_hex, numbered_lines, Image, LoadError};
+
+const DATA: u8 = 0x00;
+const END_OF_FILE: u8 = 0x01;
+const EXTENDED_SEGMENT_ADDRESS: u8 = 0x02;
+const START_SEGMENT_ADDRESS: u8 = 0x03;
+const EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const START_LINEAR_ADDRESS: u8 = 0x05;
+
+/// Loads an Intel HEX file. Since the 6502 address space is only 64KiB wide,
+/// extended addresses are only accepted as long as they don't point beyond
+/// it.
+pub fn load_intel_hex(text: &str) -> Result<Image, LoadError> {
+    let mut image = Image::default();
+    let mut base_address: u32 = 0;
+    for (line, record) in numbered_lines(text) {
+        let syntax_error = |message| LoadError::Syntax { line, message };
+        let digits = record
+            .strip_prefix(':')
+            .ok_or_else(|| syntax_error("record doesn't start with a colon"))?;
+        let bytes = decode_hex(digits, line)?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(syntax_error("invalid record length"));
+        }
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(LoadError::Checksum { line });
+        }
+        let address = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let data = &bytes[4..bytes.len() - 1];
+        match bytes[3] {
+            DATA => image.add(base_address + address, data)?,
+            END_OF_FILE => return Ok(image),
+            EXTENDED_SEGMENT_ADDRESS => base_address = address_field(data, line)? << 4,
+            EXTENDED_LINEAR_ADDRESS => base_address = address_field(data, line)? << 16,
+            START_SEGMENT_ADDRESS | START_LINEAR_ADDRESS => {
+                if data.len() != 4 {
+                    return Err(syntax_error("invalid start address"));
+                }
+                let entry = match bytes[3] {
+                    START_SEGMENT_ADDRESS => {
+                        (u16::from_be_bytes([data[0], data[1]]) as u32) << 4
+                            | u16::from_be_bytes([data[2], data[3]]) as u32
+                    }
+                    _ => u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+                };
+                if entry > 0xFFFF {
+                    return Err(LoadError::AddressOutOfRange { address: entry });
+                }
+                image.entry = Some(entry as u16);
+            }
+            _ => return Err(syntax_error("unknown record type")),
+        }
+    }
+    Err(LoadError::Truncated)
+}
+
+/// Parses a 16-bit field of an extended address record.
+fn address_field(data: &[u8], line: usize) -> Result<u32, LoadError> {
+    match data {
+        [high, low] => Ok(u16::from_be_bytes([*high, *low]) as u32),
+        _ => Err(LoadError::Syntax {
+            line,
+            message: "invalid extended address",
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::Segment;
+
+    #[test]
+    fn loads_data() {
+        let image = load_intel_hex(
+            ":03F00000A9018DD6\n\
+             :01F00300000C\n\
+             \n\
+             :020000040000FA\n\
+             :02FFFC0000F013\n\
+             :0400000500000400F3\n\
+             :00000001FF\n",
+        )
+        .unwrap();
+        assert_eq!(
+            image,
+            Image {
+                segments: vec![
+                    Segment {
+                        address: 0xF000,
+                        bytes: vec![0xA9, 0x01, 0x8D, 0x00],
+                    },
+                    Segment {
+                        address: 0xFFFC,
+                        bytes: vec![0x00, 0xF0],
+                    },
+                ],
+                entry: Some(0x0400),
+            }
+        );
+    }
+
+    #[test]
+    fn extended_addresses() {
+        // Segment 0x0100 shifts the addresses by 0x1000.
+        let image = load_intel_hex(":020000020100FB\n:01020000EA13\n:00000001FF\n").unwrap();
+        assert_eq!(
+            image.segments,
+            vec![Segment {
+                address: 0x1200,
+                bytes: vec![0xEA],
+            }]
+        );
+
+        assert_eq!(
+            load_intel_hex(":020000040001F9\n:01000000EA15\n:00000001FF\n"),
+            Err(LoadError::AddressOutOfRange { address: 0x10000 })
+        );
+        // The end of this record doesn't fit in 32 bits.
+        assert_eq!(
+            load_intel_hex(":02000004FFFFFC\n:01FFFF00EA17\n:00000001FF\n"),
+            Err(LoadError::AddressOutOfRange {
+                address: 0xFFFFFFFF
+            })
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            load_intel_hex(":01000000EA16\n:00000001FF\n"),
+            Err(LoadError::Checksum { line: 1 })
+        );
+        assert_eq!(
+            load_intel_hex("\n01000000EA15\n"),
+            Err(LoadError::Syntax {
+                line: 2,
+                message: "record doesn't start with a colon"
+            })
+        );
+        assert_eq!(
+            load_intel_hex(":02000000EA14\n"),
+            Err(LoadError::Syntax {
+                line: 1,
+                message: "invalid record length"
+            })
+        );
+        assert_eq!(
+            load_intel_hex(":01000000EA1\n"),
+            Err(LoadError::Syntax {
+                line: 1,
+                message: "invalid hexadecimal data"
+            })
+        );
+        assert_eq!(load_intel_hex(":01000000EA15\n"), Err(LoadError::Truncated));
+    }
+}
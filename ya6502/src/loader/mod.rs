@@ -0,0 +1,238 @@
+//! Loaders for various program image formats. Each loader parses a file and
+//! produces an [`Image`]: a list of segments that can be written to any
+//! [`Memory`](crate::memory::Memory).
+
+mod intel_hex;
+mod o65;
+mod srec;
+
+pub use intel_hex::load_intel_hex;
+pub use o65::load_o65;
+pub use srec::load_srec;
+
+use crate::memory::{Write, WriteResult};
+use std::error;
+use std::fmt;
+
+/// A contiguous block of bytes to be placed at a given address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+}
+
+/// A program image, ready to be written to the memory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Image {
+    pub segments: Vec<Segment>,
+    /// The entry point, if the file format specifies one.
+    pub entry: Option<u16>,
+}
+
+impl Image {
+    /// Writes all segments to a given memory, in order.
+    pub fn write_to<M: Write + ?Sized>(&self, memory: &mut M) -> WriteResult {
+        for segment in &self.segments {
+            for (offset, byte) in segment.bytes.iter().enumerate() {
+                memory.write(segment.address.wrapping_add(offset as u16), *byte)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends bytes at a given address. If they directly follow the last
+    /// segment, the segment gets extended; otherwise, a new one is created.
+    pub(crate) fn add(&mut self, address: u32, bytes: &[u8]) -> Result<(), LoadError> {
+        let end = address
+            .checked_add(bytes.len() as u32)
+            .ok_or(LoadError::AddressOutOfRange { address })?;
+        if end > 0x10000 {
+            return Err(LoadError::AddressOutOfRange { address: end - 1 });
+        }
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        match self.segments.last_mut() {
+            Some(last) if last.address as u32 + last.bytes.len() as u32 == address => {
+                last.bytes.extend_from_slice(bytes)
+            }
+            _ => self.segments.push(Segment {
+                address: address as u16,
+                bytes: bytes.to_vec(),
+            }),
+        }
+        Ok(())
+    }
+}
+
+/// Loads a C64 program file, which starts with a two-byte load address.
+pub fn load_prg(bytes: &[u8]) -> Result<Image, LoadError> {
+    if bytes.len() < 2 {
+        return Err(LoadError::Truncated);
+    }
+    let address = u16::from_le_bytes([bytes[0], bytes[1]]);
+    load_raw(&bytes[2..], address)
+}
+
+/// Loads a raw binary at a given address.
+pub fn load_raw(bytes: &[u8], origin: u16) -> Result<Image, LoadError> {
+    let mut image = Image::default();
+    image.add(origin as u32, bytes)?;
+    Ok(image)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// A malformed line in a text-based format. Lines are numbered from 1.
+    Syntax {
+        line: usize,
+        message: &'static str,
+    },
+    Checksum {
+        line: usize,
+    },
+    /// The image doesn't fit in the 16-bit address space.
+    AddressOutOfRange {
+        address: u32,
+    },
+    /// A binary file ended unexpectedly.
+    Truncated,
+    InvalidFormat(&'static str),
+    Unsupported(&'static str),
+    /// An object file refers to a symbol that is not defined anywhere.
+    UnresolvedSymbol(String),
+    /// A relocatable file can't be moved to a given base address, since it
+    /// would break the alignment requirements.
+    Misaligned {
+        base: u16,
+        alignment: u16,
+    },
+}
+
+impl error::Error for LoadError {}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Syntax { line, message } => write!(f, "Line {}: {}", line, message),
+            LoadError::Checksum { line } => write!(f, "Line {}: checksum mismatch", line),
+            LoadError::AddressOutOfRange { address } => {
+                write!(f, "Address ${:X} out of range", address)
+            }
+            LoadError::Truncated => write!(f, "Unexpected end of file"),
+            LoadError::InvalidFormat(message) => write!(f, "Invalid format: {}", message),
+            LoadError::Unsupported(feature) => write!(f, "Unsupported feature: {}", feature),
+            LoadError::UnresolvedSymbol(name) => write!(f, "Unresolved symbol: {}", name),
+            LoadError::Misaligned { base, alignment } => write!(
+                f,
+                "Base address ${:04X} is not aligned to {} bytes",
+                base, alignment
+            ),
+        }
+    }
+}
+
+/// Iterates over non-empty lines of a text file, along with their numbers.
+fn numbered_lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+}
+
+/// Decodes a string of hexadecimal digit pairs.
+fn decode_hex(digits: &str, line: usize) -> Result<Vec<u8>, LoadError> {
+    let syntax_error = LoadError::Syntax {
+        line,
+        message: "invalid hexadecimal data",
+    };
+    if !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+        return Err(syntax_error);
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| syntax_error.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Ram;
+
+    #[test]
+    fn prg() {
+        let image = load_prg(&[0x01, 0x08, 0x0B, 0x08, 0x0A]).unwrap();
+        assert_eq!(
+            image,
+            Image {
+                segments: vec![Segment {
+                    address: 0x0801,
+                    bytes: vec![0x0B, 0x08, 0x0A],
+                }],
+                entry: None,
+            }
+        );
+        assert_eq!(load_prg(&[0x01]), Err(LoadError::Truncated));
+    }
+
+    #[test]
+    fn raw() {
+        let image = load_raw(&[1, 2, 3], 0xFFFD).unwrap();
+        assert_eq!(
+            image.segments,
+            vec![Segment {
+                address: 0xFFFD,
+                bytes: vec![1, 2, 3],
+            }]
+        );
+        assert_eq!(
+            load_raw(&[1, 2, 3], 0xFFFE),
+            Err(LoadError::AddressOutOfRange { address: 0x10000 })
+        );
+        assert_eq!(load_raw(&[0; 0x10000], 0).unwrap().segments.len(), 1);
+    }
+
+    #[test]
+    fn writes_to_memory() {
+        let image = Image {
+            segments: vec![
+                Segment {
+                    address: 0x1000,
+                    bytes: vec![1, 2],
+                },
+                Segment {
+                    address: 0xFFFF,
+                    bytes: vec![3],
+                },
+            ],
+            entry: Some(0x1000),
+        };
+        let mut ram = Ram::new(16);
+        image.write_to(&mut ram).unwrap();
+        assert_eq!(ram.bytes[0x0FFF..0x1003], [0, 1, 2, 0]);
+        assert_eq!(ram.bytes[0xFFFF], 3);
+    }
+
+    #[test]
+    fn merges_contiguous_segments() {
+        let mut image = Image::default();
+        image.add(0x1000, &[1, 2]).unwrap();
+        image.add(0x1002, &[3]).unwrap();
+        image.add(0x2000, &[4]).unwrap();
+        image.add(0x2001, &[]).unwrap();
+        assert_eq!(
+            image.segments,
+            vec![
+                Segment {
+                    address: 0x1000,
+                    bytes: vec![1, 2, 3],
+                },
+                Segment {
+                    address: 0x2000,
+                    bytes: vec![4],
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,461 @@
+//! A loader for the [o65](http://www.6502.org/users/andre/o65/fileformat.html)
+//! relocatable file format, as produced by the cc65 linker.
+
+use super::{Image, LoadError};
+
+const MAGIC: [u8; 6] = [0x01, 0x00, b'o', b'6', b'5', 0x00];
+const OUT_OF_BOUNDS: LoadError = LoadError::InvalidFormat("relocation outside of the segment");
+
+mod mode {
+    pub const CPU_65816: u16 = 1 << 15;
+    pub const PAGED_RELOCATION: u16 = 1 << 14;
+    pub const SIZE_32BIT: u16 = 1 << 13;
+    pub const CHAIN: u16 = 1 << 10;
+    pub const BSS_ZERO: u16 = 1 << 9;
+    pub const ALIGNMENT: u16 = 0b11;
+}
+
+mod segment_id {
+    pub const UNDEFINED: u8 = 0;
+    pub const ABSOLUTE: u8 = 1;
+    pub const TEXT: u8 = 2;
+    pub const DATA: u8 = 3;
+    pub const BSS: u8 = 4;
+    pub const ZERO: u8 = 5;
+}
+
+mod relocation_type {
+    pub const WORD: u8 = 0x80;
+    pub const HIGH: u8 = 0x40;
+    pub const LOW: u8 = 0x20;
+}
+
+/// Loads an o65 file, relocating its text segment to a given base address.
+/// The data segment is placed directly after the text segment, and the BSS
+/// segment directly after the data segment. The zero page segment stays where
+/// it was. If the file requires it, the BSS segment is also a part of the
+/// image and gets zeroed. Undefined references are not supported, since there
+/// is nothing that could resolve them.
+pub fn load_o65(bytes: &[u8], base: u16) -> Result<Image, LoadError> {
+    let mut reader = Reader { bytes, position: 0 };
+    if reader.slice(MAGIC.len())? != MAGIC {
+        return Err(LoadError::InvalidFormat("not an o65 file"));
+    }
+    let mode = reader.word()?;
+    if mode & mode::CPU_65816 != 0 {
+        return Err(LoadError::Unsupported("65816 code"));
+    }
+    if mode & mode::SIZE_32BIT != 0 {
+        return Err(LoadError::Unsupported("32-bit o65 files"));
+    }
+    if mode & mode::CHAIN != 0 {
+        return Err(LoadError::Unsupported("chained o65 files"));
+    }
+
+    let header = Header {
+        tbase: reader.word()?,
+        tlen: reader.word()?,
+        dbase: reader.word()?,
+        dlen: reader.word()?,
+        bbase: reader.word()?,
+        blen: reader.word()?,
+    };
+    // Zero page base and length, followed by the stack size.
+    reader.slice(6)?;
+    // Header options.
+    loop {
+        match reader.byte()? {
+            0 => break,
+            length => reader.slice(length as usize - 1)?,
+        };
+    }
+
+    let text_address = base;
+    let data_address = base.wrapping_add(header.tlen);
+    let bss_address = data_address.wrapping_add(header.dlen);
+    let alignment = match mode & mode::ALIGNMENT {
+        0 => 1,
+        1 => 2,
+        2 => 4,
+        _ => 256,
+    };
+    let deltas = [
+        (text_address, header.tbase),
+        (data_address, header.dbase),
+        (bss_address, header.bbase),
+    ];
+    for (new_base, old_base) in deltas {
+        if new_base % alignment != 0 {
+            return Err(LoadError::Misaligned {
+                base: new_base,
+                alignment,
+            });
+        }
+        if mode & mode::PAGED_RELOCATION != 0 && new_base.wrapping_sub(old_base) & 0xFF != 0 {
+            return Err(LoadError::Misaligned {
+                base: new_base,
+                alignment: 256,
+            });
+        }
+    }
+
+    let mut text = reader.slice(header.tlen as usize)?.to_vec();
+    let mut data = reader.slice(header.dlen as usize)?.to_vec();
+    let undefined_count = reader.word()?;
+    let relocator = Relocator {
+        text_delta: text_address.wrapping_sub(header.tbase),
+        data_delta: data_address.wrapping_sub(header.dbase),
+        bss_delta: bss_address.wrapping_sub(header.bbase),
+        paged: mode & mode::PAGED_RELOCATION != 0,
+        undefined: (0..undefined_count)
+            .map(|_| reader.string())
+            .collect::<Result<_, _>>()?,
+    };
+    relocator.relocate(&mut text, &mut reader)?;
+    relocator.relocate(&mut data, &mut reader)?;
+
+    let mut image = Image::default();
+    image.add(text_address as u32, &text)?;
+    image.add(data_address as u32, &data)?;
+    if mode & mode::BSS_ZERO != 0 {
+        image.add(bss_address as u32, &vec![0; header.blen as usize])?;
+    }
+    Ok(image)
+}
+
+/// Segment addresses and lengths, as stored in the file header.
+struct Header {
+    tbase: u16,
+    tlen: u16,
+    dbase: u16,
+    dlen: u16,
+    bbase: u16,
+    blen: u16,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn slice(&mut self, length: usize) -> Result<&'a [u8], LoadError> {
+        let slice = self
+            .bytes
+            .get(self.position..self.position + length)
+            .ok_or(LoadError::Truncated)?;
+        self.position += length;
+        Ok(slice)
+    }
+
+    fn byte(&mut self) -> Result<u8, LoadError> {
+        Ok(self.slice(1)?[0])
+    }
+
+    fn word(&mut self) -> Result<u16, LoadError> {
+        let bytes = self.slice(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Reads a zero-terminated string.
+    fn string(&mut self) -> Result<String, LoadError> {
+        let length = self.bytes[self.position..]
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(LoadError::Truncated)?;
+        let string = String::from_utf8_lossy(self.slice(length)?).into_owned();
+        self.position += 1;
+        Ok(string)
+    }
+}
+
+struct Relocator {
+    text_delta: u16,
+    data_delta: u16,
+    bss_delta: u16,
+    paged: bool,
+    /// Names of undefined references, used for reporting errors.
+    undefined: Vec<String>,
+}
+
+impl Relocator {
+    /// Applies a relocation table read from a given reader to a segment.
+    fn relocate(&self, segment: &mut [u8], reader: &mut Reader) -> Result<(), LoadError> {
+        let mut position: isize = -1;
+        loop {
+            let mut offset = reader.byte()?;
+            if offset == 0 {
+                return Ok(());
+            }
+            while offset == 255 {
+                position += 254;
+                offset = reader.byte()?;
+            }
+            position += offset as isize;
+            let address = position as usize;
+
+            let type_byte = reader.byte()?;
+            let delta = self.delta(type_byte & 0b0001_1111, reader)?;
+            match type_byte & 0b1110_0000 {
+                relocation_type::WORD => {
+                    let bytes = segment.get_mut(address..address + 2).ok_or(OUT_OF_BOUNDS)?;
+                    let value = u16::from_le_bytes([bytes[0], bytes[1]]).wrapping_add(delta);
+                    bytes.copy_from_slice(&value.to_le_bytes());
+                }
+                relocation_type::HIGH => {
+                    let low = if self.paged { 0 } else { reader.byte()? };
+                    let byte = segment.get_mut(address).ok_or(OUT_OF_BOUNDS)?;
+                    let value = u16::from_le_bytes([low, *byte]).wrapping_add(delta);
+                    *byte = (value >> 8) as u8;
+                }
+                relocation_type::LOW => {
+                    let byte = segment.get_mut(address).ok_or(OUT_OF_BOUNDS)?;
+                    *byte = byte.wrapping_add(delta as u8);
+                }
+                _ => return Err(LoadError::Unsupported("65816 segment relocation")),
+            }
+        }
+    }
+
+    /// Returns the relocation delta for a given segment.
+    fn delta(&self, segment: u8, reader: &mut Reader) -> Result<u16, LoadError> {
+        match segment {
+            segment_id::UNDEFINED => {
+                let index = reader.word()? as usize;
+                Err(LoadError::UnresolvedSymbol(
+                    self.undefined
+                        .get(index)
+                        .cloned()
+                        .unwrap_or_else(|| format!("#{}", index)),
+                ))
+            }
+            segment_id::ABSOLUTE | segment_id::ZERO => Ok(0),
+            segment_id::TEXT => Ok(self.text_delta),
+            segment_id::DATA => Ok(self.data_delta),
+            segment_id::BSS => Ok(self.bss_delta),
+            _ => Err(LoadError::InvalidFormat("invalid segment ID")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::Segment;
+
+    /// Contents of an o65 file, used to build a binary for testing.
+    struct O65 {
+        mode: u16,
+        tbase: u16,
+        text: Vec<u8>,
+        dbase: u16,
+        data: Vec<u8>,
+        bbase: u16,
+        blen: u16,
+        undefined: Vec<&'static str>,
+        text_relocations: Vec<u8>,
+        data_relocations: Vec<u8>,
+    }
+
+    impl Default for O65 {
+        fn default() -> Self {
+            Self {
+                mode: 0,
+                tbase: 0x1000,
+                text: vec![],
+                dbase: 0x2000,
+                data: vec![],
+                bbase: 0x3000,
+                blen: 0,
+                undefined: vec![],
+                text_relocations: vec![],
+                data_relocations: vec![],
+            }
+        }
+    }
+
+    impl O65 {
+        fn to_bytes(&self) -> Vec<u8> {
+            let mut bytes = MAGIC.to_vec();
+            for word in [
+                self.mode,
+                self.tbase,
+                self.text.len() as u16,
+                self.dbase,
+                self.data.len() as u16,
+                self.bbase,
+                self.blen,
+                0x0080, // zbase
+                0x0002, // zlen
+                0x0000, // stack
+            ] {
+                bytes.extend_from_slice(&word.to_le_bytes());
+            }
+            // A single header option (file name), then the terminator.
+            bytes.extend_from_slice(&[5, 0, b'f', b'n', 0, 0]);
+            bytes.extend_from_slice(&self.text);
+            bytes.extend_from_slice(&self.data);
+            bytes.extend_from_slice(&(self.undefined.len() as u16).to_le_bytes());
+            for name in &self.undefined {
+                bytes.extend_from_slice(name.as_bytes());
+                bytes.push(0);
+            }
+            bytes.extend_from_slice(&self.text_relocations);
+            bytes.push(0);
+            bytes.extend_from_slice(&self.data_relocations);
+            bytes.push(0);
+            // No exported globals.
+            bytes.extend_from_slice(&[0, 0]);
+            bytes
+        }
+    }
+
+    const WORD: u8 = relocation_type::WORD;
+    const HIGH: u8 = relocation_type::HIGH;
+    const LOW: u8 = relocation_type::LOW;
+    use segment_id::{DATA, TEXT, UNDEFINED, ZERO};
+
+    #[test]
+    fn relocates() {
+        let file = O65 {
+            text: vec![
+                0xAD, 0x00, 0x20, // LDA $2000
+                0x4C, 0x00, 0x10, // JMP $1000
+                0xA9, 0x00, // LDA #<$2000
+                0xA2, 0x20, // LDX #>$2001
+                0x85, 0x80, // STA $80
+            ],
+            data: vec![0x03, 0x10], // .word $1003
+            text_relocations: vec![
+                2,
+                WORD | DATA,
+                3,
+                WORD | TEXT,
+                3,
+                LOW | DATA,
+                2,
+                HIGH | DATA,
+                0x01,
+                2,
+                LOW | ZERO,
+            ],
+            data_relocations: vec![1, WORD | TEXT],
+            ..O65::default()
+        };
+        assert_eq!(
+            load_o65(&file.to_bytes(), 0x3000).unwrap(),
+            Image {
+                segments: vec![Segment {
+                    address: 0x3000,
+                    bytes: vec![
+                        0xAD, 0x0C, 0x30, // LDA $300C
+                        0x4C, 0x00, 0x30, // JMP $3000
+                        0xA9, 0x0C, // LDA #<$300C
+                        0xA2, 0x30, // LDX #>$300D
+                        0x85, 0x80, // STA $80
+                        0x03, 0x30, // .word $3003
+                    ],
+                }],
+                entry: None,
+            }
+        );
+    }
+
+    #[test]
+    fn long_relocation_offsets() {
+        let mut text = vec![0; 300];
+        text[260..262].copy_from_slice(&[0x34, 0x12]);
+        let file = O65 {
+            text,
+            text_relocations: vec![255, 7, WORD | TEXT],
+            ..O65::default()
+        };
+        let image = load_o65(&file.to_bytes(), 0x1100).unwrap();
+        assert_eq!(image.segments[0].bytes[260..262], [0x34, 0x13]);
+    }
+
+    #[test]
+    fn paged_relocation() {
+        let file = O65 {
+            mode: mode::PAGED_RELOCATION,
+            text: vec![0xA9, 0x10], // LDA #>$1000
+            dbase: 0x1002,
+            bbase: 0x1002,
+            text_relocations: vec![2, HIGH | TEXT],
+            ..O65::default()
+        };
+        let image = load_o65(&file.to_bytes(), 0x4000).unwrap();
+        assert_eq!(image.segments[0].bytes, [0xA9, 0x40]);
+        assert_eq!(
+            load_o65(&file.to_bytes(), 0x4080),
+            Err(LoadError::Misaligned {
+                base: 0x4080,
+                alignment: 256
+            })
+        );
+    }
+
+    #[test]
+    fn zeroes_bss() {
+        let file = O65 {
+            mode: mode::BSS_ZERO,
+            text: vec![0xEA],
+            dbase: 0x1001,
+            data: vec![0x01],
+            bbase: 0x1002,
+            blen: 3,
+            ..O65::default()
+        };
+        assert_eq!(
+            load_o65(&file.to_bytes(), 0x0800).unwrap().segments,
+            vec![Segment {
+                address: 0x0800,
+                bytes: vec![0xEA, 0x01, 0x00, 0x00, 0x00],
+            }]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            load_o65(b"\x01\x00o64\x00", 0),
+            Err(LoadError::InvalidFormat("not an o65 file"))
+        );
+        assert_eq!(load_o65(&MAGIC, 0), Err(LoadError::Truncated));
+
+        let file = O65 {
+            mode: 2, // 4-byte alignment
+            ..O65::default()
+        };
+        assert_eq!(
+            load_o65(&file.to_bytes(), 0x1002),
+            Err(LoadError::Misaligned {
+                base: 0x1002,
+                alignment: 4
+            })
+        );
+
+        let file = O65 {
+            text: vec![0x20, 0x00, 0x00], // JSR _foo
+            undefined: vec!["_bar", "_foo"],
+            text_relocations: vec![2, WORD | UNDEFINED, 1, 0],
+            ..O65::default()
+        };
+        assert_eq!(
+            load_o65(&file.to_bytes(), 0x1000),
+            Err(LoadError::UnresolvedSymbol("_foo".to_string()))
+        );
+
+        let file = O65 {
+            text: vec![0x20, 0x00, 0x00],
+            text_relocations: vec![3, WORD | TEXT],
+            ..O65::default()
+        };
+        assert_eq!(
+            load_o65(&file.to_bytes(), 0x1000),
+            Err(LoadError::InvalidFormat(
+                "relocation outside of the segment"
+            ))
+        );
+    }
+}
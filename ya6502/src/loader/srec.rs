@@ -0,0 +1,119 @@
+use super::{decode_hex, numbered_lines, Image, LoadError};
+
+/// Loads a Motorola S-record file (S19, S28, or S37). Addresses beyond the
+/// 64KiB address space are rejected.
+pub fn load_srec(text: &str) -> Result<Image, LoadError> {
+    let mut image = Image::default();
+    for (line, record) in numbered_lines(text) {
+        let syntax_error = |message| LoadError::Syntax { line, message };
+        let (record_type, digits) = match record.as_bytes() {
+            [b'S', record_type, ..] if record_type.is_ascii_digit() => {
+                (record_type - b'0', &record[2..])
+            }
+            _ => return Err(syntax_error("record doesn't start with S and a digit")),
+        };
+        let bytes = decode_hex(digits, line)?;
+        if bytes.is_empty() || bytes.len() != bytes[0] as usize + 1 {
+            return Err(syntax_error("invalid record length"));
+        }
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0xFF {
+            return Err(LoadError::Checksum { line });
+        }
+        let address_length = match record_type {
+            0 | 1 | 5 | 9 => 2,
+            2 | 6 | 8 => 3,
+            3 | 7 => 4,
+            _ => return Err(syntax_error("unknown record type")),
+        };
+        if bytes.len() < address_length + 2 {
+            return Err(syntax_error("invalid record length"));
+        }
+        let address = bytes[1..=address_length]
+            .iter()
+            .fold(0u32, |address, byte| address << 8 | *byte as u32);
+        let data = &bytes[address_length + 1..bytes.len() - 1];
+        match record_type {
+            1..=3 => image.add(address, data)?,
+            7..=9 => {
+                if address > 0xFFFF {
+                    return Err(LoadError::AddressOutOfRange { address });
+                }
+                image.entry = Some(address as u16);
+            }
+            // Header and record count.
+            _ => {}
+        }
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::Segment;
+
+    #[test]
+    fn loads_data() {
+        let image = load_srec(
+            "S00600004844521B\n\
+             S105F000A90160\n\
+             S206000020EA00EF\n\
+             S104F0028D7C\n\
+             S5030003F9\n\
+             S9030400F8\n",
+        )
+        .unwrap();
+        assert_eq!(
+            image,
+            Image {
+                segments: vec![
+                    Segment {
+                        address: 0xF000,
+                        bytes: vec![0xA9, 0x01],
+                    },
+                    Segment {
+                        address: 0x0020,
+                        bytes: vec![0xEA, 0x00],
+                    },
+                    Segment {
+                        address: 0xF002,
+                        bytes: vec![0x8D],
+                    },
+                ],
+                entry: Some(0x0400),
+            }
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            load_srec("S105F000A9015F\n"),
+            Err(LoadError::Checksum { line: 1 })
+        );
+        assert_eq!(
+            load_srec("S4030000FC\n"),
+            Err(LoadError::Syntax {
+                line: 1,
+                message: "unknown record type"
+            })
+        );
+        assert_eq!(
+            load_srec("X105F000A9015F\n"),
+            Err(LoadError::Syntax {
+                line: 1,
+                message: "record doesn't start with S and a digit"
+            })
+        );
+        assert_eq!(
+            load_srec("S2060100000000F8\n"),
+            Err(LoadError::AddressOutOfRange { address: 0x10001 })
+        );
+        assert_eq!(
+            load_srec("S306FFFFFFFFEA13\n"),
+            Err(LoadError::AddressOutOfRange {
+                address: 0xFFFFFFFF
+            })
+        );
+    }
+}
@@ -0,0 +1,457 @@
+//! A generic address decoder that allows wiring devices together
+//! declaratively, instead of writing a custom `match` for every machine.
+
+use super::{Inspect, Memory, Poke, Read, ReadResult, Write, WriteError, WriteResult};
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+/// A device that can be mounted in a [`MemoryMap`] for reading.
+pub trait ReadDevice: Read + Inspect + fmt::Debug {}
+impl<T: Read + Inspect + fmt::Debug> ReadDevice for T {}
+
+/// A device that can be mounted in a [`MemoryMap`] for both reading and
+/// writing.
+pub trait ReadWriteDevice: ReadDevice + Write {}
+impl<T: ReadDevice + Write> ReadWriteDevice for T {}
+
+/// A device that can be poked through a [`MemoryMap`].
+trait PokeDevice: Poke + fmt::Debug {}
+impl<T: Poke + fmt::Debug> PokeDevice for T {}
+
+/// Handles to a single mounted device, one for each kind of access that the
+/// mapping allows.
+#[derive(Debug)]
+struct Device {
+    readable: Rc<RefCell<dyn ReadDevice>>,
+    writable: Option<Rc<RefCell<dyn ReadWriteDevice>>>,
+    pokeable: Option<Rc<RefCell<dyn PokeDevice>>>,
+}
+
+/// Describes where and how a device is mounted in a [`MemoryMap`]. Devices are
+/// shared using `Rc<RefCell<...>>`, so that the machine can still access them
+/// directly (for example, to tick them).
+///
+/// Note that devices receive absolute addresses (with the mirror bits cleared,
+/// see [`Mapping::with_mirror`]), not offsets from the beginning of their
+/// range. This is how the real chips see the address bus, and it allows them
+/// to do their own partial address decoding.
+#[derive(Debug)]
+pub struct Mapping {
+    device: Device,
+    range: RangeInclusive<u16>,
+    mirror: u16,
+    priority: i32,
+    enabled: bool,
+}
+
+impl Mapping {
+    /// Mounts a device on a given address range for both reading and writing.
+    pub fn read_write<D: ReadWriteDevice + Poke + 'static>(
+        range: RangeInclusive<u16>,
+        device: Rc<RefCell<D>>,
+    ) -> Self {
+        Self::new(range, device.clone(), Some(device.clone()), Some(device))
+    }
+
+    /// Mounts a device on a given address range for reading only. Writes to
+    /// this range are passed to the next mapping that accepts them, which
+    /// makes it easy to emulate ROM overlaid on top of RAM.
+    pub fn read_only<D: ReadDevice + Poke + 'static>(
+        range: RangeInclusive<u16>,
+        device: Rc<RefCell<D>>,
+    ) -> Self {
+        Self::new(range, device.clone(), None, Some(device))
+    }
+
+    /// Same as [`Mapping::read_write`], but for devices that don't support
+    /// poking. Poking addresses handled by this mapping fails.
+    pub fn read_write_without_poke<D: ReadWriteDevice + 'static>(
+        range: RangeInclusive<u16>,
+        device: Rc<RefCell<D>>,
+    ) -> Self {
+        Self::new(range, device.clone(), Some(device), None)
+    }
+
+    /// Same as [`Mapping::read_only`], but for devices that don't support
+    /// poking. Poking addresses handled by this mapping fails.
+    pub fn read_only_without_poke<D: ReadDevice + 'static>(
+        range: RangeInclusive<u16>,
+        device: Rc<RefCell<D>>,
+    ) -> Self {
+        Self::new(range, device, None, None)
+    }
+
+    fn new(
+        range: RangeInclusive<u16>,
+        readable: Rc<RefCell<dyn ReadDevice>>,
+        writable: Option<Rc<RefCell<dyn ReadWriteDevice>>>,
+        pokeable: Option<Rc<RefCell<dyn PokeDevice>>>,
+    ) -> Self {
+        Self {
+            device: Device {
+                readable,
+                writable,
+                pokeable,
+            },
+            range,
+            mirror: 0,
+            priority: 0,
+            enabled: true,
+        }
+    }
+
+    /// Marks given address bits as not decoded by this mapping. These bits are
+    /// cleared before comparing the address with the mapping range, so the
+    /// device appears at every address that only differs in these bits. The
+    /// device itself receives the address with the mirror bits cleared.
+    pub fn with_mirror(mut self, mirror: u16) -> Self {
+        self.mirror = mirror;
+        self
+    }
+
+    /// Sets the priority of this mapping. If mappings overlap, the one with
+    /// the highest priority wins; among mappings with equal priority, the one
+    /// mounted last wins.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Makes the mapping initially disabled. It can be enabled later using
+    /// [`MemoryMap::set_enabled`].
+    pub fn disabled(mut self) -> Self {
+        self.enabled = false;
+        self
+    }
+
+    /// Returns the address that should be passed to the device, or `None` if
+    /// the mapping doesn't handle a given address.
+    fn decode(&self, address: u16) -> Option<u16> {
+        let address = address & !self.mirror;
+        if self.enabled && self.range.contains(&address) {
+            Some(address)
+        } else {
+            None
+        }
+    }
+}
+
+/// Identifies a mapping mounted in a [`MemoryMap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MappingId(usize);
+
+/// A memory that dispatches reads and writes to devices mounted on address
+/// ranges. Reads from addresses not handled by any device return an open bus
+/// value, and writes to them are ignored, which is what the real hardware
+/// usually does.
+#[derive(Debug)]
+pub struct MemoryMap {
+    mappings: Vec<Mapping>,
+    /// Indices of mappings, ordered by decreasing precedence.
+    lookup_order: Vec<usize>,
+    open_bus_value: u8,
+}
+
+impl MemoryMap {
+    /// Creates an empty memory map that returns a given value when reading
+    /// from an address that isn't handled by any device.
+    pub fn new(open_bus_value: u8) -> Self {
+        Self {
+            mappings: vec![],
+            lookup_order: vec![],
+            open_bus_value,
+        }
+    }
+
+    /// Mounts a device according to a given mapping. Returns an identifier
+    /// that can be later used to switch the mapping on and off.
+    pub fn mount(&mut self, mapping: Mapping) -> MappingId {
+        let index = self.mappings.len();
+        let position = self
+            .lookup_order
+            .iter()
+            .position(|&i| self.mappings[i].priority <= mapping.priority)
+            .unwrap_or(self.lookup_order.len());
+        self.mappings.push(mapping);
+        self.lookup_order.insert(position, index);
+        MappingId(index)
+    }
+
+    /// Enables or disables a given mapping. Disabled mappings don't handle any
+    /// addresses, uncovering the mappings below them. This can be used to
+    /// emulate bank switching.
+    pub fn set_enabled(&mut self, id: MappingId, enabled: bool) {
+        self.mappings[id.0].enabled = enabled;
+    }
+
+    pub fn is_enabled(&self, id: MappingId) -> bool {
+        self.mappings[id.0].enabled
+    }
+
+    /// Finds a device that handles reads from a given address, along with the
+    /// address that should be passed to it.
+    fn readable(&self, address: u16) -> Option<(&Device, u16)> {
+        self.lookup_order.iter().find_map(|&i| {
+            let mapping = &self.mappings[i];
+            mapping
+                .decode(address)
+                .map(|address| (&mapping.device, address))
+        })
+    }
+
+    /// Finds a device that handles writes to a given address, along with the
+    /// address that should be passed to it.
+    fn writable(&self, address: u16) -> Option<(&RefCell<dyn ReadWriteDevice>, u16)> {
+        self.lookup_order.iter().find_map(|&i| {
+            let mapping = &self.mappings[i];
+            let device = mapping.device.writable.as_ref()?;
+            mapping
+                .decode(address)
+                .map(|address| (device.as_ref(), address))
+        })
+    }
+}
+
+impl Inspect for MemoryMap {
+    fn inspect(&self, address: u16) -> ReadResult {
+        match self.readable(address) {
+            Some((device, address)) => device.readable.borrow().inspect(address),
+            None => Ok(self.open_bus_value),
+        }
+    }
+}
+
+impl Read for MemoryMap {
+    fn read(&mut self, address: u16) -> ReadResult {
+        match self.readable(address) {
+            Some((device, address)) => device.readable.borrow_mut().read(address),
+            None => Ok(self.open_bus_value),
+        }
+    }
+}
+
+impl Write for MemoryMap {
+    fn write(&mut self, address: u16, value: u8) -> WriteResult {
+        match self.writable(address) {
+            Some((device, address)) => device.borrow_mut().write(address, value),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Poke for MemoryMap {
+    /// Pokes the device that is visible for reading at a given address, so
+    /// that a read-only device overlaid on top of another one gets patched.
+    /// Fails if that device doesn't support poking.
+    fn poke(&mut self, address: u16, value: u8) -> WriteResult {
+        let pokeable = self
+            .readable(address)
+            .and_then(|(device, address)| Some((device.pokeable.as_ref()?, address)));
+        match pokeable {
+            Some((device, address)) => device.borrow_mut().poke(address, value),
+            None => Err(WriteError { address, value }),
+        }
+    }
+}
+
+impl Memory for MemoryMap {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{Ram, ReadError, Rom};
+
+    fn shared<T>(device: T) -> Rc<RefCell<T>> {
+        Rc::new(RefCell::new(device))
+    }
+
+    /// A device that can only be inspected, so that we can tell reads and
+    /// inspections apart.
+    #[derive(Debug)]
+    struct InspectOnly(u8);
+
+    impl Inspect for InspectOnly {
+        fn inspect(&self, _address: u16) -> ReadResult {
+            Ok(self.0)
+        }
+    }
+
+    impl Read for InspectOnly {
+        fn read(&mut self, address: u16) -> ReadResult {
+            Err(ReadError { address })
+        }
+    }
+
+    impl Poke for InspectOnly {
+        fn poke(&mut self, _address: u16, value: u8) -> WriteResult {
+            self.0 = value;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn open_bus() {
+        let mut map = MemoryMap::new(0xAB);
+        map.mount(Mapping::read_write(0x1000..=0x1FFF, shared(Ram::new(12))));
+        assert_eq!(map.read(0x0FFF), Ok(0xAB));
+        assert_eq!(map.inspect(0x2000), Ok(0xAB));
+        assert_eq!(map.write(0x0FFF, 1), Ok(()));
+        assert_eq!(map.read(0x0FFF), Ok(0xAB));
+    }
+
+    #[test]
+    fn reads_and_writes() {
+        let ram = shared(Ram::new(16));
+        let mut map = MemoryMap::new(0);
+        map.mount(Mapping::read_write(0x0000..=0x7FFF, ram.clone()));
+        map.write(0x1234, 0x56).unwrap();
+        assert_eq!(map.read(0x1234), Ok(0x56));
+        assert_eq!(ram.borrow().bytes[0x1234], 0x56);
+
+        ram.borrow_mut().bytes[0x7FFF] = 0x78;
+        assert_eq!(map.read(0x7FFF), Ok(0x78));
+    }
+
+    #[test]
+    fn mirroring() {
+        // Decode the memory like Atari 2600 does.
+        let tia = shared(Ram::new(16));
+        let ram = shared(Ram::new(16));
+        let riot = shared(Ram::new(16));
+        let rom = shared(Rom::new(&[0x01, 0x02, 0x03, 0x04]).unwrap());
+        let mut map = MemoryMap::new(0);
+        map.mount(Mapping::read_write(0x0000..=0x007F, tia.clone()).with_mirror(0xEF00));
+        map.mount(Mapping::read_write(0x0080..=0x00FF, ram.clone()).with_mirror(0xED00));
+        map.mount(Mapping::read_write(0x0280..=0x02FF, riot.clone()).with_mirror(0xED00));
+        map.mount(Mapping::read_only(0x1000..=0x1FFF, rom).with_mirror(0xE000));
+
+        map.write(0x0A02, 1).unwrap();
+        map.write(0xE4A5, 2).unwrap();
+        map.write(0x0681, 3).unwrap();
+        assert_eq!(tia.borrow().bytes[0x0002], 1);
+        assert_eq!(ram.borrow().bytes[0x00A5], 2);
+        assert_eq!(riot.borrow().bytes[0x0281], 3);
+
+        assert_eq!(map.read(0x0002), Ok(1));
+        assert_eq!(map.read(0x00A5), Ok(2));
+        assert_eq!(map.read(0x0281), Ok(3));
+        assert_eq!(map.read(0xF001), Ok(0x02));
+        assert_eq!(map.read(0x1FFF), Ok(0x04));
+    }
+
+    #[test]
+    fn priority_overlays() {
+        let ram = shared(Ram::new(16));
+        let rom = shared(Rom::new(&[0xEE; 0x2000]).unwrap());
+        let io = shared(Ram::new(16));
+        let mut map = MemoryMap::new(0);
+        // Mount the RAM last to make sure that it's the priority, and not the
+        // order, that matters.
+        map.mount(Mapping::read_only(0xE000..=0xFFFF, rom).with_priority(1));
+        map.mount(Mapping::read_write(0xD000..=0xDFFF, io.clone()).with_priority(1));
+        map.mount(Mapping::read_write(0x0000..=0xFFFF, ram.clone()));
+
+        map.write(0x1234, 1).unwrap();
+        map.write(0xD000, 2).unwrap();
+        map.write(0xE000, 3).unwrap();
+        assert_eq!(map.read(0x1234), Ok(1));
+        assert_eq!(map.read(0xD000), Ok(2));
+        assert_eq!(map.read(0xE000), Ok(0xEE));
+
+        // The I/O area is read-write, so it shadows the RAM completely; ROM
+        // passes writes to the RAM underneath.
+        assert_eq!(ram.borrow().bytes[0xD000], 0);
+        assert_eq!(io.borrow().bytes[0xD000], 2);
+        assert_eq!(ram.borrow().bytes[0xE000], 3);
+    }
+
+    #[test]
+    fn equal_priorities() {
+        let mut map = MemoryMap::new(0);
+        map.mount(Mapping::read_only(0x0000..=0xFFFF, shared(InspectOnly(1))));
+        map.mount(Mapping::read_only(0x1000..=0x1FFF, shared(InspectOnly(2))));
+        assert_eq!(map.inspect(0x0FFF), Ok(1));
+        assert_eq!(map.inspect(0x1000), Ok(2));
+        assert_eq!(map.inspect(0x1FFF), Ok(2));
+        assert_eq!(map.inspect(0x2000), Ok(1));
+    }
+
+    #[test]
+    fn switching_mappings() {
+        let mut map = MemoryMap::new(0);
+        map.mount(Mapping::read_write(0x0000..=0xFFFF, shared(Ram::new(16))));
+        let bank0 = map.mount(Mapping::read_only(
+            0xA000..=0xBFFF,
+            shared(Rom::new(&[0xB0]).unwrap()),
+        ));
+        let bank1 = map.mount(
+            Mapping::read_only(0xA000..=0xBFFF, shared(Rom::new(&[0xB1]).unwrap())).disabled(),
+        );
+        map.write(0xA000, 0x12).unwrap();
+        assert!(map.is_enabled(bank0));
+        assert!(!map.is_enabled(bank1));
+        assert_eq!(map.read(0xA000), Ok(0xB0));
+
+        map.set_enabled(bank0, false);
+        map.set_enabled(bank1, true);
+        assert_eq!(map.read(0xA000), Ok(0xB1));
+
+        map.set_enabled(bank1, false);
+        assert_eq!(map.read(0xA000), Ok(0x12));
+    }
+
+    #[test]
+    fn inspect_does_not_read() {
+        let mut map = MemoryMap::new(0);
+        map.mount(Mapping::read_only(0x0000..=0xFFFF, shared(InspectOnly(7))));
+        assert_eq!(map.inspect(0x5678), Ok(7));
+        assert_eq!(map.read(0x5678), Err(ReadError { address: 0x5678 }));
+    }
+
+    #[test]
+    fn poking() {
+        let ram = shared(Ram::new(16));
+        let rom = shared(Rom::new(&[0xEE; 0x2000]).unwrap());
+        let mut map = MemoryMap::new(0);
+        map.mount(Mapping::read_write(0x0000..=0xDFFF, ram.clone()));
+        map.mount(Mapping::read_only(0xE000..=0xFFFF, rom.clone()));
+
+        map.poke(0x1234, 1).unwrap();
+        map.poke(0xE000, 2).unwrap();
+        assert_eq!(ram.borrow().bytes[0x1234], 1);
+        assert_eq!(ram.borrow().bytes[0xE000], 0);
+        assert_eq!(map.inspect(0xE000), Ok(2));
+
+        let mut map = MemoryMap::new(0);
+        map.mount(Mapping::read_write(0x0000..=0x0FFF, ram));
+        assert_eq!(
+            map.poke(0x1000, 3),
+            Err(WriteError {
+                address: 0x1000,
+                value: 3
+            })
+        );
+    }
+
+    #[test]
+    fn poking_devices_without_poke() {
+        let mut map = MemoryMap::new(0);
+        map.mount(Mapping::read_write(0x0000..=0xFFFF, shared(Ram::new(16))));
+        map.mount(Mapping::read_only_without_poke(
+            0x1000..=0x1FFF,
+            shared(InspectOnly(1)),
+        ));
+        assert_eq!(
+            map.poke(0x1000, 2),
+            Err(WriteError {
+                address: 0x1000,
+                value: 2
+            })
+        );
+        assert_eq!(map.inspect(0x1000), Ok(1));
+        map.poke(0x2000, 3).unwrap();
+        assert_eq!(map.inspect(0x2000), Ok(3));
+    }
+}
@@ -1,7 +1,13 @@
+use crate::power_on::PowerOnState;
+use rand::RngCore;
 use std::error;
 use std::fmt;
 use std::result::Result;
 
+mod map;
+
+pub use map::{Mapping, MappingId, MemoryMap, ReadDevice, ReadWriteDevice};
+
 pub trait Read {
     /// Reads a byte from given address. Returns the byte or error if the
     /// location is unsupported. (Note that the error feature is expected to
@@ -36,11 +42,23 @@ pub trait Write {
     fn write(&mut self, address: u16, value: u8) -> WriteResult;
 }
 
+/// A debug-only counterpart of [`Inspect`] for writing. It allows debuggers
+/// and cheat tools to edit the memory contents and chip registers.
+pub trait Poke {
+    /// Similar to [`Write::write`], but guaranteed not to trigger any side
+    /// effects that a regular write would have, such as strobes, acknowledging
+    /// interrupts, or restarting timers. Read-only locations (ROM, status
+    /// registers) are patched directly, so that a subsequent
+    /// [`Inspect::inspect`] returns the new value wherever possible. Returns
+    /// error if the location doesn't hold any state that could be changed.
+    fn poke(&mut self, address: u16, value: u8) -> WriteResult;
+}
+
 pub trait Memory: Read + Write {}
 
 pub type ReadResult = Result<u8, ReadError>;
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq)]
 pub struct ReadError {
     pub address: u16,
 }
@@ -63,7 +81,7 @@ impl fmt::Debug for ReadError {
 
 pub type WriteResult = Result<(), WriteError>;
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq)]
 pub struct WriteError {
     pub address: u16,
     pub value: u8,
@@ -115,6 +133,20 @@ impl Ram {
         }
     }
 
+    /// Creates a new RAM with an address bus of a given width (in bits), with
+    /// contents determined by a given power-on policy. The total size of the
+    /// RAM will be 2^address_width. The `component` name distinguishes between
+    /// RAM chips initialized with the same seed; see [`PowerOnState::rng`].
+    pub fn with_power_on_state(
+        power_on_state: &PowerOnState,
+        component: &str,
+        address_width: u32,
+    ) -> Ram {
+        let mut ram = Self::new(address_width);
+        power_on_state.rng(component).fill_bytes(&mut ram.bytes);
+        return ram;
+    }
+
     /// Creates 64KiB of `RAM`, putting given `program` at address 0xF000. It
     /// also sets the reset pointer to 0xF000.
     pub fn with_test_program(program: &[u8]) -> Ram {
@@ -151,6 +183,12 @@ impl Write for Ram {
     }
 }
 
+impl Poke for Ram {
+    fn poke(&mut self, address: u16, value: u8) -> WriteResult {
+        self.write(address, value)
+    }
+}
+
 impl Memory for Ram {}
 
 impl fmt::Debug for Ram {
@@ -201,6 +239,14 @@ impl Read for Rom {
     }
 }
 
+impl Poke for Rom {
+    /// Patches the ROM image.
+    fn poke(&mut self, address: u16, value: u8) -> WriteResult {
+        self.bytes[(address & self.address_mask) as usize] = value;
+        Ok(())
+    }
+}
+
 impl fmt::Debug for Rom {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> fmt::Result {
         f.debug_struct("Rom")
@@ -272,6 +318,15 @@ mod tests {
         assert_eq!(ram.read(0xE456).unwrap(), 34);
     }
 
+    #[test]
+    fn ram_with_power_on_state() {
+        let ram = Ram::with_power_on_state(&PowerOnState::Pattern, "RAM", 4);
+        assert_eq!(
+            ram.bytes,
+            [0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]
+        );
+    }
+
     #[test]
     fn ram_mirroring() {
         let mut ram = Ram::new(7);
@@ -335,6 +390,22 @@ mod tests {
         assert_eq!(rom.read(0x01237).unwrap(), 4);
     }
 
+    #[test]
+    fn ram_poke() {
+        let mut ram = Ram::new(8);
+        ram.poke(0x1234, 56).unwrap();
+        assert_eq!(ram.inspect(0x0034).unwrap(), 56);
+    }
+
+    #[test]
+    fn rom_poke() {
+        let mut rom = Rom::new(&[1, 2, 3, 4]).unwrap();
+        rom.poke(0xF002, 0xEA).unwrap();
+        assert_eq!(rom.read(0xF002).unwrap(), 0xEA);
+        assert_eq!(rom.read(0x1006).unwrap(), 0xEA);
+        assert_eq!(rom.read(0xF003).unwrap(), 4);
+    }
+
     #[test]
     fn rom_illegal_sizes() {
         // Not a power of 2
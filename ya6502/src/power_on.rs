@@ -0,0 +1,168 @@
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use std::str::FromStr;
+
+/// Determines the initial state of registers, memory, and other chip internals
+/// that are undefined after powering the hardware on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerOnState {
+    /// Random values, different on each run. This is the closest to what the
+    /// real hardware does.
+    Random,
+    /// Pseudo-random values generated from a given seed, identical on each run.
+    Seeded(u64),
+    /// All bits cleared.
+    Zero,
+    /// A fixed pattern of four $00 bytes followed by four $FF bytes, repeated
+    /// over and over. It resembles the initial contents of DRAM chips used in
+    /// many 8-bit computers.
+    Pattern,
+}
+
+impl PowerOnState {
+    /// Returns a random number generator that produces initial values of a
+    /// given component (for example, `"CPU"` or `"RAM"`) according to this
+    /// policy. Each call starts a new sequence, so that components initialized
+    /// with a deterministic policy don't depend on the order in which they are
+    /// created. With a seeded policy, the seed is mixed with the component
+    /// name, so that different components don't share the same sequence.
+    pub fn rng(&self, component: &str) -> PowerOnRng {
+        PowerOnRng(match self {
+            PowerOnState::Random => Source::Random(Box::new(StdRng::from_entropy())),
+            PowerOnState::Seeded(seed) => Source::Random(Box::new(StdRng::seed_from_u64(
+                component_seed(*seed, component),
+            ))),
+            PowerOnState::Zero => Source::Zero,
+            PowerOnState::Pattern => Source::Pattern(0),
+        })
+    }
+}
+
+/// Derives a seed for a given component using the FNV-1a hash. We don't use
+/// the standard library hasher, since its output is not guaranteed to stay the
+/// same between Rust releases.
+fn component_seed(seed: u64, component: &str) -> u64 {
+    seed.to_le_bytes()
+        .iter()
+        .chain(component.as_bytes())
+        .fold(0xCBF29CE484222325, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x100000001B3)
+        })
+}
+
+impl FromStr for PowerOnState {
+    type Err = String;
+
+    /// Parses the policy name: `random`, `zero`, `pattern`, or a number, which
+    /// is interpreted as a random seed.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(PowerOnState::Random),
+            "zero" => Ok(PowerOnState::Zero),
+            "pattern" => Ok(PowerOnState::Pattern),
+            _ => s.parse().map(PowerOnState::Seeded).map_err(|_| {
+                format!(
+                    "Invalid power-on state: {:?}; expected \"random\", \"zero\", \
+                    \"pattern\", or a numeric seed",
+                    s
+                )
+            }),
+        }
+    }
+}
+
+/// A source of initial values, as returned by [`PowerOnState::rng`]. Since it
+/// implements [`RngCore`], it can be used with all the [`rand::Rng`] methods.
+pub struct PowerOnRng(Source);
+
+enum Source {
+    Random(Box<StdRng>),
+    Zero,
+    /// Index of the next byte of the pattern.
+    Pattern(usize),
+}
+
+impl RngCore for PowerOnRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match &mut self.0 {
+            Source::Random(rng) => rng.fill_bytes(dest),
+            Source::Zero => dest.fill(0),
+            Source::Pattern(index) => {
+                for byte in dest {
+                    *byte = if *index & 0b100 == 0 { 0x00 } else { 0xFF };
+                    *index += 1;
+                }
+            }
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(power_on_state: PowerOnState) -> [u8; 10] {
+        component_bytes(power_on_state, "RAM")
+    }
+
+    fn component_bytes(power_on_state: PowerOnState, component: &str) -> [u8; 10] {
+        let mut result = [0; 10];
+        power_on_state.rng(component).fill_bytes(&mut result);
+        return result;
+    }
+
+    #[test]
+    fn deterministic_policies() {
+        assert_eq!(bytes(PowerOnState::Zero), [0; 10]);
+        assert_eq!(
+            bytes(PowerOnState::Pattern),
+            [0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00]
+        );
+        assert_eq!(
+            bytes(PowerOnState::Seeded(5)),
+            bytes(PowerOnState::Seeded(5))
+        );
+        assert_ne!(
+            bytes(PowerOnState::Seeded(5)),
+            bytes(PowerOnState::Seeded(6))
+        );
+    }
+
+    #[test]
+    fn seeded_components_are_independent() {
+        assert_ne!(
+            component_bytes(PowerOnState::Seeded(5), "RAM"),
+            component_bytes(PowerOnState::Seeded(5), "CPU")
+        );
+        assert_eq!(
+            component_bytes(PowerOnState::Zero, "RAM"),
+            component_bytes(PowerOnState::Zero, "CPU")
+        );
+    }
+
+    #[test]
+    fn parsing() {
+        assert_eq!("random".parse(), Ok(PowerOnState::Random));
+        assert_eq!("zero".parse(), Ok(PowerOnState::Zero));
+        assert_eq!("pattern".parse(), Ok(PowerOnState::Pattern));
+        assert_eq!("1234".parse(), Ok(PowerOnState::Seeded(1234)));
+        assert!("foo".parse::<PowerOnState>().is_err());
+    }
+}
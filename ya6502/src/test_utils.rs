@@ -1,11 +1,12 @@
 use crate::cpu::opcodes;
+use crate::cpu::variant::Variant;
 use crate::cpu::Cpu;
 use crate::memory::Memory;
 use crate::memory::Ram;
 use std::fmt::Debug;
 
 /// Resets the CPU and waits until the reset sequence is finished.
-pub fn reset<M: Memory + Debug>(cpu: &mut Cpu<M>) {
+pub fn reset<M: Memory + Debug, V: Variant>(cpu: &mut Cpu<M, V>) {
     cpu.reset();
     cpu.ticks(7).unwrap();
 }
@@ -15,6 +16,11 @@ pub fn reset<M: Memory + Debug>(cpu: &mut Cpu<M>) {
 /// instruction too many. It also sets the reset vector to the beginning of
 /// program.
 pub fn cpu_with_program(program: &[u8]) -> Cpu<Ram> {
+    cpu_variant_with_program(program)
+}
+
+/// Same as [`cpu_with_program`], but creates a given variant of the CPU.
+pub fn cpu_variant_with_program<V: Variant>(program: &[u8]) -> Cpu<Ram, V> {
     let mut memory = Box::new(Ram::with_test_program(program));
     memory.bytes[0xF000 + program.len()] = opcodes::HLT1;
     let mut cpu = Cpu::new(memory);